@@ -0,0 +1,24 @@
+//! Build info for `resource_monitor version`: the target triple and,
+//! when building from a git checkout, the commit.
+
+use std::process::Command;
+
+fn main() {
+    let target = std::env::var("TARGET").unwrap_or_default();
+    println!("cargo:rustc-env=RESOURCE_MONITOR_TARGET={}", target);
+
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .and_then(|o| String::from_utf8(o.stdout).ok())
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=RESOURCE_MONITOR_GIT_COMMIT={}", commit);
+
+    // Pick up new commits without watching the whole tree.
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -1,26 +1,190 @@
-use std::time::{Duration, SystemTime};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use crate::config::{ResourceKind, Thresholds};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{
+    CompositeRule, ConfirmPolicy, FlapPolicy, GrowthPolicy, ResourceKind, Thresholds, ZScoreConfig,
+};
+use crate::diag::{debug, trace};
+use crate::error::MonitorError;
 use crate::metrics::{ProcessSample, SystemSnapshot};
 
+/// Which point of a spike's life an event reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventPhase {
+    /// Provisional event from `--alert-on-start`: the spike has lasted
+    /// the minimum duration and is still open. `timestamp_end` equals
+    /// `timestamp_start` and the peak is the peak so far.
+    Start,
+    /// The spike has closed.
+    End,
+    /// The resource closed `--flap-threshold` spikes within the flap
+    /// window. `peak_value` is the number of spikes, `threshold` the
+    /// flap threshold; the event spans from the end of the oldest of
+    /// those spikes to the end of the latest.
+    Flap,
+    /// RAM use grew faster than `--ram-growth-mb-per-min` over the
+    /// growth window. `peak_value` is the fitted growth in MB/min,
+    /// `threshold` the configured one; the event spans the window.
+    Growth,
+}
+
+impl EventPhase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventPhase::Start => "start",
+            EventPhase::End => "end",
+            EventPhase::Flap => "flap",
+            EventPhase::Growth => "growth",
+        }
+    }
+}
+
 /// Spike event info for logs and alerts.
 #[derive(Debug, Clone)]
 pub struct SpikeEvent {
+    /// Identifier assigned when the spike is first reported, unique
+    /// within the run: `<ts_start>-<pid>-<seq>`. A start event and the
+    /// end event of the same spike share it.
+    pub id: String,
+    pub phase: EventPhase,
     pub resource: ResourceKind,
     pub timestamp_start: SystemTime,
     pub timestamp_end: SystemTime,
     pub peak_value: f32,
     pub threshold: f32,
+    /// Watched path for keyed resources (disk), None otherwise.
+    pub path: Option<String>,
+    /// Top processes at the peak: by RSS for RAM spikes, by CPU otherwise.
     pub top_processes: Vec<ProcessSample>,
+    /// System uptime in seconds when the spike started, if known.
+    pub uptime_start_secs: Option<u64>,
+    /// `--tag` of the run, e.g. a deploy id; set by the monitor loop.
+    pub tag: Option<String>,
+    /// Name of the `--rule` that fired, None for the resource's own
+    /// threshold.
+    pub rule: Option<String>,
 }
 
-/// Per-resource state for spike detection.
+impl SpikeEvent {
+    /// How far the peak went over the threshold, e.g. 2.0 for double
+    /// the threshold. None for flap events, whose peak is a spike count,
+    /// and for thresholds at or below zero.
+    pub fn over_ratio(&self) -> Option<f32> {
+        (self.phase != EventPhase::Flap && self.threshold > 0.0)
+            .then(|| self.peak_value / self.threshold)
+    }
+}
+
+#[cfg(test)]
+impl SpikeEvent {
+    /// End event of a `resource` spike from `start_secs` after the epoch
+    /// lasting `duration_secs`, for tests to fill in.
+    pub(crate) fn ended(
+        resource: ResourceKind,
+        start_secs: u64,
+        duration_secs: u64,
+        peak_value: f32,
+        threshold: f32,
+    ) -> Self {
+        let start = UNIX_EPOCH + Duration::from_secs(start_secs);
+        Self {
+            id: format!("{}-1-1", start_secs),
+            phase: EventPhase::End,
+            resource,
+            timestamp_start: start,
+            timestamp_end: start + Duration::from_secs(duration_secs),
+            peak_value,
+            threshold,
+            path: None,
+            top_processes: Vec::new(),
+            uptime_start_secs: None,
+            tag: None,
+            rule: None,
+        }
+    }
+}
+
+/// A spike that has started but not closed yet.
 #[derive(Debug, Clone)]
+pub struct OngoingSpike {
+    pub resource: ResourceKind,
+    pub timestamp_start: SystemTime,
+    /// Highest value so far, in the resource's unit.
+    pub peak_value: f32,
+    /// Threshold on the latest active sample.
+    pub threshold: f32,
+    /// Watched path for keyed resources (disk), None otherwise.
+    pub path: Option<String>,
+    /// Name of the `--rule` tracking it, None for the resource's own
+    /// threshold.
+    pub rule: Option<String>,
+}
+
+/// Running mean/variance of a resource for z-score detection.
+///
+/// Exact (Welford) during warmup, then an exponential moving average so
+/// the baseline follows slow drift.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Baseline {
+    pub count: u64,
+    pub mean: f64,
+    pub var: f64,
+}
+
+impl Baseline {
+    pub fn update(&mut self, value: f32, warmup_samples: u64) {
+        let x = value as f64;
+        self.count += 1;
+
+        if self.count <= warmup_samples.max(1) {
+            // Welford: population variance over the samples so far.
+            let delta = x - self.mean;
+            self.mean += delta / self.count as f64;
+            self.var += (delta * (x - self.mean) - self.var) / self.count as f64;
+        } else {
+            let alpha = 2.0 / (warmup_samples as f64 + 1.0);
+            let delta = x - self.mean;
+            self.mean += alpha * delta;
+            self.var = (1.0 - alpha) * (self.var + alpha * delta * delta);
+        }
+    }
+
+    pub fn stddev(&self) -> f64 {
+        self.var.max(0.0).sqrt()
+    }
+}
+
+/// Per-resource state for spike detection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SpikeState {
     pub in_spike: bool,
     pub spike_start: Option<SystemTime>,
     pub spike_max_value: f32,
-    pub spike_max_snapshot: Option<SystemSnapshot>,
+    /// Threshold on the latest active sample, for closing the spike
+    /// when a run ends while it is open.
+    #[serde(default)]
+    pub spike_threshold: f32,
+    /// Top processes captured at the peak sample.
+    pub spike_max_processes: Vec<ProcessSample>,
+    /// Z-score baseline; survives `reset()` since it spans spikes.
+    pub baseline: Baseline,
+    /// Recent (timestamp, over threshold) results for `--confirm`; also
+    /// survives `reset()`.
+    pub recent: VecDeque<(SystemTime, bool)>,
+    /// Id of the start event already emitted for the open spike, reused
+    /// by its end event.
+    #[serde(default)]
+    pub start_event_id: Option<String>,
+    /// System uptime when the open spike started.
+    #[serde(default)]
+    pub spike_start_uptime_secs: Option<u64>,
+    /// Monotonic start of the open spike, for its duration; not saved,
+    /// so a spike resumed from a state file falls back to timestamps.
+    #[serde(skip)]
+    pub spike_start_instant: Option<Instant>,
 }
 
 impl SpikeState {
@@ -29,7 +193,13 @@ impl SpikeState {
             in_spike: false,
             spike_start: None,
             spike_max_value: 0.0,
-            spike_max_snapshot: None,
+            spike_threshold: 0.0,
+            spike_max_processes: Vec::new(),
+            baseline: Baseline::default(),
+            recent: VecDeque::new(),
+            start_event_id: None,
+            spike_start_uptime_secs: None,
+            spike_start_instant: None,
         }
     }
 
@@ -37,16 +207,112 @@ impl SpikeState {
         self.in_spike = false;
         self.spike_start = None;
         self.spike_max_value = 0.0;
-        self.spike_max_snapshot = None;
+        self.spike_threshold = 0.0;
+        self.spike_max_processes.clear();
+        self.start_event_id = None;
+        self.spike_start_uptime_secs = None;
+        self.spike_start_instant = None;
+    }
+
+    /// How long the open spike, started at `start`, has lasted at `now`.
+    /// Measured on the monotonic clock when both ends have it, so an NTP
+    /// step mid-spike can't shrink or stretch it; from the timestamps
+    /// for replayed snapshots and spikes resumed from a state file.
+    fn elapsed(&self, start: SystemTime, now: SystemTime, instant: Option<Instant>) -> Duration {
+        match (self.spike_start_instant, instant) {
+            (Some(started), Some(instant)) => instant.saturating_duration_since(started),
+            _ => now.duration_since(start).unwrap_or(Duration::ZERO),
+        }
     }
 }
 
-/// Global analyzer state for CPU, RAM and IO.
-#[derive(Debug, Clone)]
+impl Default for SpikeState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Composite rule state: the spike tracks the furthest over-threshold
+/// ratio, plus the value/threshold of the resource that reached it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompositeState {
+    pub spike: SpikeState,
+    pub peak_value: f32,
+    pub peak_threshold: f32,
+}
+
+/// Spike state of one `--rule`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuleState {
+    /// Name of the rule's resource, e.g. "cpu".
+    pub resource: String,
+    pub spike: SpikeState,
+}
+
+/// Resource, disk path and `--rule` name of a series of spikes.
+pub type SpikeKey = (ResourceKind, Option<String>, Option<String>);
+
+/// Global analyzer state for CPU, RAM, IO and temperature, plus one
+/// state per watched disk path, the composite rule and each `--rule`.
+///
+/// Serializable so `--state-file` can carry open spikes across restarts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnalyzerState {
     pub cpu: SpikeState,
     pub ram: SpikeState,
     pub io: SpikeState,
+    pub io_read: SpikeState,
+    pub io_write: SpikeState,
+    /// Missing in state files from older versions.
+    #[serde(default)]
+    pub steal: SpikeState,
+    #[serde(default)]
+    pub iowait: SpikeState,
+    pub temp: SpikeState,
+    #[serde(default)]
+    pub fd: SpikeState,
+    #[serde(default)]
+    pub procs: SpikeState,
+    #[serde(default)]
+    pub power: SpikeState,
+    pub disk: HashMap<String, SpikeState>,
+    pub composite: CompositeState,
+    /// Spikes reported so far; sequence part of event ids.
+    pub events_closed: u64,
+    /// Spike state of each `--rule` by rule name. Missing in state
+    /// files from older versions.
+    #[serde(default)]
+    pub rules: HashMap<String, RuleState>,
+    /// End times of recent spikes per resource (and disk path or rule),
+    /// for flap detection; not carried across restarts.
+    #[serde(skip)]
+    pub recent_spikes: HashMap<SpikeKey, VecDeque<SystemTime>>,
+    /// RAM used over the growth window, oldest first; not carried across
+    /// restarts.
+    #[serde(skip)]
+    pub ram_history: VecDeque<RamSample>,
+}
+
+/// RAM used at one snapshot, for `--ram-growth-mb-per-min`.
+#[derive(Debug, Clone, Copy)]
+pub struct RamSample {
+    pub timestamp: SystemTime,
+    pub instant: Option<Instant>,
+    pub used_bytes: u64,
+}
+
+impl RamSample {
+    /// Seconds from `earlier` to this sample, on the monotonic clock when
+    /// both have it.
+    fn secs_since(&self, earlier: &RamSample) -> f64 {
+        match (earlier.instant, self.instant) {
+            (Some(a), Some(b)) => b.saturating_duration_since(a).as_secs_f64(),
+            _ => self
+                .timestamp
+                .duration_since(earlier.timestamp)
+                .map_or(0.0, |d| d.as_secs_f64()),
+        }
+    }
 }
 
 impl AnalyzerState {
@@ -55,11 +321,227 @@ impl AnalyzerState {
             cpu: SpikeState::new(),
             ram: SpikeState::new(),
             io: SpikeState::new(),
+            io_read: SpikeState::new(),
+            io_write: SpikeState::new(),
+            steal: SpikeState::new(),
+            iowait: SpikeState::new(),
+            temp: SpikeState::new(),
+            fd: SpikeState::new(),
+            procs: SpikeState::new(),
+            power: SpikeState::new(),
+            disk: HashMap::new(),
+            composite: CompositeState::default(),
+            events_closed: 0,
+            rules: HashMap::new(),
+            recent_spikes: HashMap::new(),
+            ram_history: VecDeque::new(),
+        }
+    }
+
+    /// Load a state saved by `save`, or None if the file does not exist
+    /// or was written more than `max_age` ago (a stale open spike would
+    /// otherwise absorb the whole downtime).
+    pub fn load(path: &str, max_age: Duration) -> Result<Option<Self>, MonitorError> {
+        let contents = match fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(MonitorError::Io(e)),
+        };
+
+        let saved: SavedState = serde_json::from_str(&contents)
+            .map_err(|e| MonitorError::Parse(format!("Invalid state file {}: {}", path, e)))?;
+
+        let age_secs = epoch_secs(SystemTime::now()).saturating_sub(saved.saved_ts);
+        if age_secs > max_age.as_secs() {
+            return Ok(None);
+        }
+
+        Ok(Some(saved.state))
+    }
+
+    /// Write the state to `path`, replacing it atomically.
+    pub fn save(&self, path: &str) -> Result<(), MonitorError> {
+        let saved = SavedState {
+            saved_ts: epoch_secs(SystemTime::now()),
+            state: self.clone(),
+        };
+        let json = serde_json::to_string(&saved)
+            .map_err(|e| MonitorError::Parse(format!("Cannot serialize state: {}", e)))?;
+
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Next event id for a spike that started at `start`.
+    fn next_event_id(&mut self, start: SystemTime) -> String {
+        self.events_closed += 1;
+        format!("{}-{}-{}", epoch_secs(start), std::process::id(), self.events_closed)
+    }
+
+    /// Spike state behind an event of `resource` (and `path` for disks).
+    fn spike_state_mut(
+        &mut self,
+        resource: ResourceKind,
+        path: Option<&str>,
+    ) -> Option<&mut SpikeState> {
+        match resource {
+            ResourceKind::Cpu => Some(&mut self.cpu),
+            ResourceKind::Steal => Some(&mut self.steal),
+            ResourceKind::Iowait => Some(&mut self.iowait),
+            ResourceKind::Ram => Some(&mut self.ram),
+            ResourceKind::Io => Some(&mut self.io),
+            ResourceKind::IoRead => Some(&mut self.io_read),
+            ResourceKind::IoWrite => Some(&mut self.io_write),
+            ResourceKind::Temp => Some(&mut self.temp),
+            ResourceKind::Power => Some(&mut self.power),
+            ResourceKind::Fd => Some(&mut self.fd),
+            ResourceKind::Procs => Some(&mut self.procs),
+            ResourceKind::Disk => self.disk.get_mut(path?),
+            ResourceKind::Composite => Some(&mut self.composite.spike),
+        }
+    }
+
+    /// Spikes currently open: cpu, ram, io, temp, disks by path, then
+    /// the composite rule.
+    pub fn ongoing_spikes(&self) -> Vec<OngoingSpike> {
+        let mut disks: Vec<_> = self.disk.iter().collect();
+        disks.sort_by(|a, b| a.0.cmp(b.0));
+
+        let scalars = [
+            (ResourceKind::Cpu, &self.cpu, None),
+            (ResourceKind::Steal, &self.steal, None),
+            (ResourceKind::Iowait, &self.iowait, None),
+            (ResourceKind::Ram, &self.ram, None),
+            (ResourceKind::Io, &self.io, None),
+            (ResourceKind::IoRead, &self.io_read, None),
+            (ResourceKind::IoWrite, &self.io_write, None),
+            (ResourceKind::Temp, &self.temp, None),
+            (ResourceKind::Fd, &self.fd, None),
+            (ResourceKind::Procs, &self.procs, None),
+            (ResourceKind::Power, &self.power, None),
+        ];
+        let keyed = disks
+            .into_iter()
+            .map(|(path, s)| (ResourceKind::Disk, s, Some(path.clone())));
+
+        let mut spikes: Vec<OngoingSpike> = scalars
+            .into_iter()
+            .chain(keyed)
+            .filter_map(|(resource, s, path)| {
+                Some(OngoingSpike {
+                    resource,
+                    timestamp_start: s.spike_start.filter(|_| s.in_spike)?,
+                    peak_value: s.spike_max_value,
+                    threshold: s.spike_threshold,
+                    path,
+                    rule: None,
+                })
+            })
+            .collect();
+
+        // The composite state tracks a ratio; report the resource value.
+        let composite = &self.composite;
+        if let Some(start) = composite.spike.spike_start.filter(|_| composite.spike.in_spike) {
+            spikes.push(OngoingSpike {
+                resource: ResourceKind::Composite,
+                timestamp_start: start,
+                peak_value: composite.peak_value,
+                threshold: composite.spike.spike_threshold,
+                path: None,
+                rule: None,
+            });
+        }
+
+        let mut rules: Vec<_> = self.rules.iter().collect();
+        rules.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, rule) in rules {
+            let s = &rule.spike;
+            let (Some(start), Some(resource)) = (
+                s.spike_start.filter(|_| s.in_spike),
+                ResourceKind::from_name(&rule.resource),
+            ) else {
+                continue;
+            };
+            spikes.push(OngoingSpike {
+                resource,
+                timestamp_start: start,
+                peak_value: s.spike_max_value,
+                threshold: s.spike_threshold,
+                path: None,
+                rule: Some(name.clone()),
+            });
         }
+
+        spikes
+    }
+
+    /// Number of spikes currently open, across all resources.
+    pub fn active_spikes(&self) -> usize {
+        [
+            &self.cpu,
+            &self.ram,
+            &self.io,
+            &self.io_read,
+            &self.io_write,
+            &self.steal,
+            &self.iowait,
+            &self.temp,
+            &self.fd,
+            &self.procs,
+            &self.power,
+            &self.composite.spike,
+        ]
+            .into_iter()
+            .chain(self.disk.values())
+            .chain(self.rules.values().map(|r| &r.spike))
+            .filter(|s| s.in_spike)
+            .count()
     }
 }
 
-/// Analyze one snapshot and return spike events closed on this tick.
+/// On-disk form of `--state-file`.
+#[derive(Serialize, Deserialize)]
+struct SavedState {
+    /// When the state was written, in seconds since epoch.
+    saved_ts: u64,
+    state: AnalyzerState,
+}
+
+fn epoch_secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+impl Default for AnalyzerState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How a resource's over-threshold samples become a spike.
+#[derive(Debug, Clone, Copy)]
+struct SpikePolicy {
+    min_duration_secs: u64,
+    confirm: Option<ConfirmPolicy>,
+    alert_on_start: bool,
+    ignore_first_uptime_secs: Option<u64>,
+}
+
+impl SpikePolicy {
+    /// Whether a spike starting at `uptime_secs` is too close to boot to
+    /// open.
+    fn within_boot_grace(&self, uptime_secs: Option<u64>) -> bool {
+        matches!(
+            (self.ignore_first_uptime_secs, uptime_secs),
+            (Some(grace), Some(uptime)) if uptime < grace
+        )
+    }
+}
+
+/// Analyze one snapshot and return spike events closed on this tick,
+/// plus start events for spikes that just reached their minimum
+/// duration when `thresholds.alert_on_start` is set.
 pub fn analyze_snapshot(
     snapshot: &SystemSnapshot,
     thresholds: &Thresholds,
@@ -68,93 +550,844 @@ pub fn analyze_snapshot(
 ) -> Vec<SpikeEvent> {
     let mut events = Vec::new();
 
-    // CPU
-    if let Some(th) = thresholds.cpu_threshold {
-        if let Some(ev) = update_spike_for_resource(
-            ResourceKind::Cpu,
-            snapshot.cpu_usage_percent,
-            th,
-            snapshot,
-            min_spike_duration_secs,
-            &mut state.cpu,
-        ) {
-            events.push(ev);
+    // Per-resource overrides fall back to the global minimum duration.
+    let policy = |min_duration_secs: Option<u64>| SpikePolicy {
+        min_duration_secs: min_duration_secs.unwrap_or(min_spike_duration_secs),
+        confirm: thresholds.confirm,
+        alert_on_start: thresholds.alert_on_start,
+        ignore_first_uptime_secs: thresholds.ignore_first_uptime_secs,
+    };
+    let cpu_policy = policy(thresholds.cpu_min_duration_secs);
+    let ram_policy = policy(thresholds.ram_min_duration_secs);
+    let io_policy = policy(thresholds.io_min_duration_secs);
+    let global_policy = policy(None);
+
+    if let Some(zscore) = &thresholds.zscore {
+        // Adaptive detection replaces the static CPU/RAM/IO/temp thresholds.
+        let scalars = [
+            (
+                ResourceKind::Cpu,
+                Some(snapshot.cpu_usage_percent),
+                cpu_policy,
+                &mut state.cpu,
+            ),
+            (
+                ResourceKind::Ram,
+                Some(snapshot.ram_usage_percent),
+                ram_policy,
+                &mut state.ram,
+            ),
+            (
+                ResourceKind::Io,
+                Some(io_mb_per_s(snapshot)),
+                io_policy,
+                &mut state.io,
+            ),
+            (
+                ResourceKind::Temp,
+                snapshot.cpu_temp_celsius,
+                global_policy,
+                &mut state.temp,
+            ),
+        ];
+
+        for (kind, value, policy, spike_state) in scalars {
+            let value = match value {
+                Some(v) => v,
+                None => continue,
+            };
+
+            if let Some(ev) = update_zscore(
+                kind,
+                value,
+                zscore,
+                snapshot,
+                policy,
+                spike_state,
+            ) {
+                events.push(ev);
+            }
         }
     } else {
-        state.cpu.reset();
-    }
-
-    // RAM
-    if let Some(th) = thresholds.ram_threshold {
-        if let Some(ev) = update_spike_for_resource(
-            ResourceKind::Ram,
-            snapshot.ram_usage_percent,
-            th,
-            snapshot,
-            min_spike_duration_secs,
-            &mut state.ram,
-        ) {
-            events.push(ev);
+        // CPU
+        if let Some(th) = thresholds.cpu_threshold {
+            if let Some(ev) = update_spike_for_resource(
+                ResourceKind::Cpu,
+                snapshot.cpu_usage_percent,
+                th,
+                snapshot,
+                cpu_policy,
+                &mut state.cpu,
+            ) {
+                events.push(ev);
+            }
+        } else {
+            state.cpu.reset();
+        }
+
+        // RAM
+        if let Some(th) = thresholds.ram_threshold {
+            if let Some(ev) = update_spike_for_resource(
+                ResourceKind::Ram,
+                snapshot.ram_usage_percent,
+                th,
+                snapshot,
+                ram_policy,
+                &mut state.ram,
+            ) {
+                events.push(ev);
+            }
+        } else if let Some(th_bytes) = thresholds.ram_threshold_bytes {
+            // Compared in bytes; reported as percent of total RAM so events
+            // keep the unit of every other RAM spike.
+            let threshold = ram_bytes_percent(th_bytes, snapshot.ram_total_bytes);
+            if let Some(ev) = update_spike(
+                ResourceKind::Ram,
+                snapshot.ram_used_bytes > th_bytes,
+                snapshot.ram_usage_percent,
+                threshold,
+                snapshot,
+                ram_policy,
+                &mut state.ram,
+            ) {
+                events.push(ev);
+            }
+        } else {
+            state.ram.reset();
+        }
+
+        // IO (read + write)
+        if let Some(th) = thresholds.io_threshold {
+            if let Some(ev) = update_spike_for_resource(
+                ResourceKind::Io,
+                io_mb_per_s(snapshot),
+                th,
+                snapshot,
+                io_policy,
+                &mut state.io,
+            ) {
+                events.push(ev);
+            }
+        } else {
+            state.io.reset();
+        }
+
+        // IO read and write separately
+        let directions = [
+            (
+                ResourceKind::IoRead,
+                thresholds.io_read_threshold,
+                snapshot.io_read_bytes_per_s,
+                &mut state.io_read,
+            ),
+            (
+                ResourceKind::IoWrite,
+                thresholds.io_write_threshold,
+                snapshot.io_write_bytes_per_s,
+                &mut state.io_write,
+            ),
+        ];
+
+        for (kind, threshold, bytes_per_s, spike_state) in directions {
+            match threshold {
+                Some(th) => {
+                    if let Some(ev) = update_spike_for_resource(
+                        kind,
+                        mb_per_s(bytes_per_s),
+                        th,
+                        snapshot,
+                        io_policy,
+                        spike_state,
+                    ) {
+                        events.push(ev);
+                    }
+                }
+                None => spike_state.reset(),
+            }
+        }
+
+        // Temperature (only when collected)
+        match (thresholds.temp_threshold, snapshot.cpu_temp_celsius) {
+            (Some(th), Some(temp)) => {
+                if let Some(ev) = update_spike_for_resource(
+                    ResourceKind::Temp,
+                    temp,
+                    th,
+                    snapshot,
+                    global_policy,
+                    &mut state.temp,
+                ) {
+                    events.push(ev);
+                }
+            }
+            _ => state.temp.reset(),
+        }
+    }
+
+    // CPU steal and iowait: absolute shares of CPU time, so static even
+    // in zscore mode
+    let cpu_shares = [
+        (
+            ResourceKind::Steal,
+            thresholds.steal_threshold,
+            snapshot.cpu_steal_percent,
+            &mut state.steal,
+        ),
+        (
+            ResourceKind::Iowait,
+            thresholds.iowait_threshold,
+            snapshot.cpu_iowait_percent,
+            &mut state.iowait,
+        ),
+    ];
+
+    for (kind, threshold, value, spike_state) in cpu_shares {
+        match threshold {
+            Some(th) => {
+                if let Some(ev) = update_spike_for_resource(
+                    kind,
+                    value,
+                    th,
+                    snapshot,
+                    cpu_policy,
+                    spike_state,
+                ) {
+                    events.push(ev);
+                }
+            }
+            None => spike_state.reset(),
+        }
+    }
+
+    // Open file handles (only when collected); a leak grows steadily, so
+    // a static threshold even in zscore mode
+    match (thresholds.fd_threshold, snapshot.fd_usage) {
+        (Some(th), Some(fd)) => {
+            if let Some(ev) = update_spike_for_resource(
+                ResourceKind::Fd,
+                fd.percent(),
+                th,
+                snapshot,
+                global_policy,
+                &mut state.fd,
+            ) {
+                events.push(ev);
+            }
+        }
+        _ => state.fd.reset(),
+    }
+
+    // Process count (only when collected), e.g. a fork bomb
+    match (thresholds.proc_count_threshold, snapshot.task_counts) {
+        (Some(th), Some(tasks)) => {
+            if let Some(ev) = update_spike_for_resource(
+                ResourceKind::Procs,
+                tasks.processes as f32,
+                th as f32,
+                snapshot,
+                global_policy,
+                &mut state.procs,
+            ) {
+                events.push(ev);
+            }
+        }
+        _ => state.procs.reset(),
+    }
+
+    // Battery discharge rate (only when collected); static even in
+    // zscore mode, since the draw a battery can sustain is fixed
+    match (thresholds.power_threshold, snapshot.power_draw_watts) {
+        (Some(th), Some(watts)) => {
+            if let Some(ev) = update_spike_for_resource(
+                ResourceKind::Power,
+                watts,
+                th,
+                snapshot,
+                global_policy,
+                &mut state.power,
+            ) {
+                events.push(ev);
+            }
+        }
+        _ => state.power.reset(),
+    }
+
+    // Disk usage (one state per watched path)
+    if let Some(th) = thresholds.disk_threshold {
+        for disk in &snapshot.disk_usage {
+            let disk_state = state
+                .disk
+                .entry(disk.path.clone())
+                .or_default();
+
+            if let Some(mut ev) = update_spike_for_resource(
+                ResourceKind::Disk,
+                disk.used_percent,
+                th,
+                snapshot,
+                global_policy,
+                disk_state,
+            ) {
+                ev.path = Some(disk.path.clone());
+                events.push(ev);
+            }
         }
     } else {
-        state.ram.reset();
-    }
-
-    // IO (currently always 0.0)
-    if let Some(th) = thresholds.io_threshold {
-        let total_io_bytes =
-            snapshot.io_read_bytes_per_s + snapshot.io_write_bytes_per_s;
-        let io_mb_per_s = (total_io_bytes / 1_000_000.0) as f32;
-
-        if let Some(ev) = update_spike_for_resource(
-            ResourceKind::Io,
-            io_mb_per_s,
-            th,
-            snapshot,
-            min_spike_duration_secs,
-            &mut state.io,
-        ) {
+        state.disk.clear();
+    }
+
+    // Composite rule (all conditions at once)
+    if let Some(rule) = &thresholds.composite {
+        if let Some(ev) =
+            update_composite(rule, snapshot, global_policy, &mut state.composite)
+        {
             events.push(ev);
         }
     } else {
-        state.io.reset();
+        state.composite = CompositeState::default();
+    }
+
+    // Named rules, each with its own spike independent of the resource's
+    // threshold and of other rules on it
+    state
+        .rules
+        .retain(|name, _| thresholds.rules.iter().any(|r| &r.name == name));
+    for rule in &thresholds.rules {
+        let rule_state = state.rules.entry(rule.name.clone()).or_default();
+        rule_state.resource = rule.resource.name().to_string();
+        match resource_value(snapshot, rule.resource) {
+            Some(value) => {
+                if let Some(mut ev) = update_spike_for_resource(
+                    rule.resource,
+                    value,
+                    rule.threshold,
+                    snapshot,
+                    policy(rule.min_duration_secs),
+                    &mut rule_state.spike,
+                ) {
+                    ev.rule = Some(rule.name.clone());
+                    events.push(ev);
+                }
+            }
+            None => rule_state.spike.reset(),
+        }
+    }
+
+    if let Some(flap) = &thresholds.flap {
+        let flaps = detect_flapping(flap, &events, &mut state.recent_spikes);
+        events.extend(flaps);
+    }
+
+    match &thresholds.ram_growth {
+        Some(growth) => events.extend(detect_ram_growth(growth, snapshot, &mut state.ram_history)),
+        None => state.ram_history.clear(),
+    }
+
+    for ev in &mut events {
+        if ev.id.is_empty() {
+            ev.id = state.next_event_id(ev.timestamp_start);
+        }
+        if ev.phase == EventPhase::Start {
+            let spike = match &ev.rule {
+                Some(name) => state.rules.get_mut(name).map(|r| &mut r.spike),
+                None => state.spike_state_mut(ev.resource, ev.path.as_deref()),
+            };
+            if let Some(spike) = spike {
+                spike.start_event_id = Some(ev.id.clone());
+            }
+        }
     }
 
     events
 }
 
-/// Core spike state machine for one resource.
+/// Close the spikes still open at the end of a run, as if every resource
+/// had dropped below its threshold at `now` (the last sample, whose
+/// monotonic time is `instant`). Spikes shorter than their minimum
+/// duration are dropped like any other; everything is reset afterwards.
+pub fn finalize(
+    state: &mut AnalyzerState,
+    now: SystemTime,
+    instant: Option<Instant>,
+    thresholds: &Thresholds,
+    min_spike_duration_secs: u64,
+) -> Vec<SpikeEvent> {
+    let min_duration = |secs: Option<u64>| secs.unwrap_or(min_spike_duration_secs);
+    let cpu = min_duration(thresholds.cpu_min_duration_secs);
+    let ram = min_duration(thresholds.ram_min_duration_secs);
+    let io = min_duration(thresholds.io_min_duration_secs);
+    let global = min_spike_duration_secs;
+
+    let scalars = [
+        (ResourceKind::Cpu, cpu, &mut state.cpu),
+        (ResourceKind::Steal, cpu, &mut state.steal),
+        (ResourceKind::Iowait, cpu, &mut state.iowait),
+        (ResourceKind::Ram, ram, &mut state.ram),
+        (ResourceKind::Io, io, &mut state.io),
+        (ResourceKind::IoRead, io, &mut state.io_read),
+        (ResourceKind::IoWrite, io, &mut state.io_write),
+        (ResourceKind::Temp, global, &mut state.temp),
+        (ResourceKind::Fd, global, &mut state.fd),
+        (ResourceKind::Procs, global, &mut state.procs),
+        (ResourceKind::Power, global, &mut state.power),
+    ];
+
+    let mut events: Vec<SpikeEvent> = scalars
+        .into_iter()
+        .filter_map(|(kind, min, spike)| close_spike(kind, now, instant, min, spike))
+        .collect();
+
+    let mut paths: Vec<String> = state.disk.keys().cloned().collect();
+    paths.sort();
+    for path in paths {
+        if let Some(spike) = state.disk.get_mut(&path) {
+            if let Some(mut ev) = close_spike(ResourceKind::Disk, now, instant, global, spike) {
+                ev.path = Some(path);
+                events.push(ev);
+            }
+        }
+    }
+
+    let composite = &mut state.composite;
+    if let Some(mut ev) = close_spike(
+        ResourceKind::Composite,
+        now,
+        instant,
+        global,
+        &mut composite.spike,
+    ) {
+        ev.peak_value = composite.peak_value;
+        ev.threshold = composite.peak_threshold;
+        events.push(ev);
+    }
+
+    for rule in &thresholds.rules {
+        let Some(rule_state) = state.rules.get_mut(&rule.name) else {
+            continue;
+        };
+        let min = min_duration(rule.min_duration_secs);
+        if let Some(mut ev) = close_spike(rule.resource, now, instant, min, &mut rule_state.spike) {
+            ev.rule = Some(rule.name.clone());
+            events.push(ev);
+        }
+    }
+
+    if let Some(flap) = &thresholds.flap {
+        let flaps = detect_flapping(flap, &events, &mut state.recent_spikes);
+        events.extend(flaps);
+    }
+
+    for ev in &mut events {
+        if ev.id.is_empty() {
+            ev.id = state.next_event_id(ev.timestamp_start);
+        }
+    }
+
+    events
+}
+
+/// Record the end events in `events` and return a flap event for each
+/// resource that now has `policy.spikes` spikes ending within the
+/// window. Its count starts over afterwards, so a resource that keeps
+/// flapping is reported once per `policy.spikes` spikes.
+fn detect_flapping(
+    policy: &FlapPolicy,
+    events: &[SpikeEvent],
+    recent: &mut HashMap<SpikeKey, VecDeque<SystemTime>>,
+) -> Vec<SpikeEvent> {
+    let window = Duration::from_secs(policy.window_secs);
+    let mut flaps = Vec::new();
+
+    for ev in events.iter().filter(|e| e.phase == EventPhase::End) {
+        let ends = recent
+            .entry((ev.resource, ev.path.clone(), ev.rule.clone()))
+            .or_default();
+        ends.push_back(ev.timestamp_end);
+        while let Some(&oldest) = ends.front() {
+            match ev.timestamp_end.duration_since(oldest) {
+                Ok(age) if age > window => ends.pop_front(),
+                _ => break,
+            };
+        }
+
+        if ends.len() as u64 >= policy.spikes {
+            flaps.push(SpikeEvent {
+                id: String::new(),
+                phase: EventPhase::Flap,
+                resource: ev.resource,
+                timestamp_start: ends.front().copied().unwrap_or(ev.timestamp_end),
+                timestamp_end: ev.timestamp_end,
+                peak_value: ends.len() as f32,
+                threshold: policy.spikes as f32,
+                path: ev.path.clone(),
+                top_processes: Vec::new(),
+                uptime_start_secs: None,
+                tag: None,
+                rule: ev.rule.clone(),
+            });
+            ends.clear();
+        }
+    }
+
+    flaps
+}
+
+/// Record `snapshot`'s RAM use and return a growth event when the
+/// least-squares slope over the last `policy.window_secs` exceeds
+/// `policy.mb_per_min`. The window only counts once it is full, and
+/// starts over after an alert, so a leak that keeps going is reported
+/// once per window. Snapshots without RAM bytes (replayed ones) are
+/// skipped.
+fn detect_ram_growth(
+    policy: &GrowthPolicy,
+    snapshot: &SystemSnapshot,
+    history: &mut VecDeque<RamSample>,
+) -> Option<SpikeEvent> {
+    if snapshot.ram_total_bytes == 0 {
+        return None;
+    }
+
+    let now = RamSample {
+        timestamp: snapshot.timestamp,
+        instant: snapshot.instant,
+        used_bytes: snapshot.ram_used_bytes,
+    };
+    history.push_back(now);
+
+    // Keep the newest sample at least a window old as the first one.
+    let window = policy.window_secs as f64;
+    while history.len() > 2 && now.secs_since(&history[1]) >= window {
+        history.pop_front();
+    }
+    let oldest = *history.front()?;
+    if now.secs_since(&oldest) < window {
+        return None;
+    }
+
+    let n = history.len() as f64;
+    let points: Vec<(f64, f64)> = history
+        .iter()
+        .map(|s| (s.secs_since(&oldest), s.used_bytes as f64 / 1_000_000.0))
+        .collect();
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (mut sxy, mut sxx) = (0.0, 0.0);
+    for (x, y) in &points {
+        sxy += (x - mean_x) * (y - mean_y);
+        sxx += (x - mean_x) * (x - mean_x);
+    }
+    if sxx == 0.0 {
+        return None;
+    }
+
+    let mb_per_min = (sxy / sxx * 60.0) as f32;
+    trace!(
+        "ram growth {}MB/min over {} samples (threshold {}MB/min)",
+        mb_per_min,
+        history.len(),
+        policy.mb_per_min
+    );
+    if mb_per_min <= policy.mb_per_min {
+        return None;
+    }
+
+    history.clear();
+    history.push_back(now);
+    Some(SpikeEvent {
+        id: String::new(),
+        phase: EventPhase::Growth,
+        resource: ResourceKind::Ram,
+        timestamp_start: oldest.timestamp,
+        timestamp_end: now.timestamp,
+        peak_value: mb_per_min,
+        threshold: policy.mb_per_min,
+        path: None,
+        top_processes: snapshot.top_processes_by_ram.clone(),
+        uptime_start_secs: None,
+        tag: None,
+        rule: None,
+    })
+}
+
+/// End event for `state`'s open spike closed at `now`, if it lasted
+/// `min_duration_secs`; the state is reset either way.
+fn close_spike(
+    resource: ResourceKind,
+    now: SystemTime,
+    instant: Option<Instant>,
+    min_duration_secs: u64,
+    state: &mut SpikeState,
+) -> Option<SpikeEvent> {
+    let start = state.spike_start.filter(|_| state.in_spike)?;
+    let duration = state.elapsed(start, now, instant);
+
+    let event = (duration.as_secs() >= min_duration_secs).then(|| SpikeEvent {
+        id: state.start_event_id.take().unwrap_or_default(),
+        phase: EventPhase::End,
+        resource,
+        timestamp_start: start,
+        timestamp_end: start + duration,
+        peak_value: state.spike_max_value,
+        threshold: state.spike_threshold,
+        path: None,
+        top_processes: std::mem::take(&mut state.spike_max_processes),
+        uptime_start_secs: state.spike_start_uptime_secs,
+        tag: None,
+        rule: None,
+    });
+
+    state.reset();
+    event
+}
+
+/// Combined disk IO throughput in MB/s.
+pub(crate) fn io_mb_per_s(snapshot: &SystemSnapshot) -> f32 {
+    mb_per_s(snapshot.io_read_bytes_per_s + snapshot.io_write_bytes_per_s)
+}
+
+fn mb_per_s(bytes_per_s: f64) -> f32 {
+    (bytes_per_s / 1_000_000.0) as f32
+}
+
+/// `bytes` as a percentage of `total_bytes`.
+fn ram_bytes_percent(bytes: u64, total_bytes: u64) -> f32 {
+    if total_bytes == 0 {
+        return 0.0;
+    }
+
+    (bytes as f64 / total_bytes as f64 * 100.0) as f32
+}
+
+/// Current value of a single-valued resource, if collected.
+pub(crate) fn resource_value(snapshot: &SystemSnapshot, kind: ResourceKind) -> Option<f32> {
+    match kind {
+        ResourceKind::Cpu => Some(snapshot.cpu_usage_percent),
+        ResourceKind::Steal => Some(snapshot.cpu_steal_percent),
+        ResourceKind::Iowait => Some(snapshot.cpu_iowait_percent),
+        ResourceKind::Ram => Some(snapshot.ram_usage_percent),
+        ResourceKind::Io => Some(io_mb_per_s(snapshot)),
+        ResourceKind::IoRead => Some(mb_per_s(snapshot.io_read_bytes_per_s)),
+        ResourceKind::IoWrite => Some(mb_per_s(snapshot.io_write_bytes_per_s)),
+        ResourceKind::Temp => snapshot.cpu_temp_celsius,
+        ResourceKind::Fd => snapshot.fd_usage.map(|fd| fd.percent()),
+        ResourceKind::Procs => snapshot.task_counts.map(|t| t.processes as f32),
+        ResourceKind::Power => snapshot.power_draw_watts,
+        ResourceKind::Disk | ResourceKind::Composite => None,
+    }
+}
+
+/// How far `value` is over `threshold`, as a ratio.
+fn over_ratio(value: f32, threshold: f32) -> f32 {
+    if threshold > 0.0 {
+        value / threshold
+    } else {
+        f32::INFINITY
+    }
+}
+
+/// Spike state machine for a composite rule.
+///
+/// The spike is open while every condition holds. Its peak is the resource
+/// furthest over its own threshold, reported with that resource's value
+/// and threshold.
+fn update_composite(
+    rule: &CompositeRule,
+    snapshot: &SystemSnapshot,
+    policy: SpikePolicy,
+    state: &mut CompositeState,
+) -> Option<SpikeEvent> {
+    let mut all_hold = !rule.conditions.is_empty();
+    let mut furthest: Option<(f32, f32, f32)> = None; // (ratio, value, threshold)
+
+    for cond in &rule.conditions {
+        let value = match resource_value(snapshot, cond.resource) {
+            Some(v) => v,
+            None => {
+                all_hold = false;
+                continue;
+            }
+        };
+
+        all_hold &= cond.holds(value);
+
+        let ratio = over_ratio(value, cond.value);
+        if furthest.is_none_or(|(r, _, _)| ratio > r) {
+            furthest = Some((ratio, value, cond.value));
+        }
+    }
+
+    let (ratio, value, threshold) = furthest.unwrap_or((0.0, 0.0, 0.0));
+
+    if all_hold && (!state.spike.in_spike || ratio > state.spike.spike_max_value) {
+        state.peak_value = value;
+        state.peak_threshold = threshold;
+    }
+
+    let mut event = update_spike(
+        ResourceKind::Composite,
+        all_hold,
+        ratio,
+        threshold,
+        snapshot,
+        policy,
+        &mut state.spike,
+    )?;
+
+    event.peak_value = state.peak_value;
+    event.threshold = state.peak_threshold;
+    Some(event)
+}
+
+/// Lower bound on the baseline stddev, in the resource's unit, so a
+/// perfectly flat series does not alert on the first bit of noise.
+const ZSCORE_MIN_STDDEV: f64 = 1.0;
+
+/// Spike state machine for one resource against its adaptive baseline.
+///
+/// The effective threshold (mean + k·stddev) comes from the samples seen
+/// so far; no spike opens during warmup, and anomalous samples are kept
+/// out of the baseline so a long spike does not raise its own threshold.
+fn update_zscore(
+    resource: ResourceKind,
+    value: f32,
+    zscore: &ZScoreConfig,
+    snapshot: &SystemSnapshot,
+    policy: SpikePolicy,
+    state: &mut SpikeState,
+) -> Option<SpikeEvent> {
+    let baseline = &state.baseline;
+    let stddev = baseline.stddev().max(ZSCORE_MIN_STDDEV);
+    let threshold = (baseline.mean + zscore.k as f64 * stddev) as f32;
+
+    let anomalous = baseline.count >= zscore.warmup_samples && value > threshold;
+    if !anomalous {
+        state.baseline.update(value, zscore.warmup_samples);
+    }
+
+    update_spike(
+        resource,
+        anomalous,
+        value,
+        threshold,
+        snapshot,
+        policy,
+        state,
+    )
+}
+
+/// Spike state machine for one resource compared against its threshold.
 fn update_spike_for_resource(
     resource: ResourceKind,
     value: f32,
     threshold: f32,
     snapshot: &SystemSnapshot,
-    min_spike_duration_secs: u64,
+    policy: SpikePolicy,
+    state: &mut SpikeState,
+) -> Option<SpikeEvent> {
+    update_spike(
+        resource,
+        value >= threshold,
+        value,
+        threshold,
+        snapshot,
+        policy,
+        state,
+    )
+}
+
+/// The snapshot's top processes ranked for `resource`: by RSS for RAM,
+/// so a RAM spike shows which process grew, by CPU for everything else.
+fn ranked_processes(resource: ResourceKind, snapshot: &SystemSnapshot) -> &[ProcessSample] {
+    match resource {
+        ResourceKind::Ram => &snapshot.top_processes_by_ram,
+        _ => &snapshot.top_processes,
+    }
+}
+
+/// Core spike state machine: `active` says whether the spike condition
+/// holds on this tick, `value` is what the peak tracks.
+///
+/// With `alert_on_start`, an open spike that reaches the minimum
+/// duration returns a start event once; the end event follows when it
+/// closes.
+///
+/// With a confirm policy, the spike is open while at least N of the last
+/// M ticks were active, so short dips don't end it; a confirmed spike
+/// starts at the first active tick in the window.
+fn update_spike(
+    resource: ResourceKind,
+    active: bool,
+    value: f32,
+    threshold: f32,
+    snapshot: &SystemSnapshot,
+    policy: SpikePolicy,
     state: &mut SpikeState,
 ) -> Option<SpikeEvent> {
     let now = snapshot.timestamp;
+    let mut start = now;
+
+    let active = match policy.confirm {
+        Some(confirm) => {
+            state.recent.push_back((now, active));
+            while state.recent.len() > confirm.window {
+                state.recent.pop_front();
+            }
+
+            if let Some(&(ts, _)) = state.recent.iter().find(|(_, over)| *over) {
+                start = ts;
+            }
+            state.recent.iter().filter(|(_, over)| *over).count() >= confirm.required
+        }
+        None => active,
+    };
 
     // Not in spike yet
     if !state.in_spike {
-        if value >= threshold {
+        if active {
+            // With --confirm the spike started before this sample.
+            let before_now = now.duration_since(start).map_or(0, |d| d.as_secs());
+            let uptime = snapshot.uptime_secs.map(|u| u.saturating_sub(before_now));
+            if policy.within_boot_grace(uptime) {
+                debug!(
+                    "{} over threshold within the boot grace (uptime {}s), not opened",
+                    resource.name(),
+                    uptime.unwrap_or(0)
+                );
+                return None;
+            }
+
             state.in_spike = true;
-            state.spike_start = Some(now);
+            state.spike_start_uptime_secs = uptime;
+            state.spike_start_instant = snapshot
+                .instant
+                .and_then(|i| i.checked_sub(Duration::from_secs(before_now)));
+            state.spike_start = Some(start);
             state.spike_max_value = value;
-            state.spike_max_snapshot = Some(snapshot.clone());
+            state.spike_threshold = threshold;
+            state.spike_max_processes = ranked_processes(resource, snapshot).to_vec();
+            debug!(
+                "{} spike opened: value={} threshold={} start={}",
+                resource.name(),
+                value,
+                threshold,
+                epoch_secs(start)
+            );
+            return start_event(resource, threshold, now, snapshot.instant, policy, state);
         }
         return None;
     }
 
     // Already in spike
-    if value >= threshold {
+    if active {
+        state.spike_threshold = threshold;
         if value > state.spike_max_value {
             state.spike_max_value = value;
-            state.spike_max_snapshot = Some(snapshot.clone());
+            state.spike_max_processes = ranked_processes(resource, snapshot).to_vec();
         }
-        return None;
+        return start_event(resource, threshold, now, snapshot.instant, policy, state);
     }
 
-    // Spike ended (value dropped below threshold)
+    // Spike ended (condition no longer holds)
     let start = match state.spike_start {
         Some(ts) => ts,
         None => {
@@ -163,30 +1396,755 @@ fn update_spike_for_resource(
         }
     };
 
-    let duration = match now.duration_since(start) {
-        Ok(d) => d,
-        Err(_) => Duration::from_secs(0),
-    };
+    let duration = state.elapsed(start, now, snapshot.instant);
+    debug!(
+        "{} spike closed after {}s: peak={} value={} threshold={}{}",
+        resource.name(),
+        duration.as_secs(),
+        state.spike_max_value,
+        value,
+        threshold,
+        if duration.as_secs() >= policy.min_duration_secs {
+            String::new()
+        } else {
+            format!(
+                ", dropped (shorter than {}s minimum)",
+                policy.min_duration_secs
+            )
+        }
+    );
 
     let mut event: Option<SpikeEvent> = None;
 
-    if duration.as_secs() >= min_spike_duration_secs {
-        let top_processes = state
-            .spike_max_snapshot
-            .as_ref()
-            .map(|snap| snap.top_processes.clone())
-            .unwrap_or_else(Vec::new);
+    if duration.as_secs() >= policy.min_duration_secs {
+        let top_processes = std::mem::take(&mut state.spike_max_processes);
 
         event = Some(SpikeEvent {
+            id: state.start_event_id.take().unwrap_or_default(),
+            phase: EventPhase::End,
             resource,
             timestamp_start: start,
-            timestamp_end: now,
+            // Start plus the monotonic duration, so ts_end stays after
+            // ts_start across wall clock steps.
+            timestamp_end: start + duration,
             peak_value: state.spike_max_value,
             threshold,
+            path: None,
             top_processes,
+            uptime_start_secs: state.spike_start_uptime_secs,
+            tag: None,
+            rule: None,
         });
     }
 
     state.reset();
     event
 }
+
+/// Start event for an open spike that has lasted `policy.min_duration_secs`
+/// by `now`, if `alert_on_start` is set and none was emitted yet.
+fn start_event(
+    resource: ResourceKind,
+    threshold: f32,
+    now: SystemTime,
+    instant: Option<Instant>,
+    policy: SpikePolicy,
+    state: &SpikeState,
+) -> Option<SpikeEvent> {
+    if !policy.alert_on_start || state.start_event_id.is_some() {
+        return None;
+    }
+
+    let start = state.spike_start?;
+    let duration = state.elapsed(start, now, instant);
+    if duration.as_secs() < policy.min_duration_secs {
+        return None;
+    }
+
+    Some(SpikeEvent {
+        id: String::new(),
+        phase: EventPhase::Start,
+        resource,
+        timestamp_start: start,
+        timestamp_end: start,
+        peak_value: state.spike_max_value,
+        threshold,
+        path: None,
+        top_processes: state.spike_max_processes.clone(),
+        uptime_start_secs: state.spike_start_uptime_secs,
+        tag: None,
+        rule: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NamedRule;
+
+    const START: u64 = 1_700_000_000;
+
+    /// Snapshot `secs` seconds into the run with the given CPU and RAM.
+    fn sample(secs: u64, cpu: f32, ram: f32) -> SystemSnapshot {
+        let mut snapshot = SystemSnapshot::at_secs(START + secs);
+        snapshot.cpu_usage_percent = cpu;
+        snapshot.ram_usage_percent = ram;
+        snapshot
+    }
+
+    /// Analyze `(cpu, ram)` samples taken one second apart and collect
+    /// every event.
+    fn run(thresholds: &Thresholds, min_secs: u64, series: &[(f32, f32)]) -> Vec<SpikeEvent> {
+        let mut state = AnalyzerState::new();
+        let mut events = Vec::new();
+        for (i, &(cpu, ram)) in series.iter().enumerate() {
+            let snapshot = sample(i as u64, cpu, ram);
+            events.extend(analyze_snapshot(
+                &snapshot, thresholds, min_secs, &mut state,
+            ));
+        }
+        events
+    }
+
+    fn secs(t: SystemTime) -> u64 {
+        epoch_secs(t) - START
+    }
+
+    #[test]
+    fn composite_needs_every_condition() {
+        let thresholds = Thresholds {
+            composite: Some(CompositeRule::parse("cpu>80 && ram>50").unwrap()),
+            ..Thresholds::default()
+        };
+
+        // CPU alone over its limit for a long time: nothing.
+        let cpu_only = [(95.0, 40.0); 10];
+        assert!(run(&thresholds, 2, &cpu_only).is_empty());
+
+        let both = [
+            (95.0, 40.0),
+            (95.0, 60.0),
+            (85.0, 90.0),
+            (90.0, 70.0),
+            (95.0, 40.0),
+        ];
+        let events = run(&thresholds, 2, &both);
+        assert_eq!(events.len(), 1);
+        let ev = &events[0];
+        assert_eq!(ev.resource, ResourceKind::Composite);
+        assert_eq!((secs(ev.timestamp_start), secs(ev.timestamp_end)), (1, 4));
+        // RAM at 90 is further over its 50 than CPU ever was over 80.
+        assert_eq!((ev.peak_value, ev.threshold), (90.0, 50.0));
+    }
+
+    #[test]
+    fn zscore_flags_only_the_outlier() {
+        let thresholds = Thresholds {
+            zscore: Some(ZScoreConfig {
+                k: 3.0,
+                warmup_samples: 10,
+            }),
+            ..Thresholds::default()
+        };
+
+        // Stable around 21% with noise and a mild bump, one outlier at
+        // 30s, RAM flat.
+        let mut series: Vec<(f32, f32)> = (0..40)
+            .map(|i| (if i % 2 == 0 { 20.0 } else { 22.0 }, 50.0))
+            .collect();
+        series[25].0 = 23.5;
+        series[30].0 = 80.0;
+
+        let events = run(&thresholds, 0, &series);
+        assert_eq!(events.len(), 1, "{:?}", events);
+        let ev = &events[0];
+        assert_eq!(ev.resource, ResourceKind::Cpu);
+        assert_eq!((secs(ev.timestamp_start), secs(ev.timestamp_end)), (30, 31));
+        assert_eq!(ev.peak_value, 80.0);
+    }
+
+    #[test]
+    fn zscore_does_not_alert_during_warmup() {
+        let thresholds = Thresholds {
+            zscore: Some(ZScoreConfig {
+                k: 3.0,
+                warmup_samples: 10,
+            }),
+            ..Thresholds::default()
+        };
+
+        let mut series = vec![(20.0, 50.0); 12];
+        series[3].0 = 90.0;
+        assert!(run(&thresholds, 0, &series).is_empty());
+    }
+
+    #[test]
+    fn per_resource_min_durations() {
+        let thresholds = Thresholds {
+            cpu_threshold: Some(80.0),
+            ram_threshold: Some(80.0),
+            cpu_min_duration_secs: Some(2),
+            ram_min_duration_secs: Some(10),
+            ..Thresholds::default()
+        };
+
+        // Both over their thresholds for the same 4 seconds.
+        let series = [
+            (10.0, 10.0),
+            (90.0, 90.0),
+            (90.0, 90.0),
+            (90.0, 90.0),
+            (90.0, 90.0),
+            (10.0, 10.0),
+        ];
+        let events = run(&thresholds, 5, &series);
+        assert_eq!(events.len(), 1, "{:?}", events);
+        let ev = &events[0];
+        assert_eq!(ev.resource, ResourceKind::Cpu);
+        assert_eq!((secs(ev.timestamp_start), secs(ev.timestamp_end)), (1, 5));
+
+        // Without overrides the global 5s drops both.
+        let global = Thresholds {
+            cpu_min_duration_secs: None,
+            ram_min_duration_secs: None,
+            ..thresholds
+        };
+        assert!(run(&global, 5, &series).is_empty());
+    }
+
+    #[test]
+    fn ram_bytes_threshold_compares_used_bytes() {
+        let thresholds = Thresholds {
+            ram_threshold_bytes: Some(6_000_000_000),
+            ..Thresholds::default()
+        };
+
+        // MemTotal 8 GB; used (MemTotal - MemAvailable) 5, 7, 7, then 5 GB.
+        let total: u64 = 8_000_000_000;
+        let mut state = AnalyzerState::new();
+        let mut events = Vec::new();
+        for (i, used) in [5, 7, 7, 5].into_iter().enumerate() {
+            let used = used * 1_000_000_000u64;
+            let mut snapshot = SystemSnapshot::at_secs(START + i as u64);
+            snapshot.ram_used_bytes = used;
+            snapshot.ram_total_bytes = total;
+            snapshot.ram_usage_percent = ram_bytes_percent(used, total);
+            events.extend(analyze_snapshot(&snapshot, &thresholds, 1, &mut state));
+        }
+
+        assert_eq!(events.len(), 1, "{:?}", events);
+        let ev = &events[0];
+        assert_eq!(ev.resource, ResourceKind::Ram);
+        assert_eq!((secs(ev.timestamp_start), secs(ev.timestamp_end)), (1, 3));
+        // Reported as percent of MemTotal like any RAM spike.
+        assert_eq!((ev.peak_value, ev.threshold), (87.5, 75.0));
+    }
+
+    #[test]
+    fn event_ids_are_unique_and_shared_by_start_and_end() {
+        let thresholds = Thresholds {
+            cpu_threshold: Some(80.0),
+            ram_threshold: Some(80.0),
+            alert_on_start: true,
+            ..Thresholds::default()
+        };
+
+        // CPU and RAM spike together (same start second), then CPU again.
+        let series = [
+            (90.0, 90.0),
+            (90.0, 90.0),
+            (10.0, 10.0),
+            (90.0, 10.0),
+            (90.0, 10.0),
+            (10.0, 10.0),
+        ];
+        let events = run(&thresholds, 1, &series);
+        let ends: Vec<&SpikeEvent> = events
+            .iter()
+            .filter(|e| e.phase == EventPhase::End)
+            .collect();
+        assert_eq!(ends.len(), 3);
+
+        let mut ids: Vec<&str> = ends.iter().map(|e| e.id.as_str()).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 3, "{:?}", ends);
+
+        let starts: Vec<&SpikeEvent> = events
+            .iter()
+            .filter(|e| e.phase == EventPhase::Start)
+            .collect();
+        assert_eq!(starts.len(), 3);
+        for start in starts {
+            let end = ends
+                .iter()
+                .find(|e| {
+                    e.resource == start.resource && e.timestamp_start == start.timestamp_start
+                })
+                .unwrap();
+            assert_eq!(start.id, end.id);
+        }
+        assert!(ends[0]
+            .id
+            .starts_with(&format!("{}-{}-", START, std::process::id())));
+    }
+
+    #[test]
+    fn confirm_keeps_spike_open_through_one_dip() {
+        let thresholds = Thresholds {
+            cpu_threshold: Some(80.0),
+            confirm: Some(ConfirmPolicy::parse("3/4").unwrap()),
+            ..Thresholds::default()
+        };
+
+        let mut series = vec![(10.0, 10.0); 10];
+        for (i, cpu) in [90.0, 90.0, 90.0, 10.0, 95.0, 90.0].into_iter().enumerate() {
+            series[i + 1].0 = cpu;
+        }
+        let events = run(&thresholds, 1, &series);
+        assert_eq!(events.len(), 1, "{:?}", events);
+        let ev = &events[0];
+        assert_eq!((secs(ev.timestamp_start), secs(ev.timestamp_end)), (1, 7));
+        assert_eq!(ev.peak_value, 95.0);
+
+        // Without the policy the dip splits it in two.
+        let plain = Thresholds {
+            confirm: None,
+            ..thresholds
+        };
+        assert_eq!(run(&plain, 1, &series).len(), 2);
+    }
+
+    #[test]
+    fn ongoing_spike_is_reported_until_it_closes() {
+        let thresholds = Thresholds {
+            cpu_threshold: Some(80.0),
+            ..Thresholds::default()
+        };
+
+        let series = [85.0, 90.0, 99.0, 95.0, 10.0];
+        let mut state = AnalyzerState::new();
+        let mut ongoing = Vec::new();
+        let mut events = Vec::new();
+        for (i, cpu) in series.into_iter().enumerate() {
+            let snapshot = sample(i as u64, cpu, 10.0);
+            events.extend(analyze_snapshot(&snapshot, &thresholds, 1, &mut state));
+            ongoing.push(state.ongoing_spikes());
+        }
+
+        for open in &ongoing[..4] {
+            assert_eq!(open.len(), 1);
+            assert_eq!(open[0].resource, ResourceKind::Cpu);
+            assert_eq!(secs(open[0].timestamp_start), 0);
+        }
+        let peaks: Vec<f32> = ongoing[..4].iter().map(|o| o[0].peak_value).collect();
+        assert_eq!(peaks, [85.0, 90.0, 99.0, 99.0]);
+
+        // Once closed it is an event, no longer ongoing.
+        assert!(ongoing[4].is_empty());
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].peak_value, 99.0);
+    }
+
+    #[test]
+    fn io_read_spike_with_writes_idle() {
+        let thresholds = Thresholds {
+            io_threshold: Some(150.0),
+            io_read_threshold: Some(50.0),
+            io_write_threshold: Some(50.0),
+            ..Thresholds::default()
+        };
+
+        // Reads at 10, 120, 120, 80 then 10 MB/s; nothing written.
+        let mut state = AnalyzerState::new();
+        let mut events = Vec::new();
+        for (i, read_mb) in [10.0, 120.0, 120.0, 80.0, 10.0].into_iter().enumerate() {
+            let mut snapshot = SystemSnapshot::at_secs(START + i as u64);
+            snapshot.io_read_bytes_per_s = read_mb * 1_000_000.0;
+            events.extend(analyze_snapshot(&snapshot, &thresholds, 1, &mut state));
+        }
+
+        // Only the read direction fires; combined IO stays under 150.
+        assert_eq!(events.len(), 1, "{:?}", events);
+        let ev = &events[0];
+        assert_eq!(ev.resource, ResourceKind::IoRead);
+        assert_eq!((secs(ev.timestamp_start), secs(ev.timestamp_end)), (1, 4));
+        assert_eq!((ev.peak_value, ev.threshold), (120.0, 50.0));
+    }
+
+    #[test]
+    fn saved_spike_resumes_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let path = path.to_str().unwrap();
+        let thresholds = Thresholds {
+            cpu_threshold: Some(80.0),
+            ..Thresholds::default()
+        };
+        let max_age = Duration::from_secs(3600);
+
+        // First run: the spike opens at 1 and is still open at shutdown.
+        let mut state = AnalyzerState::new();
+        for (i, cpu) in [10.0, 90.0, 95.0].into_iter().enumerate() {
+            let snapshot = sample(i as u64, cpu, 10.0);
+            assert!(analyze_snapshot(&snapshot, &thresholds, 3, &mut state).is_empty());
+        }
+        state.save(path).unwrap();
+
+        // Second run picks it up and closes it with the original start.
+        let mut state = AnalyzerState::load(path, max_age).unwrap().unwrap();
+        let mut events = Vec::new();
+        for (i, cpu) in [92.0, 85.0, 10.0].into_iter().enumerate() {
+            let snapshot = sample(3 + i as u64, cpu, 10.0);
+            events.extend(analyze_snapshot(&snapshot, &thresholds, 3, &mut state));
+        }
+        assert_eq!(events.len(), 1, "{:?}", events);
+        let ev = &events[0];
+        assert_eq!((secs(ev.timestamp_start), secs(ev.timestamp_end)), (1, 5));
+        assert_eq!(ev.peak_value, 95.0);
+
+        // A state older than the max age is discarded.
+        let stale = SavedState {
+            saved_ts: epoch_secs(SystemTime::now()) - 7200,
+            state: AnalyzerState::new(),
+        };
+        fs::write(path, serde_json::to_string(&stale).unwrap()).unwrap();
+        assert!(AnalyzerState::load(path, max_age).unwrap().is_none());
+
+        let missing = dir.path().join("missing.json");
+        assert!(AnalyzerState::load(missing.to_str().unwrap(), max_age)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn alert_on_start_emits_start_then_end() {
+        let thresholds = Thresholds {
+            cpu_threshold: Some(80.0),
+            alert_on_start: true,
+            ..Thresholds::default()
+        };
+
+        let series = [
+            (10.0, 10.0),
+            (90.0, 10.0),
+            (85.0, 10.0),
+            (95.0, 10.0),
+            (99.0, 10.0),
+            (10.0, 10.0),
+        ];
+        let mut state = AnalyzerState::new();
+        let mut phases = Vec::new();
+        for (i, &(cpu, ram)) in series.iter().enumerate() {
+            let snapshot = sample(i as u64, cpu, ram);
+            for ev in analyze_snapshot(&snapshot, &thresholds, 2, &mut state) {
+                phases.push((i, ev));
+            }
+        }
+        assert_eq!(phases.len(), 2, "{:?}", phases);
+
+        // Provisional as soon as it has lasted 2s, while still open.
+        let (at, start) = &phases[0];
+        assert_eq!(*at, 3);
+        assert_eq!(start.phase, EventPhase::Start);
+        assert_eq!(start.timestamp_end, start.timestamp_start);
+        assert_eq!(secs(start.timestamp_start), 1);
+        assert_eq!(start.peak_value, 95.0);
+
+        let (at, end) = &phases[1];
+        assert_eq!(*at, 5);
+        assert_eq!(end.phase, EventPhase::End);
+        assert_eq!((secs(end.timestamp_start), secs(end.timestamp_end)), (1, 5));
+        assert_eq!(end.peak_value, 99.0);
+        assert_eq!(end.id, start.id);
+    }
+
+    #[test]
+    fn ram_event_ranks_top_processes_by_rss() {
+        let process = |pid, cpu_percent, mb: u64| ProcessSample {
+            pid,
+            name: format!("p{}", pid),
+            cpu_percent,
+            ram_bytes: mb << 20,
+        };
+        let thresholds = Thresholds {
+            cpu_threshold: Some(90.0),
+            ram_threshold: Some(90.0),
+            ..Thresholds::default()
+        };
+        let mut state = AnalyzerState::new();
+        let mut events = Vec::new();
+        for (i, over) in [true, true, true, true, false].into_iter().enumerate() {
+            let level = if over { 95.0 } else { 10.0 };
+            let mut snapshot = sample(i as u64, level, level);
+            snapshot.top_processes = vec![process(1, 60.0, 10), process(2, 30.0, 900)];
+            snapshot.top_processes_by_ram = vec![process(2, 30.0, 900), process(1, 60.0, 10)];
+            events.extend(analyze_snapshot(&snapshot, &thresholds, 2, &mut state));
+        }
+
+        let pids = |resource| -> Vec<u32> {
+            let event = events.iter().find(|e| e.resource == resource).unwrap();
+            event.top_processes.iter().map(|p| p.pid).collect()
+        };
+        assert_eq!(pids(ResourceKind::Ram), [2, 1]);
+        assert_eq!(pids(ResourceKind::Cpu), [1, 2]);
+        let ram = events
+            .iter()
+            .find(|e| e.resource == ResourceKind::Ram)
+            .unwrap();
+        assert!(ram
+            .top_processes
+            .windows(2)
+            .all(|w| w[0].ram_bytes >= w[1].ram_bytes));
+    }
+
+    #[test]
+    fn finalize_closes_the_spike_open_at_the_end() {
+        let thresholds = Thresholds {
+            cpu_threshold: Some(90.0),
+            ..Thresholds::default()
+        };
+        let analyze = |cpu: &[f32]| {
+            let mut state = AnalyzerState::new();
+            let mut last = sample(0, 0.0, 0.0);
+            for (i, &cpu) in cpu.iter().enumerate() {
+                last = sample(i as u64, cpu, 40.0);
+                assert!(analyze_snapshot(&last, &thresholds, 3, &mut state).is_empty());
+            }
+            let events = finalize(&mut state, last.timestamp, last.instant, &thresholds, 3);
+            // Everything is reset, so a second call has nothing to close.
+            assert!(finalize(&mut state, last.timestamp, last.instant, &thresholds, 3).is_empty());
+            events
+        };
+
+        // The run ends mid-spike: closed at the last sample.
+        let events = analyze(&[10.0, 92.0, 95.0, 99.0, 96.0, 93.0]);
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.phase, EventPhase::End);
+        assert_eq!(event.resource, ResourceKind::Cpu);
+        assert_eq!(secs(event.timestamp_start), 1);
+        assert_eq!(secs(event.timestamp_end), 5);
+        assert_eq!(event.peak_value, 99.0);
+
+        // Shorter than the minimum: dropped like any other.
+        assert!(analyze(&[10.0, 10.0, 10.0, 95.0, 97.0]).is_empty());
+    }
+
+    #[test]
+    fn repeated_short_spikes_raise_one_flap_event() {
+        let flapping = |window_secs| Thresholds {
+            cpu_threshold: Some(90.0),
+            flap: Some(FlapPolicy {
+                spikes: 3,
+                window_secs,
+            }),
+            ..Thresholds::default()
+        };
+        // Three two-second spikes, ending at 2, 5 and 8.
+        let series = [(95.0, 40.0), (95.0, 40.0), (10.0, 40.0)].repeat(3);
+
+        let events = run(&flapping(60), 1, &series);
+        let phases: Vec<EventPhase> = events.iter().map(|e| e.phase).collect();
+        assert_eq!(
+            phases,
+            [
+                EventPhase::End,
+                EventPhase::End,
+                EventPhase::End,
+                EventPhase::Flap
+            ]
+        );
+        let flap = &events[3];
+        assert_eq!(flap.resource, ResourceKind::Cpu);
+        assert_eq!(
+            (secs(flap.timestamp_start), secs(flap.timestamp_end)),
+            (2, 8)
+        );
+        assert_eq!(flap.peak_value, 3.0);
+        assert_eq!(flap.threshold, 3.0);
+
+        // The first spike ended too long before the third.
+        let events = run(&flapping(5), 1, &series);
+        assert!(events.iter().all(|e| e.phase == EventPhase::End));
+    }
+
+    #[test]
+    fn spikes_right_after_boot_are_ignored() {
+        let thresholds = Thresholds {
+            cpu_threshold: Some(90.0),
+            ignore_first_uptime_secs: Some(300),
+            ..Thresholds::default()
+        };
+        let booted_at = |boot_uptime: u64| {
+            let mut state = AnalyzerState::new();
+            let mut events = Vec::new();
+            for (i, cpu) in [10.0, 95.0, 96.0, 97.0, 98.0, 10.0].into_iter().enumerate() {
+                let mut snapshot = sample(i as u64, cpu, 40.0);
+                snapshot.uptime_secs = Some(boot_uptime + i as u64);
+                events.extend(analyze_snapshot(&snapshot, &thresholds, 2, &mut state));
+            }
+            events
+        };
+
+        assert!(booted_at(60).is_empty());
+
+        let events = booted_at(3600);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].uptime_start_secs, Some(3601));
+        assert_eq!(secs(events[0].timestamp_start), 1);
+    }
+
+    #[test]
+    fn over_ratio_is_peak_over_threshold() {
+        let mut event = SpikeEvent::ended(ResourceKind::Cpu, 1_700_000_000, 5, 45.0, 30.0);
+        assert_eq!(event.over_ratio(), Some(1.5));
+
+        // A flap event's peak is a spike count, not a value.
+        event.phase = EventPhase::Flap;
+        assert_eq!(event.over_ratio(), None);
+
+        let event = SpikeEvent::ended(ResourceKind::Io, 1_700_000_000, 5, 12.0, 0.0);
+        assert_eq!(event.over_ratio(), None);
+    }
+
+    #[test]
+    fn backward_clock_step_mid_spike_keeps_the_monotonic_duration() {
+        let thresholds = Thresholds {
+            cpu_threshold: Some(90.0),
+            ..Thresholds::default()
+        };
+        let base = Instant::now();
+        // The wall clock steps back an hour after the third sample while
+        // the monotonic clock keeps going.
+        let series = |monotonic: bool| {
+            let mut state = AnalyzerState::new();
+            let mut events = Vec::new();
+            for (i, cpu) in [10.0, 95.0, 96.0, 97.0, 98.0, 10.0].into_iter().enumerate() {
+                let i = i as u64;
+                let mut snapshot = sample(i, cpu, 40.0);
+                if i > 2 {
+                    snapshot.timestamp -= Duration::from_secs(3600);
+                }
+                snapshot.instant = monotonic.then(|| base + Duration::from_secs(i));
+                events.extend(analyze_snapshot(&snapshot, &thresholds, 2, &mut state));
+            }
+            events
+        };
+
+        let events = series(true);
+        assert_eq!(events.len(), 1);
+        assert_eq!(secs(events[0].timestamp_start), 1);
+        assert_eq!(secs(events[0].timestamp_end), 5);
+
+        // On timestamps alone the step would read as no time at all.
+        assert!(series(false).is_empty());
+
+        let mut spike = SpikeState::new();
+        spike.spike_start_instant = Some(base);
+        let start = UNIX_EPOCH + Duration::from_secs(START);
+        let stepped_back = start - Duration::from_secs(3600);
+        let elapsed = spike.elapsed(start, stepped_back, Some(base + Duration::from_secs(4)));
+        assert_eq!(elapsed, Duration::from_secs(4));
+    }
+
+    #[test]
+    fn steady_ram_climb_raises_a_growth_event() {
+        let thresholds = Thresholds {
+            ram_growth: Some(GrowthPolicy {
+                mb_per_min: 10.0,
+                window_secs: 60,
+            }),
+            ..Thresholds::default()
+        };
+        // A sample every 10s, RAM used growing by `mb_per_step` each time.
+        let series = |mb_per_step: u64| {
+            let mut state = AnalyzerState::new();
+            let mut events = Vec::new();
+            for step in 0..10 {
+                let mut snapshot = sample(step * 10, 20.0, 40.0);
+                snapshot.ram_total_bytes = 16_000_000_000;
+                snapshot.ram_used_bytes = 4_000_000_000 + step * mb_per_step * 1_000_000;
+                events.extend(analyze_snapshot(&snapshot, &thresholds, 3, &mut state));
+            }
+            events
+        };
+
+        // 2MB per 10s is 12MB/min, over the 10MB/min slope.
+        let events = series(2);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].phase, EventPhase::Growth);
+        assert_eq!(events[0].resource, ResourceKind::Ram);
+        assert_eq!(secs(events[0].timestamp_start), 0);
+        assert_eq!(secs(events[0].timestamp_end), 60);
+        assert!(
+            (events[0].peak_value - 12.0).abs() < 0.01,
+            "{}",
+            events[0].peak_value
+        );
+
+        assert!(series(0).is_empty());
+        // 1MB per 10s is 6MB/min: climbing, but under the slope.
+        assert!(series(1).is_empty());
+    }
+
+    #[test]
+    fn sustained_and_burst_rules_fire_on_one_series() {
+        let thresholds = Thresholds {
+            rules: vec![
+                NamedRule::parse("sustained:cpu=70:5").unwrap(),
+                NamedRule::parse("burst:cpu=95:2").unwrap(),
+            ],
+            ..Thresholds::default()
+        };
+        let cpu = [60.0, 80.0, 80.0, 97.0, 98.0, 80.0, 80.0, 80.0, 60.0];
+        let series: Vec<(f32, f32)> = cpu.iter().map(|&c| (c, 40.0)).collect();
+        let events = run(&thresholds, 3, &series);
+
+        let fired: Vec<(Option<&str>, u64, u64, f32)> = events
+            .iter()
+            .map(|e| {
+                (
+                    e.rule.as_deref(),
+                    secs(e.timestamp_start),
+                    secs(e.timestamp_end),
+                    e.peak_value,
+                )
+            })
+            .collect();
+        assert_eq!(
+            fired,
+            [(Some("burst"), 3, 5, 98.0), (Some("sustained"), 1, 8, 98.0)]
+        );
+        assert!(events.iter().all(|e| e.resource == ResourceKind::Cpu));
+
+        // A short burst alone doesn't make the sustained rule fire.
+        let blip = [
+            (60.0, 40.0),
+            (97.0, 40.0),
+            (97.0, 40.0),
+            (98.0, 40.0),
+            (60.0, 40.0),
+        ];
+        let events = run(&thresholds, 3, &blip);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].rule.as_deref(), Some("burst"));
+
+        let parsed = NamedRule::parse("burst:cpu=95:2").unwrap();
+        assert_eq!(parsed.threshold, 95.0);
+        assert_eq!(parsed.min_duration_secs, Some(2));
+        assert_eq!(
+            NamedRule::parse("warn:ram=80").unwrap().min_duration_secs,
+            None
+        );
+        for bad in [
+            "cpu=95",
+            ":cpu=95",
+            "x:disk=90",
+            "x:cpu=hot",
+            "x:cpu=95:soon",
+        ] {
+            assert!(NamedRule::parse(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! Mean of each resource over the warmup samples, for ratio thresholds
+//! like `--cpu-threshold 2x`.
+
+use crate::analyzer::resource_value;
+use crate::config::ResourceKind;
+use crate::metrics::SystemSnapshot;
+
+#[derive(Debug, Clone, Default)]
+pub struct WarmupMeans {
+    /// (resource, sum, samples)
+    sums: Vec<(ResourceKind, f64, u64)>,
+}
+
+impl WarmupMeans {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fold one warmup snapshot in; resources that were not collected are
+    /// left out.
+    pub fn update(&mut self, snapshot: &SystemSnapshot) {
+        for resource in ResourceKind::ALL {
+            let Some(value) = resource_value(snapshot, resource) else {
+                continue;
+            };
+
+            match self.sums.iter_mut().find(|(r, _, _)| *r == resource) {
+                Some((_, sum, count)) => {
+                    *sum += value as f64;
+                    *count += 1;
+                }
+                None => self.sums.push((resource, value as f64, 1)),
+            }
+        }
+    }
+
+    /// Mean of `resource`, or None if it had no samples.
+    pub fn mean(&self, resource: ResourceKind) -> Option<f32> {
+        self.sums
+            .iter()
+            .find(|(r, _, _)| *r == resource)
+            .map(|&(_, sum, count)| (sum / count as f64) as f32)
+    }
+}
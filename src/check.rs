@@ -0,0 +1,215 @@
+use colored::*;
+
+use crate::config::{CheckConfig, OutputFormat};
+use crate::error::MonitorError;
+use crate::metrics::{check_cgroup, check_platform, read_disk_used_percent};
+
+#[cfg(target_os = "linux")]
+use crate::config::ProcFilter;
+#[cfg(target_os = "linux")]
+use crate::metrics::{
+    read_cgroup_mem_usage, read_cpu_temp_celsius, read_fd_usage, read_mem_usage, read_power_usage,
+    read_thread_count, ProcSource, DISKSTATS_SECTOR_SIZE,
+};
+#[cfg(not(target_os = "linux"))]
+use crate::{config::RuntimeConfig, metrics::SnapshotSampler};
+use crate::output::json_string;
+
+/// Outcome of reading one data source.
+struct SourceCheck {
+    name: String,
+    source: String,
+    /// Optional sources (processes, temperature) don't fail the check.
+    required: bool,
+    error: Option<String>,
+}
+
+impl SourceCheck {
+    fn new(name: &str, source: &str, required: bool, result: Result<(), MonitorError>) -> Self {
+        Self {
+            name: name.to_string(),
+            source: source.to_string(),
+            required,
+            error: result.err().map(|e| e.to_string()),
+        }
+    }
+}
+
+/// Check mode: read each data source once and report OK/FAIL per source.
+///
+/// Returns whether every required source could be read.
+pub fn run_check(config: CheckConfig) -> bool {
+    // Without /proc every source would fail with the same missing file.
+    let mut checks = match check_platform() {
+        Ok(()) => platform_checks(),
+        Err(e) => vec![SourceCheck::new("platform", "/proc", true, Err(e))],
+    };
+
+    if let Some(dir) = &config.cgroup {
+        checks.push(SourceCheck::new("cgroup", dir, true, read_cgroup(dir)));
+    }
+
+    for path in &config.watch_paths {
+        checks.push(SourceCheck::new(
+            "disk",
+            path,
+            true,
+            read_disk_used_percent(path).map(|_| ()),
+        ));
+    }
+
+    let ok = checks.iter().all(|c| !c.required || c.error.is_none());
+
+    match config.output_format {
+        OutputFormat::Json => print_checks_json(&checks, ok),
+        OutputFormat::Text | OutputFormat::Table | OutputFormat::OpenMetrics => {
+            print_checks_text(&checks, ok)
+        }
+    }
+
+    ok
+}
+
+/// One check per /proc and /sys source the Linux backend reads.
+#[cfg(target_os = "linux")]
+fn platform_checks() -> Vec<SourceCheck> {
+    let mut source = ProcSource::new();
+
+    vec![
+        SourceCheck::new(
+            "cpu",
+            "/proc/stat",
+            true,
+            source.read_cpu_usage_percent_delta().map(|_| ()),
+        ),
+        SourceCheck::new(
+            "memory",
+            "/proc/meminfo",
+            true,
+            read_mem_usage().map(|_| ()),
+        ),
+        SourceCheck::new(
+            "io",
+            "/proc/diskstats",
+            true,
+            source
+                .read_io_bytes_per_s_delta(&[], DISKSTATS_SECTOR_SIZE)
+                .map(|_| ()),
+        ),
+        SourceCheck::new(
+            "file handles",
+            "/proc/sys/fs/file-nr",
+            false,
+            read_fd_usage().map(|_| ()),
+        ),
+        SourceCheck::new(
+            "threads",
+            "/proc/loadavg",
+            false,
+            read_thread_count().map(|_| ()),
+        ),
+        SourceCheck::new(
+            "processes",
+            "/proc/[pid]/stat",
+            false,
+            source
+                .read_top_processes(1, &ProcFilter::default())
+                .map(|_| ()),
+        ),
+        SourceCheck::new(
+            "temperature",
+            "/sys/class/thermal",
+            false,
+            read_cpu_temp_celsius()
+                .map(|_| ())
+                .ok_or_else(|| MonitorError::Parse("No readable thermal zone".into())),
+        ),
+        SourceCheck::new(
+            "battery",
+            "/sys/class/power_supply",
+            false,
+            read_power_usage()
+                .map(|_| ())
+                .ok_or_else(|| MonitorError::Parse("No battery".into())),
+        ),
+    ]
+}
+
+/// Other platforms read everything through one system snapshot.
+#[cfg(not(target_os = "linux"))]
+fn platform_checks() -> Vec<SourceCheck> {
+    let config = RuntimeConfig {
+        top_n_procs: 1,
+        ..RuntimeConfig::default()
+    };
+
+    vec![SourceCheck::new(
+        "system",
+        "sysinfo",
+        true,
+        SnapshotSampler::new(config).next_sample().map(|_| ()),
+    )]
+}
+
+#[cfg(target_os = "linux")]
+fn read_cgroup(dir: &str) -> Result<(), MonitorError> {
+    check_cgroup(dir)?;
+    ProcSource::new().read_cgroup_cpu_percent_delta(dir)?;
+    read_cgroup_mem_usage(dir)?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_cgroup(dir: &str) -> Result<(), MonitorError> {
+    check_cgroup(dir)
+}
+
+fn print_checks_text(checks: &[SourceCheck], ok: bool) {
+    for c in checks {
+        let status = match (&c.error, c.required) {
+            (None, _) => "OK  ".green().bold(),
+            (Some(_), true) => "FAIL".red().bold(),
+            (Some(_), false) => "WARN".yellow().bold(),
+        };
+
+        let detail = match &c.error {
+            Some(e) => format!(": {}", e),
+            None => String::new(),
+        };
+
+        println!("{} {:<12} {}{}", status, c.name, c.source, detail);
+    }
+
+    if ok {
+        println!("{}", "All required data sources are readable.".green());
+    } else {
+        println!("{}", "Some required data sources could not be read.".red());
+    }
+}
+
+fn print_checks_json(checks: &[SourceCheck], ok: bool) {
+    let mut line = format!("{{\"ok\":{},\"checks\":[", ok);
+
+    for (i, c) in checks.iter().enumerate() {
+        if i > 0 {
+            line.push(',');
+        }
+
+        let error = match &c.error {
+            Some(e) => json_string(e),
+            None => "null".to_string(),
+        };
+
+        line.push_str(&format!(
+            "{{\"name\":{},\"source\":{},\"required\":{},\"ok\":{},\"error\":{}}}",
+            json_string(&c.name),
+            json_string(&c.source),
+            c.required,
+            c.error.is_none(),
+            error
+        ));
+    }
+
+    line.push_str("]}");
+    println!("{}", line);
+}
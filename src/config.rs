@@ -1,29 +1,909 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use std::collections::HashMap;
+use std::fmt;
+
+use regex::Regex;
+
+use crate::error::MonitorError;
+use crate::metrics::DISKSTATS_SECTOR_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ResourceKind {
     Cpu,
+    /// CPU time stolen by the hypervisor (%).
+    Steal,
+    /// CPU time idle while waiting on IO (%).
+    Iowait,
+    /// Open file handles as percent of the system limit.
+    Fd,
+    /// Number of processes.
+    Procs,
     Ram,
     Io,
+    /// Disk reads only (MB/s).
+    IoRead,
+    /// Disk writes only (MB/s).
+    IoWrite,
+    Temp,
+    /// Battery discharge rate (W); negative while charging.
+    Power,
+    Disk,
+    Composite,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl ResourceKind {
+    pub const ALL: [ResourceKind; 13] = [
+        ResourceKind::Cpu,
+        ResourceKind::Steal,
+        ResourceKind::Iowait,
+        ResourceKind::Fd,
+        ResourceKind::Procs,
+        ResourceKind::Ram,
+        ResourceKind::Io,
+        ResourceKind::IoRead,
+        ResourceKind::IoWrite,
+        ResourceKind::Temp,
+        ResourceKind::Power,
+        ResourceKind::Disk,
+        ResourceKind::Composite,
+    ];
+
+    /// Name in JSON, the event log, the database and CLI arguments.
+    pub fn name(self) -> &'static str {
+        match self {
+            ResourceKind::Cpu => "cpu",
+            ResourceKind::Steal => "steal",
+            ResourceKind::Iowait => "iowait",
+            ResourceKind::Fd => "fd",
+            ResourceKind::Procs => "procs",
+            ResourceKind::Ram => "ram",
+            ResourceKind::Io => "io",
+            ResourceKind::IoRead => "io_read",
+            ResourceKind::IoWrite => "io_write",
+            ResourceKind::Temp => "temp",
+            ResourceKind::Power => "power",
+            ResourceKind::Disk => "disk",
+            ResourceKind::Composite => "composite",
+        }
+    }
+
+    /// Inverse of `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// Default label in text output and syslog.
+    pub fn label(self) -> &'static str {
+        match self {
+            ResourceKind::Cpu => "CPU",
+            ResourceKind::Steal => "STEAL",
+            ResourceKind::Iowait => "IOWAIT",
+            ResourceKind::Fd => "FD",
+            ResourceKind::Procs => "PROCS",
+            ResourceKind::Ram => "RAM",
+            ResourceKind::Io => "IO",
+            ResourceKind::IoRead => "IO READ",
+            ResourceKind::IoWrite => "IO WRITE",
+            ResourceKind::Temp => "TEMP",
+            ResourceKind::Power => "POWER",
+            ResourceKind::Disk => "DISK",
+            ResourceKind::Composite => "COMPOSITE",
+        }
+    }
+
+    /// Default unit of event values (peak, threshold); empty for counts
+    /// and composite rules, whose unit depends on the condition.
+    pub fn unit(self) -> &'static str {
+        match self {
+            ResourceKind::Cpu
+            | ResourceKind::Steal
+            | ResourceKind::Iowait
+            | ResourceKind::Fd
+            | ResourceKind::Ram
+            | ResourceKind::Disk => "%",
+            ResourceKind::Io | ResourceKind::IoRead | ResourceKind::IoWrite => "MB/s",
+            ResourceKind::Temp => "°C",
+            ResourceKind::Power => "W",
+            ResourceKind::Procs | ResourceKind::Composite => "",
+        }
+    }
+}
+
+/// Resource labels and units for text output, with per-resource
+/// overrides from `--label` / `--unit`. JSON keeps `ResourceKind::name`.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceLabels {
+    labels: HashMap<ResourceKind, String>,
+    units: HashMap<ResourceKind, String>,
+}
+
+impl ResourceLabels {
+    pub fn new(labels: Vec<(ResourceKind, String)>, units: Vec<(ResourceKind, String)>) -> Self {
+        Self {
+            labels: labels.into_iter().collect(),
+            units: units.into_iter().collect(),
+        }
+    }
+
+    pub fn label(&self, kind: ResourceKind) -> &str {
+        self.labels.get(&kind).map_or(kind.label(), String::as_str)
+    }
+
+    pub fn unit(&self, kind: ResourceKind) -> &str {
+        self.units.get(&kind).map_or(kind.unit(), String::as_str)
+    }
+
+    /// Parse a `<resource>=<text>` override, e.g. `cpu=Processor`.
+    pub fn parse_override(s: &str) -> Result<(ResourceKind, String), MonitorError> {
+        let (name, text) = s.split_once('=').ok_or_else(|| {
+            MonitorError::Config(format!("Invalid override '{}': expected <resource>=<text>", s))
+        })?;
+        let kind = ResourceKind::from_name(name.trim()).ok_or_else(|| {
+            MonitorError::Config(format!("Unknown resource '{}' in override '{}'", name.trim(), s))
+        })?;
+        Ok((kind, text.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum OutputFormat {
+    #[default]
     Text,
     Json,
+    Table,
+    /// OpenMetrics text exposition per snapshot (live, batch and replay).
+    OpenMetrics,
 }
 
-#[derive(Debug, Clone)]
+/// How byte rates are shown in text and table output. JSON always
+/// carries raw bytes per second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ByteUnits {
+    /// Scaled by 1000: KB/s, MB/s, GB/s, TB/s.
+    #[default]
+    Decimal,
+    /// Scaled by 1024: KiB/s, MiB/s, GiB/s, TiB/s.
+    Binary,
+    /// Unscaled B/s.
+    Raw,
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct Thresholds {
     pub cpu_threshold: Option<f32>,
     pub ram_threshold: Option<f32>,
+    /// Absolute used-RAM ceiling; alternative to `ram_threshold`.
+    pub ram_threshold_bytes: Option<u64>,
     pub io_threshold: Option<f32>,
+    /// Read and write MB/s thresholds, each with its own spike events.
+    pub io_read_threshold: Option<f32>,
+    pub io_write_threshold: Option<f32>,
+    /// CPU steal time threshold in percent.
+    pub steal_threshold: Option<f32>,
+    /// CPU iowait threshold in percent.
+    pub iowait_threshold: Option<f32>,
+    /// Open file handles threshold in percent of the limit.
+    pub fd_threshold: Option<f32>,
+    /// Process count threshold.
+    pub proc_count_threshold: Option<u64>,
+    pub temp_threshold: Option<f32>,
+    /// Battery discharge rate threshold in watts.
+    pub power_threshold: Option<f32>,
+    pub disk_threshold: Option<f32>,
+    pub composite: Option<CompositeRule>,
+    /// Extra named thresholds, each tracked as its own spike.
+    pub rules: Vec<NamedRule>,
+    /// Per-resource minimum spike durations; None uses the global value.
+    pub cpu_min_duration_secs: Option<u64>,
+    pub ram_min_duration_secs: Option<u64>,
+    pub io_min_duration_secs: Option<u64>,
+    /// Adaptive detection for CPU/RAM/IO/temp; replaces their static
+    /// thresholds when set.
+    pub zscore: Option<ZScoreConfig>,
+    /// N-of-M confirmation; None opens and closes spikes on single samples.
+    pub confirm: Option<ConfirmPolicy>,
+    /// Also emit a start event once a spike reaches its minimum duration.
+    pub alert_on_start: bool,
+    /// Emit a flap event when a resource spikes repeatedly.
+    pub flap: Option<FlapPolicy>,
+    /// Emit a growth event when RAM use climbs steadily (a leak).
+    pub ram_growth: Option<GrowthPolicy>,
+    /// Don't open spikes that start within this many seconds of boot.
+    pub ignore_first_uptime_secs: Option<u64>,
+    /// Thresholds given as a multiple of the warmup mean, e.g. `2x`; set
+    /// into the fields above by `resolve_ratios` when warmup ends.
+    pub baseline_ratios: Vec<(ResourceKind, f32)>,
+    /// Time-of-day overrides; the first rule covering the current time
+    /// applies, and the fields above apply in the gaps.
+    pub schedule: Vec<ScheduleRule>,
 }
 
 impl Thresholds {
-    pub fn new(cpu: Option<f32>, ram: Option<f32>, io: Option<f32>) -> Self {
+    /// Set the static threshold of `kind`. Resources without an f32
+    /// threshold (process count, composite) are ignored.
+    pub fn set(&mut self, kind: ResourceKind, value: f32) {
+        if let Some(field) = self.static_threshold_mut(kind) {
+            *field = Some(value);
+        }
+    }
+
+    fn static_threshold_mut(&mut self, kind: ResourceKind) -> Option<&mut Option<f32>> {
+        match kind {
+            ResourceKind::Cpu => Some(&mut self.cpu_threshold),
+            ResourceKind::Steal => Some(&mut self.steal_threshold),
+            ResourceKind::Iowait => Some(&mut self.iowait_threshold),
+            ResourceKind::Ram => Some(&mut self.ram_threshold),
+            ResourceKind::Io => Some(&mut self.io_threshold),
+            ResourceKind::IoRead => Some(&mut self.io_read_threshold),
+            ResourceKind::IoWrite => Some(&mut self.io_write_threshold),
+            ResourceKind::Temp => Some(&mut self.temp_threshold),
+            ResourceKind::Power => Some(&mut self.power_threshold),
+            ResourceKind::Fd => Some(&mut self.fd_threshold),
+            ResourceKind::Disk => Some(&mut self.disk_threshold),
+            ResourceKind::Procs | ResourceKind::Composite => None,
+        }
+    }
+
+    /// Index of the first schedule rule covering `minute` of the day
+    /// (0..1440); None in the gaps between rules.
+    pub fn schedule_rule(&self, minute: u32) -> Option<usize> {
+        self.schedule.iter().position(|rule| rule.contains(minute))
+    }
+
+    /// Minimum duration of a spike of `resource`, or of the `--rule`
+    /// named `rule`; per-resource overrides fall back to `default_secs`.
+    pub fn min_duration_secs(
+        &self,
+        resource: ResourceKind,
+        rule: Option<&str>,
+        default_secs: u64,
+    ) -> u64 {
+        let secs = match rule {
+            Some(name) => self
+                .rules
+                .iter()
+                .find(|r| r.name == name)
+                .and_then(|r| r.min_duration_secs),
+            None => match resource {
+                ResourceKind::Cpu | ResourceKind::Steal | ResourceKind::Iowait => {
+                    self.cpu_min_duration_secs
+                }
+                ResourceKind::Ram => self.ram_min_duration_secs,
+                ResourceKind::Io | ResourceKind::IoRead | ResourceKind::IoWrite => {
+                    self.io_min_duration_secs
+                }
+                _ => None,
+            },
+        };
+        secs.unwrap_or(default_secs)
+    }
+
+    /// These thresholds with the overrides of schedule rule `index`.
+    pub fn with_schedule_rule(&self, index: usize) -> Thresholds {
+        let mut scheduled = self.clone();
+        for &(kind, value) in &self.schedule[index].thresholds {
+            match kind {
+                ResourceKind::Procs => {
+                    scheduled.proc_count_threshold = value.map(|v| v as u64);
+                }
+                _ => {
+                    if kind == ResourceKind::Ram {
+                        scheduled.ram_threshold_bytes = None;
+                    }
+                    if let Some(field) = scheduled.static_threshold_mut(kind) {
+                        *field = value;
+                    }
+                }
+            }
+        }
+        scheduled
+    }
+
+    /// Turn the ratio thresholds into static ones from the mean of each
+    /// resource, and return what they resolved to. A resource without a
+    /// mean (not collected during warmup) keeps no threshold.
+    pub fn resolve_ratios(
+        &mut self,
+        mean: impl Fn(ResourceKind) -> Option<f32>,
+    ) -> Vec<(ResourceKind, f32, f32)> {
+        let mut resolved = Vec::new();
+        for (kind, ratio) in std::mem::take(&mut self.baseline_ratios) {
+            if let Some(mean) = mean(kind) {
+                self.set(kind, ratio * mean);
+                resolved.push((kind, ratio, mean));
+            }
+        }
+        resolved
+    }
+}
+
+/// One `--schedule` rule: thresholds that replace the defaults between
+/// two times of day.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduleRule {
+    /// Minutes since midnight; `start` is inclusive, `end` exclusive.
+    pub start: u32,
+    pub end: u32,
+    /// New threshold of each listed resource; None turns it off.
+    pub thresholds: Vec<(ResourceKind, Option<f32>)>,
+}
+
+impl ScheduleRule {
+    /// Parse `HH:MM-HH:MM resource=value[,resource=value...]`, e.g.
+    /// `09:00-18:00 cpu=70,ram=85`. A value of `off` disables the
+    /// resource; an end before the start wraps past midnight
+    /// (`22:00-06:00`), and equal times cover the whole day.
+    pub fn parse(s: &str) -> Result<Self, MonitorError> {
+        let invalid =
+            |why: &str| MonitorError::Config(format!("Invalid schedule rule '{}': {}", s, why));
+
+        let (range, overrides) = s
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| invalid("expected HH:MM-HH:MM resource=value,..."))?;
+        let (start, end) = range
+            .split_once('-')
+            .ok_or_else(|| invalid("expected a time range like 09:00-18:00"))?;
+        let start = parse_time_of_day(start).ok_or_else(|| invalid("bad start time"))?;
+        let end = parse_time_of_day(end).ok_or_else(|| invalid("bad end time"))?;
+
+        let mut thresholds = Vec::new();
+        for item in overrides.split(',') {
+            let (name, value) = item
+                .trim()
+                .split_once('=')
+                .ok_or_else(|| invalid("expected resource=value"))?;
+            let kind = ResourceKind::from_name(name.trim())
+                .filter(|k| *k != ResourceKind::Composite)
+                .ok_or_else(|| invalid(&format!("unknown resource '{}'", name.trim())))?;
+            let value = match value.trim() {
+                "off" => None,
+                v => Some(
+                    v.parse::<f32>()
+                        .ok()
+                        .filter(|v| v.is_finite())
+                        .ok_or_else(|| invalid(&format!("bad threshold '{}'", v)))?,
+                ),
+            };
+            thresholds.push((kind, value));
+        }
+
+        Ok(Self {
+            start,
+            end,
+            thresholds,
+        })
+    }
+
+    /// Whether `minute` of the day (0..1440) falls in the rule's range.
+    pub fn contains(&self, minute: u32) -> bool {
+        if self.start <= self.end {
+            self.start == self.end || (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+impl fmt::Display for ScheduleRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start / 60,
+            self.start % 60,
+            self.end / 60,
+            self.end % 60
+        )
+    }
+}
+
+/// Minutes since midnight for `HH:MM`, with 24:00 as the end of the day.
+fn parse_time_of_day(s: &str) -> Option<u32> {
+    let (h, m) = s.trim().split_once(':')?;
+    let (h, m) = (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?);
+    match (h, m) {
+        (24, 0) => Some(24 * 60),
+        (0..=23, 0..=59) => Some(h * 60 + m),
+        _ => None,
+    }
+}
+
+/// A threshold argument: an absolute value (`80`) or a multiple of the
+/// resource's mean during warmup (`2x`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThresholdSpec {
+    Absolute(f32),
+    Ratio(f32),
+}
+
+impl ThresholdSpec {
+    pub fn parse(s: &str) -> Result<Self, MonitorError> {
+        let invalid = || {
+            MonitorError::Config(format!(
+                "Invalid threshold '{}': expected a number or a ratio like 2x",
+                s
+            ))
+        };
+
+        let s = s.trim();
+        match s.strip_suffix(['x', 'X']) {
+            Some(ratio) => {
+                let ratio = ratio.trim().parse::<f32>().map_err(|_| invalid())?;
+                if !(ratio.is_finite() && ratio > 0.0) {
+                    return Err(invalid());
+                }
+                Ok(ThresholdSpec::Ratio(ratio))
+            }
+            None => s
+                .parse::<f32>()
+                .map(ThresholdSpec::Absolute)
+                .map_err(|_| invalid()),
+        }
+    }
+}
+
+/// Spike while at least `required` of the last `window` samples are over
+/// the threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfirmPolicy {
+    pub required: usize,
+    pub window: usize,
+}
+
+impl ConfirmPolicy {
+    /// Parse `N/M` with 1 <= N <= M.
+    pub fn parse(s: &str) -> Result<Self, MonitorError> {
+        let invalid = || {
+            MonitorError::Config(format!(
+                "Invalid confirm policy '{}': expected N/M with 1 <= N <= M",
+                s
+            ))
+        };
+
+        let (n, m) = s.split_once('/').ok_or_else(invalid)?;
+        let required = n.trim().parse::<usize>().map_err(|_| invalid())?;
+        let window = m.trim().parse::<usize>().map_err(|_| invalid())?;
+
+        if required == 0 || required > window {
+            return Err(invalid());
+        }
+
+        Ok(Self { required, window })
+    }
+}
+
+/// Window `--flap-window-secs` defaults to: one hour.
+pub const DEFAULT_FLAP_WINDOW_SECS: u64 = 3600;
+
+/// Flap alert when a resource closes `spikes` spikes within
+/// `window_secs`, however short each one is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlapPolicy {
+    pub spikes: u64,
+    pub window_secs: u64,
+}
+
+/// Window `--ram-growth-window-secs` defaults to: ten minutes.
+pub const DEFAULT_RAM_GROWTH_WINDOW_SECS: u64 = 600;
+
+/// Growth alert when RAM used, fitted as a line over the last
+/// `window_secs`, rises faster than `mb_per_min`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GrowthPolicy {
+    pub mb_per_min: f32,
+    pub window_secs: u64,
+}
+
+/// Baseline-relative detection: spike when value > mean + k·stddev.
+#[derive(Debug, Clone, Copy)]
+pub struct ZScoreConfig {
+    pub k: f32,
+    /// Samples used to learn the baseline before alerting starts.
+    pub warmup_samples: u64,
+}
+
+/// Severity of a value against warn/crit bands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Ok,
+    Warn,
+    Crit,
+}
+
+/// Two-level (warn/crit) limits for one resource; either level may be
+/// unset.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Bands {
+    pub warn: Option<f32>,
+    pub crit: Option<f32>,
+}
+
+impl Bands {
+    pub fn is_set(&self) -> bool {
+        self.warn.is_some() || self.crit.is_some()
+    }
+
+    /// Crit at or above `crit`, Warn at or above `warn`, otherwise Ok.
+    pub fn classify(&self, value: f32) -> Severity {
+        if self.crit.is_some_and(|c| value >= c) {
+            Severity::Crit
+        } else if self.warn.is_some_and(|w| value >= w) {
+            Severity::Warn
+        } else {
+            Severity::Ok
+        }
+    }
+}
+
+/// Warn/crit bands per resource, in the same units as the thresholds:
+/// cpu/ram in percent, io in MB/s (read + write), temp in °C.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SeverityBands {
+    pub cpu: Bands,
+    pub ram: Bands,
+    pub io: Bands,
+    pub temp: Bands,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Greater,
+    GreaterOrEqual,
+}
+
+/// One `<resource> <op> <value>` term of a composite rule.
+#[derive(Debug, Clone)]
+pub struct Condition {
+    pub resource: ResourceKind,
+    pub comparison: Comparison,
+    pub value: f32,
+}
+
+impl Condition {
+    pub fn holds(&self, value: f32) -> bool {
+        match self.comparison {
+            Comparison::Greater => value > self.value,
+            Comparison::GreaterOrEqual => value >= self.value,
+        }
+    }
+}
+
+/// Spike condition that requires every term to hold at once,
+/// e.g. `cpu>80 && ram>90`.
+#[derive(Debug, Clone)]
+pub struct CompositeRule {
+    pub conditions: Vec<Condition>,
+}
+
+impl fmt::Display for CompositeRule {
+    /// Same syntax `parse` accepts, e.g. `cpu>80 && ram>=90`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, cond) in self.conditions.iter().enumerate() {
+            if i > 0 {
+                write!(f, " && ")?;
+            }
+
+            let op = match cond.comparison {
+                Comparison::Greater => ">",
+                Comparison::GreaterOrEqual => ">=",
+            };
+            write!(f, "{}{}{}", cond.resource.name(), op, cond.value)?;
+        }
+        Ok(())
+    }
+}
+
+impl CompositeRule {
+    /// Parse an AND (`&&`) of `<resource> > <value>` / `>=` terms.
+    /// Resources: cpu, steal, iowait, ram, fd (%), io, io_read, io_write
+    /// (MB/s), temp (°C), procs (count).
+    pub fn parse(s: &str) -> Result<Self, MonitorError> {
+        let mut conditions = Vec::new();
+
+        for term in s.split("&&") {
+            let term = term.trim();
+
+            let (name, comparison, value) = if let Some((l, r)) = term.split_once(">=") {
+                (l, Comparison::GreaterOrEqual, r)
+            } else if let Some((l, r)) = term.split_once('>') {
+                (l, Comparison::Greater, r)
+            } else {
+                return Err(MonitorError::Config(format!(
+                    "Invalid composite term '{}': expected '>' or '>='",
+                    term
+                )));
+            };
+
+            // Disks are keyed by path and composites don't nest.
+            let resource = match ResourceKind::from_name(name.trim()) {
+                Some(ResourceKind::Disk | ResourceKind::Composite) | None => {
+                    return Err(MonitorError::Config(format!(
+                        "Invalid composite resource '{}': use cpu, steal, iowait, ram, io, io_read, io_write, temp, fd or procs",
+                        name.trim()
+                    )))
+                }
+                Some(kind) => kind,
+            };
+
+            let value = value
+                .trim()
+                .parse::<f32>()
+                .map_err(|_| MonitorError::Config(format!("Invalid composite value in '{}'", term)))?;
+
+            conditions.push(Condition {
+                resource,
+                comparison,
+                value,
+            });
+        }
+
+        Ok(Self { conditions })
+    }
+}
+
+/// Named threshold from `--rule`, checked independently of the
+/// resource's own threshold and of other rules, e.g. a sustained warning
+/// and a burst alert on the same resource.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedRule {
+    pub name: String,
+    pub resource: ResourceKind,
+    pub threshold: f32,
+    /// None uses the global minimum spike duration.
+    pub min_duration_secs: Option<u64>,
+}
+
+impl NamedRule {
+    /// Parse `name:resource=threshold[:min_secs]`, e.g. `burst:cpu=95:2`.
+    pub fn parse(s: &str) -> Result<Self, MonitorError> {
+        let invalid = |why: &str| MonitorError::Config(format!("Invalid rule '{}': {}", s, why));
+
+        let mut parts = s.trim().splitn(3, ':');
+        let name = parts.next().unwrap_or("").trim();
+        if name.is_empty() {
+            return Err(invalid("expected name:resource=threshold[:min_secs]"));
+        }
+        let (resource, threshold) = parts
+            .next()
+            .and_then(|c| c.split_once('='))
+            .ok_or_else(|| invalid("expected name:resource=threshold[:min_secs]"))?;
+
+        // Disks are keyed by path and composites have their own rule.
+        let resource = ResourceKind::from_name(resource.trim())
+            .filter(|k| !matches!(k, ResourceKind::Disk | ResourceKind::Composite))
+            .ok_or_else(|| invalid(&format!("unknown resource '{}'", resource.trim())))?;
+        let threshold = threshold
+            .trim()
+            .parse::<f32>()
+            .ok()
+            .filter(|t| t.is_finite())
+            .ok_or_else(|| invalid(&format!("bad threshold '{}'", threshold.trim())))?;
+        let min_duration_secs = parts
+            .next()
+            .map(|m| {
+                m.trim()
+                    .parse::<u64>()
+                    .map_err(|_| invalid(&format!("bad minimum duration '{}'", m.trim())))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            name: name.to_string(),
+            resource,
+            threshold,
+            min_duration_secs,
+        })
+    }
+}
+
+/// Which processes are considered for the top-N list.
+#[derive(Debug, Clone, Default)]
+pub struct ProcFilter {
+    pub include: Option<Regex>,
+    pub exclude: Option<Regex>,
+    pub no_kernel_threads: bool,
+}
+
+impl ProcFilter {
+    pub fn matches(&self, name: &str, kernel_thread: bool) -> bool {
+        if self.no_kernel_threads && kernel_thread {
+            return false;
+        }
+        if let Some(include) = &self.include {
+            if !include.is_match(name) {
+                return false;
+            }
+        }
+        if let Some(exclude) = &self.exclude {
+            if exclude.is_match(name) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Line ending of the output stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    /// `\r\n`, for Windows tools and shippers that expect it.
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn as_bytes(self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::Crlf => b"\r\n",
+        }
+    }
+}
+
+/// Encoding of `--samples-log`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SamplesLogFormat {
+    /// Snapshot objects as in `--output json`.
+    #[default]
+    Json,
+    /// Length-prefixed binary records, see `samples_log`.
+    Bin,
+}
+
+/// When the event log is flushed to its file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFlush {
+    /// After every event: nothing is lost on a crash.
+    #[default]
+    Always,
+    /// After an event, if the last flush is at least this many ms old.
+    IntervalMs(u64),
+    /// Only when the buffer fills and when the log is closed.
+    Never,
+}
+
+/// Syslog facility for spike events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyslogFacility {
+    User,
+    Daemon,
+    Local(u8), // local0..local7
+}
+
+impl SyslogFacility {
+    /// Numeric facility code as defined by RFC 5424.
+    pub fn code(self) -> u8 {
+        match self {
+            SyslogFacility::User => 1,
+            SyslogFacility::Daemon => 3,
+            SyslogFacility::Local(n) => 16 + n,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SyslogConfig {
+    pub facility: SyslogFacility,
+    pub ident: String,
+}
+
+/// Metric groups read on every sample. A group that is off costs no
+/// reads; its snapshot values are 0 (cpu, ram, io) or absent (temp, fd,
+/// tasks).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Collectors {
+    /// CPU usage, steal and iowait from /proc/stat (or the cgroup).
+    pub cpu: bool,
+    /// RAM and swap from /proc/meminfo (or the cgroup).
+    pub ram: bool,
+    /// Disk IO rates from /proc/diskstats.
+    pub io: bool,
+    /// CPU temperature from /sys/class/thermal.
+    pub temp: bool,
+    /// Open file handles from /proc/sys/fs/file-nr.
+    pub fd: bool,
+    /// Process and thread counts.
+    pub tasks: bool,
+    /// Battery charge and discharge rate from /sys/class/power_supply.
+    pub power: bool,
+}
+
+impl Default for Collectors {
+    /// The metrics every snapshot line shows.
+    fn default() -> Self {
+        Self {
+            cpu: true,
+            ram: true,
+            io: true,
+            temp: false,
+            fd: false,
+            tasks: false,
+            power: false,
+        }
+    }
+}
+
+impl Collectors {
+    /// Nothing collected; `enable` turns groups on.
+    pub fn none() -> Self {
         Self {
-            cpu_threshold: cpu,
-            ram_threshold: ram,
-            io_threshold: io,
+            cpu: false,
+            ram: false,
+            io: false,
+            temp: false,
+            fd: false,
+            tasks: false,
+            power: false,
+        }
+    }
+
+    /// Turn on the group that provides `kind`.
+    pub fn enable(&mut self, kind: ResourceKind) {
+        match kind {
+            ResourceKind::Cpu | ResourceKind::Steal | ResourceKind::Iowait => self.cpu = true,
+            ResourceKind::Ram => self.ram = true,
+            ResourceKind::Io | ResourceKind::IoRead | ResourceKind::IoWrite => self.io = true,
+            ResourceKind::Temp => self.temp = true,
+            ResourceKind::Fd => self.fd = true,
+            ResourceKind::Procs => self.tasks = true,
+            ResourceKind::Power => self.power = true,
+            // Disk usage follows --watch-path; composite rules enable
+            // their terms.
+            ResourceKind::Disk | ResourceKind::Composite => {}
+        }
+    }
+
+    /// Parse a comma-separated list of resource names, e.g. `cpu,ram`.
+    pub fn parse(s: &str) -> Result<Self, MonitorError> {
+        let mut collectors = Self::none();
+        for name in s.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            match ResourceKind::from_name(name) {
+                Some(kind) if !matches!(kind, ResourceKind::Disk | ResourceKind::Composite) => {
+                    collectors.enable(kind)
+                }
+                _ => {
+                    return Err(MonitorError::Config(format!(
+                        "Unknown collector '{}' in '{}'",
+                        name, s
+                    )))
+                }
+            }
+        }
+        Ok(collectors)
+    }
+
+    /// Also turn on every group a configured threshold needs, so a
+    /// threshold never watches a metric that isn't read.
+    pub fn require(&mut self, thresholds: &Thresholds) {
+        let configured = [
+            (ResourceKind::Cpu, thresholds.cpu_threshold.is_some()),
+            (ResourceKind::Steal, thresholds.steal_threshold.is_some()),
+            (ResourceKind::Iowait, thresholds.iowait_threshold.is_some()),
+            (
+                ResourceKind::Ram,
+                thresholds.ram_threshold.is_some() || thresholds.ram_threshold_bytes.is_some(),
+            ),
+            (ResourceKind::Io, thresholds.io_threshold.is_some()),
+            (ResourceKind::IoRead, thresholds.io_read_threshold.is_some()),
+            (ResourceKind::IoWrite, thresholds.io_write_threshold.is_some()),
+            (ResourceKind::Temp, thresholds.temp_threshold.is_some()),
+            (ResourceKind::Power, thresholds.power_threshold.is_some()),
+            (ResourceKind::Fd, thresholds.fd_threshold.is_some()),
+            (ResourceKind::Procs, thresholds.proc_count_threshold.is_some()),
+        ];
+        for (kind, set) in configured {
+            if set {
+                self.enable(kind);
+            }
+        }
+        for &(kind, _) in &thresholds.baseline_ratios {
+            self.enable(kind);
+        }
+        if let Some(rule) = &thresholds.composite {
+            for cond in &rule.conditions {
+                self.enable(cond.resource);
+            }
         }
     }
 }
@@ -31,31 +911,542 @@ impl Thresholds {
 #[derive(Debug, Clone)]
 pub struct RuntimeConfig {
     pub interval_ms: u64,
+    /// Random offset of each sample, up to ± this many ms.
+    pub jitter_ms: u64,
     pub thresholds: Thresholds,
     pub min_spike_duration_secs: u64,
     pub output_format: OutputFormat,
+    pub precision: Option<usize>,
+    pub quiet: bool,
     pub log_file: Option<String>,
+    pub log_compress: bool,
+    /// Run without the event log when it can't be opened, instead of
+    /// failing.
+    pub log_best_effort: bool,
+    pub log_flush: LogFlush,
+    pub syslog: Option<SyslogConfig>,
+    pub db_file: Option<String>,
+    /// Unix socket serving spike events as JSON lines to its clients.
+    pub event_socket: Option<String>,
     pub top_n_procs: usize,
+    pub proc_filter: ProcFilter,
+    /// Name processes by their command line, cut to this many
+    /// characters, instead of the short comm (Linux only).
+    pub proc_cmdline_max_len: Option<usize>,
+    /// Sample the monitor's own CPU and RSS on every tick.
+    pub report_self_overhead: bool,
+    pub collectors: Collectors,
+    pub watch_paths: Vec<String>,
+    /// Print a liveness line to stderr every N seconds.
+    pub heartbeat_secs: Option<u64>,
+    /// Warn after every N consecutive failed snapshot reads.
+    pub failure_warn_every: Option<u64>,
+    /// Read CPU/memory from this cgroup v2 directory instead of the host.
+    pub cgroup: Option<String>,
+    /// Block devices summed for IO; empty means all whole disks.
+    pub io_devices: Vec<String>,
+    /// Bytes per diskstats sector.
+    pub io_sector_size: u64,
+    /// Colors snapshot values in text/table output; display only.
+    pub bands: SeverityBands,
+    /// Units of IO rates in text/table output.
+    pub byte_units: ByteUnits,
+    /// Resource labels and units of event and ongoing lines, and syslog.
+    pub labels: ResourceLabels,
+    /// Host name tagged on logged and JSON events, read once at startup
+    /// or set with `--host-label`.
+    pub host: Option<String>,
+    /// Also tag JSON snapshots with `host`.
+    pub snapshot_host: bool,
+    /// Free-form note attached to every spike event.
+    pub tag: Option<String>,
+    /// Attach each resource's latest spike to its OpenMetrics spike
+    /// counter as an exemplar.
+    pub exemplars: bool,
+    /// Write snapshots and events here (appending) instead of stdout.
+    pub output_file: Option<String>,
+    pub line_ending: LineEnding,
+    /// Also write every sample here, for replay.
+    pub samples_log: Option<String>,
+    pub samples_log_format: SamplesLogFormat,
+    /// Print a status line per open spike after every sample.
+    pub show_ongoing: bool,
+    /// Note on stderr why open spikes haven't fired yet and why closed
+    /// ones were discarded.
+    pub explain: bool,
+    /// Samples printed but not analyzed at startup.
+    pub warmup_samples: u64,
+    /// Analyzer state saved on clean shutdown and resumed on startup.
+    pub state_file: Option<String>,
+    /// Saved state older than this is discarded on startup.
+    pub state_max_age_secs: u64,
+}
+
+/// Default for `--state-max-age-secs`.
+pub const DEFAULT_STATE_MAX_AGE_SECS: u64 = 300;
+
+/// Default for `--proc-cmdline-max-len`.
+pub const DEFAULT_CMDLINE_MAX_LEN: usize = 256;
+
+/// Shortest sampling interval; reading /proc takes a good part of
+/// anything shorter, and CPU deltas over a few ticks are mostly noise.
+pub const MIN_INTERVAL_MS: u64 = 10;
+
+/// Default for `--sparkline-width`.
+pub const DEFAULT_SPARKLINE_WIDTH: usize = 20;
+
+/// Bucket edges of `--histogram` without a value: tens of percent.
+pub const DEFAULT_HISTOGRAM_EDGES: &str = "0,10,20,30,40,50,60,70,80,90,100";
+
+/// Ascending bucket edges of `--histogram`: `0,10,20` makes the buckets
+/// 0-10 and 10-20, the last one including its upper edge.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BucketEdges(pub Vec<f32>);
+
+impl BucketEdges {
+    /// Parse comma-separated edges, e.g. `0,50,80,90,100`; at least two,
+    /// strictly ascending.
+    pub fn parse(s: &str) -> Result<Self, MonitorError> {
+        let invalid =
+            |why: &str| MonitorError::Config(format!("Invalid histogram edges '{}': {}", s, why));
+
+        let mut edges = Vec::new();
+        for item in s.split(',') {
+            let edge = item
+                .trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|e| e.is_finite())
+                .ok_or_else(|| invalid(&format!("bad edge '{}'", item.trim())))?;
+            if edges.last().is_some_and(|&last| edge <= last) {
+                return Err(invalid("edges must be strictly ascending"));
+            }
+            edges.push(edge);
+        }
+        if edges.len() < 2 {
+            return Err(invalid("expected at least two edges"));
+        }
+
+        Ok(Self(edges))
+    }
+}
+
+impl Default for RuntimeConfig {
+    /// Same defaults as the CLI: 1s interval, 3s minimum spike duration.
+    fn default() -> Self {
+        Self {
+            interval_ms: 1000,
+            jitter_ms: 0,
+            thresholds: Thresholds::default(),
+            min_spike_duration_secs: 3,
+            output_format: OutputFormat::default(),
+            precision: None,
+            quiet: false,
+            log_file: None,
+            log_compress: false,
+            log_best_effort: false,
+            log_flush: LogFlush::default(),
+            syslog: None,
+            event_socket: None,
+            db_file: None,
+            top_n_procs: 0,
+            proc_filter: ProcFilter::default(),
+            proc_cmdline_max_len: None,
+            report_self_overhead: false,
+            collectors: Collectors::default(),
+            watch_paths: Vec::new(),
+            heartbeat_secs: None,
+            failure_warn_every: None,
+            cgroup: None,
+            io_devices: Vec::new(),
+            io_sector_size: DISKSTATS_SECTOR_SIZE,
+            bands: SeverityBands::default(),
+            byte_units: ByteUnits::default(),
+            labels: ResourceLabels::default(),
+            host: None,
+            snapshot_host: false,
+            tag: None,
+            exemplars: false,
+            output_file: None,
+            line_ending: LineEnding::default(),
+            samples_log: None,
+            samples_log_format: SamplesLogFormat::default(),
+            show_ongoing: false,
+            explain: false,
+            warmup_samples: 0,
+            state_file: None,
+            state_max_age_secs: DEFAULT_STATE_MAX_AGE_SECS,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub enum BatchLimit {
     DurationSecs(u64),
     Samples(u64),
+    /// Run until interrupted (live mode without a limit).
+    Unbounded,
 }
 
 #[derive(Debug, Clone)]
 pub struct BatchConfig {
     pub runtime: RuntimeConfig,
     pub limit: BatchLimit,
+    /// Show a progress line on stderr when it is a terminal.
+    pub progress: bool,
+    /// Draw snapshots as sparklines of this many samples instead of one
+    /// line each, when stdout is a terminal.
+    pub sparkline: Option<usize>,
+    /// Analyze the snapshots recorded in this file (`--output json`)
+    /// instead of sampling the system.
+    pub replay: Option<String>,
+    /// Close spikes still open when the run ends on its limit (or at the
+    /// end of a replay) with an end event at the last sample, unless a
+    /// state file carries them over to the next run.
+    pub finalize: bool,
+    /// Frame the JSON stream as a single array, closed when the run ends.
+    pub json_array: bool,
+    /// Count samples per resource in these buckets and print the
+    /// histograms when the run ends.
+    pub histogram: Option<BucketEdges>,
 }
 
 #[derive(Debug, Clone)]
 pub struct LogsQuery {
-    pub log_file: String,
+    /// Log files read in this order.
+    pub log_files: Vec<String>,
+    /// Directory whose `*.jsonl(.gz)` files are read after `log_files`.
+    pub log_dir: Option<String>,
     pub resource_filter: Option<ResourceKind>,
-    pub since: Option<u64>,  // seconds since epoch (optional)
-    pub until: Option<u64>,  // seconds since epoch (optional)
+    /// Only print the event with this id.
+    pub id: Option<String>,
+    /// Only events logged by runs with this `--tag`.
+    pub tag: Option<String>,
+    pub since: Option<TimeSpec>,
+    pub until: Option<TimeSpec>,
     pub limit: Option<usize>,
+    /// Only events whose peak is at least this multiple of the threshold.
+    pub min_over_ratio: Option<f32>,
+    /// Only events with a top process whose name contains this.
+    pub proc_name: Option<String>,
+    /// Only events with a top process whose name matches this.
+    pub proc_regex: Option<Regex>,
     pub output_format: OutputFormat,
+    /// Buffer matching records and print them in this order.
+    pub sort: Option<LogSortKey>,
+    pub descending: bool,
+    /// Fix truncated last lines in place instead of printing events.
+    pub repair: bool,
+    /// Resource labels and units of text output.
+    pub labels: ResourceLabels,
+}
+
+/// Time bound for logs filters: an epoch timestamp or a span before now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeSpec {
+    /// Seconds since epoch.
+    Absolute(u64),
+    /// This many seconds before the time the query runs.
+    Ago(u64),
+}
+
+impl TimeSpec {
+    /// Parse `<epoch secs>`, or a span with an s/m/h/d suffix written as
+    /// `-30m`, `now-30m` or `30m`. A bare `-N` is N seconds ago.
+    pub fn parse(s: &str) -> Result<Self, MonitorError> {
+        let s = s.trim();
+        let invalid = || {
+            MonitorError::Config(format!(
+                "Invalid time '{}': use epoch seconds or a span like -30m, 2h, now-1d",
+                s
+            ))
+        };
+
+        let (relative, spec) = if let Some(rest) = s.strip_prefix("now-") {
+            (true, rest)
+        } else if let Some(rest) = s.strip_prefix('-') {
+            (true, rest)
+        } else {
+            (false, s)
+        };
+
+        let (digits, unit) = match spec.char_indices().last() {
+            Some((i, c)) if c.is_ascii_alphabetic() => {
+                let unit = match c.to_ascii_lowercase() {
+                    's' => 1,
+                    'm' => 60,
+                    'h' => 3600,
+                    'd' => 86400,
+                    _ => return Err(invalid()),
+                };
+                (&spec[..i], Some(unit))
+            }
+            _ => (spec, None),
+        };
+
+        let value = digits.parse::<u64>().map_err(|_| invalid())?;
+
+        match (relative, unit) {
+            (false, None) => Ok(TimeSpec::Absolute(value)),
+            (_, unit) => value
+                .checked_mul(unit.unwrap_or(1))
+                .map(TimeSpec::Ago)
+                .ok_or_else(invalid),
+        }
+    }
+
+    /// Epoch seconds this bound refers to, given the current time.
+    pub fn resolve(self, now_secs: u64) -> u64 {
+        match self {
+            TimeSpec::Absolute(secs) => secs,
+            TimeSpec::Ago(secs) => now_secs.saturating_sub(secs),
+        }
+    }
+}
+
+/// Options for the `check` subcommand.
+#[derive(Debug, Clone, Default)]
+pub struct CheckConfig {
+    pub output_format: OutputFormat,
+    pub cgroup: Option<String>,
+    pub watch_paths: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ListConfig {
+    pub devices: bool,
+    pub interfaces: bool,
+    pub output_format: OutputFormat,
+}
+
+/// Options for the `nagios` subcommand.
+#[derive(Debug, Clone, Default)]
+pub struct NagiosConfig {
+    /// Warn/crit levels; resources with neither are reported but never
+    /// change the status.
+    pub bands: SeverityBands,
+    pub cgroup: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct VersionConfig {
+    pub output_format: OutputFormat,
+}
+
+/// Sort key for logs mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogSortKey {
+    Peak,
+    Duration,
+    TsStart,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_spec_absolute_and_relative() {
+        assert_eq!(
+            TimeSpec::parse("1700000000").unwrap(),
+            TimeSpec::Absolute(1_700_000_000)
+        );
+        assert_eq!(TimeSpec::parse("-30m").unwrap(), TimeSpec::Ago(1800));
+        assert_eq!(TimeSpec::parse("now-2h").unwrap(), TimeSpec::Ago(7200));
+        assert_eq!(TimeSpec::parse("1d").unwrap(), TimeSpec::Ago(86_400));
+        assert_eq!(TimeSpec::parse("-90").unwrap(), TimeSpec::Ago(90));
+        assert_eq!(TimeSpec::parse(" 45S ").unwrap(), TimeSpec::Ago(45));
+
+        for bad in ["", "soon", "-5w", "now-", "1.5h", "99999999999999999999d"] {
+            assert!(TimeSpec::parse(bad).is_err(), "{}", bad);
+        }
+
+        let now = 1_700_000_000;
+        assert_eq!(
+            TimeSpec::Absolute(1_600_000_000).resolve(now),
+            1_600_000_000
+        );
+        assert_eq!(TimeSpec::Ago(1800).resolve(now), now - 1800);
+        assert_eq!(TimeSpec::Ago(now + 1).resolve(now), 0);
+    }
+
+    #[test]
+    fn confirm_policy_parse() {
+        assert_eq!(
+            ConfirmPolicy::parse("8/10").unwrap(),
+            ConfirmPolicy {
+                required: 8,
+                window: 10
+            }
+        );
+        assert_eq!(
+            ConfirmPolicy::parse(" 1 / 1 ").unwrap(),
+            ConfirmPolicy {
+                required: 1,
+                window: 1
+            }
+        );
+        for bad in ["", "8", "0/3", "4/3", "a/3", "3/", "-1/3"] {
+            assert!(ConfirmPolicy::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn labels_and_units_per_resource() {
+        let expected = [
+            (ResourceKind::Cpu, "cpu", "CPU", "%"),
+            (ResourceKind::Steal, "steal", "STEAL", "%"),
+            (ResourceKind::Iowait, "iowait", "IOWAIT", "%"),
+            (ResourceKind::Fd, "fd", "FD", "%"),
+            (ResourceKind::Procs, "procs", "PROCS", ""),
+            (ResourceKind::Ram, "ram", "RAM", "%"),
+            (ResourceKind::Io, "io", "IO", "MB/s"),
+            (ResourceKind::IoRead, "io_read", "IO READ", "MB/s"),
+            (ResourceKind::IoWrite, "io_write", "IO WRITE", "MB/s"),
+            (ResourceKind::Temp, "temp", "TEMP", "°C"),
+            (ResourceKind::Power, "power", "POWER", "W"),
+            (ResourceKind::Disk, "disk", "DISK", "%"),
+            (ResourceKind::Composite, "composite", "COMPOSITE", ""),
+        ];
+        assert_eq!(expected.len(), ResourceKind::ALL.len());
+
+        let defaults = ResourceLabels::default();
+        for (kind, name, label, unit) in expected {
+            assert_eq!(ResourceKind::from_name(name), Some(kind));
+            assert_eq!((defaults.label(kind), defaults.unit(kind)), (label, unit));
+        }
+
+        let labels = ResourceLabels::new(
+            vec![ResourceLabels::parse_override("cpu=Processor").unwrap()],
+            vec![ResourceLabels::parse_override("io=MiB/s").unwrap()],
+        );
+        assert_eq!(labels.label(ResourceKind::Cpu), "Processor");
+        assert_eq!(labels.unit(ResourceKind::Cpu), "%");
+        assert_eq!(labels.label(ResourceKind::Io), "IO");
+        assert_eq!(labels.unit(ResourceKind::Io), "MiB/s");
+
+        for bad in ["cpu", "gpu=Graphics"] {
+            assert!(ResourceLabels::parse_override(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn threshold_spec_absolute_or_ratio() {
+        assert_eq!(
+            ThresholdSpec::parse("80").unwrap(),
+            ThresholdSpec::Absolute(80.0)
+        );
+        assert_eq!(
+            ThresholdSpec::parse(" 2x ").unwrap(),
+            ThresholdSpec::Ratio(2.0)
+        );
+        assert_eq!(
+            ThresholdSpec::parse("1.5X").unwrap(),
+            ThresholdSpec::Ratio(1.5)
+        );
+        for bad in ["", "x", "0x", "-2x", "infx", "2xx", "eighty"] {
+            assert!(ThresholdSpec::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn schedule_picks_the_first_rule_covering_the_time() {
+        let at = |h: u32, m: u32| h * 60 + m;
+        let thresholds = Thresholds {
+            cpu_threshold: Some(90.0),
+            ram_threshold: Some(95.0),
+            schedule: vec![
+                ScheduleRule::parse("09:00-18:00 cpu=70,ram=85").unwrap(),
+                ScheduleRule::parse("12:00-13:00 cpu=50").unwrap(),
+                ScheduleRule::parse("22:00-06:00 cpu=off").unwrap(),
+            ],
+            ..Thresholds::default()
+        };
+
+        // Business hours, and lunch where both day rules overlap: the
+        // first one listed wins.
+        for minute in [at(9, 0), at(12, 30), at(17, 59)] {
+            assert_eq!(thresholds.schedule_rule(minute), Some(0), "{}", minute);
+        }
+        let day = thresholds.with_schedule_rule(0);
+        assert_eq!(day.cpu_threshold, Some(70.0));
+        assert_eq!(day.ram_threshold, Some(85.0));
+
+        // The nightly window wraps past midnight.
+        assert_eq!(thresholds.schedule_rule(at(23, 0)), Some(2));
+        assert_eq!(thresholds.schedule_rule(at(5, 59)), Some(2));
+        let night = thresholds.with_schedule_rule(2);
+        assert_eq!(night.cpu_threshold, None);
+        assert_eq!(night.ram_threshold, Some(95.0));
+
+        // Gaps fall back to the thresholds given without a schedule.
+        assert_eq!(thresholds.schedule_rule(at(6, 0)), None);
+        assert_eq!(thresholds.schedule_rule(at(18, 0)), None);
+
+        for bad in [
+            "09:00-18:00",
+            "9-18 cpu=70",
+            "09:00-25:00 cpu=70",
+            "09:00-18:00 cpu=hot",
+            "09:00-18:00 composite=1",
+        ] {
+            assert!(ScheduleRule::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn proc_filter_over_a_synthetic_process_list() {
+        let processes = [
+            ("kworker/0:1", true),
+            ("kthreadd", true),
+            ("java", false),
+            ("postgres", false),
+            ("postgres: walwriter", false),
+        ];
+        let kept = |filter: &ProcFilter| -> Vec<&str> {
+            processes
+                .iter()
+                .filter(|(comm, kernel)| filter.matches(comm, *kernel))
+                .map(|(comm, _)| *comm)
+                .collect()
+        };
+        let regex = |p: &str| Some(Regex::new(p).unwrap());
+
+        assert_eq!(kept(&ProcFilter::default()).len(), processes.len());
+
+        let include = ProcFilter {
+            include: regex("^postgres"),
+            ..ProcFilter::default()
+        };
+        assert_eq!(kept(&include), ["postgres", "postgres: walwriter"]);
+
+        let exclude = ProcFilter {
+            exclude: regex("^k"),
+            ..ProcFilter::default()
+        };
+        assert_eq!(kept(&exclude), ["java", "postgres", "postgres: walwriter"]);
+
+        let both = ProcFilter {
+            include: regex("^(postgres|java)"),
+            exclude: regex("walwriter"),
+            ..ProcFilter::default()
+        };
+        assert_eq!(kept(&both), ["java", "postgres"]);
+
+        // Kernel threads go by the flag, not the name.
+        let no_kernel = ProcFilter {
+            no_kernel_threads: true,
+            ..ProcFilter::default()
+        };
+        assert_eq!(
+            kept(&no_kernel),
+            ["java", "postgres", "postgres: walwriter"]
+        );
+        let kernel_only_names = ProcFilter {
+            include: regex("^k"),
+            no_kernel_threads: true,
+            ..ProcFilter::default()
+        };
+        assert!(kept(&kernel_only_names).is_empty());
+    }
 }
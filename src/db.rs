@@ -0,0 +1,232 @@
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection};
+
+use crate::analyzer::SpikeEvent;
+use crate::sink::EventSink;
+
+/// Tables are created on first open; existing databases are reused.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS spikes (
+    id            INTEGER PRIMARY KEY,
+    event_id      TEXT,
+    phase         TEXT,
+    host          TEXT,
+    tag           TEXT,
+    resource      TEXT    NOT NULL,
+    path          TEXT,
+    rule          TEXT,
+    ts_start      INTEGER NOT NULL,
+    ts_end        INTEGER NOT NULL,
+    duration_secs INTEGER NOT NULL,
+    peak          REAL    NOT NULL,
+    threshold     REAL    NOT NULL
+);
+CREATE TABLE IF NOT EXISTS spike_procs (
+    spike_id  INTEGER NOT NULL REFERENCES spikes(id),
+    pid       INTEGER NOT NULL,
+    name      TEXT    NOT NULL,
+    cpu       REAL    NOT NULL,
+    ram_bytes INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS spikes_resource_ts ON spikes(resource, ts_start);
+";
+
+/// SQLite sink for spike events.
+pub struct DbLogger {
+    conn: Connection,
+    /// Host name stored with every spike.
+    host: Option<String>,
+}
+
+impl DbLogger {
+    /// Open (or create) the database and make sure the schema exists.
+    pub fn new(db_path: &str, host: Option<String>) -> Result<Self, Box<dyn Error>> {
+        let conn = Connection::open(db_path)?;
+        conn.execute_batch(SCHEMA)?;
+        migrate(&conn)?;
+
+        Ok(Self { conn, host })
+    }
+
+    /// Insert one spike event and its top processes.
+    pub fn log_event(&mut self, event: &SpikeEvent) -> Result<(), Box<dyn Error>> {
+        let resource_str = event.resource.name();
+
+        let ts_start = format_time_secs(event.timestamp_start);
+        let ts_end = format_time_secs(event.timestamp_end);
+        let duration_secs = match event.timestamp_end.duration_since(event.timestamp_start) {
+            Ok(d) => d.as_secs(),
+            Err(_) => 0,
+        };
+
+        let tx = self.conn.transaction()?;
+
+        tx.execute(
+            "INSERT INTO spikes (event_id, phase, host, tag, resource, path, rule, ts_start, ts_end, duration_secs, peak, threshold)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                event.id,
+                event.phase.as_str(),
+                self.host,
+                event.tag,
+                resource_str,
+                event.path,
+                event.rule,
+                ts_start as i64,
+                ts_end as i64,
+                duration_secs as i64,
+                event.peak_value as f64,
+                event.threshold as f64,
+            ],
+        )?;
+
+        let spike_id = tx.last_insert_rowid();
+
+        for p in &event.top_processes {
+            tx.execute(
+                "INSERT INTO spike_procs (spike_id, pid, name, cpu, ram_bytes)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![spike_id, p.pid, p.name, p.cpu_percent as f64, p.ram_bytes as i64],
+            )?;
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+}
+
+impl EventSink for DbLogger {
+    fn name(&self) -> &'static str {
+        "database"
+    }
+
+    fn handle(&mut self, event: &SpikeEvent) -> Result<(), Box<dyn Error>> {
+        self.log_event(event)
+    }
+}
+
+/// Bring databases created by older versions up to the current schema.
+fn migrate(conn: &Connection) -> Result<(), Box<dyn Error>> {
+    let mut stmt = conn.prepare("SELECT name FROM pragma_table_info('spikes')")?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if !columns.iter().any(|c| c == "event_id") {
+        conn.execute_batch("ALTER TABLE spikes ADD COLUMN event_id TEXT;")?;
+    }
+    if !columns.iter().any(|c| c == "phase") {
+        conn.execute_batch("ALTER TABLE spikes ADD COLUMN phase TEXT;")?;
+    }
+    if !columns.iter().any(|c| c == "host") {
+        conn.execute_batch("ALTER TABLE spikes ADD COLUMN host TEXT;")?;
+    }
+    if !columns.iter().any(|c| c == "tag") {
+        conn.execute_batch("ALTER TABLE spikes ADD COLUMN tag TEXT;")?;
+    }
+    if !columns.iter().any(|c| c == "rule") {
+        conn.execute_batch("ALTER TABLE spikes ADD COLUMN rule TEXT;")?;
+    }
+
+    Ok(())
+}
+
+/// Convert SystemTime to seconds since Unix epoch.
+fn format_time_secs(t: SystemTime) -> u64 {
+    match t.duration_since(UNIX_EPOCH) {
+        Ok(dur) => dur.as_secs(),
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ResourceKind;
+    use crate::metrics::ProcessSample;
+
+    #[test]
+    fn logged_events_query_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spikes.sqlite");
+        let path = path.to_str().unwrap();
+
+        let mut cpu = SpikeEvent::ended(ResourceKind::Cpu, 1_700_000_000, 12, 97.5, 90.0);
+        cpu.top_processes = vec![ProcessSample {
+            pid: 42,
+            name: "stress".to_string(),
+            cpu_percent: 88.0,
+            ram_bytes: 1024,
+        }];
+        let mut disk = SpikeEvent::ended(ResourceKind::Disk, 1_700_000_100, 30, 96.0, 95.0);
+        disk.path = Some("/var".to_string());
+
+        let mut logger = DbLogger::new(path, Some("web1".to_string())).unwrap();
+        logger.log_event(&cpu).unwrap();
+        logger.log_event(&disk).unwrap();
+        drop(logger);
+
+        // Reopening reuses the existing schema.
+        DbLogger::new(path, None).unwrap();
+
+        let conn = Connection::open(path).unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT resource, path, host, ts_start, ts_end, duration_secs, peak, threshold
+                 FROM spikes ORDER BY id",
+            )
+            .unwrap();
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, i64>(4)?,
+                    row.get::<_, i64>(5)?,
+                    row.get::<_, f64>(6)?,
+                    row.get::<_, f64>(7)?,
+                ))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let web1 = Some("web1".to_string());
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    "cpu".to_string(),
+                    None,
+                    web1.clone(),
+                    1_700_000_000,
+                    1_700_000_012,
+                    12,
+                    97.5,
+                    90.0
+                ),
+                (
+                    "disk".to_string(),
+                    Some("/var".to_string()),
+                    web1,
+                    1_700_000_100,
+                    1_700_000_130,
+                    30,
+                    96.0,
+                    95.0
+                ),
+            ]
+        );
+
+        let procs: (i64, u32, String) = conn
+            .query_row("SELECT spike_id, pid, name FROM spike_procs", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(procs, (1, 42, "stress".to_string()));
+    }
+}
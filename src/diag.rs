@@ -0,0 +1,53 @@
+//! `-v` / `-vv` diagnostics on stderr, for finding out why an expected
+//! spike didn't fire. Off by default, so embedding programs and normal
+//! runs see nothing.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// `-v`: spike state transitions and schedule switches.
+pub const DEBUG: u8 = 1;
+/// `-vv`: also the raw values and counter deltas of every sample.
+pub const TRACE: u8 = 2;
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(0);
+
+/// Set the level for the whole process; the CLI passes the `-v` count.
+pub fn set_verbosity(level: u8) {
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+/// Whether messages at `level` are shown.
+pub fn enabled(level: u8) -> bool {
+    VERBOSITY.load(Ordering::Relaxed) >= level
+}
+
+/// Write one diagnostic line, prefixed with its level.
+pub fn write(level: u8, args: fmt::Arguments<'_>) {
+    let prefix = if level >= TRACE {
+        "[monitor-trace]"
+    } else {
+        "[monitor-debug]"
+    };
+    eprintln!("{} {}", prefix, args);
+}
+
+/// `eprintln!` at `-v` and above, formatted only when shown.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::diag::enabled($crate::diag::DEBUG) {
+            $crate::diag::write($crate::diag::DEBUG, format_args!($($arg)*));
+        }
+    };
+}
+
+/// `eprintln!` at `-vv`, formatted only when shown.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if $crate::diag::enabled($crate::diag::TRACE) {
+            $crate::diag::write($crate::diag::TRACE, format_args!($($arg)*));
+        }
+    };
+}
+
+pub(crate) use {debug, trace};
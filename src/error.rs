@@ -0,0 +1,55 @@
+use std::fmt;
+use std::io;
+
+/// Errors returned by the metrics readers, event logging and configuration
+/// parsing.
+#[derive(Debug)]
+pub enum MonitorError {
+    /// A /proc or /sys file could not be read (missing, permission denied...).
+    ProcRead { path: String, source: io::Error },
+    /// A kernel file was read but its contents were not in the expected format.
+    Parse(String),
+    /// Invalid user-supplied configuration.
+    Config(String),
+    /// Any other I/O failure (log file, statvfs...).
+    Io(io::Error),
+}
+
+impl MonitorError {
+    /// Wrap a read failure of `path`.
+    pub fn proc_read(path: impl Into<String>, source: io::Error) -> Self {
+        MonitorError::ProcRead {
+            path: path.into(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for MonitorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MonitorError::ProcRead { path, source } => {
+                write!(f, "Failed to read {}: {}", path, source)
+            }
+            MonitorError::Parse(msg) => write!(f, "{}", msg),
+            MonitorError::Config(msg) => write!(f, "{}", msg),
+            MonitorError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MonitorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MonitorError::ProcRead { source, .. } => Some(source),
+            MonitorError::Io(e) => Some(e),
+            MonitorError::Parse(_) | MonitorError::Config(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for MonitorError {
+    fn from(e: io::Error) -> Self {
+        MonitorError::Io(e)
+    }
+}
@@ -0,0 +1,156 @@
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use crate::analyzer::SpikeEvent;
+use crate::output::event_json_fields;
+use crate::sink::EventSink;
+
+/// How long one client may block an event write before it is dropped,
+/// so a stalled reader can't hold up the monitoring loop.
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Spike event sink serving a Unix stream socket: every connected client
+/// gets each event as a JSON line, the same object as `--output json`.
+///
+/// Clients are accepted on a background thread and may connect or go
+/// away at any time; a client whose write fails is dropped. The socket
+/// file is removed when the sink is dropped.
+pub struct EventSocket {
+    path: String,
+    clients: Arc<Mutex<Vec<UnixStream>>>,
+    precision: usize,
+    host: Option<String>,
+}
+
+impl EventSocket {
+    /// Bind `path` and start accepting clients. A stale socket left by a
+    /// previous run is replaced; any other existing file is an error.
+    pub fn bind(
+        path: &str,
+        precision: usize,
+        host: Option<String>,
+    ) -> Result<Self, Box<dyn Error>> {
+        if let Ok(meta) = fs::symlink_metadata(path) {
+            if !meta.file_type().is_socket() {
+                return Err(format!("Event socket {} exists and is not a socket", path).into());
+            }
+            fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)
+            .map_err(|e| format!("Failed to bind event socket {}: {}", path, e))?;
+        let clients = Arc::new(Mutex::new(Vec::new()));
+
+        let accepted = Arc::clone(&clients);
+        thread::Builder::new()
+            .name("event-socket".into())
+            .spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    if stream.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT)).is_ok() {
+                        lock(&accepted).push(stream);
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            path: path.to_string(),
+            clients,
+            precision,
+            host,
+        })
+    }
+
+    /// Write one event to every client, dropping those that fail.
+    pub fn broadcast(&mut self, event: &SpikeEvent) {
+        let line = format!(
+            "{{\"type\":\"event\",{}}}\n",
+            event_json_fields(event, self.host.as_deref(), self.precision)
+        );
+
+        lock(&self.clients).retain_mut(|client| client.write_all(line.as_bytes()).is_ok());
+    }
+}
+
+impl EventSink for EventSocket {
+    fn name(&self) -> &'static str {
+        "event socket"
+    }
+
+    fn handle(&mut self, event: &SpikeEvent) -> Result<(), Box<dyn Error>> {
+        self.broadcast(event);
+        Ok(())
+    }
+}
+
+impl Drop for EventSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Lock the client list; a poisoned lock only means the accept thread
+/// panicked mid-push, so the list is still usable.
+fn lock(clients: &Mutex<Vec<UnixStream>>) -> MutexGuard<'_, Vec<UnixStream>> {
+    clients
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ResourceKind;
+    use std::io::{BufRead, BufReader};
+    use std::time::Instant;
+
+    /// Wait until the accept thread has taken `n` clients.
+    fn wait_for_clients(socket: &EventSocket, n: usize) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while lock(&socket.clients).len() < n {
+            assert!(Instant::now() < deadline, "clients not accepted");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn clients_receive_events_and_leaving_ones_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("monitor.sock");
+        let path = path.to_str().unwrap();
+        let mut socket = EventSocket::bind(path, 1, Some("web1".into())).unwrap();
+
+        let staying = UnixStream::connect(path).unwrap();
+        let leaving = UnixStream::connect(path).unwrap();
+        wait_for_clients(&socket, 2);
+
+        let event = SpikeEvent::ended(ResourceKind::Cpu, 1_700_000_000, 5, 97.31, 90.0);
+        socket.handle(&event).unwrap();
+        let mut reader = BufReader::new(staying);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["type"], "event");
+        assert_eq!(value["host"], "web1");
+        assert_eq!(value["resource"], "cpu");
+        assert_eq!(value["ts_start"], 1_700_000_000);
+        assert_eq!(value["peak"], 97.3);
+
+        // A client that went away is dropped on the next write, and the
+        // others keep receiving.
+        drop(leaving);
+        socket.handle(&event).unwrap();
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.ends_with("}\n"), "{}", line);
+        assert_eq!(lock(&socket.clients).len(), 1);
+
+        drop(socket);
+        assert!(fs::symlink_metadata(path).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! `--explain`: why a spike did or didn't fire, read from the analyzer
+//! state around each analyzed sample, for tuning thresholds and minimum
+//! durations without guessing.
+
+use std::time::SystemTime;
+
+use crate::analyzer::{resource_value, EventPhase, OngoingSpike, SpikeEvent};
+use crate::config::{ResourceKind, ResourceLabels, Thresholds};
+use crate::metrics::SystemSnapshot;
+
+/// Notes for spikes open after a sample that haven't lasted their
+/// minimum duration yet, e.g. `CPU 88.0% ≥ 80.0% since 2s (need 3s)`.
+pub fn pending_notes(
+    open: &[OngoingSpike],
+    snapshot: &SystemSnapshot,
+    thresholds: &Thresholds,
+    min_spike_duration_secs: u64,
+    labels: &ResourceLabels,
+    precision: usize,
+) -> Vec<String> {
+    let mut notes = Vec::new();
+    for spike in open {
+        let need = thresholds.min_duration_secs(
+            spike.resource,
+            spike.rule.as_deref(),
+            min_spike_duration_secs,
+        );
+        let since = elapsed_secs(spike.timestamp_start, snapshot.timestamp);
+        if since >= need {
+            continue;
+        }
+
+        let value = match (&spike.path, spike.resource) {
+            (Some(path), ResourceKind::Disk) => snapshot
+                .disk_usage
+                .iter()
+                .find(|d| &d.path == path)
+                .map(|d| d.used_percent),
+            _ => resource_value(snapshot, spike.resource),
+        };
+        // The composite tracks a ratio, so there is no single value.
+        let condition = match value {
+            Some(value) => {
+                let unit = labels.unit(spike.resource);
+                format!(
+                    "{:.prec$}{} {} {:.prec$}{}",
+                    value,
+                    unit,
+                    if value >= spike.threshold { "≥" } else { "<" },
+                    spike.threshold,
+                    unit,
+                    prec = precision
+                )
+            }
+            None => "over threshold".to_string(),
+        };
+
+        notes.push(format!(
+            "{} {} since {}s (need {}s)",
+            target(spike, labels),
+            condition,
+            since,
+            need
+        ));
+    }
+    notes
+}
+
+/// Notes for spikes open in `before` and gone from `after` without an
+/// end event among `events`, i.e. closed short of their minimum
+/// duration, e.g. `CPU spike discarded: 2s < 3s min`.
+pub fn discard_notes(
+    before: &[OngoingSpike],
+    after: &[OngoingSpike],
+    events: &[SpikeEvent],
+    now: SystemTime,
+    thresholds: &Thresholds,
+    min_spike_duration_secs: u64,
+    labels: &ResourceLabels,
+) -> Vec<String> {
+    let mut notes = Vec::new();
+    for spike in before {
+        let still_open = after.iter().any(|s| same_spike(s, spike));
+        let ended = events.iter().any(|e| {
+            e.phase == EventPhase::End
+                && e.resource == spike.resource
+                && e.path == spike.path
+                && e.rule == spike.rule
+        });
+        if still_open || ended {
+            continue;
+        }
+
+        let need = thresholds.min_duration_secs(
+            spike.resource,
+            spike.rule.as_deref(),
+            min_spike_duration_secs,
+        );
+        notes.push(format!(
+            "{} spike discarded: {}s < {}s min",
+            target(spike, labels),
+            elapsed_secs(spike.timestamp_start, now),
+            need
+        ));
+    }
+    notes
+}
+
+fn same_spike(a: &OngoingSpike, b: &OngoingSpike) -> bool {
+    a.resource == b.resource && a.path == b.path && a.rule == b.rule
+}
+
+/// `CPU`, `DISK (/var)` or `CPU [burst]`.
+fn target(spike: &OngoingSpike, labels: &ResourceLabels) -> String {
+    let label = labels.label(spike.resource);
+    match (&spike.path, &spike.rule) {
+        (Some(path), _) => format!("{} ({})", label, path),
+        (None, Some(rule)) => format!("{} [{}]", label, rule),
+        (None, None) => label.to_string(),
+    }
+}
+
+fn elapsed_secs(start: SystemTime, now: SystemTime) -> u64 {
+    now.duration_since(start).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::{analyze_snapshot, AnalyzerState};
+
+    #[test]
+    fn too_short_spike_is_explained_then_discarded() {
+        let thresholds = Thresholds {
+            cpu_threshold: Some(80.0),
+            ..Thresholds::default()
+        };
+        let labels = ResourceLabels::default();
+        let mut state = AnalyzerState::new();
+        let mut notes = Vec::new();
+        for (i, cpu) in [10.0, 88.0, 90.0, 10.0].into_iter().enumerate() {
+            let mut snapshot = SystemSnapshot::at_secs(1_700_000_000 + i as u64);
+            snapshot.cpu_usage_percent = cpu;
+            let before = state.ongoing_spikes();
+            let events = analyze_snapshot(&snapshot, &thresholds, 3, &mut state);
+            assert!(events.is_empty());
+            let after = state.ongoing_spikes();
+            notes.extend(discard_notes(
+                &before,
+                &after,
+                &events,
+                snapshot.timestamp,
+                &thresholds,
+                3,
+                &labels,
+            ));
+            notes.extend(pending_notes(&after, &snapshot, &thresholds, 3, &labels, 1));
+        }
+
+        assert_eq!(
+            notes,
+            [
+                "CPU 88.0% ≥ 80.0% since 0s (need 3s)",
+                "CPU 90.0% ≥ 80.0% since 1s (need 3s)",
+                "CPU spike discarded: 2s < 3s min",
+            ]
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! Samples of each resource per value bucket over a run, for "how much
+//! of the time was CPU above 80%" questions that peaks can't answer.
+
+use crate::analyzer::resource_value;
+use crate::config::{BucketEdges, ResourceKind};
+use crate::metrics::SystemSnapshot;
+
+/// One bucket of a histogram. `from` is None for the underflow bucket
+/// below the first edge, `to` for the overflow bucket above the last.
+#[derive(Debug, Clone, Copy)]
+pub struct Bucket {
+    pub from: Option<f32>,
+    pub to: Option<f32>,
+    pub samples: u64,
+}
+
+impl Bucket {
+    /// `0-10`, `<0` or `>100`, followed by `unit`.
+    pub fn label(&self, unit: &str) -> String {
+        match (self.from, self.to) {
+            (Some(from), Some(to)) => format!("{}-{}{}", from, to, unit),
+            (None, Some(to)) => format!("<{}{}", to, unit),
+            (Some(from), None) => format!(">{}{}", from, unit),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+/// Bucket counts of one resource.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    pub resource: ResourceKind,
+    /// Underflow, one bucket per pair of edges, overflow.
+    pub buckets: Vec<Bucket>,
+}
+
+impl Histogram {
+    fn new(resource: ResourceKind, edges: &[f32]) -> Self {
+        let bounds = std::iter::once(None)
+            .chain(edges.iter().copied().map(Some))
+            .chain(std::iter::once(None));
+        let buckets = bounds
+            .clone()
+            .zip(bounds.skip(1))
+            .map(|(from, to)| Bucket {
+                from,
+                to,
+                samples: 0,
+            })
+            .collect();
+
+        Self { resource, buckets }
+    }
+
+    /// Samples counted over all buckets.
+    pub fn samples(&self) -> u64 {
+        self.buckets.iter().map(|b| b.samples).sum()
+    }
+}
+
+/// Per-resource histograms, updated from every analyzed snapshot and
+/// independent of thresholds.
+#[derive(Debug, Clone)]
+pub struct Histograms {
+    edges: Vec<f32>,
+    histograms: Vec<Histogram>,
+}
+
+impl Histograms {
+    pub fn new(edges: &BucketEdges) -> Self {
+        Self {
+            edges: edges.0.clone(),
+            histograms: Vec::new(),
+        }
+    }
+
+    /// Count one snapshot. Resources that were not collected are left out.
+    pub fn update(&mut self, snapshot: &SystemSnapshot) {
+        for resource in ResourceKind::ALL {
+            let Some(value) = resource_value(snapshot, resource) else {
+                continue;
+            };
+
+            let idx = match self.histograms.iter().position(|h| h.resource == resource) {
+                Some(idx) => idx,
+                None => {
+                    self.histograms.push(Histogram::new(resource, &self.edges));
+                    self.histograms.len() - 1
+                }
+            };
+            let bucket = bucket_index(&self.edges, value);
+            self.histograms[idx].buckets[bucket].samples += 1;
+        }
+    }
+
+    /// Histograms in `ResourceKind::ALL` order of first appearance.
+    pub fn histograms(&self) -> &[Histogram] {
+        &self.histograms
+    }
+}
+
+/// Index into `Histogram::buckets` for `value`: 0 below the first edge,
+/// `edges.len()` above the last, otherwise the bucket whose lower edge
+/// is the highest one at or below `value` (the last bucket also takes
+/// its upper edge).
+fn bucket_index(edges: &[f32], value: f32) -> usize {
+    let last = edges.len() - 1;
+    if value > edges[last] {
+        return edges.len();
+    }
+    edges.partition_point(|&e| e <= value).min(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_distribution_lands_in_its_buckets() {
+        let edges = BucketEdges::parse("0,50,80,100").unwrap();
+        let mut histograms = Histograms::new(&edges);
+        for cpu in [10.0, 20.0, 50.0, 79.9, 80.0, 95.0, 100.0, 100.5, -1.0] {
+            let mut snapshot = SystemSnapshot::at_secs(1_700_000_000);
+            snapshot.cpu_usage_percent = cpu;
+            histograms.update(&snapshot);
+        }
+
+        let cpu = histograms
+            .histograms()
+            .iter()
+            .find(|h| h.resource == ResourceKind::Cpu)
+            .unwrap();
+        let counts: Vec<u64> = cpu.buckets.iter().map(|b| b.samples).collect();
+        // Lower edges are inclusive, and the last bucket takes 100 too.
+        assert_eq!(counts, [1, 2, 2, 3, 1]);
+        assert_eq!(cpu.samples(), 9);
+        let labels: Vec<String> = cpu.buckets.iter().map(|b| b.label("%")).collect();
+        assert_eq!(labels, ["<0%", "0-50%", "50-80%", "80-100%", ">100%"]);
+
+        // Temperature was never collected, so it has no histogram.
+        assert!(histograms
+            .histograms()
+            .iter()
+            .all(|h| h.resource != ResourceKind::Temp));
+    }
+}
@@ -0,0 +1,80 @@
+//! Resource spike monitor for Linux, with a reduced Windows backend.
+//!
+//! The `resource_monitor` binary is a thin CLI over this library; the
+//! sampler and analyzer can also be embedded directly:
+//!
+//! ```no_run
+//! use std::thread;
+//! use std::time::Duration;
+//!
+//! use resource_monitor::{
+//!     analyze_snapshot, AnalyzerState, RuntimeConfig, SnapshotSampler, Thresholds,
+//! };
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let config = RuntimeConfig::default();
+//! let interval = Duration::from_millis(config.interval_ms);
+//! let min_spike_duration_secs = config.min_spike_duration_secs;
+//! let mut sampler = SnapshotSampler::new(config);
+//! let thresholds = Thresholds {
+//!     cpu_threshold: Some(90.0),
+//!     ..Default::default()
+//! };
+//! let mut state = AnalyzerState::new();
+//!
+//! loop {
+//!     thread::sleep(interval);
+//!
+//!     let snapshot = sampler.next_sample()?;
+//!     let events = analyze_snapshot(&snapshot, &thresholds, min_spike_duration_secs, &mut state);
+//!
+//!     for event in events {
+//!         println!("{:?} spike peaked at {:.1}", event.resource, event.peak_value);
+//!     }
+//! }
+//! # }
+//! ```
+//!
+//! Metrics readers and the event log return [`MonitorError`], so callers
+//! can tell an unreadable kernel file from an unexpected format or a bad
+//! configuration.
+
+pub mod analyzer;
+pub mod baseline;
+pub mod batch;
+pub mod check;
+pub mod config;
+pub mod db;
+pub mod diag;
+pub mod error;
+#[cfg(unix)]
+pub mod event_socket;
+pub mod explain;
+pub mod histogram;
+pub mod list;
+pub mod live;
+pub mod logging;
+pub mod logs_mode;
+pub mod metrics;
+pub mod monitor;
+pub mod nagios;
+pub mod output;
+pub mod overhead;
+pub mod peaks;
+pub mod progress;
+pub mod replay;
+pub mod sampler;
+pub mod samples_log;
+pub mod schedule;
+pub mod shutdown;
+pub mod sink;
+pub mod sparkline;
+#[cfg(unix)]
+pub mod syslog;
+pub mod throttle;
+pub mod version;
+
+pub use analyzer::{analyze_snapshot, AnalyzerState, SpikeEvent};
+pub use config::{RuntimeConfig, Thresholds};
+pub use error::MonitorError;
+pub use metrics::{SnapshotSampler, SystemSnapshot};
@@ -0,0 +1,100 @@
+use std::error::Error;
+
+use crate::config::{ListConfig, OutputFormat};
+use crate::metrics::{
+    read_block_devices, read_net_interfaces, BlockDevice, NetInterface, DISKSTATS_SECTOR_SIZE,
+};
+use crate::output::json_string;
+
+/// List mode: print the block devices and network interfaces the kernel
+/// reports, with their cumulative counters, so `--io-device` names can
+/// be picked. Lists both when neither `devices` nor `interfaces` is set.
+pub fn run_list(config: ListConfig) -> Result<(), Box<dyn Error>> {
+    let both = !config.devices && !config.interfaces;
+
+    let devices = if config.devices || both {
+        Some(read_block_devices(DISKSTATS_SECTOR_SIZE)?)
+    } else {
+        None
+    };
+    let interfaces = if config.interfaces || both {
+        Some(read_net_interfaces()?)
+    } else {
+        None
+    };
+
+    match config.output_format {
+        OutputFormat::Json => print_list_json(devices.as_deref(), interfaces.as_deref()),
+        OutputFormat::Text | OutputFormat::Table | OutputFormat::OpenMetrics => {
+            if let Some(devices) = &devices {
+                print_devices_text(devices);
+            }
+            if let Some(interfaces) = &interfaces {
+                if devices.is_some() {
+                    println!();
+                }
+                print_interfaces_text(interfaces);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_devices_text(devices: &[BlockDevice]) {
+    println!("Block devices (/proc/diskstats, * = summed by default):");
+    println!("  {:<16} {:>18} {:>18}", "NAME", "READ BYTES", "WRITTEN BYTES");
+    for d in devices {
+        let marker = if d.default { " *" } else { "" };
+        println!(
+            "  {:<16} {:>18} {:>18}{}",
+            d.name, d.read_bytes, d.write_bytes, marker
+        );
+    }
+}
+
+fn print_interfaces_text(interfaces: &[NetInterface]) {
+    println!("Network interfaces (/proc/net/dev):");
+    println!("  {:<16} {:>18} {:>18}", "NAME", "RX BYTES", "TX BYTES");
+    for i in interfaces {
+        println!("  {:<16} {:>18} {:>18}", i.name, i.rx_bytes, i.tx_bytes);
+    }
+}
+
+/// One JSON object with a "devices" and/or "interfaces" array.
+fn print_list_json(devices: Option<&[BlockDevice]>, interfaces: Option<&[NetInterface]>) {
+    let mut fields = Vec::new();
+
+    if let Some(devices) = devices {
+        let items: Vec<String> = devices
+            .iter()
+            .map(|d| {
+                format!(
+                    "{{\"name\":{},\"read_bytes\":{},\"write_bytes\":{},\"default\":{}}}",
+                    json_string(&d.name),
+                    d.read_bytes,
+                    d.write_bytes,
+                    d.default
+                )
+            })
+            .collect();
+        fields.push(format!("\"devices\":[{}]", items.join(",")));
+    }
+
+    if let Some(interfaces) = interfaces {
+        let items: Vec<String> = interfaces
+            .iter()
+            .map(|i| {
+                format!(
+                    "{{\"name\":{},\"rx_bytes\":{},\"tx_bytes\":{}}}",
+                    json_string(&i.name),
+                    i.rx_bytes,
+                    i.tx_bytes
+                )
+            })
+            .collect();
+        fields.push(format!("\"interfaces\":[{}]", items.join(",")));
+    }
+
+    println!("{{{}}}", fields.join(","));
+}
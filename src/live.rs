@@ -1,50 +1,63 @@
 use std::error::Error;
 use std::thread;
-use std::time::Duration;
-
-use crate::analyzer::{analyze_snapshot, AnalyzerState};
-use crate::config::RuntimeConfig;
-use crate::logging::EventLogger;
-use crate::metrics::read_system_snapshot;
-use crate::output::{print_event, print_snapshot};
-
-/// Live mode: monitor until interrupted.
-pub fn run_live(config: RuntimeConfig) -> Result<(), Box<dyn Error>> {
-    let mut analyzer_state = AnalyzerState::new();
-
-    let mut logger = match &config.log_file {
-        Some(path) => Some(EventLogger::new(path)?),
-        None => None,
-    };
-
-    loop {
-        thread::sleep(Duration::from_millis(config.interval_ms));
-
-        let snapshot = match read_system_snapshot(config.top_n_procs) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("[monitor] Error reading snapshot: {e}");
-                continue;
-            }
-        };
-
-        print_snapshot(&snapshot, config.output_format);
-
-        let events = analyze_snapshot(
-            &snapshot,
-            &config.thresholds,
-            config.min_spike_duration_secs,
-            &mut analyzer_state,
-        );
-
-        for event in events {
-            print_event(&event, config.output_format);
+use std::time::{Duration, SystemTime};
+
+use crate::config::{BatchConfig, OutputFormat, RuntimeConfig};
+use crate::error::MonitorError;
+use crate::metrics::{check_platform, SnapshotSampler, SystemSnapshot};
+use crate::monitor::{open_output, run_monitor};
+use crate::output::{print_openmetrics, print_snapshot, print_table_header, SpikeCounters};
+
+/// Time between the priming read of `--once` and the one it prints.
+pub const ONCE_SAMPLE_WINDOW: Duration = Duration::from_millis(250);
+
+/// Live mode: monitor until interrupted, or until `config.limit` when
+/// `--max-samples`/`--max-duration` bound the run
+/// (`BatchLimit::Unbounded` otherwise).
+pub fn run_live(config: BatchConfig) -> Result<(), Box<dyn Error>> {
+    run_monitor(&config, "[monitor]")?;
+    Ok(())
+}
 
-            if let Some(logger) = &mut logger {
-                if let Err(e) = logger.log_event(&event) {
-                    eprintln!("[monitor] Error logging event: {e}");
-                }
+/// `live --once`: print one snapshot right away and return, for scripts
+/// and health checks. CPU and IO rates come from a priming read
+/// `ONCE_SAMPLE_WINDOW` before the printed one; nothing is analyzed,
+/// logged or sent to event sinks, and JSON output has no meta line.
+pub fn run_once(config: RuntimeConfig) -> Result<(), Box<dyn Error>> {
+    let mut out = open_output(config.output_file.as_deref(), config.line_ending)?;
+    let snapshot = read_once(&config)?;
+
+    let host = config.host.as_deref().filter(|_| config.snapshot_host);
+    match config.output_format {
+        OutputFormat::OpenMetrics => {
+            let counters = SpikeCounters::new(SystemTime::now(), false);
+            print_openmetrics(&mut out, &snapshot, &counters, config.precision, host)?;
+        }
+        format => {
+            if format == OutputFormat::Table {
+                print_table_header(&mut out)?;
             }
+            print_snapshot(
+                &mut out,
+                &snapshot,
+                format,
+                config.precision,
+                &config.bands,
+                config.byte_units,
+                host,
+            )?;
         }
     }
+    out.flush()?;
+    Ok(())
+}
+
+/// One snapshot taken now, with CPU and IO rates over
+/// `ONCE_SAMPLE_WINDOW` after a priming read.
+pub fn read_once(config: &RuntimeConfig) -> Result<SystemSnapshot, MonitorError> {
+    check_platform()?;
+    let mut sampler = SnapshotSampler::new(config.clone());
+    sampler.next_sample()?;
+    thread::sleep(ONCE_SAMPLE_WINDOW);
+    sampler.next_sample()
 }
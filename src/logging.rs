@@ -1,97 +1,189 @@
 use std::error::Error;
 use std::fs::OpenOptions;
 use std::io::{BufWriter, Write};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
 
 use crate::analyzer::SpikeEvent;
-use crate::config::ResourceKind;
+use crate::config::LogFlush;
+use crate::error::MonitorError;
+use crate::output::event_json_fields;
+use crate::sink::EventSink;
+
+/// Version of the JSON-lines event schema written by `EventLogger`.
+///
+/// 1: original format without a version field.
+/// 2: adds `"v"` and the optional `"path"` for disk events.
+/// 3: adds the event `"id"`.
+/// 4: adds the event `"phase"` (`"start"` or `"end"`).
+/// 5: adds the optional `"host"`.
+/// 6: adds the `"flap"` phase.
+/// 7: adds the optional `"uptime_start"`.
+/// 8: adds the optional `"tag"`.
+/// 9: adds the `"growth"` phase.
+/// 10: adds the optional `"rule"`.
+pub const LOG_SCHEMA_VERSION: u32 = 10;
 
 /// Simple JSON-lines logger for spike events.
+///
+/// Buffered events are flushed when the logger is dropped, so a clean
+/// shutdown loses nothing whatever the flush policy.
 pub struct EventLogger {
-    writer: BufWriter<std::fs::File>,
+    writer: Box<dyn Write>,
+    /// Decimal places of peak, threshold and process CPU.
+    precision: usize,
+    flush: LogFlush,
+    last_flush: Instant,
+    /// Host name written in every record.
+    host: Option<String>,
 }
 
 impl EventLogger {
     /// Open (or create) the log file in append mode.
-    pub fn new(log_path: &str) -> Result<Self, Box<dyn Error>> {
+    ///
+    /// The log is gzip-compressed when `compress` is set or the path ends
+    /// in `.gz`. Each run appends one gzip member; every flush is a sync
+    /// flush, so the file decompresses up to the last flushed record even
+    /// while the run is still writing.
+    ///
+    /// `flush` trades durability for fewer writes: events after the last
+    /// flush are lost if the process is killed.
+    ///
+    /// Values are rounded to `precision` decimals with the same formatter
+    /// as JSON output. Records carry `host` when set.
+    pub fn new(
+        log_path: &str,
+        compress: bool,
+        precision: usize,
+        flush: LogFlush,
+        host: Option<String>,
+    ) -> Result<Self, MonitorError> {
         let file = OpenOptions::new()
             .create(true)
             .append(true)
             .open(log_path)?;
 
+        let writer: Box<dyn Write> = if compress || log_path.ends_with(".gz") {
+            Box::new(GzEncoder::new(BufWriter::new(file), Compression::default()))
+        } else {
+            Box::new(BufWriter::new(file))
+        };
+
         Ok(Self {
-            writer: BufWriter::new(file),
+            writer,
+            precision,
+            flush,
+            last_flush: Instant::now(),
+            host,
         })
     }
 
-    /// Append one spike event as a JSON line.
-    pub fn log_event(&mut self, event: &SpikeEvent) -> Result<(), Box<dyn Error>> {
-        let resource_str = match event.resource {
-            ResourceKind::Cpu => "cpu",
-            ResourceKind::Ram => "ram",
-            ResourceKind::Io => "io",
-        };
-
-        let ts_start = format_time_secs(event.timestamp_start);
-        let ts_end = format_time_secs(event.timestamp_end);
-        let duration_secs = match event.timestamp_end.duration_since(event.timestamp_start) {
-            Ok(d) => d.as_secs(),
-            Err(_) => 0,
-        };
-
-        // Start JSON object
-        write!(
+    /// Append one spike event as a JSON line: the fields of the JSON
+    /// output event, led by the schema version.
+    pub fn log_event(&mut self, event: &SpikeEvent) -> Result<(), MonitorError> {
+        writeln!(
             self.writer,
-            "{{\"resource\":\"{}\",\"ts_start\":{},\"ts_end\":{},\"duration_secs\":{},\"peak\":{:.4},\"threshold\":{:.4},\"top\":[",
-            resource_str,
-            ts_start,
-            ts_end,
-            duration_secs,
-            event.peak_value,
-            event.threshold,
+            "{{\"v\":{},{}}}",
+            LOG_SCHEMA_VERSION,
+            event_json_fields(event, self.host.as_deref(), self.precision)
         )?;
 
-        // Top processes array
-        for (i, p) in event.top_processes.iter().enumerate() {
-            if i > 0 {
-                write!(self.writer, ",")?;
-            }
-            let name_escaped = escape_string(&p.name);
-            write!(
-                self.writer,
-                "{{\"pid\":{},\"name\":\"{}\",\"cpu\":{:.4},\"ram_bytes\":{}}}",
-                p.pid, name_escaped, p.cpu_percent, p.ram_bytes
-            )?;
+        // Flush to ensure data hits disk (a sync flush when compressed)
+        let due = match self.flush {
+            LogFlush::Always => true,
+            LogFlush::IntervalMs(ms) => self.last_flush.elapsed() >= Duration::from_millis(ms),
+            LogFlush::Never => false,
+        };
+        if due {
+            self.writer.flush()?;
+            self.last_flush = Instant::now();
         }
 
-        // Close JSON object and write newline
-        writeln!(self.writer, "]}}")?;
-
-        // Flush to ensure data hits disk
-        self.writer.flush()?;
-
         Ok(())
     }
 }
 
-/// Convert SystemTime to seconds since Unix epoch.
-fn format_time_secs(t: SystemTime) -> u64 {
-    match t.duration_since(UNIX_EPOCH) {
-        Ok(dur) => dur.as_secs(),
-        Err(_) => 0,
+impl Drop for EventLogger {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+impl EventSink for EventLogger {
+    fn name(&self) -> &'static str {
+        "log file"
+    }
+
+    fn handle(&mut self, event: &SpikeEvent) -> Result<(), Box<dyn Error>> {
+        Ok(self.log_event(event)?)
     }
 }
 
-/// Very simple JSON string escaper.
-fn escape_string(s: &str) -> String {
-    // For now, only escape backslash and double quote.
-    let mut out = String::with_capacity(s.len());
-    for ch in s.chars() {
-        match ch {
-            '\\' => out.push_str("\\\\"),
-            '"' => out.push_str("\\\""),
-            _ => out.push(ch),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{OutputFormat, ResourceKind, ResourceLabels};
+    use crate::metrics::ProcessSample;
+    use crate::output::{print_event, DEFAULT_PRECISION};
+
+    #[test]
+    fn logged_values_match_displayed_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spikes.jsonl");
+        let path = path.to_str().unwrap();
+
+        let mut event = SpikeEvent::ended(ResourceKind::Cpu, 1_700_000_000, 12, 33.333_332, 30.0);
+        event.top_processes = vec![ProcessSample {
+            pid: 42,
+            name: "stress".to_string(),
+            cpu_percent: 66.666_664,
+            ram_bytes: 1024,
+        }];
+
+        let mut logger =
+            EventLogger::new(path, false, DEFAULT_PRECISION, LogFlush::Always, None).unwrap();
+        logger.log_event(&event).unwrap();
+        drop(logger);
+        let logged: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+
+        let mut out = Vec::new();
+        let labels = ResourceLabels::default();
+        print_event(&mut out, &event, OutputFormat::Json, None, &labels, None).unwrap();
+        let shown: serde_json::Value = serde_json::from_slice(&out).unwrap();
+
+        for (field, expected) in [("peak", 33.33), ("threshold", 30.0)] {
+            assert_eq!(logged[field], expected, "{}", field);
+            assert_eq!(shown[field], logged[field], "{}", field);
+        }
+        assert_eq!(logged["top"][0]["cpu"], 66.67);
+        assert_eq!(shown["top"][0]["cpu"], logged["top"][0]["cpu"]);
+    }
+
+    #[test]
+    fn flush_policy_defers_writes_until_shutdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let event = SpikeEvent::ended(ResourceKind::Cpu, 1_700_000_000, 12, 97.5, 90.0);
+        let lines = |path: &str| std::fs::read_to_string(path).unwrap().lines().count();
+
+        for (name, flush, buffered) in [
+            ("always", LogFlush::Always, 3),
+            ("interval", LogFlush::IntervalMs(60_000), 0),
+            ("never", LogFlush::Never, 0),
+        ] {
+            let path = dir.path().join(format!("{}.jsonl", name));
+            let path = path.to_str().unwrap();
+            let mut logger = EventLogger::new(path, false, 2, flush, None).unwrap();
+            for _ in 0..3 {
+                logger.log_event(&event).unwrap();
+            }
+            assert_eq!(lines(path), buffered, "{}", name);
+
+            // Shutdown flushes whatever is still buffered.
+            drop(logger);
+            assert_eq!(lines(path), 3, "{}", name);
         }
     }
-    out
 }
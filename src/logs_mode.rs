@@ -1,15 +1,44 @@
+use std::cmp::Ordering;
 use std::error::Error;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use flate2::read::MultiGzDecoder;
 use serde::Deserialize;
 
-use crate::config::{LogsQuery, OutputFormat, ResourceKind};
+use crate::config::{LogSortKey, LogsQuery, OutputFormat, ResourceKind, ResourceLabels};
+use crate::logging::LOG_SCHEMA_VERSION;
+use crate::output::over_ratio_suffix;
 
 /// Log record as stored in the JSON-lines file.
 #[derive(Debug, Deserialize)]
 struct LogRecord {
+    /// Schema version; records written before versioning are version 1.
+    #[serde(default = "default_version")]
+    v: u32,
+    /// Event id; absent before schema v3.
+    #[serde(default)]
+    id: Option<String>,
+    /// "start" for `--alert-on-start` events, "flap" for
+    /// `--flap-threshold` ones, "growth" for `--ram-growth-mb-per-min`
+    /// ones; absent before schema v4, when every record was an end
+    /// event.
+    #[serde(default)]
+    phase: Option<String>,
+    /// Host that logged the event; absent before schema v5.
+    #[serde(default)]
+    host: Option<String>,
+    /// `--tag` of the run that logged the event; absent before schema v8.
+    #[serde(default)]
+    tag: Option<String>,
     resource: String,
+    #[serde(default)]
+    path: Option<String>,
+    /// `--rule` that fired; absent before schema v10 and for the
+    /// resource's own threshold.
+    #[serde(default)]
+    rule: Option<String>,
     ts_start: u64,
     ts_end: u64,
     duration_secs: u64,
@@ -18,6 +47,19 @@ struct LogRecord {
     top: Vec<LogProc>,
 }
 
+impl LogRecord {
+    /// Peak as a multiple of the threshold; None for flap records and
+    /// thresholds at or below zero.
+    fn over_ratio(&self) -> Option<f64> {
+        (self.phase.as_deref() != Some("flap") && self.threshold > 0.0)
+            .then(|| self.peak / self.threshold)
+    }
+}
+
+fn default_version() -> u32 {
+    1
+}
+
 #[derive(Debug, Deserialize)]
 struct LogProc {
     pid: u32,
@@ -26,99 +68,421 @@ struct LogProc {
     ram_bytes: u64,
 }
 
-/// Read log file and print events with optional filters.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Read the log files and print events with optional filters.
+///
+/// Inputs are the `--log-file` paths in the given order, then the logs
+/// in `--log-dir` sorted by name. Records stream straight to stdout
+/// unless a sort is requested; then the matching records of all inputs
+/// are buffered, sorted, and `limit` applies afterwards. An unreadable
+/// input is reported and skipped; the query fails only if none could be
+/// read.
 pub fn run_logs(query: LogsQuery) -> Result<(), Box<dyn Error>> {
-    let file = File::open(&query.log_file)?;
-    let reader = BufReader::new(file);
+    let mut inputs = query.log_files.clone();
+    if let Some(dir) = &query.log_dir {
+        inputs.extend(log_dir_files(dir)?);
+    }
 
-    let mut printed: usize = 0;
+    if query.repair {
+        return repair_logs(&inputs);
+    }
+
+    // Relative bounds are resolved once, so every record sees the same cutoff.
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let since = query.since.map(|t| t.resolve(now));
+    let until = query.until.map(|t| t.resolve(now));
 
-    for line in reader.lines() {
-        let line = line?;
+    let mut printed: usize = 0;
+    let mut warned_newer = false;
+    let mut sorted: Vec<(LogRecord, String)> = Vec::new();
+    let mut readable = 0;
+    let mut last_error: Option<Box<dyn Error>> = None;
 
-        let record: LogRecord = match serde_json::from_str(&line) {
+    'inputs: for path in &inputs {
+        let (mut reader, compressed) = match open_log(path) {
             Ok(r) => r,
             Err(e) => {
-                eprintln!("[monitor-logs] Failed to parse log line: {e}");
+                eprintln!("[monitor-logs] Skipping {}: {}", path, e);
+                last_error = Some(e.into());
                 continue;
             }
         };
+        readable += 1;
 
-        // Resource filter
-        if let Some(kind) = query.resource_filter {
-            if !resource_matches(&record, kind) {
-                continue;
+        loop {
+            let mut line = String::new();
+            match reader.read_line(&mut line) {
+                Ok(0) => break,
+                Ok(_) => {}
+                // A log still being written has no gzip trailer yet.
+                Err(e) if compressed && e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => {
+                    eprintln!("[monitor-logs] Stopped reading {}: {}", path, e);
+                    break;
+                }
             }
-        }
 
-        // Time filters
-        if let Some(since) = query.since {
-            if record.ts_start < since {
+            // Only the last line of a file can lack its newline.
+            let complete = line.ends_with('\n');
+            let line = line.trim_end_matches(['\n', '\r']).to_string();
+
+            // Blank lines and `#` comments, e.g. separators between
+            // concatenated logs, are not records.
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
                 continue;
             }
-        }
-        if let Some(until) = query.until {
-            if record.ts_start > until {
+
+            let record: LogRecord = match serde_json::from_str(&line) {
+                Ok(r) => r,
+                Err(_) if !complete => {
+                    eprintln!(
+                        "[monitor-logs] Ignoring truncated last line in {} (see --repair)",
+                        path
+                    );
+                    break;
+                }
+                Err(e) => {
+                    eprintln!("[monitor-logs] Failed to parse log line in {}: {e}", path);
+                    continue;
+                }
+            };
+
+            // Fields unknown to this reader are ignored; warn once so the user
+            // knows the output may be incomplete.
+            if record.v > LOG_SCHEMA_VERSION && !warned_newer {
+                eprintln!(
+                    "[monitor-logs] Log uses schema v{} (this build reads up to v{}); newer fields are ignored.",
+                    record.v, LOG_SCHEMA_VERSION
+                );
+                warned_newer = true;
+            }
+
+            // Resource filter
+            if let Some(kind) = query.resource_filter {
+                if !resource_matches(&record, kind) {
+                    continue;
+                }
+            }
+
+            // Id filter
+            if let Some(id) = &query.id {
+                if record.id.as_deref() != Some(id.as_str()) {
+                    continue;
+                }
+            }
+
+            // Tag filter
+            if let Some(tag) = &query.tag {
+                if record.tag.as_deref() != Some(tag.as_str()) {
+                    continue;
+                }
+            }
+
+            // Severity filter
+            if let Some(min) = query.min_over_ratio {
+                if !record.over_ratio().is_some_and(|r| r >= f64::from(min)) {
+                    continue;
+                }
+            }
+
+            // Process filters
+            if let Some(name) = &query.proc_name {
+                if !record.top.iter().any(|p| p.name.contains(name.as_str())) {
+                    continue;
+                }
+            }
+            if let Some(re) = &query.proc_regex {
+                if !record.top.iter().any(|p| re.is_match(&p.name)) {
+                    continue;
+                }
+            }
+
+            // Time filters
+            if let Some(since) = since {
+                if record.ts_start < since {
+                    continue;
+                }
+            }
+            if let Some(until) = until {
+                if record.ts_start > until {
+                    continue;
+                }
+            }
+
+            if query.sort.is_some() {
+                sorted.push((record, line));
                 continue;
             }
+
+            // Limit
+            if let Some(max) = query.limit {
+                if printed >= max {
+                    break 'inputs;
+                }
+            }
+
+            print_record(&record, &line, query.output_format, &query.labels);
+            printed += 1;
         }
+    }
 
-        // Limit
-        if let Some(max) = query.limit {
-            if printed >= max {
-                break;
+    if readable == 0 {
+        if let Some(e) = last_error {
+            return Err(e);
+        }
+    }
+
+    if let Some(key) = query.sort {
+        // Stable sort: ties keep file order in both directions.
+        sorted.sort_by(|(a, _), (b, _)| {
+            if query.descending {
+                compare_records(b, a, key)
+            } else {
+                compare_records(a, b, key)
             }
+        });
+
+        let max = query.limit.unwrap_or(usize::MAX);
+        for (record, line) in sorted.iter().take(max) {
+            print_record(record, line, query.output_format, &query.labels);
         }
+    }
 
-        match query.output_format {
-            OutputFormat::Json => {
-                println!("{}", line);
+    Ok(())
+}
+
+/// Repair each log in `paths` in place; see `repair_log`.
+fn repair_logs(paths: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut failed = 0;
+    for path in paths {
+        match repair_log(path) {
+            Ok(report) => eprintln!("[monitor-logs] {}: {}", path, report),
+            Err(e) => {
+                eprintln!("[monitor-logs] Cannot repair {}: {}", path, e);
+                failed += 1;
             }
-            OutputFormat::Text => {
-                print_record_text(&record);
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{} of {} logs could not be repaired", failed, paths.len()).into());
+    }
+    Ok(())
+}
+
+/// Outcome of repairing one log file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepairReport {
+    /// Every line was complete; the file was not touched.
+    Clean { lines: usize },
+    /// The last line was valid but lacked its newline; one was appended.
+    NewlineAdded { lines: usize },
+    /// The last line was cut off mid-record and was removed.
+    TruncatedTail { lines: usize, dropped_bytes: u64 },
+}
+
+impl std::fmt::Display for RepairReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RepairReport::Clean { lines } => write!(f, "clean, {} lines", lines),
+            RepairReport::NewlineAdded { lines } => {
+                write!(f, "added missing final newline, {} lines", lines)
             }
+            RepairReport::TruncatedTail {
+                lines,
+                dropped_bytes,
+            } => write!(
+                f,
+                "dropped truncated last line ({} bytes), {} lines salvaged",
+                dropped_bytes, lines
+            ),
         }
+    }
+}
 
-        printed += 1;
+/// Fix a plain log whose writer was killed mid-line.
+///
+/// Only the tail can be damaged by an interrupted append, so the file is
+/// repaired in place: an unparseable last line without a newline is cut
+/// off, a parseable one gets its newline. Earlier lines are never
+/// rewritten. Compressed logs are refused.
+pub fn repair_log(path: &str) -> Result<RepairReport, Box<dyn Error>> {
+    let contents = fs::read(path)?;
+    if contents.starts_with(&GZIP_MAGIC) {
+        return Err("compressed logs cannot be repaired".into());
     }
 
-    Ok(())
+    let tail_start = match contents.iter().rposition(|&b| b == b'\n') {
+        Some(i) => i + 1,
+        None => 0,
+    };
+    let complete_lines = contents[..tail_start]
+        .split(|&b| b == b'\n')
+        .filter(|l| !l.is_empty())
+        .count();
+
+    let tail = &contents[tail_start..];
+    if tail.is_empty() {
+        return Ok(RepairReport::Clean {
+            lines: complete_lines,
+        });
+    }
+
+    if serde_json::from_slice::<LogRecord>(tail).is_ok() {
+        OpenOptions::new().append(true).open(path)?.write_all(b"\n")?;
+        return Ok(RepairReport::NewlineAdded {
+            lines: complete_lines + 1,
+        });
+    }
+
+    OpenOptions::new()
+        .write(true)
+        .open(path)?
+        .set_len(tail_start as u64)?;
+    Ok(RepairReport::TruncatedTail {
+        lines: complete_lines,
+        dropped_bytes: tail.len() as u64,
+    })
 }
 
-fn resource_matches(record: & LogRecord, kind: ResourceKind) -> bool {
-    match kind {
-        ResourceKind::Cpu => record.resource == "cpu",
-        ResourceKind::Ram => record.resource == "ram",
-        ResourceKind::Io => record.resource == "io",
+/// Open a log file, transparently decompressing gzip.
+///
+/// Returns the reader and whether the file is compressed.
+fn open_log(path: &str) -> std::io::Result<(Box<dyn BufRead>, bool)> {
+    let mut file = BufReader::new(File::open(path)?);
+
+    // Detect gzip by magic bytes so --log-compress files without a .gz
+    // suffix are read transparently too.
+    let compressed = file.fill_buf()?.starts_with(&GZIP_MAGIC);
+    let reader: Box<dyn BufRead> = if compressed {
+        Box::new(BufReader::new(MultiGzDecoder::new(file)))
+    } else {
+        Box::new(file)
+    };
+
+    Ok((reader, compressed))
+}
+
+/// `*.jsonl` and `*.jsonl.gz` files in `dir`, sorted by name.
+fn log_dir_files(dir: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let read_error = |e| format!("Failed to read log directory {}: {}", dir, e);
+    let entries = fs::read_dir(dir).map_err(read_error)?;
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(read_error)?.path();
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if path.is_file() && (name.ends_with(".jsonl") || name.ends_with(".jsonl.gz")) {
+            files.push(path.to_string_lossy().into_owned());
+        }
     }
+
+    files.sort();
+    Ok(files)
 }
 
-fn print_record_text(r: &LogRecord) {
-    let resource = match r.resource.as_str() {
-        "cpu" => "CPU",
-        "ram" => "RAM",
-        "io" => "IO",
-        _ => "UNKNOWN",
+fn compare_records(a: &LogRecord, b: &LogRecord, key: LogSortKey) -> Ordering {
+    match key {
+        LogSortKey::Peak => a.peak.total_cmp(&b.peak),
+        LogSortKey::Duration => a.duration_secs.cmp(&b.duration_secs),
+        LogSortKey::TsStart => a.ts_start.cmp(&b.ts_start),
+    }
+}
+
+/// Print one record; JSON output passes the original line through.
+fn print_record(record: &LogRecord, line: &str, format: OutputFormat, labels: &ResourceLabels) {
+    match format {
+        OutputFormat::Json => {
+            println!("{}", line);
+        }
+        OutputFormat::Text | OutputFormat::Table | OutputFormat::OpenMetrics => {
+            print_record_text(record, labels);
+        }
+    }
+}
+
+fn resource_matches(record: &LogRecord, kind: ResourceKind) -> bool {
+    record.resource == kind.name()
+}
+
+fn print_record_text(r: &LogRecord, labels: &ResourceLabels) {
+    let (resource, unit) = match ResourceKind::from_name(&r.resource) {
+        Some(kind) => (labels.label(kind), labels.unit(kind)),
+        None => ("UNKNOWN", ""),
+    };
+
+    let target = match (&r.path, &r.rule) {
+        (Some(p), _) => format!(" ({})", p),
+        (None, Some(rule)) => format!(" [{}]", rule),
+        (None, None) => String::new(),
+    };
+
+    let id = match &r.id {
+        Some(id) => format!(" id={}", id),
+        None => String::new(),
+    };
+
+    let host = match &r.host {
+        Some(h) => format!(" host={}", h),
+        None => String::new(),
     };
 
-    let unit = match r.resource.as_str() {
-        "cpu" => "%",
-        "ram" => "%",
-        "io" => "MB/s",
-        _ => "",
+    let tag = match &r.tag {
+        Some(t) => format!(" tag={}", t),
+        None => String::new(),
     };
 
-    println!(
-        "[LOG] {} spike: start={} end={} duration={}s peak={:.2}{} (threshold={:.2}{})",
-        resource,
-        r.ts_start,
-        r.ts_end,
-        r.duration_secs,
-        r.peak,
-        unit,
-        r.threshold,
-        unit,
-    );
+    if r.phase.as_deref() == Some("flap") {
+        println!(
+            "[LOG] {}{} flapping:{}{}{} spikes={} start={} end={} (threshold={})",
+            resource, target, id, host, tag, r.peak, r.ts_start, r.ts_end, r.threshold,
+        );
+        return;
+    }
+
+    if r.phase.as_deref() == Some("growth") {
+        println!(
+            "[LOG] {}{} growing:{}{}{} rate={:.2}MB/min start={} end={} (threshold={:.2}MB/min){}",
+            resource,
+            target,
+            id,
+            host,
+            tag,
+            r.peak,
+            r.ts_start,
+            r.ts_end,
+            r.threshold,
+            over_ratio_suffix(r.over_ratio().map(|r| r as f32)),
+        );
+    } else {
+        let phase = match r.phase.as_deref() {
+            Some("start") => " started",
+            _ => "",
+        };
+
+        println!(
+            "[LOG] {}{} spike{}:{}{}{} start={} end={} duration={}s peak={:.2}{} (threshold={:.2}{}){}",
+            resource,
+            target,
+            phase,
+            id,
+            host,
+            tag,
+            r.ts_start,
+            r.ts_end,
+            r.duration_secs,
+            r.peak,
+            unit,
+            r.threshold,
+            unit,
+            over_ratio_suffix(r.over_ratio().map(|r| r as f32)),
+        );
+    }
 
     if !r.top.is_empty() {
         println!("      Top processes at peak (from log):");
@@ -130,3 +494,118 @@ fn print_record_text(r: &LogRecord) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::SpikeEvent;
+    use crate::config::LogFlush;
+    use crate::logging::EventLogger;
+
+    #[test]
+    fn schema_version_is_written_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spikes.jsonl");
+        let path = path.to_str().unwrap();
+
+        let event = SpikeEvent::ended(ResourceKind::Cpu, 1_700_000_000, 12, 97.5, 90.0);
+        let mut logger = EventLogger::new(path, false, 2, LogFlush::Always, None).unwrap();
+        logger.log_event(&event).unwrap();
+        drop(logger);
+
+        let line = fs::read_to_string(path).unwrap();
+        assert!(line.starts_with(&format!("{{\"v\":{},", LOG_SCHEMA_VERSION)));
+        let record: LogRecord = serde_json::from_str(line.trim_end()).unwrap();
+        assert_eq!(record.v, LOG_SCHEMA_VERSION);
+        assert_eq!(record.resource, "cpu");
+        assert_eq!(record.duration_secs, 12);
+    }
+
+    #[test]
+    fn unversioned_record_is_version_1() {
+        let line = r#"{"resource":"cpu","ts_start":1700000000,"ts_end":1700000012,"duration_secs":12,"peak":97.5,"threshold":90.0,"top":[]}"#;
+        let record: LogRecord = serde_json::from_str(line).unwrap();
+        assert_eq!(record.v, 1);
+        assert_eq!(record.id, None);
+        assert_eq!(record.phase, None);
+    }
+
+    /// Records of `path`, stopping like `run_logs` at the unfinished
+    /// gzip member of a log still being written.
+    fn read_records(path: &str) -> Vec<LogRecord> {
+        let (reader, _) = open_log(path).unwrap();
+        reader
+            .lines()
+            .map_while(|line| match line {
+                Ok(line) => Some(serde_json::from_str(&line).unwrap()),
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => None,
+                Err(e) => panic!("{}", e),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn gzip_log_round_trips_mid_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spikes.jsonl.gz");
+        let path = path.to_str().unwrap();
+
+        let cpu = SpikeEvent::ended(ResourceKind::Cpu, 1_700_000_000, 12, 97.5, 90.0);
+        let ram = SpikeEvent::ended(ResourceKind::Ram, 1_700_000_100, 5, 95.0, 90.0);
+
+        let mut logger = EventLogger::new(path, false, 2, LogFlush::Always, None).unwrap();
+        logger.log_event(&cpu).unwrap();
+        logger.log_event(&ram).unwrap();
+
+        // Readable while the run is still writing.
+        let (_, compressed) = open_log(path).unwrap();
+        assert!(compressed);
+        let records = read_records(path);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].resource, "cpu");
+        assert_eq!(records[1].resource, "ram");
+        drop(logger);
+
+        // A second run appends another gzip member.
+        let mut logger = EventLogger::new(path, false, 2, LogFlush::Always, None).unwrap();
+        logger.log_event(&cpu).unwrap();
+        drop(logger);
+        assert_eq!(read_records(path).len(), 3);
+    }
+
+    #[test]
+    fn repair_drops_truncated_last_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spikes.jsonl");
+        let path = path.to_str().unwrap();
+
+        let mut logger = EventLogger::new(path, false, 2, LogFlush::Always, None).unwrap();
+        for start in [1_700_000_000, 1_700_000_100] {
+            let event = SpikeEvent::ended(ResourceKind::Cpu, start, 12, 97.5, 90.0);
+            logger.log_event(&event).unwrap();
+        }
+        drop(logger);
+        let complete = fs::read_to_string(path).unwrap();
+
+        // Killed mid-append.
+        fs::write(path, format!("{}{{\"v\":2,\"id\":\"17", complete)).unwrap();
+        let report = repair_log(path).unwrap();
+        assert_eq!(
+            report,
+            RepairReport::TruncatedTail {
+                lines: 2,
+                dropped_bytes: 15
+            }
+        );
+        assert_eq!(fs::read_to_string(path).unwrap(), complete);
+        assert_eq!(repair_log(path).unwrap(), RepairReport::Clean { lines: 2 });
+
+        // A whole record missing only its newline is kept.
+        fs::write(path, complete.trim_end()).unwrap();
+        assert_eq!(
+            repair_log(path).unwrap(),
+            RepairReport::NewlineAdded { lines: 2 }
+        );
+        assert_eq!(fs::read_to_string(path).unwrap(), complete);
+    }
+}
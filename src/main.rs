@@ -1,19 +1,26 @@
-mod analyzer;
-mod batch;
-mod config;
-mod logging;
-mod metrics;
-mod output;
-mod live;
-mod logs_mode;
-
-use clap::{Parser, Subcommand, CommandFactory};
-use crate::batch::run_batch;
-use crate::config::{
-    BatchConfig, BatchLimit, LogsQuery, OutputFormat, ResourceKind, Thresholds, RuntimeConfig,
+use std::error::Error;
+use std::process::ExitCode;
+
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use regex::Regex;
+use resource_monitor::batch::run_batch;
+use resource_monitor::check::run_check;
+use resource_monitor::diag;
+use resource_monitor::error::MonitorError;
+use resource_monitor::config::{
+    Bands, BatchConfig, BucketEdges, BatchLimit, ByteUnits, CheckConfig, Collectors, CompositeRule, ListConfig, ConfirmPolicy, FlapPolicy, GrowthPolicy, LineEnding, LogFlush, LogSortKey, LogsQuery, NagiosConfig, NamedRule, OutputFormat, ProcFilter, ResourceKind, SamplesLogFormat, ScheduleRule,
+    ResourceLabels, SeverityBands, SyslogConfig, SyslogFacility, ThresholdSpec, Thresholds, TimeSpec, RuntimeConfig, VersionConfig, ZScoreConfig,
+    DEFAULT_CMDLINE_MAX_LEN, DEFAULT_FLAP_WINDOW_SECS, DEFAULT_HISTOGRAM_EDGES, DEFAULT_RAM_GROWTH_WINDOW_SECS, DEFAULT_SPARKLINE_WIDTH, DEFAULT_STATE_MAX_AGE_SECS, MIN_INTERVAL_MS,
 };
-use crate::live::run_live;
-use crate::logs_mode::run_logs;
+use resource_monitor::list::run_list;
+use resource_monitor::live::{run_live, run_once};
+use resource_monitor::logs_mode::run_logs;
+use resource_monitor::nagios::run_nagios;
+use resource_monitor::replay::run_replay;
+use resource_monitor::metrics::{check_cgroup, check_platform, read_hostname, DISKSTATS_SECTOR_SIZE};
+use resource_monitor::shutdown;
+use resource_monitor::version::run_version;
 
 /// CLI entry point.
 #[derive(Parser, Debug)]
@@ -30,6 +37,495 @@ use crate::logs_mode::run_logs;
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+
+    /// Disable colored output (also disabled when stdout is not a terminal).
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Diagnostics on stderr: -v for spike state transitions, -vv also for the raw values of every sample.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+}
+
+/// Warn/crit bands for coloring snapshot values.
+#[derive(Args, Debug)]
+struct BandArgs {
+    /// CPU warn level in percent (snapshot value shown yellow).
+    #[arg(long, env = "MONITOR_CPU_WARN")]
+    cpu_warn: Option<f32>,
+
+    /// CPU crit level in percent (snapshot value shown red).
+    #[arg(long, env = "MONITOR_CPU_CRIT")]
+    cpu_crit: Option<f32>,
+
+    /// RAM warn level in percent.
+    #[arg(long, env = "MONITOR_RAM_WARN")]
+    ram_warn: Option<f32>,
+
+    /// RAM crit level in percent.
+    #[arg(long, env = "MONITOR_RAM_CRIT")]
+    ram_crit: Option<f32>,
+
+    /// IO warn level in MB/s (read + write).
+    #[arg(long, env = "MONITOR_IO_WARN")]
+    io_warn: Option<f32>,
+
+    /// IO crit level in MB/s (read + write).
+    #[arg(long, env = "MONITOR_IO_CRIT")]
+    io_crit: Option<f32>,
+
+    /// CPU temperature warn level in °C.
+    #[arg(long, env = "MONITOR_TEMP_WARN")]
+    temp_warn: Option<f32>,
+
+    /// CPU temperature crit level in °C.
+    #[arg(long, env = "MONITOR_TEMP_CRIT")]
+    temp_crit: Option<f32>,
+}
+
+impl BandArgs {
+    fn into_bands(self) -> SeverityBands {
+        SeverityBands {
+            cpu: Bands { warn: self.cpu_warn, crit: self.cpu_crit },
+            ram: Bands { warn: self.ram_warn, crit: self.ram_crit },
+            io: Bands { warn: self.io_warn, crit: self.io_crit },
+            temp: Bands { warn: self.temp_warn, crit: self.temp_crit },
+        }
+    }
+}
+
+/// Thresholds and detection options of the commands that analyze
+/// snapshots (live, batch and replay).
+#[derive(Args, Debug)]
+struct DetectArgs {
+    /// CPU spike threshold in percent (0-100), or a multiple of the warmup mean like 2x (also for the RAM, IO, steal, iowait, temp and fd thresholds).
+    #[arg(long, env = "MONITOR_CPU_THRESHOLD", value_parser = ThresholdSpec::parse)]
+    cpu_threshold: Option<ThresholdSpec>,
+
+    /// RAM spike threshold in percent (0-100).
+    #[arg(long, env = "MONITOR_RAM_THRESHOLD", value_parser = ThresholdSpec::parse)]
+    ram_threshold: Option<ThresholdSpec>,
+
+    /// IO spike threshold in MB/s (disk read + write).
+    #[arg(long, env = "MONITOR_IO_THRESHOLD", value_parser = ThresholdSpec::parse)]
+    io_threshold: Option<ThresholdSpec>,
+
+    /// IO read spike threshold in MB/s (separate events from --io-threshold).
+    #[arg(long, env = "MONITOR_IO_READ_THRESHOLD", value_parser = ThresholdSpec::parse)]
+    io_read_threshold: Option<ThresholdSpec>,
+
+    /// IO write spike threshold in MB/s (separate events from --io-threshold).
+    #[arg(long, env = "MONITOR_IO_WRITE_THRESHOLD", value_parser = ThresholdSpec::parse)]
+    io_write_threshold: Option<ThresholdSpec>,
+
+    /// CPU steal time spike threshold in percent.
+    #[arg(long, env = "MONITOR_STEAL_THRESHOLD", value_parser = ThresholdSpec::parse)]
+    steal_threshold: Option<ThresholdSpec>,
+
+    /// CPU iowait spike threshold in percent.
+    #[arg(long, env = "MONITOR_IOWAIT_THRESHOLD", value_parser = ThresholdSpec::parse)]
+    iowait_threshold: Option<ThresholdSpec>,
+
+    /// CPU temperature spike threshold in °C (implies --temp in live and batch).
+    #[arg(long, env = "MONITOR_TEMP_THRESHOLD", value_parser = ThresholdSpec::parse)]
+    temp_threshold: Option<ThresholdSpec>,
+
+    /// Battery discharge rate spike threshold in watts (implies --power in live and batch).
+    #[arg(long, env = "MONITOR_POWER_THRESHOLD", value_parser = ThresholdSpec::parse)]
+    power_threshold: Option<ThresholdSpec>,
+
+    /// Open file handles spike threshold in percent of the system limit (implies --fd in live and batch).
+    #[arg(long, env = "MONITOR_FD_THRESHOLD", value_parser = ThresholdSpec::parse)]
+    fd_threshold: Option<ThresholdSpec>,
+
+    /// Process count spike threshold (implies --proc-count in live and batch).
+    #[arg(long, env = "MONITOR_PROC_COUNT_THRESHOLD")]
+    proc_count_threshold: Option<u64>,
+
+    /// Filesystem usage spike threshold in percent for --watch-path mounts (the recorded ones in replay).
+    #[arg(long, env = "MONITOR_DISK_THRESHOLD")]
+    disk_threshold: Option<f32>,
+
+    /// Composite spike rule, e.g. "cpu>80 && ram>90" (all terms must hold).
+    #[arg(long, env = "MONITOR_COMPOSITE")]
+    composite: Option<String>,
+
+    /// Detection mode: threshold (static limits) or zscore (adaptive baseline).
+    #[arg(long, env = "MONITOR_DETECT_MODE", default_value = "threshold", value_parser = ["threshold", "zscore"])]
+    detect_mode: String,
+
+    /// Z-score mode: spike when value > mean + k·stddev.
+    #[arg(long, env = "MONITOR_ZSCORE_K", default_value_t = 3.0)]
+    zscore_k: f32,
+
+    /// Z-score mode: samples used to learn the baseline before alerting.
+    #[arg(long, env = "MONITOR_ZSCORE_WARMUP", default_value_t = 30)]
+    zscore_warmup: u64,
+
+    /// Minimum spike duration in seconds.
+    #[arg(long, env = "MONITOR_MIN_SPIKE_DURATION_SECS", default_value_t = 3)]
+    min_spike_duration_secs: u64,
+
+    /// Print but don't analyze the first N samples, so rates and baselines settle.
+    #[arg(long, env = "MONITOR_WARMUP_SAMPLES", default_value_t = 0)]
+    warmup_samples: u64,
+
+    /// Spike while at least N of the last M samples are over the threshold, e.g. 8/10.
+    #[arg(long, env = "MONITOR_CONFIRM", value_parser = ConfirmPolicy::parse)]
+    confirm: Option<ConfirmPolicy>,
+
+    /// Also emit an event when a spike reaches its minimum duration, before it ends.
+    #[arg(long, env = "MONITOR_ALERT_ON_START")]
+    alert_on_start: bool,
+
+    /// Emit a flap event when a resource spikes N times within --flap-window-secs.
+    #[arg(long, env = "MONITOR_FLAP_THRESHOLD", value_parser = clap::value_parser!(u64).range(1..))]
+    flap_threshold: Option<u64>,
+
+    /// Window in seconds that --flap-threshold counts spikes over.
+    #[arg(long, env = "MONITOR_FLAP_WINDOW_SECS", default_value_t = DEFAULT_FLAP_WINDOW_SECS)]
+    flap_window_secs: u64,
+
+    /// Ignore spikes that start within this many seconds of boot (cold caches, services starting).
+    #[arg(long, env = "MONITOR_IGNORE_FIRST_UPTIME_SECS")]
+    ignore_first_uptime_secs: Option<u64>,
+
+    /// Thresholds for a time of day, e.g. "09:00-18:00 cpu=70,ram=85" (local time); repeatable, first match wins.
+    #[arg(long = "schedule", value_parser = ScheduleRule::parse)]
+    schedule: Vec<ScheduleRule>,
+
+    /// Extra named threshold with its own spikes, e.g. "burst:cpu=95:2" (name:resource=threshold[:min_secs]); repeatable.
+    #[arg(long = "rule", value_parser = NamedRule::parse)]
+    rules: Vec<NamedRule>,
+
+    /// Minimum CPU spike duration in seconds (overrides the global value).
+    #[arg(long, env = "MONITOR_CPU_MIN_DURATION")]
+    cpu_min_duration: Option<u64>,
+
+    /// Minimum RAM spike duration in seconds (overrides the global value).
+    #[arg(long, env = "MONITOR_RAM_MIN_DURATION")]
+    ram_min_duration: Option<u64>,
+
+    /// Minimum IO spike duration in seconds (overrides the global value).
+    #[arg(long, env = "MONITOR_IO_MIN_DURATION")]
+    io_min_duration: Option<u64>,
+}
+
+/// How snapshots and events are printed.
+#[derive(Args, Debug)]
+struct OutputArgs {
+    /// Output format: text, json, table or openmetrics.
+    #[arg(long = "output", env = "MONITOR_OUTPUT", default_value = "text", value_parser = parse_stream_format)]
+    format: OutputFormat,
+
+    /// Append snapshots and events to this file instead of stdout (uncolored).
+    #[arg(long, env = "MONITOR_OUTPUT_FILE")]
+    output_file: Option<String>,
+
+    /// Line ending of snapshots and events: lf or crlf.
+    #[arg(long, env = "MONITOR_LINE_ENDING", default_value = "lf", value_parser = parse_line_ending)]
+    line_ending: LineEnding,
+
+    /// With --output openmetrics, attach each resource's latest spike to its counter as an exemplar.
+    #[arg(long, env = "MONITOR_EXEMPLARS")]
+    exemplars: bool,
+
+    #[command(flatten)]
+    bands: BandArgs,
+
+    /// Decimal places for values in text/JSON output and the event log.
+    #[arg(long, env = "MONITOR_PRECISION")]
+    precision: Option<usize>,
+
+    /// Show IO rates as plain B/s in text/table output instead of KB/s, MB/s, ...
+    #[arg(long, env = "MONITOR_RAW_BYTES")]
+    raw_bytes: bool,
+
+    /// Scale IO rates by 1024 (KiB/s, MiB/s, ...) instead of 1000.
+    #[arg(long, env = "MONITOR_BINARY_UNITS")]
+    binary_units: bool,
+
+    /// Text label of a resource in event lines and syslog, e.g. cpu=Processor; repeatable.
+    #[arg(long = "label", value_parser = ResourceLabels::parse_override)]
+    labels: Vec<(ResourceKind, String)>,
+
+    /// Unit suffix of a resource's event values, e.g. io=MBps; repeatable.
+    #[arg(long = "unit", value_parser = ResourceLabels::parse_override)]
+    units: Vec<(ResourceKind, String)>,
+
+    /// Host name tagged on logged and JSON events (default: the system host name; none in replay).
+    #[arg(long, env = "MONITOR_HOST_LABEL")]
+    host_label: Option<String>,
+
+    /// Free-form note attached to every spike event, e.g. deploy-1234 (find them with logs --tag).
+    #[arg(long, env = "MONITOR_TAG")]
+    tag: Option<String>,
+
+    /// Only print spike events, not per-sample lines.
+    #[arg(long, env = "MONITOR_QUIET")]
+    quiet: bool,
+}
+
+/// Where spike events are stored.
+#[derive(Args, Debug)]
+struct LogArgs {
+    /// Optional log file path for spike events.
+    #[arg(long, env = "MONITOR_LOG_FILE")]
+    log_file: Option<String>,
+
+    /// Gzip-compress the log file (implied by a .gz log file suffix).
+    #[arg(long, env = "MONITOR_LOG_COMPRESS")]
+    log_compress: bool,
+
+    /// If the log file can't be opened, warn and run without it instead of exiting.
+    #[arg(long, env = "MONITOR_LOG_BEST_EFFORT")]
+    log_best_effort: bool,
+
+    /// Optional SQLite database path for spike events.
+    #[arg(long = "db", env = "MONITOR_DB")]
+    db_file: Option<String>,
+}
+
+/// Options shared by live, batch and replay.
+#[derive(Args, Debug)]
+struct MonitorArgs {
+    #[command(flatten)]
+    detect: DetectArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    #[command(flatten)]
+    log: LogArgs,
+}
+
+/// Options of the commands that sample the running system (live and
+/// batch); replayed snapshots carry no RAM bytes or process list.
+#[derive(Args, Debug)]
+struct SystemArgs {
+    /// Sampling interval in milliseconds (at least 10; 0 is rejected).
+    #[arg(long, env = "MONITOR_INTERVAL_MS", default_value_t = 1000, value_parser = parse_interval_ms)]
+    interval_ms: u64,
+
+    /// Samples per second, e.g. 4 for --interval-ms 250.
+    #[arg(long, env = "MONITOR_RATE", value_parser = parse_rate)]
+    rate: Option<f64>,
+
+    /// Move each sample randomly by up to ±N ms, so sampling doesn't alias with periodic load.
+    #[arg(long, env = "MONITOR_JITTER_MS", default_value_t = 0)]
+    jitter_ms: u64,
+
+    /// RAM spike threshold as absolute used bytes (suffixes K, M, G, T).
+    #[arg(long, env = "MONITOR_RAM_THRESHOLD_BYTES", value_parser = parse_byte_size)]
+    ram_threshold_bytes: Option<u64>,
+
+    /// Emit a growth event when RAM used climbs faster than this many MB per minute over --ram-growth-window-secs (leak detection).
+    #[arg(long, env = "MONITOR_RAM_GROWTH_MB_PER_MIN")]
+    ram_growth_mb_per_min: Option<f32>,
+
+    /// Window in seconds that --ram-growth-mb-per-min fits the RAM growth over.
+    #[arg(long, env = "MONITOR_RAM_GROWTH_WINDOW_SECS", default_value_t = DEFAULT_RAM_GROWTH_WINDOW_SECS, value_parser = clap::value_parser!(u64).range(1..))]
+    ram_growth_window_secs: u64,
+
+    /// Also tag JSON snapshots and OpenMetrics samples with the host name.
+    #[arg(long, env = "MONITOR_SNAPSHOT_HOST")]
+    snapshot_host: bool,
+
+    /// Print a liveness line to stderr every N seconds (also in quiet mode).
+    #[arg(long, env = "MONITOR_HEARTBEAT_SECS")]
+    heartbeat_secs: Option<u64>,
+
+    /// Warn on stderr after every N consecutive failed samples.
+    #[arg(long, env = "MONITOR_FAILURE_WARN_EVERY")]
+    failure_warn_every: Option<u64>,
+
+    /// Save open spikes to this file on Ctrl-C/SIGTERM and resume them on startup.
+    #[arg(long, env = "MONITOR_STATE_FILE")]
+    state_file: Option<String>,
+
+    /// Ignore a state file saved more than this many seconds ago.
+    #[arg(long, env = "MONITOR_STATE_MAX_AGE_SECS", default_value_t = DEFAULT_STATE_MAX_AGE_SECS)]
+    state_max_age_secs: u64,
+
+    /// When to flush the log file: always (every event), interval or never (on exit).
+    #[arg(long, env = "MONITOR_LOG_FLUSH", default_value = "always", value_parser = ["always", "interval", "never"])]
+    log_flush: String,
+
+    /// Minimum time between two flushes with --log-flush interval.
+    #[arg(long, env = "MONITOR_LOG_FLUSH_INTERVAL_MS", default_value_t = 1000)]
+    log_flush_interval_ms: u64,
+
+    /// Also send spike events to the local syslog/journald.
+    #[arg(long, env = "MONITOR_SYSLOG")]
+    syslog: bool,
+
+    /// Syslog facility: user, daemon or local0-local7.
+    #[arg(long, env = "MONITOR_SYSLOG_FACILITY", default_value = "user", value_parser = parse_syslog_facility)]
+    syslog_facility: SyslogFacility,
+
+    /// Syslog identity (program name) for spike messages.
+    #[arg(long, env = "MONITOR_SYSLOG_IDENT", default_value = "resource_monitor")]
+    syslog_ident: String,
+
+    /// Serve spike events as JSON lines to clients of this Unix socket.
+    #[arg(long, env = "MONITOR_EVENT_SOCKET")]
+    event_socket: Option<String>,
+
+    /// Number of top processes (by CPU) to record in spike events.
+    #[arg(long, env = "MONITOR_TOP_N_PROCS", default_value_t = 0)]
+    top_n_procs: usize,
+
+    /// Only consider processes whose name matches this regex.
+    #[arg(long, env = "MONITOR_PROC_INCLUDE")]
+    proc_include: Option<String>,
+
+    /// Ignore processes whose name matches this regex.
+    #[arg(long, env = "MONITOR_PROC_EXCLUDE")]
+    proc_exclude: Option<String>,
+
+    /// Ignore kernel threads (e.g. kworker) in the top processes.
+    #[arg(long, env = "MONITOR_NO_KERNEL_THREADS")]
+    no_kernel_threads: bool,
+
+    /// Name top processes by their full command line instead of the short comm (Linux).
+    #[arg(long, env = "MONITOR_PROC_CMDLINE")]
+    proc_cmdline: bool,
+
+    /// Maximum length of a --proc-cmdline name, in characters.
+    #[arg(long, env = "MONITOR_PROC_CMDLINE_MAX_LEN", default_value_t = DEFAULT_CMDLINE_MAX_LEN)]
+    proc_cmdline_max_len: usize,
+
+    /// Also write every sample to this file, for replay (replaced if it exists).
+    #[arg(long, env = "MONITOR_SAMPLES_LOG")]
+    samples_log: Option<String>,
+
+    /// Encoding of --samples-log: json, or bin for compact records at high sample rates.
+    #[arg(long, env = "MONITOR_SAMPLES_LOG_FORMAT", default_value = "json", value_parser = parse_samples_log_format)]
+    samples_log_format: SamplesLogFormat,
+
+    /// Report the monitor's own CPU and RSS per snapshot and in the summary.
+    #[arg(long, env = "MONITOR_REPORT_SELF_OVERHEAD")]
+    report_self_overhead: bool,
+
+    /// Note on stderr why open spikes haven't fired yet and why short ones were discarded.
+    #[arg(long, env = "MONITOR_EXPLAIN")]
+    explain: bool,
+
+    /// Only count IO of this block device (diskstats name); repeat for several.
+    #[arg(long = "io-device")]
+    io_devices: Vec<String>,
+
+    /// Bytes per diskstats sector.
+    #[arg(long, env = "MONITOR_IO_SECTOR_SIZE", default_value_t = DISKSTATS_SECTOR_SIZE)]
+    io_sector_size: u64,
+
+    /// Read CPU and memory from this cgroup v2 directory instead of the host.
+    #[arg(long, env = "MONITOR_CGROUP")]
+    cgroup: Option<String>,
+
+    /// Only read these metrics, e.g. cpu,ram (plus whatever the thresholds need); default: cpu, ram and io.
+    #[arg(long, env = "MONITOR_COLLECT", value_parser = Collectors::parse)]
+    collect: Option<Collectors>,
+
+    /// Collect CPU temperature from /sys/class/thermal.
+    #[arg(long, env = "MONITOR_TEMP")]
+    temp: bool,
+
+    /// Collect battery charge and discharge rate from /sys/class/power_supply.
+    #[arg(long, env = "MONITOR_POWER")]
+    power: bool,
+
+    /// Collect open file handles from /proc/sys/fs/file-nr.
+    #[arg(long, env = "MONITOR_FD")]
+    fd: bool,
+
+    /// Count processes and threads.
+    #[arg(long, env = "MONITOR_PROC_COUNT")]
+    proc_count: bool,
+
+    /// Path whose filesystem usage is tracked (repeatable).
+    #[arg(long = "watch-path")]
+    watch_paths: Vec<String>,
+}
+
+impl SystemArgs {
+    /// Add the sampling options to a `runtime` built from the shared
+    /// ones.
+    fn into_runtime(self, runtime: RuntimeConfig) -> Result<RuntimeConfig, Box<dyn Error>> {
+        let thresholds = Thresholds {
+            ram_threshold_bytes: self.ram_threshold_bytes,
+            ram_growth: self.ram_growth_mb_per_min.map(|mb_per_min| GrowthPolicy {
+                mb_per_min,
+                window_secs: self.ram_growth_window_secs,
+            }),
+            ..runtime.thresholds
+        };
+
+        let mut collectors = self.collect.unwrap_or_default();
+        collectors.temp |= self.temp;
+        collectors.power |= self.power;
+        collectors.fd |= self.fd;
+        collectors.tasks |= self.proc_count;
+        collectors.require(&thresholds);
+
+        Ok(RuntimeConfig {
+            interval_ms: clamp_interval_ms(
+                self.rate.map(rate_interval_ms).unwrap_or(self.interval_ms),
+            ),
+            jitter_ms: self.jitter_ms,
+            thresholds,
+            log_flush: parse_log_flush(&self.log_flush, self.log_flush_interval_ms),
+            syslog: self.syslog.then_some(SyslogConfig {
+                facility: self.syslog_facility,
+                ident: self.syslog_ident,
+            }),
+            event_socket: self.event_socket,
+            top_n_procs: self.top_n_procs,
+            proc_filter: ProcFilter {
+                include: parse_regex(self.proc_include.as_deref())?,
+                exclude: parse_regex(self.proc_exclude.as_deref())?,
+                no_kernel_threads: self.no_kernel_threads,
+            },
+            proc_cmdline_max_len: self.proc_cmdline.then_some(self.proc_cmdline_max_len),
+            samples_log: self.samples_log,
+            samples_log_format: self.samples_log_format,
+            report_self_overhead: self.report_self_overhead,
+            explain: self.explain,
+            collectors,
+            watch_paths: self.watch_paths,
+            heartbeat_secs: self.heartbeat_secs,
+            failure_warn_every: self.failure_warn_every,
+            cgroup: self.cgroup,
+            io_devices: self.io_devices,
+            io_sector_size: self.io_sector_size,
+            host: runtime.host.or_else(read_hostname),
+            snapshot_host: self.snapshot_host,
+            state_file: self.state_file,
+            state_max_age_secs: self.state_max_age_secs,
+            ..runtime
+        })
+    }
+}
+
+/// When batch and replay stop, and how they exit on a spike.
+#[derive(Args, Debug)]
+struct StopArgs {
+    /// Stop after this many samples; without it batch takes 10 (unless --duration-secs is set) and replay reads the whole file.
+    #[arg(long, env = "MONITOR_SAMPLES")]
+    samples: Option<u64>,
+
+    /// Exit with this code (default 1) if any spike was detected.
+    #[arg(long, env = "MONITOR_EXIT_ON_SPIKE", num_args = 0..=1, default_missing_value = "1", value_parser = parse_exit_on_spike)]
+    exit_on_spike: Option<u8>,
+}
+
+impl StopArgs {
+    fn exit_code(&self, spike_seen: bool) -> ExitCode {
+        match self.exit_on_spike {
+            Some(code) if spike_seen => ExitCode::from(code),
+            _ => ExitCode::SUCCESS,
+        }
+    }
 }
 
 /// CLI subcommands.
@@ -37,217 +533,499 @@ struct Cli {
 enum Commands {
     /// Live monitoring mode (run until interrupted).
     Live {
-        /// Sampling interval in milliseconds.
-        #[arg(long, default_value_t = 1000)]
-        interval_ms: u64,
+        /// Stop after this many samples instead of running until interrupted.
+        #[arg(long, env = "MONITOR_MAX_SAMPLES")]
+        max_samples: Option<u64>,
 
-        /// CPU spike threshold in percent (0-100).
-        #[arg(long)]
-        cpu_threshold: Option<f32>,
+        /// Stop after this many seconds instead of running until interrupted.
+        #[arg(long, env = "MONITOR_MAX_DURATION")]
+        max_duration: Option<u64>,
 
-        /// RAM spike threshold in percent (0-100).
-        #[arg(long)]
-        ram_threshold: Option<f32>,
+        /// Print one snapshot right away and exit, without analysis (for scripts and health checks).
+        #[arg(long, env = "MONITOR_ONCE", conflicts_with_all = ["max_samples", "max_duration", "sparkline"])]
+        once: bool,
 
-        /// IO spike threshold in MB/s (currently not implemented).
-        #[arg(long)]
-        io_threshold: Option<f32>,
+        /// Print a status line for every open spike after each sample.
+        #[arg(long, env = "MONITOR_SHOW_ONGOING")]
+        show_ongoing: bool,
 
-        /// Minimum spike duration in seconds.
-        #[arg(long, default_value_t = 3)]
-        min_spike_duration_secs: u64,
+        /// Draw a rolling sparkline per resource on one line, redrawn in place, instead of a line per sample (text/table output on a terminal only).
+        #[arg(long, env = "MONITOR_SPARKLINE")]
+        sparkline: bool,
 
-        /// Output format: text or json.
-        #[arg(long, default_value = "text")]
-        output: String,
+        /// Samples shown in each sparkline.
+        #[arg(long, env = "MONITOR_SPARKLINE_WIDTH", default_value_t = DEFAULT_SPARKLINE_WIDTH)]
+        sparkline_width: usize,
 
-        /// Optional log file path for spike events.
-        #[arg(long)]
-        log_file: Option<String>,
+        #[command(flatten)]
+        monitor: MonitorArgs,
 
-        /// Number of top processes to record in spike events (not implemented yet).
-        #[arg(long, default_value_t = 0)]
-        top_n_procs: usize,
+        #[command(flatten)]
+        system: SystemArgs,
     },
 
     /// Batch mode: stop after N samples or N seconds.
     Batch {
-        /// Sampling interval in milliseconds.
-        #[arg(long, default_value_t = 1000)]
-        interval_ms: u64,
-
         /// Total duration in seconds (exclusive with --samples).
-        #[arg(long)]
+        #[arg(long, env = "MONITOR_DURATION_SECS")]
         duration_secs: Option<u64>,
 
-        /// Total number of samples (exclusive with --duration-secs).
+        #[command(flatten)]
+        stop: StopArgs,
+
+        /// Show a progress line on stderr while running (only when stderr is a terminal).
+        #[arg(long, env = "MONITOR_PROGRESS")]
+        progress: bool,
+
+        /// With --output json, write the whole run as one JSON array instead of one object per line.
+        #[arg(long, env = "MONITOR_JSON_ARRAY")]
+        json_array: bool,
+
+        /// Count samples per resource in buckets between these edges (default 0,10,...,100) and print the histograms at the end.
+        #[arg(long, env = "MONITOR_HISTOGRAM", num_args = 0..=1, default_missing_value = DEFAULT_HISTOGRAM_EDGES, value_parser = BucketEdges::parse)]
+        histogram: Option<BucketEdges>,
+
+        #[command(flatten)]
+        monitor: MonitorArgs,
+
+        #[command(flatten)]
+        system: SystemArgs,
+    },
+
+    /// Show spike events stored in one or more log files.
+    Logs {
+        /// Log file path; repeat to query several files (read in the given order).
+        #[arg(long = "log-file", required_unless_present = "log_dir")]
+        log_files: Vec<String>,
+
+        /// Also read every *.jsonl / *.jsonl.gz file in this directory, sorted by name.
         #[arg(long)]
-        samples: Option<u64>,
+        log_dir: Option<String>,
 
-        /// CPU spike threshold in percent (0-100).
+        /// Filter by resource: cpu, steal, iowait, ram, io, io_read, io_write, temp, fd, procs, disk or composite.
         #[arg(long)]
-        cpu_threshold: Option<f32>,
+        resource: Option<String>,
 
-        /// RAM spike threshold in percent (0-100).
+        /// Only show the event with this id (schema v3 logs).
         #[arg(long)]
-        ram_threshold: Option<f32>,
+        id: Option<String>,
 
-        /// IO spike threshold in MB/s (currently not implemented).
+        /// Only show events logged by runs with this --tag (schema v8 logs).
         #[arg(long)]
-        io_threshold: Option<f32>,
+        tag: Option<String>,
 
-        /// Minimum spike duration in seconds.
-        #[arg(long, default_value_t = 3)]
-        min_spike_duration_secs: u64,
+        /// Only show events with ts_start >= this: epoch seconds or a span
+        /// before now such as -30m, 2h or now-1d (s/m/h/d).
+        #[arg(long, allow_hyphen_values = true, value_parser = TimeSpec::parse)]
+        since: Option<TimeSpec>,
 
-        /// Output format: text or json.
-        #[arg(long, default_value = "text")]
-        output: String,
+        /// Only show events with ts_start <= this (same forms as --since).
+        #[arg(long, allow_hyphen_values = true, value_parser = TimeSpec::parse)]
+        until: Option<TimeSpec>,
+
+        /// Only show events from this span before now, e.g. 2h; same as
+        /// --since now-<span>.
+        #[arg(long, conflicts_with = "since", value_parser = parse_span)]
+        last: Option<TimeSpec>,
 
-        /// Optional log file path for spike events.
+        /// Limit number of events shown (applied after sorting).
         #[arg(long)]
-        log_file: Option<String>,
+        limit: Option<usize>,
 
-        /// Number of top processes to record in spike events (not implemented yet).
-        #[arg(long, default_value_t = 0)]
-        top_n_procs: usize,
-    },
+        /// Only show spikes whose peak is at least this multiple of the threshold, e.g. 1.5.
+        #[arg(long)]
+        min_over_ratio: Option<f32>,
 
-    /// Show spike events stored in a log file.
-    Logs {
-        /// Log file path.
+        /// Only show events with a top process whose name contains this,
+        /// e.g. postgres; events logged without top processes are left out.
         #[arg(long)]
-        log_file: String,
+        proc_name: Option<String>,
+
+        /// Like --proc-name, with a regex matched against the process name.
+        #[arg(long, conflicts_with = "proc_name")]
+        proc_regex: Option<String>,
 
-        /// Filter by resource: cpu, ram or io.
+        /// Sort events by peak, duration or ts_start (buffers the matching events).
         #[arg(long)]
-        resource: Option<String>,
+        sort: Option<String>,
+
+        /// Sort in descending order.
+        #[arg(long, requires = "sort")]
+        desc: bool,
+
+        /// Output format: text or json.
+        #[arg(long, default_value = "text", value_parser = parse_output_format)]
+        output: OutputFormat,
+
+        /// Text label of a resource, e.g. cpu=Processor; repeatable.
+        #[arg(long = "label", value_parser = ResourceLabels::parse_override)]
+        labels: Vec<(ResourceKind, String)>,
 
-        /// Only show events with ts_start >= this (seconds since epoch).
+        /// Unit suffix of a resource's values, e.g. io=MBps; repeatable.
+        #[arg(long = "unit", value_parser = ResourceLabels::parse_override)]
+        units: Vec<(ResourceKind, String)>,
+
+        /// Drop a truncated last line (from a killed writer) from each log
+        /// file in place, instead of printing events.
         #[arg(long)]
-        since: Option<u64>,
+        repair: bool,
+    },
 
-        /// Only show events with ts_start <= this (seconds since epoch).
+    /// Read each data source once and report whether it is usable.
+    Check {
+        /// Also check this cgroup v2 directory.
         #[arg(long)]
-        until: Option<u64>,
+        cgroup: Option<String>,
+
+        /// Also check filesystem usage of the mount containing this path; repeatable.
+        #[arg(long = "watch-path")]
+        watch_paths: Vec<String>,
 
-        /// Limit number of events shown.
+        /// Output format: text or json.
+        #[arg(long, default_value = "text", value_parser = parse_output_format)]
+        output: OutputFormat,
+    },
+
+    /// Take one reading and report it as a Nagios plugin (exit 0 OK, 1 WARNING, 2 CRITICAL, 3 UNKNOWN).
+    Nagios {
+        /// Read CPU and memory from this cgroup v2 directory instead of the host.
         #[arg(long)]
-        limit: Option<usize>,
+        cgroup: Option<String>,
+
+        #[command(flatten)]
+        bands: BandArgs,
+    },
+
+    /// List block devices and network interfaces with their counters.
+    List {
+        /// List block devices from /proc/diskstats (names for --io-device).
+        #[arg(long)]
+        devices: bool,
+
+        /// List network interfaces from /proc/net/dev.
+        #[arg(long)]
+        interfaces: bool,
 
         /// Output format: text or json.
-        #[arg(long, default_value = "text")]
-        output: String,
+        #[arg(long, default_value = "text", value_parser = parse_output_format)]
+        output: OutputFormat,
+    },
+
+    /// Run snapshots recorded with --output json through spike detection.
+    Replay {
+        /// JSON stream of a live or batch run (--output json); other objects than snapshots are skipped.
+        #[arg(long, env = "MONITOR_SNAPSHOTS")]
+        snapshots: String,
+
+        #[command(flatten)]
+        stop: StopArgs,
+
+        #[command(flatten)]
+        monitor: MonitorArgs,
+    },
+
+    /// Show the version, log schema, commit, target and supported resources.
+    Version {
+        /// Print one JSON object instead of text.
+        #[arg(long)]
+        json: bool,
     },
 }
 
+/// Exit codes:
+/// - 0: success (in batch with --exit-on-spike: no spike detected)
+/// - 1: batch with --exit-on-spike detected a spike (configurable as 1
+///   or 3-255; 0 and 2 are refused so a spike can't pass for success
+///   or for an error)
+/// - 2: error (I/O, invalid configuration, failed `check`, ...), also
+///   clap's code for usage errors
+const EXIT_ERROR: u8 = 2;
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+fn run() -> Result<ExitCode, Box<dyn Error>> {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // Options of the subcommand, for where a value came from.
+    let args = matches.subcommand().map_or(&matches, |(_, m)| m);
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
+    if cli.no_color {
+        colored::control::set_override(false);
+    }
+    diag::set_verbosity(cli.verbose);
 
     match cli.command {
         // ----------------------------
         // LIVE MODE
         // ----------------------------
         Some(Commands::Live {
-            interval_ms,
-            cpu_threshold,
-            ram_threshold,
-            io_threshold,
-            min_spike_duration_secs,
-            output,
-            log_file,
-            top_n_procs,
+            max_samples,
+            max_duration,
+            once,
+            show_ongoing,
+            sparkline,
+            sparkline_width,
+            monitor,
+            system,
         }) => {
-            let thresholds = Thresholds::new(cpu_threshold, ram_threshold, io_threshold);
-            let output_format = parse_output_format(&output);
-
-            let config = RuntimeConfig {
-                interval_ms,
-                thresholds,
-                min_spike_duration_secs,
-                output_format,
-                log_file,
-                top_n_procs,
+            let runtime = RuntimeConfig {
+                show_ongoing,
+                ..runtime_config(monitor, Some(system), args)?
             };
 
-            run_live(config)
+            if once {
+                run_once(runtime)?;
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            // Sparklines redraw a line in place, so they need a colored
+            // terminal and would corrupt a JSON stream or a file.
+            let sparkline = if !sparkline {
+                None
+            } else if matches!(
+                runtime.output_format,
+                OutputFormat::Json | OutputFormat::OpenMetrics
+            ) || !colored::control::SHOULD_COLORIZE.should_colorize()
+            {
+                eprintln!(
+                    "[monitor] Warning: --sparkline needs text or table output on a color terminal; ignoring it"
+                );
+                None
+            } else {
+                Some(sparkline_width)
+            };
+
+            let (keep_samples, keep_duration) =
+                resolve_exclusive(args, "max_samples", "max_duration")?;
+            let max_samples = max_samples.filter(|_| keep_samples);
+            let max_duration = max_duration.filter(|_| keep_duration);
+            let limit = match (max_samples, max_duration) {
+                (Some(s), _) => BatchLimit::Samples(s),
+                (None, Some(d)) => BatchLimit::DurationSecs(d),
+                (None, None) => BatchLimit::Unbounded,
+            };
+
+            shutdown::install();
+            run_live(BatchConfig {
+                runtime,
+                limit,
+                progress: false,
+                sparkline,
+                replay: None,
+                finalize: false,
+                json_array: false,
+                histogram: None,
+            })?;
+            Ok(ExitCode::SUCCESS)
         }
 
         // ----------------------------
         // BATCH MODE
         // ----------------------------
         Some(Commands::Batch {
-            interval_ms,
             duration_secs,
-            samples,
-            cpu_threshold,
-            ram_threshold,
-            io_threshold,
-            min_spike_duration_secs,
-            output,
-            log_file,
-            top_n_procs,
+            stop,
+            progress,
+            json_array,
+            histogram,
+            monitor,
+            system,
         }) => {
-            let thresholds = Thresholds::new(cpu_threshold, ram_threshold, io_threshold);
-            let output_format = parse_output_format(&output);
+            let runtime = runtime_config(monitor, Some(system), args)?;
+            if json_array && runtime.output_format != OutputFormat::Json {
+                return Err("--json-array needs --output json".into());
+            }
 
             let limit = if let Some(d) = duration_secs {
                 BatchLimit::DurationSecs(d)
-            } else if let Some(s) = samples {
+            } else if let Some(s) = stop.samples {
                 BatchLimit::Samples(s)
             } else {
                 BatchLimit::Samples(10)
             };
 
-            let runtime = RuntimeConfig {
-                interval_ms,
-                thresholds,
-                min_spike_duration_secs,
-                output_format,
-                log_file,
-                top_n_procs,
+            let config = BatchConfig {
+                runtime,
+                limit,
+                progress,
+                sparkline: None,
+                replay: None,
+                finalize: true,
+                json_array,
+                histogram,
             };
-
-            let config = BatchConfig { runtime, limit };
-            run_batch(config)
+            shutdown::install();
+            let spike_seen = run_batch(config)?;
+            Ok(stop.exit_code(spike_seen))
         }
 
         // ----------------------------
         // LOGS MODE
         // ----------------------------
         Some(Commands::Logs {
-            log_file,
+            log_files,
+            log_dir,
             resource,
+            id,
+            tag,
             since,
             until,
+            last,
             limit,
+            min_over_ratio,
+            proc_name,
+            proc_regex,
+            sort,
+            desc,
             output,
+            labels,
+            units,
+            repair,
         }) => {
             // Parse resource filter
             let resource_filter: Option<ResourceKind> = match resource.as_deref() {
-                Some("cpu") => Some(ResourceKind::Cpu),
-                Some("ram") => Some(ResourceKind::Ram),
-                Some("io") => Some(ResourceKind::Io),
+                Some(name) => match ResourceKind::from_name(name) {
+                    Some(kind) => Some(kind),
+                    None => {
+                        eprintln!("Invalid resource filter '{}', ignoring filter.", name);
+                        None
+                    }
+                },
+                None => None,
+            };
+
+            let sort = match sort.as_deref() {
+                Some("peak") => Some(LogSortKey::Peak),
+                Some("duration") => Some(LogSortKey::Duration),
+                Some("ts_start") => Some(LogSortKey::TsStart),
                 Some(other) => {
-                    eprintln!("Invalid resource filter '{}', ignoring filter.", other);
+                    eprintln!("Invalid sort key '{}', keeping file order.", other);
                     None
                 }
                 None => None,
             };
 
-            let output_format = parse_output_format(&output);
-
             let query = LogsQuery {
-                log_file,
+                log_files,
+                log_dir,
                 resource_filter,
-                since,
+                id,
+                tag,
+                since: last.or(since),
                 until,
                 limit,
-                output_format,
+                min_over_ratio,
+                proc_name,
+                proc_regex: parse_regex(proc_regex.as_deref())?,
+                output_format: output,
+                sort,
+                descending: desc,
+                repair,
+                labels: ResourceLabels::new(labels, units),
+            };
+
+            run_logs(query)?;
+            Ok(ExitCode::SUCCESS)
+        }
+
+        // ----------------------------
+        // CHECK MODE
+        // ----------------------------
+        Some(Commands::Check {
+            cgroup,
+            watch_paths,
+            output,
+        }) => {
+            let config = CheckConfig {
+                output_format: output,
+                cgroup,
+                watch_paths,
             };
 
-            run_logs(query)
+            if run_check(config) {
+                Ok(ExitCode::SUCCESS)
+            } else {
+                Ok(ExitCode::from(EXIT_ERROR))
+            }
+        }
+
+        // ----------------------------
+        // NAGIOS MODE
+        // ----------------------------
+        Some(Commands::Nagios { cgroup, bands }) => {
+            let status = run_nagios(NagiosConfig {
+                bands: bands.into_bands(),
+                cgroup,
+            });
+            Ok(ExitCode::from(status.exit_code()))
+        }
+
+        // ----------------------------
+        // LIST MODE
+        // ----------------------------
+        Some(Commands::List {
+            devices,
+            interfaces,
+            output,
+        }) => {
+            check_platform()?;
+            run_list(ListConfig {
+                devices,
+                interfaces,
+                output_format: output,
+            })?;
+            Ok(ExitCode::SUCCESS)
+        }
+
+        // ----------------------------
+        // REPLAY MODE
+        // ----------------------------
+        Some(Commands::Replay {
+            snapshots,
+            stop,
+            monitor,
+        }) => {
+            let runtime = runtime_config(monitor, None, args)?;
+
+            let limit = match stop.samples {
+                Some(s) => BatchLimit::Samples(s),
+                None => BatchLimit::Unbounded,
+            };
+
+            shutdown::install();
+            let spike_seen = run_replay(BatchConfig {
+                runtime,
+                limit,
+                progress: false,
+                sparkline: None,
+                replay: Some(snapshots),
+                finalize: true,
+                json_array: false,
+                histogram: None,
+            })?;
+            Ok(stop.exit_code(spike_seen))
+        }
+
+        // ----------------------------
+        // VERSION MODE
+        // ----------------------------
+        Some(Commands::Version { json }) => {
+            run_version(VersionConfig {
+                output_format: if json {
+                    OutputFormat::Json
+                } else {
+                    OutputFormat::Text
+                },
+            });
+            Ok(ExitCode::SUCCESS)
         }
 
         // ----------------------------
@@ -257,19 +1035,443 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             let mut cmd = Cli::command();
             cmd.print_help()?;
             println!();
-            Ok(())
+            Ok(ExitCode::SUCCESS)
         }
     }
 }
 
-/// Convert string to OutputFormat.
-fn parse_output_format(s: &str) -> OutputFormat {
+/// Build the configuration of live, batch and replay from their shared
+/// options, plus the sampling options of live and batch (`None` for
+/// replay, whose snapshots carry no process list, so nothing is collected
+/// beyond what the file holds). `matches` are the subcommand's, to settle
+/// options that exclude each other (see `resolve_exclusive`).
+fn runtime_config(
+    args: MonitorArgs,
+    mut system: Option<SystemArgs>,
+    matches: &ArgMatches,
+) -> Result<RuntimeConfig, Box<dyn Error>> {
+    let MonitorArgs {
+        mut detect,
+        mut output,
+        log,
+    } = args;
+    let (keep_raw, keep_binary) = resolve_exclusive(matches, "raw_bytes", "binary_units")?;
+    output.raw_bytes &= keep_raw;
+    output.binary_units &= keep_binary;
+    if let Some(system) = &mut system {
+        let (keep_ram, keep_ram_bytes) =
+            resolve_exclusive(matches, "ram_threshold", "ram_threshold_bytes")?;
+        detect.ram_threshold = detect.ram_threshold.filter(|_| keep_ram);
+        system.ram_threshold_bytes = system.ram_threshold_bytes.filter(|_| keep_ram_bytes);
+        let (_, keep_rate) = resolve_exclusive(matches, "interval_ms", "rate")?;
+        system.rate = system.rate.filter(|_| keep_rate);
+    }
+    if let Some(system) = &system {
+        check_platform()?;
+        if let Some(dir) = &system.cgroup {
+            check_cgroup(dir)?;
+        }
+    }
+
+    let mut ratios = Vec::new();
+    let mut absolute = |spec, kind| threshold_value(spec, kind, &mut ratios);
+    let thresholds = Thresholds {
+        cpu_threshold: absolute(detect.cpu_threshold, ResourceKind::Cpu),
+        ram_threshold: absolute(detect.ram_threshold, ResourceKind::Ram),
+        ram_threshold_bytes: None,
+        io_threshold: absolute(detect.io_threshold, ResourceKind::Io),
+        io_read_threshold: absolute(detect.io_read_threshold, ResourceKind::IoRead),
+        io_write_threshold: absolute(detect.io_write_threshold, ResourceKind::IoWrite),
+        steal_threshold: absolute(detect.steal_threshold, ResourceKind::Steal),
+        iowait_threshold: absolute(detect.iowait_threshold, ResourceKind::Iowait),
+        temp_threshold: absolute(detect.temp_threshold, ResourceKind::Temp),
+        power_threshold: absolute(detect.power_threshold, ResourceKind::Power),
+        fd_threshold: absolute(detect.fd_threshold, ResourceKind::Fd),
+        proc_count_threshold: detect.proc_count_threshold,
+        disk_threshold: detect.disk_threshold,
+        composite: detect
+            .composite
+            .as_deref()
+            .map(CompositeRule::parse)
+            .transpose()?,
+        cpu_min_duration_secs: detect.cpu_min_duration,
+        ram_min_duration_secs: detect.ram_min_duration,
+        io_min_duration_secs: detect.io_min_duration,
+        zscore: parse_detect_mode(&detect.detect_mode, detect.zscore_k, detect.zscore_warmup),
+        confirm: detect.confirm,
+        alert_on_start: detect.alert_on_start,
+        flap: detect.flap_threshold.map(|spikes| FlapPolicy {
+            spikes,
+            window_secs: detect.flap_window_secs,
+        }),
+        // Replayed snapshots carry no RAM bytes.
+        ram_growth: None,
+        ignore_first_uptime_secs: detect.ignore_first_uptime_secs,
+        baseline_ratios: ratios,
+        schedule: detect.schedule,
+        rules: detect.rules,
+    };
+    if !thresholds.baseline_ratios.is_empty() && detect.warmup_samples == 0 {
+        return Err(
+            "Ratio thresholds like 2x need --warmup-samples to measure the baseline".into(),
+        );
+    }
+    // Escape codes don't belong in a file.
+    if output.output_file.is_some() {
+        colored::control::set_override(false);
+    }
+
+    let runtime = RuntimeConfig {
+        thresholds,
+        min_spike_duration_secs: detect.min_spike_duration_secs,
+        output_format: output.format,
+        precision: output.precision,
+        quiet: output.quiet,
+        log_file: log.log_file,
+        log_compress: log.log_compress,
+        log_best_effort: log.log_best_effort,
+        db_file: log.db_file,
+        bands: output.bands.into_bands(),
+        byte_units: byte_units(output.raw_bytes, output.binary_units),
+        labels: ResourceLabels::new(output.labels, output.units),
+        host: output.host_label,
+        tag: output.tag,
+        output_file: output.output_file,
+        line_ending: output.line_ending,
+        exemplars: output.exemplars,
+        warmup_samples: detect.warmup_samples,
+        ..RuntimeConfig::default()
+    };
+    match system {
+        Some(system) => system.into_runtime(runtime),
+        None => Ok(runtime),
+    }
+}
+
+/// Settle two options that exclude each other, returning which of `a` and
+/// `b` to keep. An option set by its MONITOR_ variable gives way to the
+/// other one given on the command line, so a deployment's environment
+/// doesn't break a one-off flag; set the same way, they are an error.
+/// Both must be options of the subcommand `matches` belongs to.
+fn resolve_exclusive(matches: &ArgMatches, a: &str, b: &str) -> Result<(bool, bool), String> {
+    // A switch set to false (MONITOR_RAW_BYTES=false) is not set at all.
+    let source = |id: &str| match matches.try_get_one::<bool>(id) {
+        Ok(Some(false)) => None,
+        _ => matches
+            .value_source(id)
+            .filter(|s| *s != ValueSource::DefaultValue),
+    };
+    match (source(a), source(b)) {
+        (Some(from_a), Some(from_b)) if from_a == from_b => Err(format!(
+            "the argument '--{}' cannot be used with '--{}'",
+            a.replace('_', "-"),
+            b.replace('_', "-")
+        )),
+        (Some(ValueSource::EnvVariable), Some(_)) => Ok((false, true)),
+        (Some(_), Some(ValueSource::EnvVariable)) => Ok((true, false)),
+        _ => Ok((true, true)),
+    }
+}
+
+/// An --exit-on-spike code that can't be confused with success (0) or
+/// `EXIT_ERROR`.
+fn parse_exit_on_spike(s: &str) -> Result<u8, String> {
+    match s.trim().parse::<u8>() {
+        Ok(code) if code != 0 && code != EXIT_ERROR => Ok(code),
+        Ok(code) => Err(format!(
+            "exit code {} is reserved: use 1 or 3-255 ({} means an error)",
+            code, EXIT_ERROR
+        )),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parse a --rate value in samples per second.
+fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(format!("invalid rate '{}': expected samples per second > 0", s)),
+    }
+}
+
+/// Convert a --rate in Hz to a sampling interval in whole milliseconds.
+fn rate_interval_ms(rate: f64) -> u64 {
+    (1000.0 / rate).round() as u64
+}
+
+/// A zero interval would spin the sampler on a core with meaningless
+/// deltas, so it is rejected outright.
+fn parse_interval_ms(s: &str) -> Result<u64, String> {
+    match s.trim().parse::<u64>() {
+        Ok(0) => Err(format!(
+            "interval must be at least {} ms; 0 would sample in a busy loop",
+            MIN_INTERVAL_MS
+        )),
+        Ok(ms) => Ok(ms),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Raise an interval below `MIN_INTERVAL_MS` (from --interval-ms or a
+/// high --rate) to the minimum, with a warning.
+fn clamp_interval_ms(interval_ms: u64) -> u64 {
+    if interval_ms < MIN_INTERVAL_MS {
+        eprintln!(
+            "Warning: sampling interval {} ms is below the minimum; using {} ms.",
+            interval_ms, MIN_INTERVAL_MS
+        );
+        return MIN_INTERVAL_MS;
+    }
+    interval_ms
+}
+
+/// Parse an --output format.
+fn parse_output_format(s: &str) -> Result<OutputFormat, String> {
     match s {
-        "text" => OutputFormat::Text,
-        "json" => OutputFormat::Json,
-        other => {
-            eprintln!("Invalid output '{}', using 'text'.", other);
-            OutputFormat::Text
+        "text" => Ok(OutputFormat::Text),
+        "json" => Ok(OutputFormat::Json),
+        "table" => Ok(OutputFormat::Table),
+        _ => Err("expected text, json or table".into()),
+    }
+}
+
+/// Parse the --output of live, batch and replay mode, which also stream
+/// OpenMetrics.
+fn parse_stream_format(s: &str) -> Result<OutputFormat, String> {
+    match s {
+        "openmetrics" => Ok(OutputFormat::OpenMetrics),
+        other => parse_output_format(other)
+            .map_err(|_| "expected text, json, table or openmetrics".into()),
+    }
+}
+
+fn parse_samples_log_format(s: &str) -> Result<SamplesLogFormat, String> {
+    match s {
+        "json" => Ok(SamplesLogFormat::Json),
+        "bin" => Ok(SamplesLogFormat::Bin),
+        _ => Err("expected json or bin".into()),
+    }
+}
+
+fn parse_line_ending(s: &str) -> Result<LineEnding, String> {
+    match s {
+        "lf" => Ok(LineEnding::Lf),
+        "crlf" => Ok(LineEnding::Crlf),
+        _ => Err("expected lf or crlf".into()),
+    }
+}
+
+/// Convert --raw-bytes / --binary-units to the IO rate display units.
+fn byte_units(raw_bytes: bool, binary_units: bool) -> ByteUnits {
+    match (raw_bytes, binary_units) {
+        (true, _) => ByteUnits::Raw,
+        (false, true) => ByteUnits::Binary,
+        (false, false) => ByteUnits::Decimal,
+    }
+}
+
+/// Parse a byte count with an optional binary suffix (K, M, G, T).
+fn parse_byte_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (digits, multiplier) = match s.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => {
+            let shift = match c.to_ascii_uppercase() {
+                'K' => 10,
+                'M' => 20,
+                'G' => 30,
+                'T' => 40,
+                other => return Err(format!("unknown size suffix '{}'", other)),
+            };
+            (&s[..i], 1u64 << shift)
         }
+        _ => (s, 1),
+    };
+
+    let value = digits
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| format!("invalid byte size '{}'", s))?;
+    if value < 0.0 {
+        return Err(format!("invalid byte size '{}'", s));
+    }
+
+    Ok((value * multiplier as f64) as u64)
+}
+
+/// Parse a --last span; unlike --since it is always relative.
+fn parse_span(s: &str) -> Result<TimeSpec, MonitorError> {
+    match TimeSpec::parse(s)? {
+        TimeSpec::Absolute(secs) => Ok(TimeSpec::Ago(secs)),
+        relative => Ok(relative),
+    }
+}
+
+/// Absolute value of a threshold argument; a ratio is queued in `ratios`
+/// and resolved once warmup has measured the baseline.
+fn threshold_value(
+    spec: Option<ThresholdSpec>,
+    kind: ResourceKind,
+    ratios: &mut Vec<(ResourceKind, f32)>,
+) -> Option<f32> {
+    match spec? {
+        ThresholdSpec::Absolute(value) => Some(value),
+        ThresholdSpec::Ratio(ratio) => {
+            ratios.push((kind, ratio));
+            None
+        }
+    }
+}
+
+/// Convert --detect-mode (threshold or zscore, checked by clap) and its
+/// z-score parameters to a detection config.
+fn parse_detect_mode(mode: &str, k: f32, warmup_samples: u64) -> Option<ZScoreConfig> {
+    (mode == "zscore").then_some(ZScoreConfig { k, warmup_samples })
+}
+
+/// Convert a `--log-flush` policy (always, interval or never, checked by
+/// clap) to LogFlush; `interval` flushes at most every `interval_ms`.
+fn parse_log_flush(s: &str, interval_ms: u64) -> LogFlush {
+    match s {
+        "interval" => LogFlush::IntervalMs(interval_ms),
+        "never" => LogFlush::Never,
+        _ => LogFlush::Always,
+    }
+}
+
+/// Parse a --syslog-facility.
+fn parse_syslog_facility(s: &str) -> Result<SyslogFacility, String> {
+    match s {
+        "user" => Ok(SyslogFacility::User),
+        "daemon" => Ok(SyslogFacility::Daemon),
+        other => match other.strip_prefix("local").and_then(|n| n.parse::<u8>().ok()) {
+            Some(n) if n <= 7 => Ok(SyslogFacility::Local(n)),
+            _ => Err("expected user, daemon or local0-local7".into()),
+        },
+    }
+}
+
+/// Compile an optional process-name regex, failing at startup if invalid.
+fn parse_regex(pattern: Option<&str>) -> Result<Option<Regex>, Box<dyn Error>> {
+    match pattern {
+        Some(p) => Regex::new(p)
+            .map(Some)
+            .map_err(|e| format!("Invalid process regex '{}': {}", p, e).into()),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run `test` on a thread with a main-sized stack: the derived
+    /// parser and `Commands` overflow a test thread's 2 MiB in debug
+    /// builds.
+    fn on_main_stack(test: fn()) {
+        std::thread::Builder::new()
+            .stack_size(8 << 20)
+            .spawn(test)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    /// The runtime config of a live, batch or replay command line.
+    fn parsed_runtime(args: &[&str]) -> Result<RuntimeConfig, Box<dyn Error>> {
+        let matches =
+            Cli::command().try_get_matches_from(["resource_monitor"].iter().chain(args))?;
+        let args = matches.subcommand().map_or(&matches, |(_, m)| m);
+        match Cli::from_arg_matches(&matches)?.command {
+            Some(Commands::Live {
+                monitor, system, ..
+            })
+            | Some(Commands::Batch {
+                monitor, system, ..
+            }) => runtime_config(monitor, Some(system), args),
+            Some(Commands::Replay { monitor, .. }) => runtime_config(monitor, None, args),
+            _ => panic!("not live, batch or replay"),
+        }
+    }
+
+    #[test]
+    fn process_regex_must_compile() {
+        assert!(parse_regex(None).unwrap().is_none());
+        let re = parse_regex(Some("^post(gres)?$")).unwrap().unwrap();
+        assert!(re.is_match("postgres"));
+        let err = parse_regex(Some("(unclosed")).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Invalid process regex '(unclosed'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn last_is_since_now_minus_span() {
+        for span in ["2h", "-2h", "now-2h"] {
+            assert_eq!(
+                parse_span(span).unwrap(),
+                TimeSpec::parse("now-2h").unwrap()
+            );
+        }
+        // A bare number is a span too, not an epoch timestamp.
+        assert_eq!(parse_span("90").unwrap(), TimeSpec::Ago(90));
+
+        on_main_stack(|| {
+            let logs = ["resource_monitor", "logs", "--log-file", "spikes.jsonl"];
+            let cli = Cli::try_parse_from(logs.iter().chain(&["--last", "30m"])).unwrap();
+            match cli.command {
+                Some(Commands::Logs { last, since, .. }) => {
+                    assert_eq!(last, Some(TimeSpec::Ago(1800)));
+                    assert_eq!(since, None);
+                }
+                _ => panic!("not the logs command"),
+            }
+            let both = ["--last", "1h", "--since", "-2h"];
+            assert!(Cli::try_parse_from(logs.iter().chain(&both)).is_err());
+        });
+    }
+
+    #[test]
+    fn rate_converts_to_interval_and_conflicts_with_it() {
+        assert_eq!(rate_interval_ms(parse_rate("4").unwrap()), 250);
+        assert_eq!(rate_interval_ms(parse_rate("0.5").unwrap()), 2000);
+        assert_eq!(rate_interval_ms(parse_rate(" 3 ").unwrap()), 333);
+        for bad in ["0", "-1", "fast", "inf", "NaN"] {
+            assert!(parse_rate(bad).is_err(), "{}", bad);
+        }
+
+        on_main_stack(|| {
+            for command in ["live", "batch"] {
+                let rate = parsed_runtime(&[command, "--rate", "4"]).unwrap();
+                assert_eq!(rate.interval_ms, 250);
+                let both = [command, "--rate", "4", "--interval-ms", "250"];
+                let err = parsed_runtime(&both).unwrap_err();
+                assert_eq!(
+                    err.to_string(),
+                    "the argument '--interval-ms' cannot be used with '--rate'"
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn zero_interval_is_rejected_and_one_ms_clamped() {
+        assert!(parse_interval_ms("0").unwrap_err().contains("busy loop"));
+        assert_eq!(parse_interval_ms("1"), Ok(1));
+        assert_eq!(clamp_interval_ms(1), MIN_INTERVAL_MS);
+        assert_eq!(clamp_interval_ms(MIN_INTERVAL_MS), MIN_INTERVAL_MS);
+        assert_eq!(clamp_interval_ms(250), 250);
+        // A rate above 1000/s rounds to 0 ms, which is clamped as well.
+        assert_eq!(clamp_interval_ms(rate_interval_ms(5000.0)), MIN_INTERVAL_MS);
+
+        on_main_stack(|| {
+            let err = Cli::try_parse_from(["resource_monitor", "batch", "--interval-ms", "0"])
+                .unwrap_err();
+            assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation);
+            let runtime = parsed_runtime(&["batch", "--interval-ms", "1"]).unwrap();
+            assert_eq!(runtime.interval_ms, MIN_INTERVAL_MS);
+        });
     }
 }
@@ -1,10 +1,42 @@
-use std::error::Error;
-use std::fs;
-use std::sync::{Mutex, OnceLock};
-use std::time::SystemTime;
+//! System metrics: snapshot types and the per-platform sources.
+//!
+//! Linux reads /proc and /sys directly; Windows uses `sysinfo` and
+//! currently reports CPU, RAM/swap, processes and disk usage (IO rates
+//! are 0). Other platforms build but refuse to sample. `SnapshotSampler` owns a platform source with its own delta
+//! baselines, so there is no process-wide sampling state.
 
-/// Simple process info (placeholder for future use).
-#[derive(Debug, Clone)]
+use std::time::{Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::RuntimeConfig;
+use crate::error::MonitorError;
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "linux")]
+pub use linux::*;
+
+#[cfg(windows)]
+mod windows;
+#[cfg(windows)]
+pub use windows::*;
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod unsupported;
+#[cfg(not(any(target_os = "linux", windows)))]
+pub use unsupported::*;
+
+/// Metrics source of the platform this crate was built for.
+#[cfg(target_os = "linux")]
+pub type PlatformSource = ProcSource;
+#[cfg(windows)]
+pub type PlatformSource = WindowsSource;
+#[cfg(not(any(target_os = "linux", windows)))]
+pub type PlatformSource = UnsupportedSource;
+
+/// Per-process sample for the top-N list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessSample {
     pub pid: u32,
     pub name: String,
@@ -12,159 +44,213 @@ pub struct ProcessSample {
     pub ram_bytes: u64,
 }
 
-/// System metrics snapshot for one tick.
+/// Filesystem usage for one watched path.
 #[derive(Debug, Clone)]
-pub struct SystemSnapshot {
-    pub timestamp: SystemTime,
-    pub cpu_usage_percent: f32,
-    pub ram_usage_percent: f32,
-    pub io_read_bytes_per_s: f64,    // 0.0 for now
-    pub io_write_bytes_per_s: f64,   // 0.0 for now
-    pub top_processes: Vec<ProcessSample>,
+pub struct DiskUsage {
+    pub path: String,
+    pub used_percent: f32,
 }
 
-/// Raw CPU times from /proc/stat.
+/// RAM and swap usage from one /proc/meminfo or cgroup read.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemUsage {
+    pub ram_percent: f32,
+    pub swap_percent: f32,
+    pub ram_used_bytes: u64,
+    pub ram_total_bytes: u64,
+}
+
+/// System-wide open file handles from /proc/sys/fs/file-nr.
 #[derive(Debug, Clone, Copy)]
-struct CpuTimes {
-    idle_all: u64,
-    total: u64,
+pub struct FdUsage {
+    pub open: u64,
+    pub max: u64,
 }
 
-/// Global state for last CPU times.
-static LAST_CPU_TIMES: OnceLock<Mutex<Option<CpuTimes>>> = OnceLock::new();
+impl FdUsage {
+    /// Open handles as a percentage of the limit.
+    pub fn percent(&self) -> f32 {
+        if self.max == 0 {
+            return 0.0;
+        }
+        (self.open as f64 / self.max as f64 * 100.0) as f32
+    }
+}
 
-fn cpu_state() -> &'static Mutex<Option<CpuTimes>> {
-    LAST_CPU_TIMES.get_or_init(|| Mutex::new(None))
+/// Battery charge and power draw from one power_supply read; either
+/// part is None when no battery reports it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PowerUsage {
+    pub battery_percent: Option<f32>,
+    /// Positive while discharging, negative while charging.
+    pub draw_watts: Option<f32>,
 }
 
-/// Build a SystemSnapshot using /proc data.
-pub fn read_system_snapshot(
-    _top_n_procs: usize,
-) -> Result<SystemSnapshot, Box<dyn Error>> {
-    let timestamp = SystemTime::now();
+/// The monitor's own CPU share (of the whole machine, like top-N
+/// processes) and resident memory.
+#[derive(Debug, Clone, Copy)]
+pub struct SelfUsage {
+    pub cpu_percent: f32,
+    pub rss_bytes: u64,
+}
 
-    let cpu_usage_percent = read_cpu_usage_percent_delta()?;
-    let ram_usage_percent = read_ram_usage_percent()?;
+/// Number of processes and threads on the system.
+#[derive(Debug, Clone, Copy)]
+pub struct TaskCounts {
+    pub processes: u64,
+    pub threads: u64,
+}
 
-    // IO not implemented yet.
-    let io_read_bytes_per_s = 0.0;
-    let io_write_bytes_per_s = 0.0;
-    let top_processes = Vec::new();
+/// CPU time shares since the previous /proc/stat read.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuUsage {
+    /// Busy time, excluding steal.
+    pub usage_percent: f32,
+    /// Time the hypervisor ran other guests while this one was runnable.
+    pub steal_percent: f32,
+    /// Idle time with IO outstanding; counted as idle, not usage.
+    pub iowait_percent: f32,
+}
 
-    Ok(SystemSnapshot {
-        timestamp,
-        cpu_usage_percent,
-        ram_usage_percent,
-        io_read_bytes_per_s,
-        io_write_bytes_per_s,
-        top_processes,
-    })
+/// System metrics snapshot for one tick.
+#[derive(Debug, Clone)]
+pub struct SystemSnapshot {
+    pub timestamp: SystemTime,
+    pub cpu_usage_percent: f32,
+    /// Host CPU steal time; 0 on bare metal and on Windows.
+    pub cpu_steal_percent: f32,
+    /// Host CPU iowait; 0 on Windows.
+    pub cpu_iowait_percent: f32,
+    pub ram_usage_percent: f32,
+    /// Used RAM (total minus available) and total RAM, in bytes.
+    pub ram_used_bytes: u64,
+    pub ram_total_bytes: u64,
+    pub swap_usage_percent: f32,
+    pub io_read_bytes_per_s: f64,
+    pub io_write_bytes_per_s: f64,
+    pub cpu_temp_celsius: Option<f32>,
+    /// Battery charge and discharge rate (negative while charging); only
+    /// collected with `Collectors::power`, None without a battery.
+    pub battery_percent: Option<f32>,
+    pub power_draw_watts: Option<f32>,
+    /// Open file handles; only collected with `Collectors::fd`.
+    pub fd_usage: Option<FdUsage>,
+    /// Process and thread counts; only collected with `Collectors::tasks`.
+    pub task_counts: Option<TaskCounts>,
+    pub disk_usage: Vec<DiskUsage>,
+    /// Top N processes by CPU, then RSS.
+    pub top_processes: Vec<ProcessSample>,
+    /// Top N processes by RSS, for RAM spikes.
+    pub top_processes_by_ram: Vec<ProcessSample>,
+    /// The monitor's own usage; only collected with
+    /// `RuntimeConfig::report_self_overhead`.
+    pub self_usage: Option<SelfUsage>,
+    /// Seconds since boot; None when unavailable (or replayed from a
+    /// stream recorded without it).
+    pub uptime_secs: Option<u64>,
+    /// Monotonic time of the sample, so spike durations survive wall
+    /// clock steps; None for replayed snapshots.
+    pub instant: Option<Instant>,
+}
+
+/// Cumulative IO counters of one block device, for `list --devices`.
+#[derive(Debug, Clone)]
+pub struct BlockDevice {
+    /// Name as accepted by `--io-device`.
+    pub name: String,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    /// Summed into the IO rates when no `--io-device` is given.
+    pub default: bool,
 }
 
-/// Read aggregated CPU times from /proc/stat.
-fn read_raw_cpu_times() -> Result<CpuTimes, Box<dyn Error>> {
-    let contents = fs::read_to_string("/proc/stat")?;
-    let mut lines = contents.lines();
+/// Cumulative traffic counters of one network interface, for
+/// `list --interfaces`.
+#[derive(Debug, Clone)]
+pub struct NetInterface {
+    pub name: String,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
 
-    let first_line = lines
-        .next()
-        .ok_or("Empty /proc/stat or unexpected format")?;
+/// Sectors in /proc/diskstats are 512-byte units regardless of the
+/// device's physical sector size.
+pub const DISKSTATS_SECTOR_SIZE: u64 = 512;
 
-    let mut parts = first_line.split_whitespace();
+/// A platform backend producing one snapshot per call.
+///
+/// Rates (CPU %, IO bytes/s) are deltas since the previous call on the
+/// same source, so the first snapshot reports them as 0.
+pub trait MetricsSource {
+    fn read_snapshot(&mut self, config: &RuntimeConfig) -> Result<SystemSnapshot, MonitorError>;
+}
 
-    let tag = parts.next().ok_or("Malformed 'cpu' line in /proc/stat")?;
-    if tag != "cpu" {
-        return Err("First line in /proc/stat does not start with 'cpu'".into());
-    }
+/// Snapshots on demand, for embedding without the built-in monitoring
+/// loop; the caller decides when to sample.
+///
+/// Each sampler owns its source and delta baselines, so independent
+/// samplers (e.g. with different cgroups or IO devices) can coexist.
+/// As with any source, the first snapshot reports rates as 0.
+///
+/// Also an endless iterator of `next_sample` results.
+pub struct SnapshotSampler {
+    source: PlatformSource,
+    config: RuntimeConfig,
+}
 
-    let mut values: Vec<u64> = Vec::new();
-    for p in parts {
-        if let Ok(v) = p.parse::<u64>() {
-            values.push(v);
+impl SnapshotSampler {
+    pub fn new(config: RuntimeConfig) -> Self {
+        Self {
+            source: PlatformSource::default(),
+            config,
         }
     }
 
-    if values.len() < 4 {
-        return Err("Not enough CPU fields in /proc/stat".into());
+    pub fn config(&self) -> &RuntimeConfig {
+        &self.config
     }
 
-    let user = values[0];
-    let nice = values[1];
-    let system = values[2];
-    let idle = values[3];
-    let iowait = values.get(4).copied().unwrap_or(0);
-    let irq = values.get(5).copied().unwrap_or(0);
-    let softirq = values.get(6).copied().unwrap_or(0);
-    let steal = values.get(7).copied().unwrap_or(0);
-
-    let idle_all = idle + iowait;
-    let non_idle = user + nice + system + irq + softirq + steal;
-    let total = idle_all + non_idle;
-
-    Ok(CpuTimes { idle_all, total })
+    /// Read one snapshot; rates are deltas since the previous call.
+    pub fn next_sample(&mut self) -> Result<SystemSnapshot, MonitorError> {
+        self.source.read_snapshot(&self.config)
+    }
 }
 
-/// CPU usage (%) based on delta between calls.
-fn read_cpu_usage_percent_delta() -> Result<f32, Box<dyn Error>> {
-    let current = read_raw_cpu_times()?;
-
-    let state_mutex = cpu_state();
-    let mut guard = state_mutex
-        .lock()
-        .map_err(|_| "Failed to lock CPU state mutex")?;
+impl Iterator for SnapshotSampler {
+    type Item = Result<SystemSnapshot, MonitorError>;
 
-    if let Some(prev) = *guard {
-        let delta_total = current.total.saturating_sub(prev.total);
-        let delta_idle = current.idle_all.saturating_sub(prev.idle_all);
-
-        *guard = Some(current);
-
-        if delta_total == 0 {
-            return Ok(0.0);
-        }
-
-        let non_idle = delta_total.saturating_sub(delta_idle);
-        let usage = (non_idle as f32 / delta_total as f32) * 100.0;
-        Ok(usage)
-    } else {
-        *guard = Some(current);
-        Ok(0.0)
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_sample())
     }
 }
 
-/// RAM usage (%) from /proc/meminfo.
-fn read_ram_usage_percent() -> Result<f32, Box<dyn Error>> {
-    let contents = fs::read_to_string("/proc/meminfo")?;
-
-    let mut mem_total_kb: Option<u64> = None;
-    let mut mem_available_kb: Option<u64> = None;
-
-    for line in contents.lines() {
-        if line.starts_with("MemTotal:") {
-            if let Some(value_str) = line.split_whitespace().nth(1) {
-                if let Ok(v) = value_str.parse::<u64>() {
-                    mem_total_kb = Some(v);
-                }
-            }
-        } else if line.starts_with("MemAvailable:") {
-            if let Some(value_str) = line.split_whitespace().nth(1) {
-                if let Ok(v) = value_str.parse::<u64>() {
-                    mem_available_kb = Some(v);
-                }
-            }
+#[cfg(test)]
+impl SystemSnapshot {
+    /// Snapshot taken `secs` after the epoch with every metric zero or
+    /// uncollected, for tests to fill in.
+    pub(crate) fn at_secs(secs: u64) -> Self {
+        Self {
+            timestamp: SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs),
+            cpu_usage_percent: 0.0,
+            cpu_steal_percent: 0.0,
+            cpu_iowait_percent: 0.0,
+            ram_usage_percent: 0.0,
+            ram_used_bytes: 0,
+            ram_total_bytes: 0,
+            swap_usage_percent: 0.0,
+            io_read_bytes_per_s: 0.0,
+            io_write_bytes_per_s: 0.0,
+            cpu_temp_celsius: None,
+            battery_percent: None,
+            power_draw_watts: None,
+            fd_usage: None,
+            task_counts: None,
+            disk_usage: Vec::new(),
+            top_processes: Vec::new(),
+            top_processes_by_ram: Vec::new(),
+            self_usage: None,
+            uptime_secs: None,
+            instant: None,
         }
     }
-
-    let mem_total = mem_total_kb.ok_or("Missing MemTotal in /proc/meminfo")?;
-    let mem_available =
-        mem_available_kb.ok_or("Missing MemAvailable in /proc/meminfo")?;
-
-    if mem_total == 0 {
-        return Ok(0.0);
-    }
-
-    let used = mem_total.saturating_sub(mem_available);
-    let usage_percent = (used as f32 / mem_total as f32) * 100.0;
-
-    Ok(usage_percent)
 }
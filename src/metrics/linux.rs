@@ -0,0 +1,1722 @@
+//! Linux metrics source: /proc, /sys and cgroup v2 files.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::mem::MaybeUninit;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+use std::time::{Instant, SystemTime};
+
+use super::{
+    BlockDevice, CpuUsage, DiskUsage, FdUsage, MemUsage, MetricsSource, NetInterface, PowerUsage,
+    ProcessSample, SelfUsage, SystemSnapshot, TaskCounts,
+};
+use crate::config::{ProcFilter, RuntimeConfig};
+use crate::diag::trace;
+use crate::error::MonitorError;
+
+/// Raw CPU times from /proc/stat.
+#[derive(Debug, Clone, Copy)]
+struct CpuTimes {
+    idle_all: u64,
+    iowait: u64,
+    steal: u64,
+    total: u64,
+}
+
+impl CpuTimes {
+    /// CPU usage, steal and iowait (%) from `prev` to these times.
+    fn usage_since(&self, prev: &CpuTimes) -> CpuUsage {
+        let delta_total = self.total.saturating_sub(prev.total);
+        let delta_idle = self.idle_all.saturating_sub(prev.idle_all);
+        let delta_steal = self.steal.saturating_sub(prev.steal);
+        let delta_iowait = self.iowait.saturating_sub(prev.iowait);
+        trace!(
+            "cpu ticks delta: total={} idle={} steal={} iowait={}",
+            delta_total,
+            delta_idle,
+            delta_steal,
+            delta_iowait
+        );
+
+        if delta_total == 0 {
+            return CpuUsage::default();
+        }
+
+        let busy = delta_total
+            .saturating_sub(delta_idle)
+            .saturating_sub(delta_steal);
+        CpuUsage {
+            usage_percent: (busy as f32 / delta_total as f32) * 100.0,
+            steal_percent: (delta_steal as f32 / delta_total as f32) * 100.0,
+            iowait_percent: (delta_iowait as f32 / delta_total as f32) * 100.0,
+        }
+    }
+}
+
+/// Cumulative disk byte counters and when they were read.
+#[derive(Debug, Clone, Copy)]
+struct IoCounters {
+    at: Instant,
+    read_bytes: u64,
+    write_bytes: u64,
+}
+
+impl IoCounters {
+    /// Read/write bytes per second from `prev` to these counters, over
+    /// the time measured between the two reads.
+    fn rates_since(&self, prev: &IoCounters) -> (f64, f64) {
+        let elapsed = self.at.duration_since(prev.at).as_secs_f64();
+        if elapsed <= 0.0 {
+            return (0.0, 0.0);
+        }
+
+        let read = self.read_bytes.saturating_sub(prev.read_bytes);
+        let write = self.write_bytes.saturating_sub(prev.write_bytes);
+        trace!(
+            "diskstats delta: read={} write={} bytes over {:.3}s",
+            read,
+            write,
+            elapsed
+        );
+
+        (read as f64 / elapsed, write as f64 / elapsed)
+    }
+}
+
+/// Cumulative cgroup CPU time and when it was read.
+#[derive(Debug, Clone, Copy)]
+struct CgroupCpu {
+    at: Instant,
+    usage_usec: u64,
+}
+
+/// CPU ticks per process and total CPU ticks at the previous process scan.
+#[derive(Debug, Default)]
+struct ProcTimes {
+    total: u64,
+    ticks: HashMap<u32, u64>,
+}
+
+/// This process's CPU ticks and total CPU ticks at the previous read.
+#[derive(Debug, Clone, Copy)]
+struct OwnTimes {
+    total: u64,
+    ticks: u64,
+}
+
+/// Where the kernel's proc filesystem is mounted.
+const PROC_ROOT: &str = "/proc";
+
+/// Read a whole /proc file, keeping the path in the error.
+fn read_proc_file(path: impl AsRef<Path>) -> Result<String, MonitorError> {
+    let path = path.as_ref();
+    fs::read_to_string(path).map_err(|e| MonitorError::proc_read(path.display().to_string(), e))
+}
+
+/// Metrics source reading /proc and /sys.
+///
+/// Delta baselines (CPU, IO, cgroup CPU, per-process ticks) are kept per
+/// source, so independent sources don't disturb each other's rates.
+#[derive(Debug)]
+pub struct ProcSource {
+    /// The proc filesystem every /proc read goes through.
+    proc_root: PathBuf,
+    cpu: Option<CpuTimes>,
+    io: Option<IoCounters>,
+    cgroup_cpu: Option<CgroupCpu>,
+    procs: Option<ProcTimes>,
+    own: Option<OwnTimes>,
+}
+
+impl Default for ProcSource {
+    fn default() -> Self {
+        Self::with_proc_root(PROC_ROOT)
+    }
+}
+
+impl ProcSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A source reading `proc_root` instead of /proc, e.g. a host's proc
+    /// mounted into a container, or a fixture directory.
+    pub fn with_proc_root(proc_root: impl Into<PathBuf>) -> Self {
+        Self {
+            proc_root: proc_root.into(),
+            cpu: None,
+            io: None,
+            cgroup_cpu: None,
+            procs: None,
+            own: None,
+        }
+    }
+
+    /// CPU usage, steal and iowait time (%) since the previous call.
+    ///
+    /// Steal is reported on its own and not counted as usage, so a noisy
+    /// neighbor on the hypervisor doesn't look like local load.
+    pub fn read_cpu_usage_percent_delta(&mut self) -> Result<CpuUsage, MonitorError> {
+        let current = read_raw_cpu_times(&self.proc_root)?;
+
+        match self.cpu.replace(current) {
+            Some(prev) => Ok(current.usage_since(&prev)),
+            None => Ok(CpuUsage::default()),
+        }
+    }
+
+    /// Disk read/write throughput (bytes/s) since the previous call.
+    ///
+    /// Rates are divided by the measured time between reads, not the
+    /// configured interval, so sleep jitter or slow ticks don't skew them.
+    /// The first call has no baseline and reports 0.0.
+    ///
+    /// `devices` restricts the sum to those diskstats names (partitions
+    /// included); when empty, all whole physical disks are summed.
+    pub fn read_io_bytes_per_s_delta(
+        &mut self,
+        devices: &[String],
+        sector_size: u64,
+    ) -> Result<(f64, f64), MonitorError> {
+        let contents = read_proc_file(self.proc_root.join("diskstats"))?;
+        let (read_sectors, write_sectors) = if devices.is_empty() {
+            parse_diskstats(&contents, is_whole_disk)
+        } else {
+            parse_diskstats(&contents, |name| devices.iter().any(|d| d == name))
+        };
+
+        let current = IoCounters {
+            at: Instant::now(),
+            read_bytes: read_sectors * sector_size,
+            write_bytes: write_sectors * sector_size,
+        };
+
+        match self.io.replace(current) {
+            Some(prev) => Ok(current.rates_since(&prev)),
+            None => Ok((0.0, 0.0)),
+        }
+    }
+
+    /// CPU usage (%) of a cgroup v2 since the previous call.
+    ///
+    /// Derived from the `usage_usec` delta in cpu.stat over the measured time.
+    /// 100% means the cgroup used all the CPU it may: its cpu.max quota, or
+    /// every online CPU when unlimited. The first call reports 0.0.
+    pub fn read_cgroup_cpu_percent_delta(&mut self, dir: &str) -> Result<f32, MonitorError> {
+        let path = format!("{}/cpu.stat", dir);
+        let usage_usec = parse_cgroup_cpu_stat(&read_proc_file(&path)?)
+            .ok_or_else(|| MonitorError::Parse(format!("Missing usage_usec in {}", path)))?;
+
+        let current = CgroupCpu {
+            at: Instant::now(),
+            usage_usec,
+        };
+
+        let prev = match self.cgroup_cpu.replace(current) {
+            Some(p) => p,
+            None => return Ok(0.0),
+        };
+
+        let elapsed_usec = current.at.duration_since(prev.at).as_secs_f64() * 1_000_000.0;
+        if elapsed_usec <= 0.0 {
+            return Ok(0.0);
+        }
+
+        // cpu.max is optional (absent in the root cgroup).
+        let quota_cpus = fs::read_to_string(format!("{}/cpu.max", dir))
+            .ok()
+            .and_then(|c| parse_cgroup_cpu_max(&c));
+        let cpus = match quota_cpus {
+            Some(q) => q.min(online_cpus()),
+            None => online_cpus(),
+        };
+
+        let used = current.usage_usec.saturating_sub(prev.usage_usec) as f64;
+        Ok((used / (elapsed_usec * cpus) * 100.0) as f32)
+    }
+
+    /// Top N processes by CPU share since the previous scan.
+    ///
+    /// Filters are applied before truncation, so N is the number of matching
+    /// processes. The first scan has no baseline and reports 0% CPU.
+    pub fn read_top_processes(
+        &mut self,
+        top_n: usize,
+        filter: &ProcFilter,
+    ) -> Result<Vec<ProcessSample>, MonitorError> {
+        Ok(self.scan_processes(top_n, filter, None)?.top)
+    }
+
+    /// Walk /proc for the top N processes by CPU and by RSS; with
+    /// `cmdline_max_len`, the survivors are renamed after their command line
+    /// (filters still match the comm).
+    fn scan_processes(
+        &mut self,
+        top_n: usize,
+        filter: &ProcFilter,
+        cmdline_max_len: Option<usize>,
+    ) -> Result<ProcessScan, MonitorError> {
+        let total = read_raw_cpu_times(&self.proc_root)?.total;
+        let page_size = page_size();
+
+        let prev = self.procs.take().unwrap_or_default();
+        let delta_total = total.saturating_sub(prev.total);
+
+        let mut current = ProcTimes {
+            total,
+            ticks: HashMap::new(),
+        };
+        let mut processes = Vec::new();
+        let mut count = 0;
+
+        let entries = fs::read_dir(&self.proc_root)
+            .map_err(|e| MonitorError::proc_read(self.proc_root.display().to_string(), e))?;
+        for entry in entries.flatten() {
+            let pid = match entry
+                .file_name()
+                .to_str()
+                .and_then(|n| n.parse::<u32>().ok())
+            {
+                Some(pid) => pid,
+                None => continue,
+            };
+
+            // Processes may exit between listing and reading; just skip them.
+            let stat = match fs::read_to_string(entry.path().join("stat"))
+                .ok()
+                .and_then(|c| parse_proc_stat(&c))
+            {
+                Some(s) => s,
+                None => continue,
+            };
+
+            current.ticks.insert(pid, stat.cpu_ticks);
+            count += 1;
+
+            if !filter.matches(&stat.comm, stat.kernel_thread) {
+                continue;
+            }
+
+            let cpu_percent = match prev.ticks.get(&pid) {
+                Some(&before) if delta_total > 0 => {
+                    let delta = stat.cpu_ticks.saturating_sub(before);
+                    (delta as f32 / delta_total as f32) * 100.0
+                }
+                _ => 0.0,
+            };
+
+            processes.push(ProcessSample {
+                pid,
+                name: stat.comm,
+                cpu_percent,
+                ram_bytes: stat.rss_pages * page_size,
+            });
+        }
+
+        self.procs = Some(current);
+
+        let mut by_ram = processes.clone();
+        by_ram.sort_by_key(|p| Reverse(p.ram_bytes));
+        by_ram.truncate(top_n);
+
+        processes.sort_by(|a, b| {
+            b.cpu_percent
+                .total_cmp(&a.cpu_percent)
+                .then(b.ram_bytes.cmp(&a.ram_bytes))
+        });
+        processes.truncate(top_n);
+
+        // Only the reported processes pay for the extra read.
+        if let Some(max_len) = cmdline_max_len {
+            for p in processes.iter_mut().chain(by_ram.iter_mut()) {
+                p.name = read_cmdline_name(&self.proc_root, p.pid, &p.name, max_len);
+            }
+        }
+
+        Ok(ProcessScan {
+            top: processes,
+            top_by_ram: by_ram,
+            count,
+        })
+    }
+
+    /// The monitor's own CPU share since the previous call, computed like
+    /// a top-N process's from /proc/self/stat, and its RSS. CPU is 0 on
+    /// the first call.
+    fn read_self_usage_delta(&mut self) -> Result<SelfUsage, MonitorError> {
+        let total = read_raw_cpu_times(&self.proc_root)?.total;
+        let path = self.proc_root.join("self/stat");
+        let stat = parse_proc_stat(&read_proc_file(&path)?).ok_or_else(|| {
+            MonitorError::Parse(format!("Unexpected format in {}", path.display()))
+        })?;
+
+        let cpu_percent = match self.own {
+            Some(prev) if total > prev.total => {
+                let delta = stat.cpu_ticks.saturating_sub(prev.ticks);
+                (delta as f32 / (total - prev.total) as f32) * 100.0
+            }
+            _ => 0.0,
+        };
+        self.own = Some(OwnTimes {
+            total,
+            ticks: stat.cpu_ticks,
+        });
+
+        Ok(SelfUsage {
+            cpu_percent,
+            rss_bytes: stat.rss_pages * page_size(),
+        })
+    }
+}
+
+impl MetricsSource for ProcSource {
+    fn read_snapshot(&mut self, config: &RuntimeConfig) -> Result<SystemSnapshot, MonitorError> {
+        let timestamp = SystemTime::now();
+        let collect = config.collectors;
+
+        // Steal and iowait are host properties, so they are read from
+        // /proc/stat even when CPU usage comes from a cgroup.
+        let host_cpu = if collect.cpu {
+            self.read_cpu_usage_percent_delta()?
+        } else {
+            CpuUsage::default()
+        };
+        let cpu_usage_percent = match &config.cgroup {
+            Some(cgroup) if collect.cpu => self.read_cgroup_cpu_percent_delta(cgroup)?,
+            _ => host_cpu.usage_percent,
+        };
+        let mem = match &config.cgroup {
+            _ if !collect.ram => MemUsage::default(),
+            Some(cgroup) => read_cgroup_mem_usage_at(&self.proc_root, cgroup)?,
+            None => read_mem_usage_at(&self.proc_root)?,
+        };
+
+        let (io_read_bytes_per_s, io_write_bytes_per_s) = if collect.io {
+            self.read_io_bytes_per_s_delta(&config.io_devices, config.io_sector_size)?
+        } else {
+            (0.0, 0.0)
+        };
+
+        // The top-N scan already walks /proc; reuse its process count.
+        let (top_processes, top_processes_by_ram, scanned) = if config.top_n_procs > 0 {
+            let scan = self.scan_processes(
+                config.top_n_procs,
+                &config.proc_filter,
+                config.proc_cmdline_max_len,
+            )?;
+            (scan.top, scan.top_by_ram, Some(scan.count))
+        } else {
+            (Vec::new(), Vec::new(), None)
+        };
+
+        let task_counts = if collect.tasks {
+            let processes = match scanned {
+                Some(count) => count,
+                None => count_pid_dirs(&self.proc_root)?,
+            };
+            Some(TaskCounts {
+                processes,
+                threads: read_thread_count_at(&self.proc_root)?,
+            })
+        } else {
+            None
+        };
+
+        let cpu_temp_celsius = if collect.temp {
+            read_cpu_temp_celsius()
+        } else {
+            None
+        };
+
+        let power = if collect.power {
+            read_power_usage()
+        } else {
+            None
+        };
+
+        let fd_usage = if collect.fd {
+            Some(read_fd_usage_at(&self.proc_root)?)
+        } else {
+            None
+        };
+
+        let mut disk_usage = Vec::with_capacity(config.watch_paths.len());
+        for path in &config.watch_paths {
+            disk_usage.push(DiskUsage {
+                path: path.clone(),
+                used_percent: read_disk_used_percent(path)?,
+            });
+        }
+
+        // Read last, so the ticks include this sample's own reads.
+        let self_usage = if config.report_self_overhead {
+            Some(self.read_self_usage_delta()?)
+        } else {
+            None
+        };
+
+        Ok(SystemSnapshot {
+            timestamp,
+            cpu_usage_percent,
+            cpu_steal_percent: host_cpu.steal_percent,
+            cpu_iowait_percent: host_cpu.iowait_percent,
+            ram_usage_percent: mem.ram_percent,
+            ram_used_bytes: mem.ram_used_bytes,
+            ram_total_bytes: mem.ram_total_bytes,
+            swap_usage_percent: mem.swap_percent,
+            io_read_bytes_per_s,
+            io_write_bytes_per_s,
+            cpu_temp_celsius,
+            battery_percent: power.and_then(|p| p.battery_percent),
+            power_draw_watts: power.and_then(|p| p.draw_watts),
+            fd_usage,
+            task_counts,
+            disk_usage,
+            top_processes,
+            top_processes_by_ram,
+            self_usage,
+            uptime_secs: read_uptime_secs_at(&self.proc_root),
+            instant: Some(Instant::now()),
+        })
+    }
+}
+
+/// Read aggregated CPU times from /proc/stat.
+fn read_raw_cpu_times(proc_root: &Path) -> Result<CpuTimes, MonitorError> {
+    parse_cpu_times(&read_proc_file(proc_root.join("stat"))?)
+}
+
+/// Aggregated CPU times from the first (`cpu`) line of /proc/stat.
+fn parse_cpu_times(contents: &str) -> Result<CpuTimes, MonitorError> {
+    let mut lines = contents.lines();
+
+    let first_line = lines
+        .next()
+        .ok_or_else(|| MonitorError::Parse("Empty /proc/stat or unexpected format".into()))?;
+
+    let mut parts = first_line.split_whitespace();
+
+    let tag = parts
+        .next()
+        .ok_or_else(|| MonitorError::Parse("Malformed 'cpu' line in /proc/stat".into()))?;
+    if tag != "cpu" {
+        return Err(MonitorError::Parse(
+            "First line in /proc/stat does not start with 'cpu'".into(),
+        ));
+    }
+
+    let mut values: Vec<u64> = Vec::new();
+    for p in parts {
+        if let Ok(v) = p.parse::<u64>() {
+            values.push(v);
+        }
+    }
+
+    if values.len() < 4 {
+        return Err(MonitorError::Parse("Not enough CPU fields in /proc/stat".into()));
+    }
+
+    let user = values[0];
+    let nice = values[1];
+    let system = values[2];
+    let idle = values[3];
+    let iowait = values.get(4).copied().unwrap_or(0);
+    let irq = values.get(5).copied().unwrap_or(0);
+    let softirq = values.get(6).copied().unwrap_or(0);
+    let steal = values.get(7).copied().unwrap_or(0);
+
+    let idle_all = idle + iowait;
+    let non_idle = user + nice + system + irq + softirq + steal;
+    let total = idle_all + non_idle;
+
+    Ok(CpuTimes {
+        idle_all,
+        iowait,
+        steal,
+        total,
+    })
+}
+
+/// Sum sectors read and written over the /proc/diskstats rows accepted by
+/// `include` (called with the device name).
+fn parse_diskstats(contents: &str, include: impl Fn(&str) -> bool) -> (u64, u64) {
+    let mut read_sectors: u64 = 0;
+    let mut write_sectors: u64 = 0;
+
+    for (name, read, write) in parse_diskstats_rows(contents) {
+        if include(name) {
+            read_sectors += read;
+            write_sectors += write;
+        }
+    }
+
+    (read_sectors, write_sectors)
+}
+
+/// (device name, sectors read, sectors written) per /proc/diskstats row.
+fn parse_diskstats_rows(contents: &str) -> impl Iterator<Item = (&str, u64, u64)> {
+    contents.lines().filter_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 10 {
+            return None;
+        }
+
+        Some((
+            fields[2],
+            fields[5].parse::<u64>().unwrap_or(0),
+            fields[9].parse::<u64>().unwrap_or(0),
+        ))
+    })
+}
+
+/// Every block device in /proc/diskstats with its cumulative counters.
+pub fn read_block_devices(sector_size: u64) -> Result<Vec<BlockDevice>, MonitorError> {
+    read_block_devices_at(Path::new(PROC_ROOT), sector_size)
+}
+
+fn read_block_devices_at(
+    proc_root: &Path,
+    sector_size: u64,
+) -> Result<Vec<BlockDevice>, MonitorError> {
+    let contents = read_proc_file(proc_root.join("diskstats"))?;
+    Ok(parse_diskstats_rows(&contents)
+        .map(|(name, read, write)| BlockDevice {
+            name: name.to_string(),
+            read_bytes: read * sector_size,
+            write_bytes: write * sector_size,
+            default: is_whole_disk(name),
+        })
+        .collect())
+}
+
+/// Every network interface in /proc/net/dev with its cumulative counters.
+pub fn read_net_interfaces() -> Result<Vec<NetInterface>, MonitorError> {
+    read_net_interfaces_at(Path::new(PROC_ROOT))
+}
+
+fn read_net_interfaces_at(proc_root: &Path) -> Result<Vec<NetInterface>, MonitorError> {
+    let contents = read_proc_file(proc_root.join("net/dev"))?;
+    Ok(parse_net_dev(&contents))
+}
+
+/// Parse /proc/net/dev: two header lines, then
+/// `  eth0: <rx bytes> <7 more rx fields> <tx bytes> ...` per interface.
+fn parse_net_dev(contents: &str) -> Vec<NetInterface> {
+    contents
+        .lines()
+        .skip(2)
+        .filter_map(|line| {
+            let (name, counters) = line.split_once(':')?;
+            let fields: Vec<&str> = counters.split_whitespace().collect();
+            Some(NetInterface {
+                name: name.trim().to_string(),
+                rx_bytes: fields.first()?.parse().ok()?,
+                tx_bytes: fields.get(8)?.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Whole physical disks only: partitions would double-count their disk,
+/// and loop/ram/device-mapper/md devices sit on top of other devices.
+fn is_whole_disk(name: &str) -> bool {
+    const VIRTUAL_PREFIXES: [&str; 5] = ["loop", "ram", "zram", "dm-", "md"];
+    if VIRTUAL_PREFIXES.iter().any(|p| name.starts_with(p)) {
+        return false;
+    }
+
+    Path::new("/sys/block").join(name).exists()
+}
+
+/// RAM and swap usage from /proc/meminfo.
+pub fn read_mem_usage() -> Result<MemUsage, MonitorError> {
+    read_mem_usage_at(Path::new(PROC_ROOT))
+}
+
+fn read_mem_usage_at(proc_root: &Path) -> Result<MemUsage, MonitorError> {
+    parse_meminfo(&read_proc_file(proc_root.join("meminfo"))?)
+}
+
+/// Parse /proc/meminfo into RAM and swap usage.
+///
+/// Kernels before 3.14 have no MemAvailable; available memory is then
+/// estimated as MemFree + Buffers + Cached, with a one-time warning.
+fn parse_meminfo(contents: &str) -> Result<MemUsage, MonitorError> {
+    let mut mem_total_kb: Option<u64> = None;
+    let mut mem_available_kb: Option<u64> = None;
+    let mut mem_free_kb: Option<u64> = None;
+    let mut buffers_kb: Option<u64> = None;
+    let mut cached_kb: Option<u64> = None;
+    let mut swap_total_kb: Option<u64> = None;
+    let mut swap_free_kb: Option<u64> = None;
+
+    for line in contents.lines() {
+        let mut parts = line.split_whitespace();
+        let key = parts.next();
+        let value = parts.next().and_then(|v| v.parse::<u64>().ok());
+
+        match key {
+            Some("MemTotal:") => mem_total_kb = value,
+            Some("MemAvailable:") => mem_available_kb = value,
+            Some("MemFree:") => mem_free_kb = value,
+            Some("Buffers:") => buffers_kb = value,
+            Some("Cached:") => cached_kb = value,
+            Some("SwapTotal:") => swap_total_kb = value,
+            Some("SwapFree:") => swap_free_kb = value,
+            _ => {}
+        }
+    }
+
+    let mem_total = mem_total_kb
+        .ok_or_else(|| MonitorError::Parse("Missing MemTotal in /proc/meminfo".into()))?;
+
+    let mem_available = match mem_available_kb {
+        Some(available) => available,
+        None => {
+            let free = mem_free_kb.ok_or_else(|| {
+                MonitorError::Parse("Missing MemAvailable and MemFree in /proc/meminfo".into())
+            })?;
+
+            static WARN_NO_AVAILABLE: Once = Once::new();
+            WARN_NO_AVAILABLE.call_once(|| {
+                eprintln!(
+                    "[monitor] /proc/meminfo has no MemAvailable (kernel < 3.14); \
+                     estimating it as MemFree + Buffers + Cached."
+                );
+            });
+
+            free + buffers_kb.unwrap_or(0) + cached_kb.unwrap_or(0)
+        }
+    };
+
+    // No swap configured is not an error.
+    let swap_percent = match (swap_total_kb, swap_free_kb) {
+        (Some(total), Some(free)) => used_percent(total, free),
+        _ => 0.0,
+    };
+
+    Ok(MemUsage {
+        ram_percent: used_percent(mem_total, mem_available),
+        swap_percent,
+        ram_used_bytes: mem_total.saturating_sub(mem_available) * 1024,
+        ram_total_bytes: mem_total * 1024,
+    })
+}
+
+/// Used share (%) of `total`, given the amount still free/available.
+fn used_percent(total: u64, free: u64) -> f32 {
+    if total == 0 {
+        return 0.0;
+    }
+
+    let used = total.saturating_sub(free);
+    (used as f32 / total as f32) * 100.0
+}
+
+/// Check that /proc is mounted, so running without it (another OS, a
+/// container without procfs) fails at startup instead of on every sample.
+pub fn check_platform() -> Result<(), MonitorError> {
+    check_proc_root(Path::new(PROC_ROOT))
+}
+
+/// Check that `root` is a proc filesystem, i.e. has a readable `stat`.
+pub fn check_proc_root(root: &Path) -> Result<(), MonitorError> {
+    if root.join("stat").is_file() {
+        return Ok(());
+    }
+
+    Err(MonitorError::Config(format!(
+        "This tool requires Linux /proc; current platform unsupported ({} not found)",
+        root.join("stat").display()
+    )))
+}
+
+/// Check that `dir` is a cgroup v2 directory with the files `--cgroup`
+/// reads, so a wrong path fails at startup instead of on every sample.
+pub fn check_cgroup(dir: &str) -> Result<(), MonitorError> {
+    for file in ["cpu.stat", "memory.current", "memory.max"] {
+        let path = Path::new(dir).join(file);
+        if !path.is_file() {
+            return Err(MonitorError::Config(format!(
+                "'{}' is not a cgroup v2 directory: missing {}",
+                dir, file
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Memory and swap usage of a cgroup v2.
+///
+/// Usage is memory.current against the cgroup's limit: memory.max, or
+/// memory.high (the throttling point) when memory.max is "max". A cgroup
+/// with neither, or a limit above the host's MemTotal, is measured
+/// against MemTotal, so the percentage is always of memory it can
+/// actually get. Swap uses memory.swap.current/memory.swap.max when both
+/// exist and are limited.
+pub fn read_cgroup_mem_usage(dir: &str) -> Result<MemUsage, MonitorError> {
+    read_cgroup_mem_usage_at(Path::new(PROC_ROOT), dir)
+}
+
+fn read_cgroup_mem_usage_at(proc_root: &Path, dir: &str) -> Result<MemUsage, MonitorError> {
+    let current = read_cgroup_value(&format!("{}/memory.current", dir))?
+        .ok_or_else(|| MonitorError::Parse(format!("Unexpected 'max' in {}/memory.current", dir)))?;
+
+    // memory.high is absent in the root cgroup.
+    let limit = match read_cgroup_value(&format!("{}/memory.max", dir))? {
+        Some(max) => Some(max),
+        None => read_cgroup_value(&format!("{}/memory.high", dir)).unwrap_or(None),
+    };
+    let host_total = read_mem_total_bytes(proc_root)?;
+    let limit = limit.map_or(host_total, |l| l.min(host_total));
+
+    let swap_current = read_cgroup_value(&format!("{}/memory.swap.current", dir));
+    let swap_max = read_cgroup_value(&format!("{}/memory.swap.max", dir));
+    let swap_percent = match (swap_current, swap_max) {
+        (Ok(Some(used)), Ok(Some(max))) => used_percent(max, max.saturating_sub(used)),
+        _ => 0.0,
+    };
+
+    Ok(MemUsage {
+        ram_percent: used_percent(limit, limit.saturating_sub(current)),
+        swap_percent,
+        ram_used_bytes: current,
+        ram_total_bytes: limit,
+    })
+}
+
+/// Read a single-value cgroup file; None means "max" (unlimited).
+fn read_cgroup_value(path: &str) -> Result<Option<u64>, MonitorError> {
+    let contents = read_proc_file(path)?;
+    parse_cgroup_value(&contents)
+        .ok_or_else(|| MonitorError::Parse(format!("Unexpected contents in {}", path)))
+}
+
+/// Parse a cgroup value: a byte count, or "max" for no limit (None inside).
+fn parse_cgroup_value(contents: &str) -> Option<Option<u64>> {
+    match contents.trim() {
+        "max" => Some(None),
+        v => v.parse::<u64>().ok().map(Some),
+    }
+}
+
+/// `usage_usec` from cgroup v2 cpu.stat.
+fn parse_cgroup_cpu_stat(contents: &str) -> Option<u64> {
+    contents.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (Some("usage_usec"), Some(v)) => v.parse::<u64>().ok(),
+            _ => None,
+        }
+    })
+}
+
+/// CPU limit from cgroup v2 cpu.max (`<quota> <period>`), in CPUs.
+/// None when unlimited.
+fn parse_cgroup_cpu_max(contents: &str) -> Option<f64> {
+    let mut parts = contents.split_whitespace();
+    let quota = parts.next()?.parse::<f64>().ok()?;
+    let period = parts.next()?.parse::<f64>().ok()?;
+    if period <= 0.0 {
+        return None;
+    }
+
+    Some(quota / period)
+}
+
+/// MemTotal from /proc/meminfo, in bytes.
+fn read_mem_total_bytes(proc_root: &Path) -> Result<u64, MonitorError> {
+    let contents = read_proc_file(proc_root.join("meminfo"))?;
+    contents
+        .lines()
+        .find_map(|line| {
+            line.strip_prefix("MemTotal:")
+                .and_then(|v| v.split_whitespace().next())
+                .and_then(|v| v.parse::<u64>().ok())
+        })
+        .map(|kb| kb * 1024)
+        .ok_or_else(|| MonitorError::Parse("Missing MemTotal in /proc/meminfo".into()))
+}
+
+/// CPU temperature (°C) from /sys/class/thermal.
+///
+/// Prefers the `x86_pkg_temp` zone, otherwise takes the highest reading.
+/// Any missing or unreadable zone is skipped; returns None if nothing is found.
+pub fn read_cpu_temp_celsius() -> Option<f32> {
+    read_thermal_zones(Path::new("/sys/class/thermal"))
+}
+
+fn read_thermal_zones(root: &Path) -> Option<f32> {
+    let entries = fs::read_dir(root).ok()?;
+
+    let mut highest: Option<f32> = None;
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        if !name.to_string_lossy().starts_with("thermal_zone") {
+            continue;
+        }
+
+        let zone = entry.path();
+        let temp = match read_zone_temp(&zone) {
+            Some(t) => t,
+            None => continue,
+        };
+
+        let zone_type = fs::read_to_string(zone.join("type")).unwrap_or_default();
+        if zone_type.trim() == "x86_pkg_temp" {
+            return Some(temp);
+        }
+
+        highest = Some(highest.map_or(temp, |h| h.max(temp)));
+    }
+
+    highest
+}
+
+fn read_zone_temp(zone: &Path) -> Option<f32> {
+    let contents = fs::read_to_string(zone.join("temp")).ok()?;
+    parse_thermal_temp(&contents)
+}
+
+/// Parse a thermal zone `temp` file (millidegrees Celsius).
+fn parse_thermal_temp(contents: &str) -> Option<f32> {
+    let millis = contents.trim().parse::<i64>().ok()?;
+    Some(millis as f32 / 1000.0)
+}
+
+/// Battery charge and power draw from /sys/class/power_supply.
+///
+/// Every `BAT*` supply counts: the charge is the mean of their
+/// capacities and the draw the sum of their rates. Unreadable files are
+/// skipped; returns None without a battery (desktops, servers).
+pub fn read_power_usage() -> Option<PowerUsage> {
+    read_power_supplies(Path::new("/sys/class/power_supply"))
+}
+
+fn read_power_supplies(root: &Path) -> Option<PowerUsage> {
+    let entries = fs::read_dir(root).ok()?;
+
+    let mut capacities = Vec::new();
+    let mut draw_watts: Option<f32> = None;
+
+    for entry in entries.flatten() {
+        if !entry.file_name().to_string_lossy().starts_with("BAT") {
+            continue;
+        }
+
+        let battery = entry.path();
+        let read = |file: &str| fs::read_to_string(battery.join(file)).ok();
+
+        if let Some(capacity) = read("capacity").and_then(|c| c.trim().parse::<f32>().ok()) {
+            capacities.push(capacity);
+        }
+        if let Some(watts) = parse_battery_draw_watts(
+            read("status").as_deref(),
+            read("power_now").as_deref(),
+            read("current_now").as_deref(),
+            read("voltage_now").as_deref(),
+        ) {
+            draw_watts = Some(draw_watts.unwrap_or(0.0) + watts);
+        }
+    }
+
+    if capacities.is_empty() && draw_watts.is_none() {
+        return None;
+    }
+    let battery_percent =
+        (!capacities.is_empty()).then(|| capacities.iter().sum::<f32>() / capacities.len() as f32);
+
+    Some(PowerUsage {
+        battery_percent,
+        draw_watts,
+    })
+}
+
+/// Discharge rate (W) of one battery from its `status` and `power_now`
+/// (µW), or `current_now` (µA) times `voltage_now` (µV) when the driver
+/// has no power_now. Negative while charging, 0 when full; drivers
+/// differ in the sign of the raw values, so only `status` decides it.
+fn parse_battery_draw_watts(
+    status: Option<&str>,
+    power_now: Option<&str>,
+    current_now: Option<&str>,
+    voltage_now: Option<&str>,
+) -> Option<f32> {
+    let micro = |s: Option<&str>| {
+        s?.trim()
+            .parse::<i64>()
+            .ok()
+            .map(|v| v.unsigned_abs() as f64)
+    };
+
+    let watts = match micro(power_now) {
+        Some(uw) => uw / 1e6,
+        None => micro(current_now)? * micro(voltage_now)? / 1e12,
+    };
+    let sign = match status.map(str::trim) {
+        Some("Charging") => -1.0,
+        Some("Full") | Some("Not charging") => 0.0,
+        _ => 1.0,
+    };
+
+    Some((watts * sign) as f32)
+}
+
+/// System-wide open file handles and their limit.
+pub fn read_fd_usage() -> Result<FdUsage, MonitorError> {
+    read_fd_usage_at(Path::new(PROC_ROOT))
+}
+
+fn read_fd_usage_at(proc_root: &Path) -> Result<FdUsage, MonitorError> {
+    let path = proc_root.join("sys/fs/file-nr");
+    let contents = read_proc_file(&path)?;
+    parse_file_nr(&contents)
+        .ok_or_else(|| MonitorError::Parse(format!("Unexpected format in {}", path.display())))
+}
+
+/// Parse `file-nr`: allocated handles, free allocated handles (always 0
+/// since 2.6), and the maximum.
+fn parse_file_nr(contents: &str) -> Option<FdUsage> {
+    let fields: Vec<u64> = contents
+        .split_whitespace()
+        .map(|f| f.parse().ok())
+        .collect::<Option<_>>()?;
+
+    match fields[..] {
+        [allocated, free, max] => Some(FdUsage {
+            open: allocated.saturating_sub(free),
+            max,
+        }),
+        _ => None,
+    }
+}
+
+/// Filesystem usage (%) for the mount containing `path`, via statvfs.
+///
+/// Matches `df`: used / (used + available to unprivileged users).
+pub fn read_disk_used_percent(path: &str) -> Result<f32, MonitorError> {
+    let c_path = CString::new(path)
+        .map_err(|_| MonitorError::Config(format!("Watch path '{}' contains a NUL byte", path)))?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+
+    // SAFETY: `c_path` is a valid NUL-terminated string and `stat` points to
+    // writable memory large enough for a `statvfs` struct.
+    let rc = unsafe { libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) };
+    if rc != 0 {
+        let err = io::Error::last_os_error();
+        return Err(MonitorError::Io(io::Error::new(
+            err.kind(),
+            format!("statvfs failed for '{}': {}", path, err),
+        )));
+    }
+
+    // SAFETY: statvfs returned 0, so the struct has been initialized.
+    let stat = unsafe { stat.assume_init() };
+
+    // fsblkcnt_t is not u64 on every target.
+    #[allow(clippy::unnecessary_cast)]
+    let (blocks, bfree, bavail) = (stat.f_blocks as u64, stat.f_bfree as u64, stat.f_bavail as u64);
+
+    let used = blocks.saturating_sub(bfree);
+    let denom = used + bavail;
+
+    if denom == 0 {
+        return Ok(0.0);
+    }
+
+    Ok((used as f32 / denom as f32) * 100.0)
+}
+
+/// Fields of `/proc/[pid]/stat` used for process sampling.
+#[derive(Debug, Clone)]
+struct ProcStat {
+    comm: String,
+    kernel_thread: bool,
+    cpu_ticks: u64,
+    rss_pages: u64,
+}
+
+/// PF_KTHREAD in the `/proc/[pid]/stat` flags field.
+const PF_KTHREAD: u64 = 0x0020_0000;
+
+/// Result of one walk over /proc.
+struct ProcessScan {
+    /// By CPU, then RSS.
+    top: Vec<ProcessSample>,
+    top_by_ram: Vec<ProcessSample>,
+    /// Processes whose stat could be read, before filtering.
+    count: u64,
+}
+
+/// Command line of `pid` as a process name, like `ps -o args`: kernel
+/// threads (empty cmdline) show as `[comm]`. Keeps `comm` if the process
+/// exited since its stat was read.
+fn read_cmdline_name(proc_root: &Path, pid: u32, comm: &str, max_len: usize) -> String {
+    match fs::read(proc_root.join(pid.to_string()).join("cmdline")) {
+        Ok(bytes) => parse_cmdline(&bytes, max_len).unwrap_or_else(|| format!("[{}]", comm)),
+        Err(_) => comm.to_string(),
+    }
+}
+
+/// Join the NUL-separated arguments of `/proc/[pid]/cmdline` with spaces,
+/// truncated to `max_len` characters; None if there are none.
+///
+/// Control characters inside arguments become spaces so the name stays
+/// on one line in text output and logs.
+fn parse_cmdline(bytes: &[u8], max_len: usize) -> Option<String> {
+    let args: Vec<String> = bytes
+        .split(|&b| b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect();
+    if args.is_empty() {
+        return None;
+    }
+
+    let name = args
+        .join(" ")
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .take(max_len)
+        .collect();
+    Some(name)
+}
+
+/// Number of processes: the numeric entries of /proc.
+pub fn count_processes() -> Result<u64, MonitorError> {
+    count_pid_dirs(Path::new(PROC_ROOT))
+}
+
+/// Number of numeric (pid) entries in `proc_root`.
+fn count_pid_dirs(proc_root: &Path) -> Result<u64, MonitorError> {
+    let entries = fs::read_dir(proc_root)
+        .map_err(|e| MonitorError::proc_read(proc_root.display().to_string(), e))?;
+    let count = entries
+        .flatten()
+        .filter(|e| e.file_name().to_str().is_some_and(|n| n.parse::<u32>().is_ok()))
+        .count();
+    Ok(count as u64)
+}
+
+/// Number of threads system-wide, from the `running/total` field of
+/// /proc/loadavg; avoids reading every `/proc/[pid]/status`.
+pub fn read_thread_count() -> Result<u64, MonitorError> {
+    read_thread_count_at(Path::new(PROC_ROOT))
+}
+
+fn read_thread_count_at(proc_root: &Path) -> Result<u64, MonitorError> {
+    let path = proc_root.join("loadavg");
+    let contents = read_proc_file(&path)?;
+    parse_loadavg_threads(&contents)
+        .ok_or_else(|| MonitorError::Parse(format!("Unexpected format in {}", path.display())))
+}
+
+/// Parse the thread total from /proc/loadavg, e.g.
+/// `0.20 0.18 0.12 1/80 11206` -> 80.
+fn parse_loadavg_threads(contents: &str) -> Option<u64> {
+    let field = contents.split_whitespace().nth(3)?;
+    let (_, total) = field.split_once('/')?;
+    total.parse().ok()
+}
+
+/// Seconds since boot, from the first field of /proc/uptime.
+pub fn read_uptime_secs() -> Option<u64> {
+    read_uptime_secs_at(Path::new(PROC_ROOT))
+}
+
+fn read_uptime_secs_at(proc_root: &Path) -> Option<u64> {
+    let contents = fs::read_to_string(proc_root.join("uptime")).ok()?;
+    parse_uptime_secs(&contents)
+}
+
+/// Parse whole seconds from /proc/uptime, e.g. `35.72 131.45` -> 35.
+fn parse_uptime_secs(contents: &str) -> Option<u64> {
+    let field = contents.split_whitespace().next()?;
+    let secs = field.parse::<f64>().ok()?;
+    (secs.is_finite() && secs >= 0.0).then_some(secs as u64)
+}
+
+/// Parse the contents of `/proc/[pid]/stat`.
+fn parse_proc_stat(contents: &str) -> Option<ProcStat> {
+    // comm is wrapped in parentheses and may itself contain spaces or ')'.
+    let open = contents.find('(')?;
+    let close = contents.rfind(')')?;
+    let comm = contents.get(open + 1..close)?.to_string();
+
+    // Fields after comm, starting at field 3 (state).
+    let rest: Vec<&str> = contents.get(close + 1..)?.split_whitespace().collect();
+
+    let flags = rest.get(6)?.parse::<u64>().ok()?;
+    let utime = rest.get(11)?.parse::<u64>().ok()?;
+    let stime = rest.get(12)?.parse::<u64>().ok()?;
+    let rss_pages = rest.get(21)?.parse::<i64>().ok()?.max(0) as u64;
+
+    Some(ProcStat {
+        comm,
+        kernel_thread: flags & PF_KTHREAD != 0,
+        cpu_ticks: utime + stime,
+        rss_pages,
+    })
+}
+
+/// Host name from /proc/sys/kernel/hostname.
+pub fn read_hostname() -> Option<String> {
+    let name = fs::read_to_string("/proc/sys/kernel/hostname").ok()?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+fn online_cpus() -> f64 {
+    // SAFETY: sysconf has no preconditions.
+    let n = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+    if n > 0 {
+        n as f64
+    } else {
+        1.0
+    }
+}
+
+fn page_size() -> u64 {
+    // SAFETY: sysconf has no preconditions.
+    let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if size > 0 {
+        size as u64
+    } else {
+        4096
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Collectors, Thresholds};
+    use crate::metrics::SnapshotSampler;
+    use std::time::Duration;
+
+    /// Create `thermal_zone<n>` under `root` with the given type and temp
+    /// files; None leaves the file out.
+    fn thermal_zone(root: &Path, n: u32, zone_type: Option<&str>, temp: Option<&str>) {
+        let zone = root.join(format!("thermal_zone{}", n));
+        fs::create_dir(&zone).unwrap();
+        if let Some(t) = zone_type {
+            fs::write(zone.join("type"), format!("{}\n", t)).unwrap();
+        }
+        if let Some(t) = temp {
+            fs::write(zone.join("temp"), t).unwrap();
+        }
+    }
+
+    #[test]
+    fn thermal_temp_is_millidegrees() {
+        assert_eq!(parse_thermal_temp("45000\n"), Some(45.0));
+        assert_eq!(parse_thermal_temp("51250"), Some(51.25));
+        assert_eq!(parse_thermal_temp("-5000\n"), Some(-5.0));
+        assert_eq!(parse_thermal_temp(""), None);
+        assert_eq!(parse_thermal_temp("n/a\n"), None);
+    }
+
+    #[test]
+    fn thermal_zones_prefer_x86_pkg_temp() {
+        let dir = tempfile::tempdir().unwrap();
+        thermal_zone(dir.path(), 0, Some("acpitz"), Some("90000\n"));
+        thermal_zone(dir.path(), 1, Some("x86_pkg_temp"), Some("62000\n"));
+        thermal_zone(dir.path(), 2, Some("iwlwifi_1"), Some("40000\n"));
+
+        assert_eq!(read_thermal_zones(dir.path()), Some(62.0));
+    }
+
+    #[test]
+    fn thermal_zones_fall_back_to_highest_reading() {
+        let dir = tempfile::tempdir().unwrap();
+        thermal_zone(dir.path(), 0, Some("acpitz"), Some("48000\n"));
+        thermal_zone(dir.path(), 1, None, Some("71500\n"));
+        // Unreadable zones are skipped.
+        thermal_zone(dir.path(), 2, Some("pch_skylake"), None);
+        thermal_zone(dir.path(), 3, Some("bogus"), Some("garbage\n"));
+        fs::create_dir(dir.path().join("cooling_device0")).unwrap();
+
+        assert_eq!(read_thermal_zones(dir.path()), Some(71.5));
+    }
+
+    #[test]
+    fn thermal_zones_missing_yield_none() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(read_thermal_zones(dir.path()), None);
+        assert_eq!(read_thermal_zones(&dir.path().join("missing")), None);
+    }
+
+    #[test]
+    fn io_rates_use_measured_gap() {
+        let at = Instant::now();
+        let prev = IoCounters {
+            at,
+            read_bytes: 1_000_000,
+            write_bytes: 5_000,
+        };
+        // Read 2.5s apart although the configured interval might be 1s.
+        let current = IoCounters {
+            at: at + Duration::from_millis(2500),
+            read_bytes: 6_000_000,
+            write_bytes: 5_000,
+        };
+        assert_eq!(current.rates_since(&prev), (2_000_000.0, 0.0));
+        assert_eq!(prev.rates_since(&prev), (0.0, 0.0));
+    }
+
+    /// Write cgroup files into `dir`.
+    fn cgroup_files(dir: &Path, files: &[(&str, &str)]) {
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+    }
+
+    #[test]
+    fn cgroup_values_and_cpu_files_parse() {
+        assert_eq!(parse_cgroup_value("536870912\n"), Some(Some(536_870_912)));
+        assert_eq!(parse_cgroup_value("max\n"), Some(None));
+        assert_eq!(parse_cgroup_value("lots\n"), None);
+
+        let cpu_stat = "usage_usec 8123456\nuser_usec 6000000\nsystem_usec 2123456\n";
+        assert_eq!(parse_cgroup_cpu_stat(cpu_stat), Some(8_123_456));
+        assert_eq!(parse_cgroup_cpu_stat("user_usec 1\n"), None);
+
+        assert_eq!(parse_cgroup_cpu_max("150000 100000\n"), Some(1.5));
+        assert_eq!(parse_cgroup_cpu_max("max 100000\n"), None);
+    }
+
+    #[test]
+    fn cgroup_memory_is_measured_against_its_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        cgroup_files(
+            dir.path(),
+            &[
+                ("memory.current", "67108864\n"),
+                ("memory.max", "268435456\n"),
+                ("memory.swap.current", "1048576\n"),
+                ("memory.swap.max", "4194304\n"),
+            ],
+        );
+        let usage = read_cgroup_mem_usage(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(usage.ram_percent, 25.0);
+        assert_eq!(usage.swap_percent, 25.0);
+        assert_eq!(
+            (usage.ram_used_bytes, usage.ram_total_bytes),
+            (67_108_864, 268_435_456)
+        );
+
+        // No memory.max: memory.high is the limit.
+        cgroup_files(
+            dir.path(),
+            &[("memory.max", "max\n"), ("memory.high", "134217728\n")],
+        );
+        let usage = read_cgroup_mem_usage(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(usage.ram_percent, 50.0);
+    }
+
+    #[test]
+    fn cgroup_check_names_the_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+        cgroup_files(
+            dir.path(),
+            &[("cpu.stat", "usage_usec 1\n"), ("memory.current", "1\n")],
+        );
+
+        let err = check_cgroup(path).unwrap_err().to_string();
+        assert!(err.contains("missing memory.max"), "{}", err);
+
+        cgroup_files(dir.path(), &[("memory.max", "max\n")]);
+        assert!(check_cgroup(path).is_ok());
+    }
+
+    const DISKSTATS: &str = "\
+   7       0 loop0 57 0 2206 10 0 0 0 0 0 40 10 0 0 0 0 0 0
+   8       0 sda 1000 10 20000 500 2000 20 40000 900 0 1200 1400 0 0 0 0 0 0
+   8       1 sda1 900 10 18000 450 1900 20 38000 850 0 1100 1300 0 0 0 0 0 0
+ 259       0 nvme0n1 300 0 6000 100 400 0 8000 200 0 250 300 0 0 0 0 0 0
+";
+
+    #[test]
+    fn diskstats_device_filter_selects_rows() {
+        let only = |devices: &'static [&'static str]| {
+            parse_diskstats(DISKSTATS, move |name| devices.contains(&name))
+        };
+        assert_eq!(only(&["sda"]), (20_000, 40_000));
+        assert_eq!(only(&["sda1", "nvme0n1"]), (24_000, 46_000));
+        assert_eq!(only(&["sdb"]), (0, 0));
+
+        let names: Vec<&str> = parse_diskstats_rows(DISKSTATS).map(|(n, _, _)| n).collect();
+        assert_eq!(names, ["loop0", "sda", "sda1", "nvme0n1"]);
+    }
+
+    #[test]
+    fn meminfo_without_mem_available_estimates_it() {
+        // A 2.6-era meminfo: no MemAvailable.
+        let meminfo = "\
+MemTotal:        8000000 kB
+MemFree:         1000000 kB
+Buffers:          500000 kB
+Cached:          2500000 kB
+SwapCached:            0 kB
+SwapTotal:       2000000 kB
+SwapFree:        1500000 kB
+";
+        let usage = parse_meminfo(meminfo).unwrap();
+        // Available: 1000000 + 500000 + 2500000 kB = half of MemTotal.
+        assert_eq!(usage.ram_percent, 50.0);
+        assert_eq!(usage.swap_percent, 25.0);
+        assert_eq!(usage.ram_used_bytes, 4_000_000 * 1024);
+        assert_eq!(usage.ram_total_bytes, 8_000_000 * 1024);
+
+        let with_available = format!("{}MemAvailable:    6000000 kB\n", meminfo);
+        assert_eq!(parse_meminfo(&with_available).unwrap().ram_percent, 25.0);
+
+        assert!(parse_meminfo("MemTotal: 8000000 kB\n").is_err());
+    }
+
+    /// /proc/stat with the aggregate line built from the given fields.
+    fn proc_stat(user: u64, idle: u64, iowait: u64, steal: u64) -> String {
+        format!(
+            "cpu  {} 0 500 {} {} 20 10 {} 0 0\ncpu0 1 0 1 1 0 0 0 0 0 0\nintr 12345\n",
+            user, idle, iowait, steal
+        )
+    }
+
+    #[test]
+    fn steal_rises_without_counting_as_usage() {
+        let prev = parse_cpu_times(&proc_stat(1000, 8000, 100, 100)).unwrap();
+        // 1000 ticks: 50 user, 550 idle, 400 stolen by the hypervisor.
+        let current = parse_cpu_times(&proc_stat(1050, 8550, 100, 500)).unwrap();
+
+        let usage = current.usage_since(&prev);
+        assert_eq!(usage.usage_percent, 5.0);
+        assert_eq!(usage.steal_percent, 40.0);
+        assert_eq!(usage.iowait_percent, 0.0);
+
+        // No ticks elapsed: all zero rather than NaN.
+        let idle = current.usage_since(&current);
+        assert_eq!(idle.usage_percent, 0.0);
+        assert_eq!(idle.steal_percent, 0.0);
+
+        assert!(parse_cpu_times("intr 1\n").is_err());
+        assert!(parse_cpu_times("cpu  1 2 3\n").is_err());
+    }
+
+    #[test]
+    fn iowait_grows_as_idle_not_usage() {
+        let prev = parse_cpu_times(&proc_stat(1000, 8000, 100, 0)).unwrap();
+        // 200 ticks: 20 user, 30 idle, 150 waiting on IO.
+        let current = parse_cpu_times(&proc_stat(1020, 8030, 250, 0)).unwrap();
+
+        let usage = current.usage_since(&prev);
+        assert_eq!(usage.iowait_percent, 75.0);
+        assert_eq!(usage.usage_percent, 10.0);
+        assert_eq!(usage.steal_percent, 0.0);
+
+        // Kernels before 2.5.41 have no iowait column.
+        let old = parse_cpu_times("cpu  10 0 5 80\n").unwrap();
+        assert_eq!((old.iowait, old.total), (0, 95));
+    }
+
+    #[test]
+    fn file_nr_parses_and_gives_percent() {
+        let fd = parse_file_nr("9632\t0\t9223372036854775807\n").unwrap();
+        assert_eq!((fd.open, fd.max), (9632, i64::MAX as u64));
+
+        let fd = parse_file_nr("2048 48 8000\n").unwrap();
+        assert_eq!((fd.open, fd.max), (2000, 8000));
+        assert_eq!(fd.percent(), 25.0);
+        assert_eq!(FdUsage { open: 5, max: 0 }.percent(), 0.0);
+
+        for bad in ["", "2048 0", "2048 0 8000 1", "a 0 8000"] {
+            assert!(parse_file_nr(bad).is_none(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn tasks_counted_from_synthetic_proc() {
+        let proc_root = tempfile::tempdir().unwrap();
+        for entry in ["1", "42", "31337", "self", "sys", "net", "1a"] {
+            fs::create_dir(proc_root.path().join(entry)).unwrap();
+        }
+        fs::write(
+            proc_root.path().join("loadavg"),
+            "0.20 0.18 0.12 3/412 31337\n",
+        )
+        .unwrap();
+
+        assert_eq!(count_pid_dirs(proc_root.path()).unwrap(), 3);
+        let loadavg = fs::read_to_string(proc_root.path().join("loadavg")).unwrap();
+        assert_eq!(parse_loadavg_threads(&loadavg), Some(412));
+
+        assert_eq!(parse_loadavg_threads("0.20 0.18 0.12"), None);
+        assert_eq!(parse_loadavg_threads("0.20 0.18 0.12 3 412"), None);
+        assert!(count_pid_dirs(&proc_root.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn cmdline_args_are_joined_and_truncated() {
+        let cmdline = b"/usr/bin/java\0-Xmx2g\0-jar\0orders-service.jar\0";
+        assert_eq!(
+            parse_cmdline(cmdline, 256).as_deref(),
+            Some("/usr/bin/java -Xmx2g -jar orders-service.jar")
+        );
+        assert_eq!(parse_cmdline(cmdline, 13).as_deref(), Some("/usr/bin/java"));
+
+        // Empty arguments collapse; control characters become spaces.
+        assert_eq!(
+            parse_cmdline(b"sh\0\0-c\0a\tb\nc", 64).as_deref(),
+            Some("sh -c a b c")
+        );
+
+        // Kernel threads have an empty cmdline.
+        assert_eq!(parse_cmdline(b"", 64), None);
+        assert_eq!(parse_cmdline(b"\0\0", 64), None);
+    }
+
+    #[test]
+    fn list_fixtures_parse_devices_and_interfaces() {
+        let counters: Vec<(&str, u64, u64)> = parse_diskstats_rows(DISKSTATS).collect();
+        assert_eq!(counters[1], ("sda", 20_000, 40_000));
+        assert_eq!(counters[3], ("nvme0n1", 6_000, 8_000));
+
+        let net_dev = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:  123456     100    0    0    0     0          0         0   123456     100    0    0    0     0       0          0
+  eth0:98765432   65000    0    0    0     0          0        12 1234567   9000    0    0    0     0       0          0
+ short: 1 2 3
+";
+        let interfaces = parse_net_dev(net_dev);
+        let parsed: Vec<(&str, u64, u64)> = interfaces
+            .iter()
+            .map(|i| (i.name.as_str(), i.rx_bytes, i.tx_bytes))
+            .collect();
+        assert_eq!(
+            parsed,
+            [("lo", 123_456, 123_456), ("eth0", 98_765_432, 1_234_567)]
+        );
+    }
+
+    #[test]
+    fn only_configured_collectors_are_read() {
+        let thresholds = Thresholds {
+            cpu_threshold: Some(90.0),
+            ..Thresholds::default()
+        };
+        let mut collectors = Collectors::none();
+        collectors.require(&thresholds);
+        let config = RuntimeConfig {
+            thresholds,
+            collectors,
+            ..RuntimeConfig::default()
+        };
+
+        // A proc root with nothing but `stat`: reading RAM, tasks, fds or
+        // diskstats would fail, so the snapshots succeed only if the
+        // unconfigured collectors are skipped.
+        let proc = tempfile::tempdir().unwrap();
+        fs::write(proc.path().join("stat"), proc_stat(1000, 8000, 100, 0)).unwrap();
+        let mut source = ProcSource::with_proc_root(proc.path());
+        for _ in 0..2 {
+            let snapshot = source.read_snapshot(&config).unwrap();
+            assert_eq!(snapshot.ram_total_bytes, 0);
+            assert!(snapshot.task_counts.is_none() && snapshot.fd_usage.is_none());
+            assert!(snapshot.uptime_secs.is_none());
+        }
+        assert!(!proc.path().join("diskstats").exists());
+        assert!(source.cpu.is_some());
+        assert!(source.io.is_none());
+
+        let with_io = RuntimeConfig {
+            collectors: Collectors {
+                io: true,
+                ..collectors
+            },
+            ..RuntimeConfig::default()
+        };
+        assert!(source.read_snapshot(&with_io).is_err());
+        fs::write(proc.path().join("diskstats"), DISKSTATS).unwrap();
+        source.read_snapshot(&with_io).unwrap();
+        assert!(source.io.is_some());
+    }
+
+    #[test]
+    fn samplers_keep_their_own_baselines() {
+        let config = RuntimeConfig::default();
+        let mut a = SnapshotSampler::new(config.clone());
+        let mut b = SnapshotSampler::new(config);
+
+        a.next_sample().unwrap();
+        a.next_sample().unwrap();
+        assert!(a.source.cpu.is_some() && a.source.io.is_some());
+        assert!(b.source.cpu.is_none() && b.source.io.is_none());
+
+        // B's first sample has no baseline of its own, whatever A read.
+        let first = b.next_sample().unwrap();
+        assert_eq!(first.cpu_usage_percent, 0.0);
+        assert_eq!(first.io_read_bytes_per_s, 0.0);
+        assert_eq!(first.io_write_bytes_per_s, 0.0);
+
+        // Sampling B leaves A's baseline where A's last read put it.
+        let a_io = a.source.io.unwrap().at;
+        b.next_sample().unwrap();
+        assert_eq!(a.source.io.unwrap().at, a_io);
+        assert!(b.source.io.unwrap().at > a_io);
+    }
+
+    #[test]
+    fn unlimited_cgroup_memory_falls_back_to_host_total() {
+        let dir = tempfile::tempdir().unwrap();
+        cgroup_files(
+            dir.path(),
+            &[
+                ("memory.current", "67108864\n"),
+                ("memory.max", "max\n"),
+                ("memory.high", "max\n"),
+            ],
+        );
+        let host_total = read_mem_total_bytes(Path::new(PROC_ROOT)).unwrap();
+        let usage = read_cgroup_mem_usage(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(usage.ram_total_bytes, host_total);
+        let expected = used_percent(host_total, host_total - 67_108_864);
+        assert_eq!(usage.ram_percent, expected);
+        // Without swap files there is no swap usage to report.
+        assert_eq!(usage.swap_percent, 0.0);
+
+        // A limit above the host's memory can't be reached either.
+        cgroup_files(
+            dir.path(),
+            &[("memory.max", &format!("{}\n", host_total * 2))],
+        );
+        let usage = read_cgroup_mem_usage(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(usage.ram_total_bytes, host_total);
+    }
+
+    #[test]
+    fn uptime_is_the_first_field_in_whole_seconds() {
+        assert_eq!(parse_uptime_secs("350735.47 234388.90\n"), Some(350_735));
+        assert_eq!(parse_uptime_secs("12.99 3.00"), Some(12));
+        assert_eq!(parse_uptime_secs("0.00 0.00\n"), Some(0));
+        for bad in ["", "soon 1.0", "-1.0 0.0", "inf 0.0", "NaN 0.0"] {
+            assert_eq!(parse_uptime_secs(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn missing_proc_root_is_a_clear_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = check_proc_root(&dir.path().join("nonexistent")).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("This tool requires Linux /proc; current platform unsupported"),
+            "{}",
+            err
+        );
+
+        // A directory without `stat` is not a proc filesystem either.
+        assert!(check_proc_root(dir.path()).is_err());
+        fs::write(dir.path().join("stat"), "cpu  1 0 1 100 0 0 0 0 0 0\n").unwrap();
+        assert!(check_proc_root(dir.path()).is_ok());
+    }
+
+    #[test]
+    fn self_overhead_is_reported_after_a_few_ticks() {
+        let config = RuntimeConfig {
+            report_self_overhead: true,
+            ..RuntimeConfig::default()
+        };
+        let mut sampler = SnapshotSampler::new(config);
+        let mut usage = None;
+        for _ in 0..3 {
+            // Some work of our own to account for between ticks.
+            let mut x = 0u64;
+            for i in 0..2_000_000u64 {
+                x = std::hint::black_box(x.wrapping_add(i * i));
+            }
+            usage = sampler.next_sample().unwrap().self_usage;
+        }
+        let usage = usage.expect("self usage requested");
+        assert!(usage.cpu_percent.is_finite() && usage.cpu_percent >= 0.0);
+        assert!(usage.rss_bytes > 0);
+
+        let mut plain = SnapshotSampler::new(RuntimeConfig::default());
+        assert!(plain.next_sample().unwrap().self_usage.is_none());
+    }
+
+    /// A power_supply class directory entry named `name` with `files`.
+    fn power_supply(root: &Path, name: &str, files: &[(&str, &str)]) {
+        let dir = root.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, contents) in files {
+            fs::write(dir.join(file), contents).unwrap();
+        }
+    }
+
+    #[test]
+    fn batteries_are_read_from_power_supply() {
+        // A desktop: mains only, or no power_supply class at all.
+        let desktop = tempfile::tempdir().unwrap();
+        power_supply(
+            desktop.path(),
+            "AC",
+            &[("online", "1\n"), ("type", "Mains\n")],
+        );
+        assert!(read_power_supplies(desktop.path()).is_none());
+        assert!(read_power_supplies(&desktop.path().join("missing")).is_none());
+
+        let laptop = tempfile::tempdir().unwrap();
+        power_supply(laptop.path(), "AC", &[("online", "0\n")]);
+        power_supply(
+            laptop.path(),
+            "BAT0",
+            &[
+                ("capacity", "80\n"),
+                ("status", "Discharging\n"),
+                ("power_now", "12500000\n"),
+            ],
+        );
+        let usage = read_power_supplies(laptop.path()).unwrap();
+        assert_eq!(usage.battery_percent, Some(80.0));
+        assert_eq!(usage.draw_watts, Some(12.5));
+
+        // A second battery charging, reporting current and voltage only.
+        power_supply(
+            laptop.path(),
+            "BAT1",
+            &[
+                ("capacity", "60\n"),
+                ("status", "Charging\n"),
+                ("current_now", "1000000\n"),
+                ("voltage_now", "12000000\n"),
+            ],
+        );
+        let usage = read_power_supplies(laptop.path()).unwrap();
+        assert_eq!(usage.battery_percent, Some(70.0));
+        assert_eq!(usage.draw_watts, Some(0.5));
+    }
+
+    #[test]
+    fn battery_draw_sign_follows_status() {
+        let draw = |status, power| parse_battery_draw_watts(Some(status), Some(power), None, None);
+        assert_eq!(draw("Discharging\n", "7000000"), Some(7.0));
+        // Some drivers report a negative rate; only the status decides.
+        assert_eq!(draw("Discharging\n", "-7000000"), Some(7.0));
+        assert_eq!(draw("Charging\n", "7000000"), Some(-7.0));
+        assert_eq!(draw("Full\n", "7000000"), Some(0.0));
+        assert_eq!(
+            parse_battery_draw_watts(Some("Discharging"), None, Some("1000000"), None),
+            None
+        );
+        assert_eq!(
+            parse_battery_draw_watts(None, Some("n/a"), None, None),
+            None
+        );
+    }
+}
@@ -0,0 +1,57 @@
+//! Stand-in source for platforms without a metrics backend (macOS, the
+//! BSDs).
+//!
+//! The crate builds so `version`, `logs` and `replay` still work, while
+//! anything that samples the system stops at `check_platform` with the
+//! same message a Linux host without /proc gets.
+
+use super::{BlockDevice, MetricsSource, NetInterface, SystemSnapshot};
+use crate::config::RuntimeConfig;
+use crate::error::MonitorError;
+
+/// Metrics source that never yields a snapshot.
+#[derive(Debug, Default)]
+pub struct UnsupportedSource;
+
+impl UnsupportedSource {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl MetricsSource for UnsupportedSource {
+    fn read_snapshot(&mut self, _config: &RuntimeConfig) -> Result<SystemSnapshot, MonitorError> {
+        Err(unsupported())
+    }
+}
+
+pub fn read_disk_used_percent(_path: &str) -> Result<f32, MonitorError> {
+    Err(unsupported())
+}
+
+pub fn read_hostname() -> Option<String> {
+    None
+}
+
+/// There is no /proc to read here.
+pub fn check_platform() -> Result<(), MonitorError> {
+    Err(unsupported())
+}
+
+pub fn check_cgroup(_dir: &str) -> Result<(), MonitorError> {
+    Err(MonitorError::Config(
+        "--cgroup is only supported on Linux".into(),
+    ))
+}
+
+pub fn read_block_devices(_sector_size: u64) -> Result<Vec<BlockDevice>, MonitorError> {
+    Err(unsupported())
+}
+
+pub fn read_net_interfaces() -> Result<Vec<NetInterface>, MonitorError> {
+    Err(unsupported())
+}
+
+fn unsupported() -> MonitorError {
+    MonitorError::Config("This tool requires Linux /proc; current platform unsupported".into())
+}
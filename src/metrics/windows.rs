@@ -0,0 +1,215 @@
+//! Windows metrics source backed by `sysinfo`.
+//!
+//! CPU, RAM/swap, top processes and disk usage are supported; IO rates
+//! and CPU temperature are not collected yet (reported as 0 / None).
+
+use std::cmp::Reverse;
+use std::path::Path;
+use std::time::{Instant, SystemTime};
+
+use sysinfo::{Disks, ProcessesToUpdate, System};
+
+use super::{
+    BlockDevice, DiskUsage, MetricsSource, NetInterface, ProcessSample, SelfUsage, SystemSnapshot,
+};
+use crate::config::{ProcFilter, RuntimeConfig};
+use crate::error::MonitorError;
+
+/// Metrics source using the Windows APIs through `sysinfo`.
+///
+/// `sysinfo` computes CPU usage against its previous refresh, so the
+/// same `System` must be reused across snapshots.
+pub struct WindowsSource {
+    system: System,
+}
+
+impl WindowsSource {
+    pub fn new() -> Self {
+        Self {
+            system: System::new(),
+        }
+    }
+
+    /// Top N processes by CPU share of the whole machine, and by memory.
+    fn top_processes(
+        &mut self,
+        top_n: usize,
+        filter: &ProcFilter,
+    ) -> (Vec<ProcessSample>, Vec<ProcessSample>) {
+        self.system.refresh_processes(ProcessesToUpdate::All, true);
+
+        // sysinfo reports per-core percent; scale to the whole machine
+        // like the Linux source does.
+        let cpus = self.system.cpus().len().max(1) as f32;
+
+        let mut processes: Vec<ProcessSample> = self
+            .system
+            .processes()
+            .values()
+            .filter_map(|p| {
+                let name = p.name().to_string_lossy().into_owned();
+                if !filter.matches(&name, false) {
+                    return None;
+                }
+
+                Some(ProcessSample {
+                    pid: p.pid().as_u32(),
+                    name,
+                    cpu_percent: p.cpu_usage() / cpus,
+                    ram_bytes: p.memory(),
+                })
+            })
+            .collect();
+
+        let mut by_ram = processes.clone();
+        by_ram.sort_by_key(|p| Reverse(p.ram_bytes));
+        by_ram.truncate(top_n);
+
+        processes.sort_by(|a, b| {
+            b.cpu_percent
+                .total_cmp(&a.cpu_percent)
+                .then(b.ram_bytes.cmp(&a.ram_bytes))
+        });
+        processes.truncate(top_n);
+
+        (processes, by_ram)
+    }
+
+    /// The monitor's own CPU share of the whole machine and memory.
+    fn self_usage(&mut self) -> Option<SelfUsage> {
+        let pid = sysinfo::get_current_pid().ok()?;
+        self.system
+            .refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+
+        let cpus = self.system.cpus().len().max(1) as f32;
+        let process = self.system.process(pid)?;
+        Some(SelfUsage {
+            cpu_percent: process.cpu_usage() / cpus,
+            rss_bytes: process.memory(),
+        })
+    }
+}
+
+impl Default for WindowsSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MetricsSource for WindowsSource {
+    fn read_snapshot(&mut self, config: &RuntimeConfig) -> Result<SystemSnapshot, MonitorError> {
+        if config.cgroup.is_some() {
+            return Err(cgroup_unsupported());
+        }
+
+        let timestamp = SystemTime::now();
+
+        let collect = config.collectors;
+        if collect.cpu {
+            self.system.refresh_cpu_usage();
+        }
+        if collect.ram {
+            self.system.refresh_memory();
+        }
+
+        let total = self.system.total_memory();
+        let used = total.saturating_sub(self.system.available_memory());
+
+        let (top_processes, top_processes_by_ram) = if config.top_n_procs > 0 {
+            self.top_processes(config.top_n_procs, &config.proc_filter)
+        } else {
+            (Vec::new(), Vec::new())
+        };
+
+        let mut disk_usage = Vec::with_capacity(config.watch_paths.len());
+        for path in &config.watch_paths {
+            disk_usage.push(DiskUsage {
+                path: path.clone(),
+                used_percent: read_disk_used_percent(path)?,
+            });
+        }
+
+        let self_usage = if config.report_self_overhead {
+            self.self_usage()
+        } else {
+            None
+        };
+
+        Ok(SystemSnapshot {
+            timestamp,
+            cpu_usage_percent: self.system.global_cpu_usage(),
+            cpu_steal_percent: 0.0,
+            cpu_iowait_percent: 0.0,
+            ram_usage_percent: percent(used, total),
+            ram_used_bytes: used,
+            ram_total_bytes: total,
+            swap_usage_percent: percent(self.system.used_swap(), self.system.total_swap()),
+            io_read_bytes_per_s: 0.0,
+            io_write_bytes_per_s: 0.0,
+            cpu_temp_celsius: None,
+            battery_percent: None,
+            power_draw_watts: None,
+            fd_usage: None,
+            task_counts: None,
+            disk_usage,
+            top_processes,
+            top_processes_by_ram,
+            self_usage,
+            uptime_secs: Some(System::uptime()),
+            instant: Some(Instant::now()),
+        })
+    }
+}
+
+/// Usage (%) of the volume containing `path` (longest matching mount point).
+pub fn read_disk_used_percent(path: &str) -> Result<f32, MonitorError> {
+    let disks = Disks::new_with_refreshed_list();
+    let target = Path::new(path);
+
+    let disk = disks
+        .list()
+        .iter()
+        .filter(|d| target.starts_with(d.mount_point()))
+        .max_by_key(|d| d.mount_point().as_os_str().len())
+        .ok_or_else(|| MonitorError::Config(format!("No volume found for '{}'", path)))?;
+
+    let total = disk.total_space();
+    Ok(percent(total.saturating_sub(disk.available_space()), total))
+}
+
+/// Computer name as reported by Windows.
+pub fn read_hostname() -> Option<String> {
+    System::host_name()
+}
+
+/// The Windows APIs are always there.
+pub fn check_platform() -> Result<(), MonitorError> {
+    Ok(())
+}
+
+/// cgroups only exist on Linux.
+pub fn check_cgroup(_dir: &str) -> Result<(), MonitorError> {
+    Err(cgroup_unsupported())
+}
+
+/// Block device counters come from /proc/diskstats, which Windows lacks.
+pub fn read_block_devices(_sector_size: u64) -> Result<Vec<BlockDevice>, MonitorError> {
+    Err(MonitorError::Config("Listing block devices is only supported on Linux".into()))
+}
+
+/// Interface counters come from /proc/net/dev, which Windows lacks.
+pub fn read_net_interfaces() -> Result<Vec<NetInterface>, MonitorError> {
+    Err(MonitorError::Config("Listing network interfaces is only supported on Linux".into()))
+}
+
+fn cgroup_unsupported() -> MonitorError {
+    MonitorError::Config("--cgroup is only supported on Linux".into())
+}
+
+fn percent(used: u64, total: u64) -> f32 {
+    if total == 0 {
+        return 0.0;
+    }
+
+    (used as f64 / total as f64 * 100.0) as f32
+}
@@ -0,0 +1,676 @@
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::{self, LineWriter, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::analyzer::{analyze_snapshot, finalize, AnalyzerState, EventPhase, SpikeEvent};
+use crate::baseline::WarmupMeans;
+use colored::Colorize;
+
+use crate::config::{BatchConfig, BatchLimit, LineEnding, OutputFormat, RuntimeConfig, Thresholds};
+use crate::diag::trace;
+use crate::explain::{discard_notes, pending_notes};
+use crate::histogram::Histograms;
+use crate::output::{
+    print_event, print_histogram_json, print_meta, print_ongoing, print_openmetrics,
+    print_snapshot, print_table_header, JsonArrayWriter, LineEndingWriter, SpikeCounters,
+    DEFAULT_PRECISION,
+};
+use crate::overhead::SelfOverhead;
+use crate::peaks::HighWaterMarks;
+use crate::progress::Progress;
+use crate::replay::SnapshotReader;
+use crate::sampler::{spawn_sampler, Sample, SampleQueue};
+use crate::samples_log::SamplesLog;
+use crate::schedule::{minute_of_day, Heartbeat};
+use crate::shutdown;
+use crate::sink::{build_sinks, EventSink};
+use crate::sparkline::Sparkline;
+use crate::throttle::{ErrorThrottle, ERROR_ROLLUP};
+
+/// Where the monitoring loop gets its snapshots.
+enum SampleSource {
+    /// Live reads on the sampler thread.
+    Sampler(Arc<SampleQueue>),
+    /// Recorded snapshots, read as fast as they are analyzed.
+    Replay(SnapshotReader),
+}
+
+impl SampleSource {
+    fn open(config: &BatchConfig) -> Result<Self, Box<dyn Error>> {
+        match &config.replay {
+            Some(path) => Ok(SampleSource::Replay(SnapshotReader::open(path)?)),
+            None => Ok(SampleSource::Sampler(spawn_sampler(&config.runtime)?)),
+        }
+    }
+
+    /// Next sample; Ok(None) once a replay reaches the end of its file.
+    fn next(&mut self) -> Result<Option<Sample>, Box<dyn Error>> {
+        match self {
+            SampleSource::Sampler(queue) => match queue.pop() {
+                Some(sample) => Ok(Some(sample)),
+                None => Err("sampler thread stopped".into()),
+            },
+            SampleSource::Replay(reader) => Ok(reader.next_sample()),
+        }
+    }
+
+    fn discarded(&self) -> u64 {
+        match self {
+            SampleSource::Sampler(queue) => queue.discarded(),
+            SampleSource::Replay(_) => 0,
+        }
+    }
+
+    fn close(&self) {
+        if let SampleSource::Sampler(queue) = self {
+            queue.close();
+        }
+    }
+}
+
+/// Monitoring loop shared by live, batch and replay mode.
+///
+/// Snapshots are read on a dedicated sampler thread and consumed here, so
+/// printing and event sinks never delay a read. If this side falls
+/// behind, the oldest queued snapshots are discarded and counted; they
+/// don't count toward a sample limit. Failed reads do, so a flaky /proc
+/// can't stall a bounded run. With `config.replay` the snapshots come
+/// from a recorded file instead, and the run also ends at its end.
+///
+/// Runs until `config.limit` is reached (forever when unbounded) or a
+/// shutdown signal arrives, and returns whether at least one spike event
+/// was detected. `tag` prefixes every stderr line, e.g. `"[monitor]"`.
+pub fn run_monitor(config: &BatchConfig, tag: &'static str) -> Result<bool, Box<dyn Error>> {
+    let runtime = &config.runtime;
+    let mut analyzer_state = load_state(
+        runtime.state_file.as_deref(),
+        runtime.state_max_age_secs,
+        tag,
+    );
+
+    let mut sinks = build_sinks(runtime, tag)?;
+    let mut out = open_output(runtime.output_file.as_deref(), runtime.line_ending)?;
+    if config.json_array {
+        out = Box::new(JsonArrayWriter::new(out));
+    }
+
+    let start = Instant::now();
+    let mut samples: u64 = 0;
+    let mut dropped: u64 = 0;
+    let mut discarded: u64 = 0;
+    let mut consecutive_failures: u64 = 0;
+    let mut read_errors = ErrorThrottle::new(tag, ERROR_ROLLUP);
+    let mut lag_warnings = ErrorThrottle::new(tag, ERROR_ROLLUP);
+    let mut spike_seen = false;
+    let mut spikes: u64 = 0;
+    let mut peaks = HighWaterMarks::new();
+    let mut histograms = config.histogram.as_ref().map(Histograms::new);
+    let mut overhead = SelfOverhead::new();
+    // Ratio thresholds are resolved from `warmup_means` when warmup ends.
+    let mut thresholds = runtime.thresholds.clone();
+    // The --schedule rule in effect and the thresholds it yields.
+    let mut scheduled: Option<(usize, Thresholds)> = None;
+    let mut warmup_means = WarmupMeans::new();
+    let mut header_printed = false;
+    let mut last_snapshot = None;
+
+    if runtime.output_format == OutputFormat::Json {
+        print_meta(&mut out, runtime)?;
+    }
+
+    let mut samples_log = match &runtime.samples_log {
+        Some(path) => Some(SamplesLog::create(path, runtime.samples_log_format)?),
+        None => None,
+    };
+
+    let mut source = SampleSource::open(config)?;
+    let mut heartbeat = runtime
+        .heartbeat_secs
+        .map(|s| Heartbeat::new(Duration::from_secs(s)));
+    let mut progress = if config.progress {
+        Progress::new(config.limit)
+    } else {
+        None
+    };
+    let mut sparkline = config
+        .sparkline
+        .filter(|_| !runtime.quiet)
+        .and_then(Sparkline::new);
+    let prec = runtime.precision.unwrap_or(DEFAULT_PRECISION);
+    let mut spike_counters = (runtime.output_format == OutputFormat::OpenMetrics)
+        .then(|| SpikeCounters::new(SystemTime::now(), runtime.exemplars));
+
+    loop {
+        // Check stop conditions
+        if shutdown::requested() {
+            break;
+        }
+        match config.limit {
+            BatchLimit::DurationSecs(max_secs) => {
+                if start.elapsed().as_secs() >= max_secs {
+                    break;
+                }
+            }
+            BatchLimit::Samples(max_samples) => {
+                if samples + dropped >= max_samples {
+                    break;
+                }
+            }
+            BatchLimit::Unbounded => {}
+        }
+
+        let Some(sample) = source.next()? else {
+            break;
+        };
+        // The signal may have arrived while waiting for this sample.
+        if shutdown::requested() {
+            break;
+        }
+
+        let newly_discarded = source.discarded();
+        if newly_discarded > discarded {
+            discarded = newly_discarded;
+            lag_warnings
+                .error("Output fell behind the sampler; discarding oldest snapshots".into());
+        }
+
+        let snapshot = match sample {
+            Ok(s) => {
+                consecutive_failures = 0;
+                read_errors.clear();
+                s
+            }
+            Err(e) => {
+                read_errors.error(format!("Error reading snapshot: {e}"));
+                dropped += 1;
+                consecutive_failures += 1;
+                if let Some(every) = runtime.failure_warn_every {
+                    if consecutive_failures.is_multiple_of(every) {
+                        eprintln!(
+                            "{} Warning: {} consecutive samples dropped",
+                            tag, consecutive_failures
+                        );
+                    }
+                }
+                continue;
+            }
+        };
+
+        if let Some(log) = &mut samples_log {
+            log.write(&snapshot)?;
+        }
+
+        // Warmup samples are shown but not analyzed, so rate metrics and
+        // adaptive baselines settle before anything can alert.
+        let warming_up = samples < runtime.warmup_samples;
+        trace!(
+            "sample ts={}{}: cpu={}% steal={}% iowait={}% ram={}% ({}/{} bytes) io_read={} B/s io_write={} B/s",
+            snapshot
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            if warming_up { " (warmup)" } else { "" },
+            snapshot.cpu_usage_percent,
+            snapshot.cpu_steal_percent,
+            snapshot.cpu_iowait_percent,
+            snapshot.ram_usage_percent,
+            snapshot.ram_used_bytes,
+            snapshot.ram_total_bytes,
+            snapshot.io_read_bytes_per_s,
+            snapshot.io_write_bytes_per_s
+        );
+
+        if let Some(sparkline) = &mut sparkline {
+            sparkline.push(&snapshot);
+        } else if !runtime.quiet {
+            if runtime.output_format == OutputFormat::Table && !header_printed {
+                print_table_header(&mut out)?;
+                header_printed = true;
+            }
+            if warming_up && runtime.output_format == OutputFormat::Text {
+                write!(out, "{} ", "warmup".dimmed())?;
+            }
+            print_snapshot(
+                &mut out,
+                &snapshot,
+                runtime.output_format,
+                runtime.precision,
+                &runtime.bands,
+                runtime.byte_units,
+                runtime.host.as_deref().filter(|_| runtime.snapshot_host),
+            )?;
+        }
+
+        overhead.update(&snapshot);
+        if warming_up {
+            warmup_means.update(&snapshot);
+        } else {
+            peaks.update(&snapshot);
+            if let Some(histograms) = &mut histograms {
+                histograms.update(&snapshot);
+            }
+        }
+
+        let mut events = Vec::new();
+        let explain = runtime.explain && !warming_up;
+        let open_before = if explain {
+            analyzer_state.ongoing_spikes()
+        } else {
+            Vec::new()
+        };
+
+        if !warming_up && !thresholds.schedule.is_empty() {
+            let rule = thresholds.schedule_rule(minute_of_day(snapshot.timestamp));
+            if rule != scheduled.as_ref().map(|(index, _)| *index) {
+                // Spikes open under the old thresholds end at the switch.
+                events = finalize(
+                    &mut analyzer_state,
+                    snapshot.timestamp,
+                    snapshot.instant,
+                    scheduled.as_ref().map_or(&thresholds, |(_, t)| t),
+                    runtime.min_spike_duration_secs,
+                );
+                match rule {
+                    Some(index) => {
+                        eprintln!("{} Schedule {} in effect", tag, thresholds.schedule[index])
+                    }
+                    None => eprintln!(
+                        "{} No schedule rule in effect, using default thresholds",
+                        tag
+                    ),
+                }
+                scheduled = rule.map(|index| (index, thresholds.with_schedule_rule(index)));
+            }
+        }
+
+        if !warming_up {
+            events.extend(analyze_snapshot(
+                &snapshot,
+                scheduled.as_ref().map_or(&thresholds, |(_, t)| t),
+                runtime.min_spike_duration_secs,
+                &mut analyzer_state,
+            ));
+        }
+
+        if explain {
+            let in_effect = scheduled.as_ref().map_or(&thresholds, |(_, t)| t);
+            let open = analyzer_state.ongoing_spikes();
+            let discarded = discard_notes(
+                &open_before,
+                &open,
+                &events,
+                snapshot.timestamp,
+                in_effect,
+                runtime.min_spike_duration_secs,
+                &runtime.labels,
+            );
+            let pending = pending_notes(
+                &open,
+                &snapshot,
+                in_effect,
+                runtime.min_spike_duration_secs,
+                &runtime.labels,
+                runtime.precision.unwrap_or(1),
+            );
+            for note in discarded.into_iter().chain(pending) {
+                eprintln!("{} {}", tag, note);
+            }
+        }
+
+        spike_seen |= !events.is_empty();
+        spikes += events.iter().filter(|e| e.phase == EventPhase::End).count() as u64;
+
+        if let Some(sparkline) = &mut sparkline {
+            if !events.is_empty() || runtime.show_ongoing {
+                sparkline.clear(&mut out)?;
+            }
+        }
+
+        if let Some(counters) = &mut spike_counters {
+            events.iter().for_each(|e| counters.record(e));
+            if !runtime.quiet {
+                print_openmetrics(
+                    &mut out,
+                    &snapshot,
+                    counters,
+                    runtime.precision,
+                    runtime.host.as_deref().filter(|_| runtime.snapshot_host),
+                )?;
+            }
+        }
+
+        for mut event in events {
+            event.tag = runtime.tag.clone();
+            emit_event(&mut out, &mut sinks, &event, runtime, tag)?;
+        }
+
+        // Closed spikes were reset above, so only still-open ones show.
+        if runtime.show_ongoing && !warming_up {
+            for spike in analyzer_state.ongoing_spikes() {
+                print_ongoing(
+                    &mut out,
+                    &spike,
+                    snapshot.timestamp,
+                    runtime.output_format,
+                    runtime.precision,
+                    &runtime.labels,
+                )?;
+            }
+        }
+
+        if let Some(sparkline) = &mut sparkline {
+            sparkline.draw(&mut out, &runtime.labels, prec)?;
+        }
+
+        last_snapshot = Some(snapshot);
+        samples += 1;
+        if let Some(progress) = &mut progress {
+            progress.update(samples, spikes, start.elapsed());
+        }
+        if warming_up && samples == runtime.warmup_samples {
+            eprintln!("{} Warmup done after {} samples", tag, samples);
+            for (kind, ratio, mean) in thresholds.resolve_ratios(|k| warmup_means.mean(k)) {
+                eprintln!(
+                    "{} {} threshold: {}x warmup mean {:.prec$}{} = {:.prec$}{}",
+                    tag,
+                    runtime.labels.label(kind),
+                    ratio,
+                    mean,
+                    runtime.labels.unit(kind),
+                    ratio * mean,
+                    runtime.labels.unit(kind)
+                );
+            }
+        }
+
+        if let Some(heartbeat) = &mut heartbeat {
+            if heartbeat.due() {
+                eprintln!(
+                    "{} still alive, {} samples ({} dropped, {} discarded), {} active spikes",
+                    tag,
+                    samples,
+                    dropped,
+                    discarded,
+                    analyzer_state.active_spikes()
+                );
+            }
+        }
+    }
+
+    source.close();
+    if let Some(log) = &mut samples_log {
+        log.flush()?;
+    }
+    if let Some(progress) = &mut progress {
+        progress.finish();
+    }
+    if let Some(sparkline) = &mut sparkline {
+        sparkline.clear(&mut out)?;
+    }
+
+    // A spike still open at the limit would otherwise never be reported.
+    // With a state file it is resumed by the next run instead.
+    if config.finalize && runtime.state_file.is_none() && !shutdown::requested() {
+        if let Some(last) = last_snapshot.filter(|_| samples > runtime.warmup_samples) {
+            let now = last.timestamp;
+            let open_before = if runtime.explain {
+                analyzer_state.ongoing_spikes()
+            } else {
+                Vec::new()
+            };
+            let events = finalize(
+                &mut analyzer_state,
+                now,
+                last.instant,
+                &thresholds,
+                runtime.min_spike_duration_secs,
+            );
+            let discarded = discard_notes(
+                &open_before,
+                &[],
+                &events,
+                now,
+                &thresholds,
+                runtime.min_spike_duration_secs,
+                &runtime.labels,
+            );
+            for note in discarded {
+                eprintln!("{} {}", tag, note);
+            }
+            spike_seen |= !events.is_empty();
+            // OpenMetrics prints no event lines, so the closed spikes only
+            // show in one more exposition of the counters.
+            if let Some(counters) = spike_counters.as_mut().filter(|_| !events.is_empty()) {
+                events.iter().for_each(|e| counters.record(e));
+                if !runtime.quiet {
+                    print_openmetrics(
+                        &mut out,
+                        &last,
+                        counters,
+                        runtime.precision,
+                        runtime.host.as_deref().filter(|_| runtime.snapshot_host),
+                    )?;
+                }
+            }
+            for mut event in events {
+                event.tag = runtime.tag.clone();
+                emit_event(&mut out, &mut sinks, &event, runtime, tag)?;
+            }
+        }
+    }
+
+    read_errors.flush();
+    lag_warnings.flush();
+
+    if let Some(path) = &runtime.state_file {
+        if let Err(e) = analyzer_state.save(path) {
+            eprintln!("{} Error saving state to {}: {e}", tag, path);
+        }
+    }
+    eprintln!(
+        "{} done: {} samples, {} dropped, {} discarded",
+        tag, samples, dropped, discarded
+    );
+    for mark in peaks.marks() {
+        let ts = mark
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        eprintln!(
+            "{} peak {}: {:.prec$}{} at {}",
+            tag,
+            runtime.labels.label(mark.resource),
+            mark.value,
+            runtime.labels.unit(mark.resource),
+            ts
+        );
+    }
+    if let Some(cpu_mean) = overhead.cpu_mean() {
+        eprintln!(
+            "{} self: cpu mean {:.prec$}% max {:.prec$}%, rss max {:.1}MB over {} samples",
+            tag,
+            cpu_mean,
+            overhead.cpu_max,
+            overhead.rss_max_bytes as f64 / 1_000_000.0,
+            overhead.samples()
+        );
+    }
+    for histogram in histograms.iter().flat_map(|h| h.histograms()) {
+        if runtime.output_format == OutputFormat::Json {
+            print_histogram_json(&mut out, histogram, prec)?;
+            continue;
+        }
+
+        let samples = histogram.samples();
+        let unit = runtime.labels.unit(histogram.resource);
+        eprintln!(
+            "{} histogram {} ({} samples):",
+            tag,
+            runtime.labels.label(histogram.resource),
+            samples
+        );
+        for bucket in &histogram.buckets {
+            // Under- and overflow only show when something fell there.
+            if (bucket.from.is_none() || bucket.to.is_none()) && bucket.samples == 0 {
+                continue;
+            }
+            eprintln!(
+                "{}   {:>14} {:>8} {:>6.1}%",
+                tag,
+                bucket.label(unit),
+                bucket.samples,
+                bucket.samples as f64 / samples as f64 * 100.0
+            );
+        }
+    }
+
+    Ok(spike_seen)
+}
+
+/// Print `event` and hand it to every sink; sink errors are reported and
+/// don't stop the run.
+fn emit_event(
+    out: &mut dyn Write,
+    sinks: &mut [Box<dyn EventSink>],
+    event: &SpikeEvent,
+    runtime: &RuntimeConfig,
+    tag: &str,
+) -> io::Result<()> {
+    print_event(
+        out,
+        event,
+        runtime.output_format,
+        runtime.precision,
+        &runtime.labels,
+        runtime.host.as_deref(),
+    )?;
+
+    for sink in sinks {
+        if let Err(e) = sink.handle(event) {
+            eprintln!("{} Error writing event to {}: {e}", tag, sink.name());
+        }
+    }
+    Ok(())
+}
+
+/// Data stream destination: `path` opened for append, or stdout, with
+/// lines ended by `line_ending`.
+///
+/// Files are flushed per line, so the stream can be followed while the
+/// monitor runs.
+pub(crate) fn open_output(
+    path: Option<&str>,
+    line_ending: LineEnding,
+) -> Result<Box<dyn Write>, Box<dyn Error>> {
+    let inner: Box<dyn Write> = match path {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("Failed to open output file {}: {}", path, e))?;
+            Box::new(LineWriter::new(file))
+        }
+        None => Box::new(io::stdout()),
+    };
+    Ok(Box::new(LineEndingWriter::new(inner, line_ending)))
+}
+
+/// Resume from `path` if given, else start fresh. A missing, stale or
+/// unreadable state file is not fatal.
+fn load_state(path: Option<&str>, max_age_secs: u64, tag: &str) -> AnalyzerState {
+    let Some(path) = path else {
+        return AnalyzerState::new();
+    };
+
+    match AnalyzerState::load(path, Duration::from_secs(max_age_secs)) {
+        Ok(Some(state)) => {
+            eprintln!(
+                "{} Resumed state from {} ({} open spikes)",
+                tag,
+                path,
+                state.active_spikes()
+            );
+            state
+        }
+        Ok(None) => AnalyzerState::new(),
+        Err(e) => {
+            eprintln!("{} Ignoring state file {}: {e}", tag, path);
+            AnalyzerState::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ResourceKind;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Sink recording the id of every event it gets, or failing each one.
+    struct RecordingSink {
+        name: &'static str,
+        fail: bool,
+        seen: Rc<RefCell<Vec<(&'static str, String)>>>,
+    }
+
+    impl EventSink for RecordingSink {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn handle(&mut self, event: &SpikeEvent) -> Result<(), Box<dyn Error>> {
+            self.seen.borrow_mut().push((self.name, event.id.clone()));
+            if self.fail {
+                return Err("disk full".into());
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn every_event_reaches_every_sink_despite_errors() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let sink = |name, fail| -> Box<dyn EventSink> {
+            Box::new(RecordingSink {
+                name,
+                fail,
+                seen: Rc::clone(&seen),
+            })
+        };
+        let mut sinks = vec![
+            sink("first", false),
+            sink("broken", true),
+            sink("last", false),
+        ];
+
+        let runtime = RuntimeConfig {
+            output_format: OutputFormat::Json,
+            ..RuntimeConfig::default()
+        };
+        let mut out = Vec::new();
+        for id in ["e1", "e2"] {
+            let mut event = SpikeEvent::ended(ResourceKind::Cpu, 1_700_000_000, 5, 97.0, 90.0);
+            event.id = id.to_string();
+            emit_event(&mut out, &mut sinks, &event, &runtime, "[test]").unwrap();
+        }
+
+        assert_eq!(
+            *seen.borrow(),
+            [
+                ("first", "e1".to_string()),
+                ("broken", "e1".to_string()),
+                ("last", "e1".to_string()),
+                ("first", "e2".to_string()),
+                ("broken", "e2".to_string()),
+                ("last", "e2".to_string()),
+            ]
+        );
+        // The events are printed too, whatever the sinks did.
+        assert_eq!(String::from_utf8(out).unwrap().lines().count(), 2);
+    }
+}
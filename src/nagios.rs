@@ -0,0 +1,284 @@
+//! Nagios plugin protocol: one reading, one `STATUS - summary | perfdata`
+//! line, and the status as exit code.
+
+use crate::analyzer::io_mb_per_s;
+use crate::config::{Bands, Collectors, NagiosConfig, RuntimeConfig, Severity};
+use crate::live::read_once;
+use crate::metrics::SystemSnapshot;
+
+/// Plugin states; the discriminant is the exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginStatus {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+    Unknown = 3,
+}
+
+impl PluginStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            PluginStatus::Ok => "OK",
+            PluginStatus::Warning => "WARNING",
+            PluginStatus::Critical => "CRITICAL",
+            PluginStatus::Unknown => "UNKNOWN",
+        }
+    }
+
+    pub fn exit_code(self) -> u8 {
+        self as u8
+    }
+
+    /// Precedence when combining resources: critical over warning over
+    /// unknown over ok.
+    fn rank(self) -> u8 {
+        match self {
+            PluginStatus::Ok => 0,
+            PluginStatus::Unknown => 1,
+            PluginStatus::Warning => 2,
+            PluginStatus::Critical => 3,
+        }
+    }
+}
+
+impl From<Severity> for PluginStatus {
+    fn from(severity: Severity) -> Self {
+        match severity {
+            Severity::Ok => PluginStatus::Ok,
+            Severity::Warn => PluginStatus::Warning,
+            Severity::Crit => PluginStatus::Critical,
+        }
+    }
+}
+
+/// One reported resource.
+struct Check {
+    label: &'static str,
+    /// Perfdata label.
+    name: &'static str,
+    /// None when the resource could not be read.
+    value: Option<f32>,
+    decimals: usize,
+    /// Unit in the summary and, when a valid perfdata UOM, the perfdata.
+    unit: &'static str,
+    perf_unit: &'static str,
+    /// Min and max of the perfdata, for percentages.
+    range: Option<(u32, u32)>,
+    bands: Bands,
+}
+
+impl Check {
+    fn status(&self) -> PluginStatus {
+        match self.value {
+            Some(v) => self.bands.classify(v).into(),
+            None => PluginStatus::Unknown,
+        }
+    }
+
+    fn summary(&self) -> String {
+        let mut text = match self.value {
+            Some(v) => format!(
+                "{} {:.prec$}{}",
+                self.label,
+                v,
+                self.unit,
+                prec = self.decimals
+            ),
+            None => format!("{} unavailable", self.label),
+        };
+        let status = self.status();
+        if status != PluginStatus::Ok {
+            text.push_str(&format!(" ({})", status.name()));
+        }
+        text
+    }
+
+    /// `name=value[UOM];[warn];[crit];[min];[max]`; None without a value.
+    fn perfdata(&self) -> Option<String> {
+        let value = self.value?;
+        let level = |l: Option<f32>| l.map(|l| l.to_string()).unwrap_or_default();
+        let mut perf = format!(
+            "{}={:.prec$}{};{};{}",
+            self.name,
+            value,
+            self.perf_unit,
+            level(self.bands.warn),
+            level(self.bands.crit),
+            prec = self.decimals
+        );
+        if let Some((min, max)) = self.range {
+            perf.push_str(&format!(";{};{}", min, max));
+        }
+        Some(perf)
+    }
+}
+
+/// Nagios mode: take one reading, print the plugin line and return the
+/// worst status across resources, which the caller exits with. A failed
+/// reading is UNKNOWN.
+pub fn run_nagios(config: NagiosConfig) -> PluginStatus {
+    let runtime = RuntimeConfig {
+        collectors: Collectors {
+            temp: config.bands.temp.is_set(),
+            ..Collectors::default()
+        },
+        cgroup: config.cgroup.clone(),
+        ..RuntimeConfig::default()
+    };
+
+    match read_once(&runtime) {
+        Ok(snapshot) => {
+            let (status, line) = report(&snapshot, &config);
+            println!("{}", line);
+            status
+        }
+        Err(e) => {
+            println!("RESOURCES UNKNOWN - {}", e);
+            PluginStatus::Unknown
+        }
+    }
+}
+
+/// Worst status and plugin line for `snapshot`. CPU, RAM and IO are
+/// always reported; temperature only when it has bands.
+fn report(snapshot: &SystemSnapshot, config: &NagiosConfig) -> (PluginStatus, String) {
+    let bands = &config.bands;
+    let mut checks = vec![
+        Check {
+            label: "CPU",
+            name: "cpu",
+            value: Some(snapshot.cpu_usage_percent),
+            decimals: 1,
+            unit: "%",
+            perf_unit: "%",
+            range: Some((0, 100)),
+            bands: bands.cpu,
+        },
+        Check {
+            label: "RAM",
+            name: "ram",
+            value: Some(snapshot.ram_usage_percent),
+            decimals: 1,
+            unit: "%",
+            perf_unit: "%",
+            range: Some((0, 100)),
+            bands: bands.ram,
+        },
+        Check {
+            label: "IO",
+            name: "io",
+            value: Some(io_mb_per_s(snapshot)),
+            decimals: 2,
+            unit: "MB/s",
+            // MB/s is not a perfdata UOM; the value is unitless there.
+            perf_unit: "",
+            range: None,
+            bands: bands.io,
+        },
+    ];
+    if bands.temp.is_set() {
+        checks.push(Check {
+            label: "TEMP",
+            name: "temp",
+            value: snapshot.cpu_temp_celsius,
+            decimals: 1,
+            unit: "°C",
+            perf_unit: "",
+            range: None,
+            bands: bands.temp,
+        });
+    }
+
+    let status = checks
+        .iter()
+        .map(Check::status)
+        .max_by_key(|s| s.rank())
+        .unwrap_or(PluginStatus::Ok);
+    let summary: Vec<String> = checks.iter().map(Check::summary).collect();
+    let perfdata: Vec<String> = checks.iter().filter_map(Check::perfdata).collect();
+
+    let line = format!(
+        "RESOURCES {} - {} | {}",
+        status.name(),
+        summary.join(", "),
+        perfdata.join(" ")
+    );
+    (status, line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SeverityBands;
+
+    fn config(cpu: Bands, temp: Bands) -> NagiosConfig {
+        NagiosConfig {
+            bands: SeverityBands {
+                cpu,
+                temp,
+                ..SeverityBands::default()
+            },
+            cgroup: None,
+        }
+    }
+
+    fn reading(cpu: f32) -> SystemSnapshot {
+        let mut snapshot = SystemSnapshot::at_secs(1_700_000_000);
+        snapshot.cpu_usage_percent = cpu;
+        snapshot.ram_usage_percent = 40.0;
+        snapshot.io_read_bytes_per_s = 1_500_000.0;
+        snapshot
+    }
+
+    #[test]
+    fn exit_code_per_band() {
+        let cpu = Bands {
+            warn: Some(80.0),
+            crit: Some(95.0),
+        };
+        let config = config(cpu, Bands::default());
+
+        let (status, line) = report(&reading(42.1), &config);
+        assert_eq!(status.exit_code(), 0);
+        assert_eq!(
+            line,
+            "RESOURCES OK - CPU 42.1%, RAM 40.0%, IO 1.50MB/s | cpu=42.1%;80;95;0;100 ram=40.0%;;;0;100 io=1.50;;"
+        );
+
+        let (status, line) = report(&reading(80.0), &config);
+        assert_eq!(status.exit_code(), 1);
+        assert!(
+            line.starts_with("RESOURCES WARNING - CPU 80.0% (WARNING),"),
+            "{}",
+            line
+        );
+
+        let (status, line) = report(&reading(99.5), &config);
+        assert_eq!(status.exit_code(), 2);
+        assert!(
+            line.starts_with("RESOURCES CRITICAL - CPU 99.5% (CRITICAL),"),
+            "{}",
+            line
+        );
+    }
+
+    #[test]
+    fn unreadable_resource_is_unknown_unless_something_is_worse() {
+        let temp = Bands {
+            warn: Some(70.0),
+            crit: Some(90.0),
+        };
+        // No temperature sensor: temp has bands but no value.
+        let (status, line) = report(&reading(42.1), &config(Bands::default(), temp));
+        assert_eq!(status.exit_code(), 3);
+        assert!(line.contains("TEMP unavailable (UNKNOWN)"), "{}", line);
+        assert!(!line.contains("temp="), "{}", line);
+
+        let cpu = Bands {
+            warn: Some(80.0),
+            crit: None,
+        };
+        let (status, _) = report(&reading(85.0), &config(cpu, temp));
+        assert_eq!(status, PluginStatus::Warning);
+    }
+}
@@ -1,7 +1,12 @@
-use crate::analyzer::SpikeEvent;
-use crate::config::{OutputFormat, ResourceKind};
+use crate::analyzer::{io_mb_per_s, EventPhase, OngoingSpike, SpikeEvent};
+use crate::config::{
+    Bands, ByteUnits, LineEnding, OutputFormat, ResourceKind, ResourceLabels, RuntimeConfig,
+    Severity, SeverityBands,
+};
+use crate::histogram::Histogram;
 use crate::metrics::SystemSnapshot;
 use colored::*;
+use std::io::{self, Write};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Format SystemTime as seconds since Unix epoch.
@@ -12,17 +17,266 @@ fn format_time_secs(t: SystemTime) -> String {
     }
 }
 
-/// Human unit label for resource values.
-fn resource_unit(kind: ResourceKind) -> &'static str {
-    match kind {
-        ResourceKind::Cpu => "%",
-        ResourceKind::Ram => "%",
-        ResourceKind::Io => "MB/s",
+/// Quote and escape a string for JSON output.
+pub(crate) fn json_string(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_else(|_| "\"\"".to_string())
+}
+
+/// Decimal places of event values (peak, threshold, process CPU) in JSON
+/// output and the event log when `--precision` is not given.
+pub const DEFAULT_PRECISION: usize = 2;
+
+/// JSON number for `value` rounded to `decimals`, in shortest form:
+/// 42.0 stays "42.0" rather than "42.0000", and 33.333333 becomes
+/// "33.33" everywhere it is written. Non-finite values are `null`.
+pub(crate) fn json_number(value: f64, decimals: usize) -> String {
+    serde_json::Number::from_f64(round_to(value, decimals))
+        .map(|n| n.to_string())
+        .unwrap_or_else(|| "null".to_string())
+}
+
+/// Format a byte rate in the largest unit that keeps the value below
+/// the next step, e.g. 11_800_000.0 -> "11.80 MB/s" (decimal, precision
+/// 2) or "11.25 MiB/s" (binary). `ByteUnits::Raw` keeps plain B/s.
+pub fn format_byte_rate(bytes_per_s: f64, units: ByteUnits, precision: usize) -> String {
+    let (base, suffixes): (f64, &[&str]) = match units {
+        ByteUnits::Decimal => (1000.0, &["B/s", "KB/s", "MB/s", "GB/s", "TB/s"]),
+        ByteUnits::Binary => (1024.0, &["B/s", "KiB/s", "MiB/s", "GiB/s", "TiB/s"]),
+        ByteUnits::Raw => return format!("{:.precision$} B/s", bytes_per_s),
+    };
+
+    let mut value = bytes_per_s;
+    let mut unit = 0;
+    // Compare the rounded value so 999.999 KB/s becomes 1.00 MB/s, not 1000.00 KB/s.
+    while unit + 1 < suffixes.len() && round_to(value, precision).abs() >= base {
+        value /= base;
+        unit += 1;
+    }
+
+    format!("{:.precision$} {}", value, suffixes[unit])
+}
+
+/// `value` rounded to `precision` decimals.
+fn round_to(value: f64, precision: usize) -> f64 {
+    let factor = 10f64.powi(precision.min(15) as i32);
+    (value * factor).round() / factor
+}
+
+/// Color for a value in its warn/crit bands; None when the resource has
+/// no bands, so callers keep their usual color.
+fn severity_color(value: f32, bands: &Bands) -> Option<Color> {
+    if !bands.is_set() {
+        return None;
+    }
+
+    Some(match bands.classify(value) {
+        Severity::Ok => Color::Green,
+        Severity::Warn => Color::Yellow,
+        Severity::Crit => Color::Red,
+    })
+}
+
+/// Apply an optional color to an already formatted value.
+fn paint(text: String, color: Option<Color>) -> ColoredString {
+    match color {
+        Some(c) => text.color(c),
+        None => text.normal(),
+    }
+}
+
+/// JSON value of an optional number, `null` when unset.
+fn json_opt<T: std::fmt::Display>(value: Option<T>) -> String {
+    match value {
+        Some(v) => v.to_string(),
+        None => "null".to_string(),
+    }
+}
+
+/// `"host":<name>,` for a JSON object, or nothing without a host.
+fn host_field(host: Option<&str>) -> String {
+    match host {
+        Some(h) => format!("\"host\":{},", json_string(h)),
+        None => String::new(),
+    }
+}
+
+/// Fields of a spike event as JSON, without the enclosing braces.
+///
+/// Shared by JSON output and the event log, which only differ in the
+/// leading `"type"` / `"v"` field, so the same spike is written
+/// byte-for-byte the same in both.
+pub(crate) fn event_json_fields(
+    event: &SpikeEvent,
+    host: Option<&str>,
+    precision: usize,
+) -> String {
+    let duration_secs = match event.timestamp_end.duration_since(event.timestamp_start) {
+        Ok(d) => d.as_secs(),
+        Err(_) => 0,
+    };
+
+    let path_str = match &event.path {
+        Some(p) => format!("\"path\":{},", json_string(p)),
+        None => String::new(),
+    };
+
+    let tag_str = match &event.tag {
+        Some(t) => format!("\"tag\":{},", json_string(t)),
+        None => String::new(),
+    };
+
+    let rule_str = match &event.rule {
+        Some(r) => format!("\"rule\":{},", json_string(r)),
+        None => String::new(),
+    };
+
+    let uptime_str = match event.uptime_start_secs {
+        Some(secs) => format!("\"uptime_start\":{},", secs),
+        None => String::new(),
+    };
+
+    let mut fields = format!(
+        "{}{}\"id\":{},\"phase\":\"{}\",\"resource\":\"{}\",{}{}\"ts_start\":{},\"ts_end\":{},\"duration_secs\":{},\"peak\":{},\"threshold\":{},{}\"top\":[",
+        host_field(host),
+        tag_str,
+        json_string(&event.id),
+        event.phase.as_str(),
+        event.resource.name(),
+        path_str,
+        rule_str,
+        format_time_secs(event.timestamp_start),
+        format_time_secs(event.timestamp_end),
+        duration_secs,
+        json_number(event.peak_value.into(), precision),
+        json_number(event.threshold.into(), precision),
+        uptime_str,
+    );
+
+    for (i, p) in event.top_processes.iter().enumerate() {
+        if i > 0 {
+            fields.push(',');
+        }
+        fields.push_str(&format!(
+            "{{\"pid\":{},\"name\":{},\"cpu\":{},\"ram_bytes\":{}}}",
+            p.pid,
+            json_string(&p.name),
+            json_number(p.cpu_percent.into(), precision),
+            p.ram_bytes
+        ));
     }
+
+    fields.push(']');
+    fields
+}
+
+/// ` over=1.62x !!` for text alerts: the over-threshold ratio, then one
+/// `!` from 1.2x, two from 1.5x and three from 2x, so a spike that
+/// doubled its threshold stands out from one that barely crossed it.
+pub(crate) fn over_ratio_suffix(over_ratio: Option<f32>) -> String {
+    let Some(ratio) = over_ratio else {
+        return String::new();
+    };
+
+    let marks = match ratio {
+        r if r >= 2.0 => " !!!",
+        r if r >= 1.5 => " !!",
+        r if r >= 1.2 => " !",
+        _ => "",
+    };
+    format!(" over={:.2}x{}", ratio, marks)
+}
+
+/// Print the JSON stream header describing this run.
+///
+/// Emitted once before the first snapshot in JSON mode (also with
+/// `--quiet`) so consumers know what produced the stream. Unset
+/// thresholds are `null`.
+pub fn print_meta(out: &mut dyn Write, config: &RuntimeConfig) -> io::Result<()> {
+    let t = &config.thresholds;
+
+    let composite = match &t.composite {
+        Some(rule) => json_string(&rule.to_string()),
+        None => "null".to_string(),
+    };
+    let hostname = match &config.host {
+        Some(h) => json_string(h),
+        None => "null".to_string(),
+    };
+
+    writeln!(
+        out,
+        "{{\"type\":\"meta\",\"interval_ms\":{},\"thresholds\":{{\"cpu\":{},\"ram\":{},\"ram_bytes\":{},\"io\":{},\"io_read\":{},\"io_write\":{},\"steal\":{},\"iowait\":{},\"temp\":{},\"power\":{},\"fd\":{},\"procs\":{},\"disk\":{},\"composite\":{}}},\"warmup_samples\":{},\"hostname\":{},\"started_ts\":{}}}",
+        config.interval_ms,
+        json_opt(t.cpu_threshold),
+        json_opt(t.ram_threshold),
+        json_opt(t.ram_threshold_bytes),
+        json_opt(t.io_threshold),
+        json_opt(t.io_read_threshold),
+        json_opt(t.io_write_threshold),
+        json_opt(t.steal_threshold),
+        json_opt(t.iowait_threshold),
+        json_opt(t.temp_threshold),
+        json_opt(t.power_threshold),
+        json_opt(t.fd_threshold),
+        json_opt(t.proc_count_threshold),
+        json_opt(t.disk_threshold),
+        composite,
+        config.warmup_samples,
+        hostname,
+        format_time_secs(SystemTime::now()),
+    )?;
+    Ok(())
+}
+
+/// Column widths for table output: TIME, percentages, IO rates.
+const TABLE_TIME_WIDTH: usize = 10;
+const TABLE_PCT_WIDTH: usize = 7;
+const TABLE_IO_WIDTH: usize = 14;
+
+/// Print the table header row (once per run, before the first snapshot).
+pub fn print_table_header(out: &mut dyn Write) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:>tw$} | {:>pw$} | {:>pw$} | {:>pw$} | {:>pw$} | {:>pw$} | {:>iw$} | {:>iw$}",
+        "TIME",
+        "CPU%",
+        "STEAL%",
+        "IOWAIT%",
+        "RAM%",
+        "SWAP%",
+        "IO R",
+        "IO W",
+        tw = TABLE_TIME_WIDTH,
+        pw = TABLE_PCT_WIDTH,
+        iw = TABLE_IO_WIDTH,
+    )?;
+    Ok(())
 }
 
 /// Print one line with current system metrics.
-pub fn print_snapshot(snapshot: &SystemSnapshot, format: OutputFormat) {
+///
+/// `precision` overrides the decimal places of every value; when None,
+/// percentages use 1 decimal and IO rates 2.
+///
+/// In text and table output, values of resources with `bands` are
+/// colored green/yellow/red by severity and IO rates are scaled per
+/// `byte_units`. JSON is never colored and keeps raw B/s.
+pub fn print_snapshot(
+    out: &mut dyn Write,
+    snapshot: &SystemSnapshot,
+    format: OutputFormat,
+    precision: Option<usize>,
+    bands: &SeverityBands,
+    byte_units: ByteUnits,
+    host: Option<&str>,
+) -> io::Result<()> {
+    let pct = precision.unwrap_or(1);
+    let io = precision.unwrap_or(2);
+
+    let cpu_color = severity_color(snapshot.cpu_usage_percent, &bands.cpu);
+    let ram_color = severity_color(snapshot.ram_usage_percent, &bands.ram);
+    let io_color = severity_color(io_mb_per_s(snapshot), &bands.io);
+
     match format {
         OutputFormat::Text => {
             let ts = format_time_secs(snapshot.timestamp);
@@ -32,36 +286,225 @@ pub fn print_snapshot(snapshot: &SystemSnapshot, format: OutputFormat) {
             let ram_label = "RAM".green().bold();
             let io_label = "IO".magenta().bold();
 
-            println!(
-                "{} {}: {:.1}% | {}: {:.1}% | {}: {:.2} B/s r, {:.2} B/s w",
+            let temp_str = match snapshot.cpu_temp_celsius {
+                Some(t) => format!(
+                    " | {}: {}",
+                    "TEMP".yellow().bold(),
+                    paint(format!("{:.pct$}°C", t), severity_color(t, &bands.temp))
+                ),
+                None => String::new(),
+            };
+
+            let mut power_str = String::new();
+            if snapshot.battery_percent.is_some() || snapshot.power_draw_watts.is_some() {
+                power_str = format!(" | {}:", "BAT".yellow().bold());
+                if let Some(percent) = snapshot.battery_percent {
+                    power_str.push_str(&format!(" {:.pct$}%", percent));
+                }
+                if let Some(watts) = snapshot.power_draw_watts {
+                    power_str.push_str(&format!(" {:.pct$}W", watts));
+                }
+            }
+
+            let fd_str = match snapshot.fd_usage {
+                Some(fd) => format!(
+                    " | {}: {} ({:.pct$}%)",
+                    "FD".blue().bold(),
+                    fd.open,
+                    fd.percent()
+                ),
+                None => String::new(),
+            };
+
+            let tasks_str = match snapshot.task_counts {
+                Some(tasks) => format!(
+                    " | {}: {} ({} threads)",
+                    "PROCS".blue().bold(),
+                    tasks.processes,
+                    tasks.threads
+                ),
+                None => String::new(),
+            };
+
+            let mut disk_str = String::new();
+            for d in &snapshot.disk_usage {
+                disk_str.push_str(&format!(
+                    " | {} {}: {:.pct$}%",
+                    "DISK".blue().bold(),
+                    d.path,
+                    d.used_percent
+                ));
+            }
+
+            let self_str = match snapshot.self_usage {
+                Some(usage) => format!(
+                    " | {}: {:.pct$}% {:.1}MB",
+                    "SELF".dimmed().bold(),
+                    usage.cpu_percent,
+                    usage.rss_bytes as f64 / 1_000_000.0
+                ),
+                None => String::new(),
+            };
+
+            writeln!(
+                out,
+                "{} {}: {} (steal {:.pct$}%, iowait {:.pct$}%) | {}: {} | {}: {}, {}{}{}{}{}{}{}",
                 ts_str,
                 cpu_label,
-                snapshot.cpu_usage_percent,
+                paint(format!("{:.pct$}%", snapshot.cpu_usage_percent), cpu_color),
+                snapshot.cpu_steal_percent,
+                snapshot.cpu_iowait_percent,
                 ram_label,
-                snapshot.ram_usage_percent,
+                paint(format!("{:.pct$}%", snapshot.ram_usage_percent), ram_color),
                 io_label,
-                snapshot.io_read_bytes_per_s,
-                snapshot.io_write_bytes_per_s,
-            );
+                paint(
+                    format!("{} r", format_byte_rate(snapshot.io_read_bytes_per_s, byte_units, io)),
+                    io_color
+                ),
+                paint(
+                    format!("{} w", format_byte_rate(snapshot.io_write_bytes_per_s, byte_units, io)),
+                    io_color
+                ),
+                temp_str,
+                power_str,
+                fd_str,
+                tasks_str,
+                disk_str,
+                self_str,
+            )?;
+        }
+        OutputFormat::Table => {
+            // Pad before coloring so escape codes don't break alignment.
+            let ts = format!("{:>w$}", format_time_secs(snapshot.timestamp), w = TABLE_TIME_WIDTH);
+            let cpu = format!("{:>w$.pct$}", snapshot.cpu_usage_percent, w = TABLE_PCT_WIDTH);
+            let steal = format!("{:>w$.pct$}", snapshot.cpu_steal_percent, w = TABLE_PCT_WIDTH);
+            let iowait = format!("{:>w$.pct$}", snapshot.cpu_iowait_percent, w = TABLE_PCT_WIDTH);
+            let ram = format!("{:>w$.pct$}", snapshot.ram_usage_percent, w = TABLE_PCT_WIDTH);
+            let swap = format!("{:>w$.pct$}", snapshot.swap_usage_percent, w = TABLE_PCT_WIDTH);
+            let io_r = format_byte_rate(snapshot.io_read_bytes_per_s, byte_units, io);
+            let io_w = format_byte_rate(snapshot.io_write_bytes_per_s, byte_units, io);
+            let io_r = format!("{:>w$}", io_r, w = TABLE_IO_WIDTH);
+            let io_w = format!("{:>w$}", io_w, w = TABLE_IO_WIDTH);
+
+            writeln!(
+                out,
+                "{} | {} | {} | {} | {} | {} | {} | {}",
+                ts.dimmed(),
+                cpu.color(cpu_color.unwrap_or(Color::Cyan)),
+                steal.cyan(),
+                iowait.cyan(),
+                ram.color(ram_color.unwrap_or(Color::Green)),
+                swap.yellow(),
+                io_r.color(io_color.unwrap_or(Color::Magenta)),
+                io_w.color(io_color.unwrap_or(Color::Magenta)),
+            )?;
         }
         OutputFormat::Json => {
             let ts = format_time_secs(snapshot.timestamp);
-            println!(
-                "{{\"ts\":{},\"cpu\":{:.1},\"ram\":{:.1},\"io_read\":{:.2},\"io_write\":{:.2}}}",
+            let temp_str = match snapshot.cpu_temp_celsius {
+                Some(t) => format!(",\"temp\":{}", json_number(t.into(), pct)),
+                None => String::new(),
+            };
+            let mut power_str = String::new();
+            if let Some(percent) = snapshot.battery_percent {
+                power_str.push_str(&format!(
+                    ",\"battery\":{}",
+                    json_number(percent.into(), pct)
+                ));
+            }
+            if let Some(watts) = snapshot.power_draw_watts {
+                power_str.push_str(&format!(",\"power\":{}", json_number(watts.into(), pct)));
+            }
+            let fd_str = match snapshot.fd_usage {
+                Some(fd) => format!(",\"fd\":{},\"fd_max\":{}", fd.open, fd.max),
+                None => String::new(),
+            };
+            let tasks_str = match snapshot.task_counts {
+                Some(tasks) => format!(
+                    ",\"procs\":{},\"threads\":{}",
+                    tasks.processes, tasks.threads
+                ),
+                None => String::new(),
+            };
+            let uptime_str = match snapshot.uptime_secs {
+                Some(secs) => format!(",\"uptime\":{}", secs),
+                None => String::new(),
+            };
+            let self_str = match snapshot.self_usage {
+                Some(usage) => format!(
+                    ",\"self_cpu\":{},\"self_rss\":{}",
+                    json_number(usage.cpu_percent.into(), pct),
+                    usage.rss_bytes
+                ),
+                None => String::new(),
+            };
+
+            let mut disk_str = String::new();
+            if !snapshot.disk_usage.is_empty() {
+                disk_str.push_str(",\"disk\":{");
+                for (i, d) in snapshot.disk_usage.iter().enumerate() {
+                    if i > 0 {
+                        disk_str.push(',');
+                    }
+                    disk_str.push_str(&format!(
+                        "{}:{}",
+                        json_string(&d.path),
+                        json_number(d.used_percent.into(), pct)
+                    ));
+                }
+                disk_str.push('}');
+            }
+
+            writeln!(
+                out,
+                "{{\"type\":\"snapshot\",{}\"ts\":{},\"cpu\":{},\"steal\":{},\"iowait\":{},\"ram\":{},\"io_read\":{},\"io_write\":{}{}{}{}{}{}{}{}}}",
+                host_field(host),
                 ts,
-                snapshot.cpu_usage_percent,
-                snapshot.ram_usage_percent,
-                snapshot.io_read_bytes_per_s,
-                snapshot.io_write_bytes_per_s,
-            );
+                json_number(snapshot.cpu_usage_percent.into(), pct),
+                json_number(snapshot.cpu_steal_percent.into(), pct),
+                json_number(snapshot.cpu_iowait_percent.into(), pct),
+                json_number(snapshot.ram_usage_percent.into(), pct),
+                json_number(snapshot.io_read_bytes_per_s, io),
+                json_number(snapshot.io_write_bytes_per_s, io),
+                temp_str,
+                power_str,
+                fd_str,
+                tasks_str,
+                uptime_str,
+                self_str,
+                disk_str,
+            )?;
         }
+        // Written after analysis with the spike counters, see
+        // `print_openmetrics`.
+        OutputFormat::OpenMetrics => {}
     }
+    Ok(())
 }
 
 /// Print a spike event (alert) in text or JSON format.
-pub fn print_event(event: &SpikeEvent, format: OutputFormat) {
+///
+/// Only data goes to `out` (stdout or --output-file); diagnostics belong
+/// on stderr so that JSON output stays one parseable object per line.
+///
+/// `precision` overrides the decimal places of every value; when None,
+/// peak/threshold use `DEFAULT_PRECISION` and process CPU 1 in text.
+/// JSON values are rounded like the event log's, so both show the same
+/// number. Text labels and units come from `labels`; JSON always uses
+/// the resource name.
+pub fn print_event(
+    out: &mut dyn Write,
+    event: &SpikeEvent,
+    format: OutputFormat,
+    precision: Option<usize>,
+    labels: &ResourceLabels,
+    host: Option<&str>,
+) -> io::Result<()> {
+    let prec = precision.unwrap_or(DEFAULT_PRECISION);
+    let pct = precision.unwrap_or(1);
+
     match format {
-        OutputFormat::Text => {
+        OutputFormat::Text | OutputFormat::Table => {
             let ts_start = format_time_secs(event.timestamp_start);
             let ts_end = format_time_secs(event.timestamp_end);
             let duration_secs = match event.timestamp_end.duration_since(event.timestamp_start) {
@@ -69,78 +512,901 @@ pub fn print_event(event: &SpikeEvent, format: OutputFormat) {
                 Err(_) => 0,
             };
 
-            let resource = match event.resource {
-                ResourceKind::Cpu => "CPU",
-                ResourceKind::Ram => "RAM",
-                ResourceKind::Io => "IO",
+            let resource = labels.label(event.resource);
+
+            let target = match (&event.path, &event.rule) {
+                (Some(p), _) => format!(" ({})", p),
+                (None, Some(rule)) => format!(" [{}]", rule),
+                (None, None) => String::new(),
             };
 
-            let unit = resource_unit(event.resource);
+            let unit = labels.unit(event.resource);
 
-            let header = format!(
-                ">>> {} spike: start={} end={} duration={}s peak={:.2}{} (threshold={:.2}{})",
-                resource,
-                ts_start,
-                ts_end,
-                duration_secs,
-                event.peak_value,
-                unit,
-                event.threshold,
-                unit,
-            )
+            let id = match &event.tag {
+                Some(t) => format!("{} tag={}", event.id, t),
+                None => event.id.clone(),
+            };
+
+            let header = match event.phase {
+                EventPhase::Start => format!(
+                    ">>> {}{} spike started: id={} start={} peak={:.prec$}{} (threshold={:.prec$}{}){}",
+                    resource,
+                    target,
+                    id,
+                    ts_start,
+                    event.peak_value,
+                    unit,
+                    event.threshold,
+                    unit,
+                    over_ratio_suffix(event.over_ratio()),
+                ),
+                EventPhase::End => format!(
+                    ">>> {}{} spike: id={} start={} end={} duration={}s peak={:.prec$}{} (threshold={:.prec$}{}){}",
+                    resource,
+                    target,
+                    id,
+                    ts_start,
+                    ts_end,
+                    duration_secs,
+                    event.peak_value,
+                    unit,
+                    event.threshold,
+                    unit,
+                    over_ratio_suffix(event.over_ratio()),
+                ),
+                EventPhase::Flap => format!(
+                    ">>> {}{} flapping: id={} spikes={} start={} end={} (threshold={})",
+                    resource,
+                    target,
+                    id,
+                    event.peak_value,
+                    ts_start,
+                    ts_end,
+                    event.threshold,
+                ),
+                EventPhase::Growth => format!(
+                    ">>> {}{} growing: id={} rate={:.prec$}MB/min start={} end={} (threshold={:.prec$}MB/min){}",
+                    resource,
+                    target,
+                    id,
+                    event.peak_value,
+                    ts_start,
+                    ts_end,
+                    event.threshold,
+                    over_ratio_suffix(event.over_ratio()),
+                ),
+            }
             .red()
             .bold();
 
-            println!("{}", header);
+            writeln!(out, "{}", header)?;
 
             if !event.top_processes.is_empty() {
-                println!("{}", "    Top processes at peak:".yellow());
+                writeln!(out, "{}", "    Top processes at peak:".yellow())?;
                 for p in &event.top_processes {
-                    println!(
-                        "      PID {} ({}) CPU={:.1}% RAM={} bytes",
+                    writeln!(
+                        out,
+                        "      PID {} ({}) CPU={:.pct$}% RAM={} bytes",
                         p.pid.to_string().cyan(),
                         p.name,
                         p.cpu_percent,
                         p.ram_bytes
-                    );
+                    )?;
                 }
             }
         }
         OutputFormat::Json => {
-            let ts_start = format_time_secs(event.timestamp_start);
-            let ts_end = format_time_secs(event.timestamp_end);
-            let duration_secs = match event.timestamp_end.duration_since(event.timestamp_start) {
-                Ok(d) => d.as_secs(),
-                Err(_) => 0,
+            // One write per event so the JSON line is never split.
+            writeln!(
+                out,
+                "{{\"type\":\"event\",{}}}",
+                event_json_fields(event, host, prec)
+            )?;
+        }
+        // Spikes only show as counters in the next exposition.
+        OutputFormat::OpenMetrics => {}
+    }
+    Ok(())
+}
+
+/// Print a status line for a spike that is still open.
+///
+/// `now` is the timestamp of the snapshot just analyzed; elapsed time is
+/// measured from the spike start to it.
+pub fn print_ongoing(
+    out: &mut dyn Write,
+    spike: &OngoingSpike,
+    now: SystemTime,
+    format: OutputFormat,
+    precision: Option<usize>,
+    labels: &ResourceLabels,
+) -> io::Result<()> {
+    let prec = precision.unwrap_or(DEFAULT_PRECISION);
+    let elapsed_secs = now
+        .duration_since(spike.timestamp_start)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    match format {
+        OutputFormat::Text | OutputFormat::Table => {
+            let resource = labels.label(spike.resource);
+
+            let target = match (&spike.path, &spike.rule) {
+                (Some(p), _) => format!(" ({})", p),
+                (None, Some(rule)) => format!(" [{}]", rule),
+                (None, None) => String::new(),
+            };
+
+            let unit = labels.unit(spike.resource);
+
+            let line = format!(
+                "... {}{} spike ongoing: start={} elapsed={}s peak={:.prec$}{}",
+                resource,
+                target,
+                format_time_secs(spike.timestamp_start),
+                elapsed_secs,
+                spike.peak_value,
+                unit,
+            );
+
+            writeln!(out, "{}", line.yellow())?;
+        }
+        OutputFormat::Json => {
+            let resource_str = spike.resource.name();
+
+            let path_str = match &spike.path {
+                Some(p) => format!("\"path\":{},", json_string(p)),
+                None => String::new(),
             };
 
-            let resource_str = match event.resource {
-                ResourceKind::Cpu => "cpu",
-                ResourceKind::Ram => "ram",
-                ResourceKind::Io => "io",
+            let rule_str = match &spike.rule {
+                Some(r) => format!("\"rule\":{},", json_string(r)),
+                None => String::new(),
             };
 
-            print!(
-                "{{\"resource\":\"{}\",\"ts_start\":{},\"ts_end\":{},\"duration_secs\":{},\"peak\":{:.2},\"threshold\":{:.2},\"top\":[",
+            writeln!(
+                out,
+                "{{\"type\":\"ongoing\",\"resource\":\"{}\",{}{}\"ts_start\":{},\"elapsed_secs\":{},\"peak\":{}}}",
                 resource_str,
-                ts_start,
-                ts_end,
-                duration_secs,
-                event.peak_value,
-                event.threshold,
-            );
+                path_str,
+                rule_str,
+                format_time_secs(spike.timestamp_start),
+                elapsed_secs,
+                json_number(spike.peak_value.into(), prec),
+            )?;
+        }
+        OutputFormat::OpenMetrics => {}
+    }
+    Ok(())
+}
+
+/// Write a `--histogram` as a JSON stream object; null bounds mark the
+/// underflow and overflow buckets.
+pub fn print_histogram_json(
+    out: &mut dyn Write,
+    histogram: &Histogram,
+    precision: usize,
+) -> io::Result<()> {
+    let bound = |b: Option<f32>| match b {
+        Some(v) => json_number(v.into(), precision),
+        None => "null".to_string(),
+    };
+    let buckets: Vec<String> = histogram
+        .buckets
+        .iter()
+        .map(|b| {
+            format!(
+                "{{\"from\":{},\"to\":{},\"samples\":{}}}",
+                bound(b.from),
+                bound(b.to),
+                b.samples
+            )
+        })
+        .collect();
 
-            for (i, p) in event.top_processes.iter().enumerate() {
-                if i > 0 {
-                    print!(",");
+    writeln!(
+        out,
+        "{{\"type\":\"histogram\",\"resource\":\"{}\",\"samples\":{},\"buckets\":[{}]}}",
+        histogram.resource.name(),
+        histogram.samples(),
+        buckets.join(",")
+    )
+}
+
+/// Prefix of every OpenMetrics metric name.
+const METRIC_PREFIX: &str = "resource_monitor";
+
+/// Closed spikes per resource (and watched path) for the OpenMetrics
+/// spike counter, each with its latest event when exemplars are on.
+#[derive(Debug)]
+pub struct SpikeCounters {
+    /// Start of the run, the counters' `_created` value.
+    created: SystemTime,
+    exemplars: bool,
+    counts: Vec<SpikeCount>,
+}
+
+#[derive(Debug)]
+struct SpikeCount {
+    resource: ResourceKind,
+    path: Option<String>,
+    total: u64,
+    /// (event id, peak, end) of the latest spike.
+    latest: Option<(String, f32, SystemTime)>,
+}
+
+impl SpikeCounters {
+    pub fn new(created: SystemTime, exemplars: bool) -> Self {
+        Self {
+            created,
+            exemplars,
+            counts: Vec::new(),
+        }
+    }
+
+    /// Count a closed spike; start events are not counted.
+    pub fn record(&mut self, event: &SpikeEvent) {
+        if event.phase != EventPhase::End {
+            return;
+        }
+
+        let idx = match self
+            .counts
+            .iter()
+            .position(|c| c.resource == event.resource && c.path == event.path)
+        {
+            Some(idx) => idx,
+            None => {
+                self.counts.push(SpikeCount {
+                    resource: event.resource,
+                    path: event.path.clone(),
+                    total: 0,
+                    latest: None,
+                });
+                self.counts.len() - 1
+            }
+        };
+
+        let count = &mut self.counts[idx];
+        count.total += 1;
+        if self.exemplars {
+            count.latest = Some((event.id.clone(), event.peak_value, event.timestamp_end));
+        }
+    }
+}
+
+/// Escape a label value: backslash, double quote and newline.
+fn label_value(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// OpenMetrics number for `value` rounded to `decimals`.
+fn metric_number(value: f64, decimals: usize) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        round_to(value, decimals).to_string()
+    }
+}
+
+/// One OpenMetrics exposition, built in memory so it is written at once.
+struct Exposition {
+    text: String,
+    /// Labels on every sample (the host).
+    common: Vec<(&'static str, String)>,
+    /// Sample timestamp, seconds since epoch.
+    ts: String,
+}
+
+impl Exposition {
+    /// TYPE, UNIT and HELP lines of a metric family.
+    fn family(&mut self, name: &str, kind: &str, unit: Option<&str>, help: &str) {
+        self.text
+            .push_str(&format!("# TYPE {}_{} {}\n", METRIC_PREFIX, name, kind));
+        if let Some(unit) = unit {
+            self.text
+                .push_str(&format!("# UNIT {}_{} {}\n", METRIC_PREFIX, name, unit));
+        }
+        self.text
+            .push_str(&format!("# HELP {}_{} {}\n", METRIC_PREFIX, name, help));
+    }
+
+    /// A sample line; `suffix` follows the timestamp (an exemplar).
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: &str, suffix: &str) {
+        let labels: Vec<String> = self
+            .common
+            .iter()
+            .map(|(k, v)| (*k, v.as_str()))
+            .chain(labels.iter().copied())
+            .map(|(k, v)| format!("{}=\"{}\"", k, label_value(v)))
+            .collect();
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels.join(","))
+        };
+
+        self.text.push_str(&format!(
+            "{}_{}{} {} {}{}\n",
+            METRIC_PREFIX, name, labels, value, self.ts, suffix
+        ));
+    }
+
+    /// A gauge family with a single unlabeled sample.
+    fn gauge(&mut self, name: &str, unit: Option<&str>, help: &str, value: String) {
+        self.family(name, "gauge", unit, help);
+        self.sample(name, &[], &value, "");
+    }
+}
+
+/// Print a snapshot and the spike counters as one OpenMetrics
+/// exposition, terminated by `# EOF` so each one validates on its own.
+///
+/// Gauges are named after the snapshot fields with their unit as suffix
+/// (`_percent`, `_bytes`, `_bytes_per_second`, `_celsius`); optional
+/// resources only appear when collected. Spikes are the
+/// `resource_monitor_spikes` counter labeled by resource (and path),
+/// created at the start of the run, with the latest spike's id, peak and
+/// end time as exemplar when `counters` keeps them. `host` is added as a
+/// label on every sample.
+pub fn print_openmetrics(
+    out: &mut dyn Write,
+    snapshot: &SystemSnapshot,
+    counters: &SpikeCounters,
+    precision: Option<usize>,
+    host: Option<&str>,
+) -> io::Result<()> {
+    let pct = precision.unwrap_or(1);
+    let io = precision.unwrap_or(2);
+    let mut exp = Exposition {
+        text: String::new(),
+        common: host.map(|h| ("host", h.to_string())).into_iter().collect(),
+        ts: format_time_secs(snapshot.timestamp),
+    };
+
+    exp.gauge(
+        "cpu_usage_percent",
+        Some("percent"),
+        "CPU busy time, excluding steal.",
+        metric_number(snapshot.cpu_usage_percent.into(), pct),
+    );
+    exp.gauge(
+        "cpu_steal_percent",
+        Some("percent"),
+        "CPU time stolen by the hypervisor.",
+        metric_number(snapshot.cpu_steal_percent.into(), pct),
+    );
+    exp.gauge(
+        "cpu_iowait_percent",
+        Some("percent"),
+        "CPU idle time with IO outstanding.",
+        metric_number(snapshot.cpu_iowait_percent.into(), pct),
+    );
+    exp.gauge(
+        "ram_usage_percent",
+        Some("percent"),
+        "Used RAM.",
+        metric_number(snapshot.ram_usage_percent.into(), pct),
+    );
+    exp.gauge(
+        "ram_used_bytes",
+        Some("bytes"),
+        "Used RAM (total minus available).",
+        snapshot.ram_used_bytes.to_string(),
+    );
+    exp.gauge(
+        "swap_usage_percent",
+        Some("percent"),
+        "Used swap.",
+        metric_number(snapshot.swap_usage_percent.into(), pct),
+    );
+    exp.gauge(
+        "io_read_bytes_per_second",
+        Some("bytes_per_second"),
+        "Disk read throughput.",
+        metric_number(snapshot.io_read_bytes_per_s, io),
+    );
+    exp.gauge(
+        "io_write_bytes_per_second",
+        Some("bytes_per_second"),
+        "Disk write throughput.",
+        metric_number(snapshot.io_write_bytes_per_s, io),
+    );
+    if let Some(temp) = snapshot.cpu_temp_celsius {
+        exp.gauge(
+            "cpu_temp_celsius",
+            Some("celsius"),
+            "CPU temperature.",
+            metric_number(temp.into(), pct),
+        );
+    }
+    if let Some(percent) = snapshot.battery_percent {
+        exp.gauge(
+            "battery_percent",
+            Some("percent"),
+            "Battery charge.",
+            metric_number(percent.into(), pct),
+        );
+    }
+    if let Some(watts) = snapshot.power_draw_watts {
+        exp.gauge(
+            "power_draw_watts",
+            Some("watts"),
+            "Battery discharge rate; negative while charging.",
+            metric_number(watts.into(), pct),
+        );
+    }
+    if let Some(fd) = snapshot.fd_usage {
+        exp.gauge("open_fds", None, "Open file handles.", fd.open.to_string());
+        exp.gauge("max_fds", None, "File handle limit.", fd.max.to_string());
+    }
+    if let Some(tasks) = snapshot.task_counts {
+        exp.gauge("processes", None, "Processes.", tasks.processes.to_string());
+        exp.gauge("threads", None, "Threads.", tasks.threads.to_string());
+    }
+    if !snapshot.disk_usage.is_empty() {
+        exp.family(
+            "disk_used_percent",
+            "gauge",
+            Some("percent"),
+            "Filesystem usage of a watched path.",
+        );
+        for d in &snapshot.disk_usage {
+            let value = metric_number(d.used_percent.into(), pct);
+            exp.sample("disk_used_percent", &[("path", &d.path)], &value, "");
+        }
+    }
+
+    exp.family(
+        "spikes",
+        "counter",
+        None,
+        "Spikes closed since the monitor started.",
+    );
+    let created = format_time_secs(counters.created);
+    for count in &counters.counts {
+        let mut labels = vec![("resource", count.resource.name())];
+        if let Some(path) = &count.path {
+            labels.push(("path", path));
+        }
+
+        let exemplar = match &count.latest {
+            Some((id, peak, end)) => format!(
+                " # {{event_id=\"{}\"}} {} {}",
+                label_value(id),
+                metric_number((*peak).into(), precision.unwrap_or(DEFAULT_PRECISION)),
+                format_time_secs(*end)
+            ),
+            None => String::new(),
+        };
+        exp.sample("spikes_total", &labels, &count.total.to_string(), &exemplar);
+        exp.sample("spikes_created", &labels, &created, "");
+    }
+
+    exp.text.push_str("# EOF\n");
+    out.write_all(exp.text.as_bytes())?;
+    out.flush()
+}
+
+/// Writer turning a stream of one JSON object per line into a single
+/// JSON array: `[` before the first object, `,` between objects and `]`
+/// once the writer is finished or dropped, so the array is closed on
+/// every way out of a run (limit, signal or error).
+pub struct JsonArrayWriter {
+    inner: Box<dyn Write>,
+    /// Bytes of the current, not yet complete line.
+    line: Vec<u8>,
+    items: u64,
+    finished: bool,
+}
+
+impl JsonArrayWriter {
+    pub fn new(inner: Box<dyn Write>) -> Self {
+        Self {
+            inner,
+            line: Vec::new(),
+            items: 0,
+            finished: false,
+        }
+    }
+
+    /// Write the buffered line as the next array element. The newline
+    /// is held back until the separator or the closing bracket.
+    fn emit_line(&mut self) -> io::Result<()> {
+        let item = self.line.trim_ascii_end();
+        if !item.is_empty() {
+            let separator: &[u8] = if self.items == 0 { b"[\n" } else { b",\n" };
+            self.inner.write_all(separator)?;
+            self.inner.write_all(item)?;
+            self.items += 1;
+        }
+        self.line.clear();
+        Ok(())
+    }
+
+    /// Close the array (`[]` if nothing was written). Later calls do
+    /// nothing.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+
+        self.emit_line()?;
+        let close: &[u8] = if self.items == 0 { b"[]\n" } else { b"\n]\n" };
+        self.inner.write_all(close)?;
+        self.inner.flush()
+    }
+}
+
+impl Write for JsonArrayWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for chunk in buf.split_inclusive(|&b| b == b'\n') {
+            self.line.extend_from_slice(chunk);
+            if self.line.ends_with(b"\n") {
+                self.emit_line()?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Drop for JsonArrayWriter {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// Writer ending every line of the output stream with `LineEnding`, and
+/// the stream itself with a line ending and a flush once it is finished
+/// or dropped, so a consumer never sees a cut-off last record.
+pub struct LineEndingWriter {
+    inner: Box<dyn Write>,
+    ending: LineEnding,
+    /// Nothing written yet, or the last byte ended a line.
+    at_line_start: bool,
+    finished: bool,
+}
+
+impl LineEndingWriter {
+    pub fn new(inner: Box<dyn Write>, ending: LineEnding) -> Self {
+        Self {
+            inner,
+            ending,
+            at_line_start: true,
+            finished: false,
+        }
+    }
+
+    /// Terminate a partial last line and flush. Later calls do nothing.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+
+        if !self.at_line_start {
+            self.inner.write_all(self.ending.as_bytes())?;
+            self.at_line_start = true;
+        }
+        self.inner.flush()
+    }
+}
+
+impl Write for LineEndingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for chunk in buf.split_inclusive(|&b| b == b'\n') {
+            match chunk.strip_suffix(b"\n") {
+                Some(line) => {
+                    self.inner.write_all(line)?;
+                    self.inner.write_all(self.ending.as_bytes())?;
                 }
-                print!(
-                    "{{\"pid\":{},\"name\":\"{}\",\"cpu\":{:.1},\"ram_bytes\":{}}}",
-                    p.pid, p.name, p.cpu_percent, p.ram_bytes
-                );
+                None => self.inner.write_all(chunk)?,
             }
+        }
+        if let Some(&last) = buf.last() {
+            self.at_line_start = last == b'\n';
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
 
-            println!("]}}");
+impl Drop for LineEndingWriter {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::ProcessSample;
+    use std::time::Duration;
+
+    fn snapshot() -> SystemSnapshot {
+        colored::control::set_override(false);
+        let mut snapshot = SystemSnapshot::at_secs(1_700_000_000);
+        snapshot.cpu_usage_percent = 42.345;
+        snapshot.cpu_steal_percent = 1.5;
+        snapshot.cpu_iowait_percent = 0.25;
+        snapshot.ram_usage_percent = 63.1;
+        snapshot.io_read_bytes_per_s = 1_234_567.0;
+        snapshot.io_write_bytes_per_s = 0.0;
+        snapshot
+    }
+
+    fn event() -> SpikeEvent {
+        colored::control::set_override(false);
+        let mut event = SpikeEvent::ended(ResourceKind::Cpu, 1_700_000_000, 12, 97.125, 90.0);
+        event.id = "e1".to_string();
+        event.top_processes = vec![ProcessSample {
+            pid: 42,
+            name: "stress".to_string(),
+            cpu_percent: 88.75,
+            ram_bytes: 1024,
+        }];
+        event
+    }
+
+    fn snapshot_output(format: OutputFormat, precision: Option<usize>) -> String {
+        let mut out = Vec::new();
+        print_snapshot(
+            &mut out,
+            &snapshot(),
+            format,
+            precision,
+            &SeverityBands::default(),
+            ByteUnits::Decimal,
+            None,
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn event_output(format: OutputFormat, precision: Option<usize>) -> String {
+        let mut out = Vec::new();
+        print_event(
+            &mut out,
+            &event(),
+            format,
+            precision,
+            &ResourceLabels::default(),
+            None,
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn snapshot_precision_zero_and_three() {
+        assert_eq!(
+            snapshot_output(OutputFormat::Text, Some(0)),
+            "[1700000000] CPU: 42% (steal 2%, iowait 0%) | RAM: 63% | IO: 1 MB/s r, 0 B/s w\n"
+        );
+        assert_eq!(
+            snapshot_output(OutputFormat::Text, Some(3)),
+            "[1700000000] CPU: 42.345% (steal 1.500%, iowait 0.250%) | RAM: 63.100% | IO: 1.235 MB/s r, 0.000 B/s w\n"
+        );
+        assert_eq!(
+            snapshot_output(OutputFormat::Json, Some(0)),
+            "{\"type\":\"snapshot\",\"ts\":1700000000,\"cpu\":42.0,\"steal\":2.0,\"iowait\":0.0,\"ram\":63.0,\"io_read\":1234567.0,\"io_write\":0.0}\n"
+        );
+        assert_eq!(
+            snapshot_output(OutputFormat::Json, Some(3)),
+            "{\"type\":\"snapshot\",\"ts\":1700000000,\"cpu\":42.345,\"steal\":1.5,\"iowait\":0.25,\"ram\":63.1,\"io_read\":1234567.0,\"io_write\":0.0}\n"
+        );
+    }
+
+    #[test]
+    fn event_precision_zero_and_three() {
+        assert_eq!(
+            event_output(OutputFormat::Text, Some(0)),
+            ">>> CPU spike: id=e1 start=1700000000 end=1700000012 duration=12s peak=97% (threshold=90%) over=1.08x\n    Top processes at peak:\n      PID 42 (stress) CPU=89% RAM=1024 bytes\n"
+        );
+        assert_eq!(
+            event_output(OutputFormat::Text, Some(3)),
+            ">>> CPU spike: id=e1 start=1700000000 end=1700000012 duration=12s peak=97.125% (threshold=90.000%) over=1.08x\n    Top processes at peak:\n      PID 42 (stress) CPU=88.750% RAM=1024 bytes\n"
+        );
+        assert_eq!(
+            event_output(OutputFormat::Json, Some(0)),
+            "{\"type\":\"event\",\"id\":\"e1\",\"phase\":\"end\",\"resource\":\"cpu\",\"ts_start\":1700000000,\"ts_end\":1700000012,\"duration_secs\":12,\"peak\":97.0,\"threshold\":90.0,\"top\":[{\"pid\":42,\"name\":\"stress\",\"cpu\":89.0,\"ram_bytes\":1024}]}\n"
+        );
+        assert_eq!(
+            event_output(OutputFormat::Json, Some(3)),
+            "{\"type\":\"event\",\"id\":\"e1\",\"phase\":\"end\",\"resource\":\"cpu\",\"ts_start\":1700000000,\"ts_end\":1700000012,\"duration_secs\":12,\"peak\":97.125,\"threshold\":90.0,\"top\":[{\"pid\":42,\"name\":\"stress\",\"cpu\":88.75,\"ram_bytes\":1024}]}\n"
+        );
+    }
+
+    #[test]
+    fn severity_color_per_band() {
+        let bands = Bands {
+            warn: Some(70.0),
+            crit: Some(90.0),
+        };
+        assert_eq!(severity_color(50.0, &bands), Some(Color::Green));
+        assert_eq!(severity_color(70.0, &bands), Some(Color::Yellow));
+        assert_eq!(severity_color(89.9, &bands), Some(Color::Yellow));
+        assert_eq!(severity_color(90.0, &bands), Some(Color::Red));
+
+        let crit_only = Bands {
+            warn: None,
+            crit: Some(90.0),
+        };
+        assert_eq!(severity_color(80.0, &crit_only), Some(Color::Green));
+        assert_eq!(severity_color(95.0, &crit_only), Some(Color::Red));
+
+        // No bands: the caller keeps its label color.
+        assert_eq!(severity_color(99.0, &Bands::default()), None);
+    }
+
+    #[test]
+    fn ongoing_line_shows_elapsed_and_peak() {
+        colored::control::set_override(false);
+        let spike = OngoingSpike {
+            resource: ResourceKind::Cpu,
+            timestamp_start: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            peak_value: 97.5,
+            threshold: 90.0,
+            path: None,
+            rule: None,
+        };
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_045);
+        let mut out = Vec::new();
+        let labels = ResourceLabels::default();
+        print_ongoing(&mut out, &spike, now, OutputFormat::Text, None, &labels).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "... CPU spike ongoing: start=1700000000 elapsed=45s peak=97.50%\n"
+        );
+    }
+
+    #[test]
+    fn byte_rates_scale_to_units() {
+        let cases: &[(f64, ByteUnits, usize, &str)] = &[
+            (0.0, ByteUnits::Decimal, 2, "0.00 B/s"),
+            (999.0, ByteUnits::Decimal, 2, "999.00 B/s"),
+            (1_500.0, ByteUnits::Decimal, 1, "1.5 KB/s"),
+            (11_800_000.0, ByteUnits::Decimal, 1, "11.8 MB/s"),
+            (12_345_678.0, ByteUnits::Decimal, 2, "12.35 MB/s"),
+            (999_999.0, ByteUnits::Decimal, 2, "1.00 MB/s"),
+            (999_999.0, ByteUnits::Decimal, 3, "999.999 KB/s"),
+            (3e12, ByteUnits::Decimal, 0, "3 TB/s"),
+            (5e15, ByteUnits::Decimal, 0, "5000 TB/s"),
+            (1_024.0, ByteUnits::Binary, 2, "1.00 KiB/s"),
+            (1_536.0, ByteUnits::Binary, 1, "1.5 KiB/s"),
+            (1_073_741_824.0, ByteUnits::Binary, 0, "1 GiB/s"),
+            (12_345_678.0, ByteUnits::Raw, 2, "12345678.00 B/s"),
+        ];
+        for &(bytes, units, precision, expected) in cases {
+            assert_eq!(
+                format_byte_rate(bytes, units, precision),
+                expected,
+                "{} {:?}",
+                bytes,
+                units
+            );
         }
     }
+
+    #[test]
+    fn table_rows_line_up_with_the_header() {
+        let mut header = Vec::new();
+        print_table_header(&mut header).unwrap();
+        assert_eq!(
+            String::from_utf8(header).unwrap(),
+            "      TIME |    CPU% |  STEAL% | IOWAIT% |    RAM% |   SWAP% |           IO R |           IO W\n"
+        );
+        assert_eq!(
+            snapshot_output(OutputFormat::Table, None),
+            "1700000000 |    42.3 |     1.5 |     0.2 |    63.1 |     0.0 |      1.23 MB/s |       0.00 B/s\n"
+        );
+        // Events print the same alert in text and table output.
+        assert_eq!(
+            event_output(OutputFormat::Table, None),
+            event_output(OutputFormat::Text, None)
+        );
+    }
+
+    #[test]
+    fn openmetrics_exposition_is_exact() {
+        let created = UNIX_EPOCH + Duration::from_secs(1_699_999_000);
+        let mut counters = SpikeCounters::new(created, true);
+        counters.record(&event());
+        let mut out = Vec::new();
+        print_openmetrics(&mut out, &snapshot(), &counters, None, Some("web1")).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                "# TYPE resource_monitor_cpu_usage_percent gauge\n",
+                "# UNIT resource_monitor_cpu_usage_percent percent\n",
+                "# HELP resource_monitor_cpu_usage_percent CPU busy time, excluding steal.\n",
+                "resource_monitor_cpu_usage_percent{host=\"web1\"} 42.3 1700000000\n",
+                "# TYPE resource_monitor_cpu_steal_percent gauge\n",
+                "# UNIT resource_monitor_cpu_steal_percent percent\n",
+                "# HELP resource_monitor_cpu_steal_percent CPU time stolen by the hypervisor.\n",
+                "resource_monitor_cpu_steal_percent{host=\"web1\"} 1.5 1700000000\n",
+                "# TYPE resource_monitor_cpu_iowait_percent gauge\n",
+                "# UNIT resource_monitor_cpu_iowait_percent percent\n",
+                "# HELP resource_monitor_cpu_iowait_percent CPU idle time with IO outstanding.\n",
+                "resource_monitor_cpu_iowait_percent{host=\"web1\"} 0.3 1700000000\n",
+                "# TYPE resource_monitor_ram_usage_percent gauge\n",
+                "# UNIT resource_monitor_ram_usage_percent percent\n",
+                "# HELP resource_monitor_ram_usage_percent Used RAM.\n",
+                "resource_monitor_ram_usage_percent{host=\"web1\"} 63.1 1700000000\n",
+                "# TYPE resource_monitor_ram_used_bytes gauge\n",
+                "# UNIT resource_monitor_ram_used_bytes bytes\n",
+                "# HELP resource_monitor_ram_used_bytes Used RAM (total minus available).\n",
+                "resource_monitor_ram_used_bytes{host=\"web1\"} 0 1700000000\n",
+                "# TYPE resource_monitor_swap_usage_percent gauge\n",
+                "# UNIT resource_monitor_swap_usage_percent percent\n",
+                "# HELP resource_monitor_swap_usage_percent Used swap.\n",
+                "resource_monitor_swap_usage_percent{host=\"web1\"} 0 1700000000\n",
+                "# TYPE resource_monitor_io_read_bytes_per_second gauge\n",
+                "# UNIT resource_monitor_io_read_bytes_per_second bytes_per_second\n",
+                "# HELP resource_monitor_io_read_bytes_per_second Disk read throughput.\n",
+                "resource_monitor_io_read_bytes_per_second{host=\"web1\"} 1234567 1700000000\n",
+                "# TYPE resource_monitor_io_write_bytes_per_second gauge\n",
+                "# UNIT resource_monitor_io_write_bytes_per_second bytes_per_second\n",
+                "# HELP resource_monitor_io_write_bytes_per_second Disk write throughput.\n",
+                "resource_monitor_io_write_bytes_per_second{host=\"web1\"} 0 1700000000\n",
+                "# TYPE resource_monitor_spikes counter\n",
+                "# HELP resource_monitor_spikes Spikes closed since the monitor started.\n",
+                "resource_monitor_spikes_total{host=\"web1\",resource=\"cpu\"} 1 1700000000 # {event_id=\"e1\"} 97.13 1700000012\n",
+                "resource_monitor_spikes_created{host=\"web1\",resource=\"cpu\"} 1699999000 1700000000\n",
+                "# EOF\n",
+            )
+        );
+
+        // Snapshots and events are not printed on their own.
+        assert_eq!(snapshot_output(OutputFormat::OpenMetrics, None), "");
+        assert_eq!(event_output(OutputFormat::OpenMetrics, None), "");
+    }
+
+    /// Writer into a buffer the test keeps a handle on.
+    #[derive(Clone, Default)]
+    struct Shared(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn line_ending_writer_terminates_every_line() {
+        let buf = Shared::default();
+        let mut out = LineEndingWriter::new(Box::new(buf.clone()), LineEnding::Crlf);
+        out.write_all(b"{\"a\":1}\n{\"b\":").unwrap();
+        out.write_all(b"2}\n{\"c\":3}").unwrap();
+        drop(out);
+        // The cut-off last record gets its line ending on the way out.
+        assert_eq!(
+            buf.0.borrow().as_slice(),
+            b"{\"a\":1}\r\n{\"b\":2}\r\n{\"c\":3}\r\n"
+        );
+
+        let buf = Shared::default();
+        let mut out = LineEndingWriter::new(Box::new(buf.clone()), LineEnding::Lf);
+        out.write_all(b"x\ny\n").unwrap();
+        out.finish().unwrap();
+        out.finish().unwrap();
+        assert_eq!(buf.0.borrow().as_slice(), b"x\ny\n");
+    }
 }
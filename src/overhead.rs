@@ -0,0 +1,42 @@
+//! The monitor's own CPU and memory over a run (`--report-self-overhead`),
+//! so the observer effect of a given interval and collector set can be
+//! measured instead of guessed.
+
+use crate::metrics::SystemSnapshot;
+
+/// Mean and peak of the monitor's own usage.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SelfOverhead {
+    samples: u64,
+    cpu_sum: f64,
+    pub cpu_max: f32,
+    pub rss_max_bytes: u64,
+}
+
+impl SelfOverhead {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fold one snapshot in; snapshots without self usage are left out.
+    pub fn update(&mut self, snapshot: &SystemSnapshot) {
+        let Some(usage) = snapshot.self_usage else {
+            return;
+        };
+
+        self.samples += 1;
+        self.cpu_sum += usage.cpu_percent as f64;
+        self.cpu_max = self.cpu_max.max(usage.cpu_percent);
+        self.rss_max_bytes = self.rss_max_bytes.max(usage.rss_bytes);
+    }
+
+    /// Snapshots that carried self usage.
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// Mean CPU share, or None without samples.
+    pub fn cpu_mean(&self) -> Option<f32> {
+        (self.samples > 0).then(|| (self.cpu_sum / self.samples as f64) as f32)
+    }
+}
@@ -0,0 +1,91 @@
+//! Highest value of each resource over a run, spike or not.
+
+use std::time::SystemTime;
+
+use crate::analyzer::resource_value;
+use crate::config::ResourceKind;
+use crate::metrics::SystemSnapshot;
+
+/// Highest value of one resource and the snapshot it came from.
+#[derive(Debug, Clone, Copy)]
+pub struct HighWaterMark {
+    pub resource: ResourceKind,
+    /// In the resource's unit (%, MB/s, °C, count).
+    pub value: f32,
+    pub timestamp: SystemTime,
+}
+
+/// Per-resource high-water marks, updated from every analyzed snapshot
+/// and independent of thresholds, for "did we ever get close" questions.
+#[derive(Debug, Clone, Default)]
+pub struct HighWaterMarks {
+    marks: Vec<HighWaterMark>,
+}
+
+impl HighWaterMarks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fold one snapshot in. Resources that were not collected (e.g.
+    /// temperature without `--temp`) are left out; the first of equal
+    /// maxima keeps its timestamp.
+    pub fn update(&mut self, snapshot: &SystemSnapshot) {
+        for resource in ResourceKind::ALL {
+            let Some(value) = resource_value(snapshot, resource) else {
+                continue;
+            };
+
+            match self.marks.iter_mut().find(|m| m.resource == resource) {
+                Some(mark) if value > mark.value => {
+                    mark.value = value;
+                    mark.timestamp = snapshot.timestamp;
+                }
+                Some(_) => {}
+                None => self.marks.push(HighWaterMark {
+                    resource,
+                    value,
+                    timestamp: snapshot.timestamp,
+                }),
+            }
+        }
+    }
+
+    /// Marks in `ResourceKind::ALL` order of first appearance.
+    pub fn marks(&self) -> &[HighWaterMark] {
+        &self.marks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn maxima_keep_the_time_they_were_first_reached() {
+        let start = 1_700_000_000;
+        let series = [(10.0, 50.0), (80.0, 45.0), (80.0, 55.0), (40.0, 60.0)];
+        let mut peaks = HighWaterMarks::new();
+        for (i, (cpu, ram)) in series.into_iter().enumerate() {
+            let mut snapshot = SystemSnapshot::at_secs(start + i as u64);
+            snapshot.cpu_usage_percent = cpu;
+            snapshot.ram_usage_percent = ram;
+            snapshot.io_read_bytes_per_s = if i == 2 { 5_000_000.0 } else { 0.0 };
+            peaks.update(&snapshot);
+        }
+
+        let mark = |resource| {
+            let m = peaks.marks().iter().find(|m| m.resource == resource)?;
+            let secs = m.timestamp.duration_since(UNIX_EPOCH).ok()?.as_secs();
+            Some((m.value, secs - start))
+        };
+        assert_eq!(mark(ResourceKind::Cpu), Some((80.0, 1)));
+        assert_eq!(mark(ResourceKind::Ram), Some((60.0, 3)));
+        assert_eq!(mark(ResourceKind::Io), Some((5.0, 2)));
+        assert_eq!(mark(ResourceKind::IoWrite), Some((0.0, 0)));
+        // Not collected: no mark at all.
+        assert_eq!(mark(ResourceKind::Temp), None);
+        assert_eq!(peaks.marks()[0].resource, ResourceKind::Cpu);
+    }
+}
@@ -0,0 +1,109 @@
+//! Batch progress line on stderr.
+
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+use crate::config::BatchLimit;
+
+/// Minimum time between two redraws of the progress line.
+const REFRESH: Duration = Duration::from_millis(250);
+
+/// Single stderr line, redrawn in place, showing how far a batch run is
+/// toward its limit. Only drawn when stderr is a terminal, so redirected
+/// stderr and the stdout data stream never see it.
+#[derive(Debug)]
+pub struct Progress<W: Write = io::Stderr> {
+    out: W,
+    limit: BatchLimit,
+    last_draw: Option<Instant>,
+}
+
+impl Progress {
+    /// None when stderr is not a terminal.
+    pub fn new(limit: BatchLimit) -> Option<Self> {
+        if !io::stderr().is_terminal() {
+            return None;
+        }
+
+        Some(Self::with_writer(io::stderr(), limit))
+    }
+}
+
+impl<W: Write> Progress<W> {
+    /// Like `new`, drawing to `out` whether or not it is a terminal.
+    pub fn with_writer(out: W, limit: BatchLimit) -> Self {
+        Self {
+            out,
+            limit,
+            last_draw: None,
+        }
+    }
+
+    /// Redraw the line, at most once per `REFRESH`.
+    pub fn update(&mut self, samples: u64, spikes: u64, elapsed: Duration) {
+        if self.last_draw.is_some_and(|t| t.elapsed() < REFRESH) {
+            return;
+        }
+        self.last_draw = Some(Instant::now());
+
+        let line = match self.limit {
+            BatchLimit::Samples(total) => {
+                format!("{}/{} samples, {} spikes", samples, total, spikes)
+            }
+            BatchLimit::DurationSecs(total) => format!(
+                "{}/{}s, {} samples, {} spikes",
+                elapsed.as_secs().min(total),
+                total,
+                samples,
+                spikes
+            ),
+            BatchLimit::Unbounded => format!("{} samples, {} spikes", samples, spikes),
+        };
+
+        // Carriage return and erase-to-end-of-line redraw in place.
+        let _ = write!(self.out, "\r{}\x1b[K", line);
+        let _ = self.out.flush();
+    }
+
+    /// Erase the line, so the summary that follows starts clean.
+    pub fn finish(&mut self) {
+        if self.last_draw.is_some() {
+            let _ = write!(self.out, "\r\x1b[K");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drawn(progress: &Progress<Vec<u8>>) -> String {
+        String::from_utf8(progress.out.clone()).unwrap()
+    }
+
+    #[test]
+    fn line_is_redrawn_in_place_and_erased() {
+        let mut progress = Progress::with_writer(Vec::new(), BatchLimit::Samples(10_000));
+        progress.update(347, 3, Duration::from_secs(35));
+        assert_eq!(drawn(&progress), "\r347/10000 samples, 3 spikes\x1b[K");
+
+        // Within the refresh period nothing is redrawn.
+        progress.update(348, 3, Duration::from_secs(35));
+        assert_eq!(drawn(&progress), "\r347/10000 samples, 3 spikes\x1b[K");
+
+        progress.finish();
+        assert!(drawn(&progress).ends_with("\x1b[K\r\x1b[K"));
+    }
+
+    #[test]
+    fn duration_limit_shows_elapsed_time() {
+        let mut progress = Progress::with_writer(Vec::new(), BatchLimit::DurationSecs(60));
+        progress.update(120, 0, Duration::from_secs(61));
+        assert_eq!(drawn(&progress), "\r60/60s, 120 samples, 0 spikes\x1b[K");
+
+        // Nothing drawn, nothing to erase.
+        let mut idle = Progress::with_writer(Vec::new(), BatchLimit::Unbounded);
+        idle.finish();
+        assert!(drawn(&idle).is_empty());
+    }
+}
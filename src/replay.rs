@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde::Deserialize;
+
+use crate::config::BatchConfig;
+use crate::error::MonitorError;
+use crate::metrics::{DiskUsage, FdUsage, SystemSnapshot, TaskCounts};
+use crate::monitor::run_monitor;
+use crate::sampler::Sample;
+use crate::samples_log::{BinSnapshotReader, MAGIC};
+
+/// Replay mode: run recorded snapshots through the analyzer instead of
+/// reading /proc, so a detection run can be reproduced exactly.
+///
+/// Returns whether at least one spike event was detected.
+pub fn run_replay(config: BatchConfig) -> Result<bool, Box<dyn Error>> {
+    run_monitor(&config, "[monitor-replay]")
+}
+
+/// Snapshot object of the JSON output stream (`--output json`).
+#[derive(Debug, Deserialize)]
+struct SnapshotRecord {
+    ts: u64,
+    cpu: f32,
+    /// Absent in streams recorded before steal and iowait were sampled.
+    #[serde(default)]
+    steal: f32,
+    #[serde(default)]
+    iowait: f32,
+    ram: f32,
+    io_read: f64,
+    io_write: f64,
+    #[serde(default)]
+    temp: Option<f32>,
+    #[serde(default)]
+    battery: Option<f32>,
+    #[serde(default)]
+    power: Option<f32>,
+    #[serde(default)]
+    fd: Option<u64>,
+    #[serde(default)]
+    fd_max: Option<u64>,
+    #[serde(default)]
+    procs: Option<u64>,
+    #[serde(default)]
+    threads: Option<u64>,
+    #[serde(default)]
+    disk: BTreeMap<String, f32>,
+    #[serde(default)]
+    uptime: Option<u64>,
+}
+
+impl From<SnapshotRecord> for SystemSnapshot {
+    fn from(r: SnapshotRecord) -> Self {
+        SystemSnapshot {
+            timestamp: UNIX_EPOCH + Duration::from_secs(r.ts),
+            cpu_usage_percent: r.cpu,
+            cpu_steal_percent: r.steal,
+            cpu_iowait_percent: r.iowait,
+            ram_usage_percent: r.ram,
+            // Not in the stream: --ram-threshold-bytes and process
+            // attribution can't be replayed.
+            ram_used_bytes: 0,
+            ram_total_bytes: 0,
+            swap_usage_percent: 0.0,
+            io_read_bytes_per_s: r.io_read,
+            io_write_bytes_per_s: r.io_write,
+            cpu_temp_celsius: r.temp,
+            battery_percent: r.battery,
+            power_draw_watts: r.power,
+            fd_usage: r.fd.zip(r.fd_max).map(|(open, max)| FdUsage { open, max }),
+            task_counts: r.procs.map(|processes| TaskCounts {
+                processes,
+                threads: r.threads.unwrap_or(0),
+            }),
+            disk_usage: r
+                .disk
+                .into_iter()
+                .map(|(path, used_percent)| DiskUsage { path, used_percent })
+                .collect(),
+            top_processes: Vec::new(),
+            top_processes_by_ram: Vec::new(),
+            // Usage of the recording monitor, not of this one.
+            self_usage: None,
+            uptime_secs: r.uptime,
+            instant: None,
+        }
+    }
+}
+
+/// Snapshots recorded with `--output json` or `--samples-log`, read in
+/// file order; a binary samples log is recognized by its header.
+///
+/// Other stream objects (meta, event, ongoing), blank lines and `#`
+/// comments are skipped; a line that isn't valid JSON or lacks snapshot
+/// fields, or a binary record that doesn't decode, is returned as an
+/// error, so it counts as a dropped sample.
+pub struct SnapshotReader {
+    path: String,
+    input: Input,
+    /// Line, or binary record, last read.
+    line_no: u64,
+}
+
+enum Input {
+    Json(Lines<BufReader<File>>),
+    Bin(BinSnapshotReader),
+}
+
+impl SnapshotReader {
+    pub fn open(path: &str) -> Result<Self, MonitorError> {
+        let file = File::open(path).map_err(|e| {
+            MonitorError::Config(format!("Failed to open snapshot file {}: {}", path, e))
+        })?;
+
+        let mut reader = BufReader::new(file);
+        let input = if reader.fill_buf()?.starts_with(MAGIC) {
+            Input::Bin(BinSnapshotReader::open(reader, path)?)
+        } else {
+            Input::Json(reader.lines())
+        };
+
+        Ok(Self {
+            path: path.to_string(),
+            input,
+            line_no: 0,
+        })
+    }
+
+    /// Next recorded snapshot; None at the end of the file.
+    pub fn next_sample(&mut self) -> Option<Sample> {
+        let lines = match &mut self.input {
+            Input::Json(lines) => lines,
+            Input::Bin(reader) => {
+                let snapshot = reader.next_snapshot()?;
+                self.line_no += 1;
+                return Some(snapshot.map_err(|e| {
+                    MonitorError::Parse(format!("{} record {}: {}", self.path, self.line_no, e))
+                }));
+            }
+        };
+
+        loop {
+            let line = match lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e.into())),
+            };
+            self.line_no += 1;
+
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            let value: serde_json::Value = match serde_json::from_str(trimmed) {
+                Ok(v) => v,
+                Err(e) => return Some(Err(parse_error(&self.path, self.line_no, e))),
+            };
+            if value.get("type").and_then(|t| t.as_str()) != Some("snapshot") {
+                continue;
+            }
+
+            return Some(
+                serde_json::from_value::<SnapshotRecord>(value)
+                    .map(SystemSnapshot::from)
+                    .map_err(|e| parse_error(&self.path, self.line_no, e)),
+            );
+        }
+    }
+}
+
+fn parse_error(path: &str, line_no: u64, e: serde_json::Error) -> MonitorError {
+    MonitorError::Parse(format!("{} line {}: {}", path, line_no, e))
+}
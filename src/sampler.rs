@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::RuntimeConfig;
+use crate::error::MonitorError;
+use crate::metrics::{SnapshotSampler, SystemSnapshot};
+use crate::schedule::Ticker;
+
+/// Snapshots the sampler may get ahead of the consumer before the oldest
+/// ones are discarded.
+pub const SAMPLE_QUEUE_CAPACITY: usize = 64;
+
+/// Result of one sampler read, failed reads included.
+pub type Sample = Result<SystemSnapshot, MonitorError>;
+
+/// Bounded queue between the sampler thread and the consumer.
+///
+/// `push` never blocks: when the queue is full the oldest sample is
+/// discarded and counted, so a slow consumer (terminal, disk flushes)
+/// can't delay the next read and skew CPU deltas.
+#[derive(Debug)]
+pub struct SampleQueue {
+    state: Mutex<QueueState>,
+    ready: Condvar,
+    capacity: usize,
+}
+
+#[derive(Debug)]
+struct QueueState {
+    samples: VecDeque<Sample>,
+    discarded: u64,
+    closed: bool,
+}
+
+impl SampleQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                samples: VecDeque::with_capacity(capacity),
+                discarded: 0,
+                closed: false,
+            }),
+            ready: Condvar::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Queue a sample, discarding the oldest one if the queue is full.
+    /// Ignored once the queue is closed.
+    pub fn push(&self, sample: Sample) {
+        let mut state = self.lock();
+        if state.closed {
+            return;
+        }
+        if state.samples.len() >= self.capacity {
+            state.samples.pop_front();
+            state.discarded += 1;
+        }
+        state.samples.push_back(sample);
+        drop(state);
+
+        self.ready.notify_one();
+    }
+
+    /// Wait for the next sample; None once the sampler has stopped and
+    /// the queue is drained.
+    pub fn pop(&self) -> Option<Sample> {
+        let mut state = self.lock();
+        loop {
+            if let Some(sample) = state.samples.pop_front() {
+                return Some(sample);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self
+                .ready
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    /// Samples discarded so far because the consumer fell behind.
+    pub fn discarded(&self) -> u64 {
+        self.lock().discarded
+    }
+
+    /// Stop the queue: the sampler thread exits at its next tick, and
+    /// `pop` returns None once the remaining samples are drained.
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.ready.notify_all();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Closes the queue when the sampler thread exits, even by panic, so the
+/// consumer doesn't wait forever.
+struct CloseOnDrop(Arc<SampleQueue>);
+
+impl Drop for CloseOnDrop {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// Start a thread that reads a snapshot every `config.interval_ms` and
+/// pushes it to the returned queue, until the queue is closed.
+pub fn spawn_sampler(config: &RuntimeConfig) -> std::io::Result<Arc<SampleQueue>> {
+    let queue = Arc::new(SampleQueue::new(SAMPLE_QUEUE_CAPACITY));
+    let guard = CloseOnDrop(Arc::clone(&queue));
+    let interval = Duration::from_millis(config.interval_ms);
+    let jitter = Duration::from_millis(config.jitter_ms);
+    let mut sampler = SnapshotSampler::new(config.clone());
+
+    thread::Builder::new()
+        .name("sampler".into())
+        .spawn(move || {
+            // Priming read: establishes the CPU/IO/process baselines so the
+            // first queued snapshot carries real deltas instead of
+            // artificial zeros. Errors are reported by the first real read.
+            let _ = sampler.next_sample();
+
+            let mut ticker = Ticker::with_jitter(interval, jitter);
+            while !guard.0.is_closed() {
+                ticker.wait();
+                guard.0.push(sampler.next_sample());
+            }
+        })?;
+
+    Ok(queue)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Instant;
+
+    #[test]
+    fn first_queued_snapshot_has_real_cpu_delta() {
+        // Keep one core busy so the interval has CPU use to measure.
+        let stop = Arc::new(AtomicBool::new(false));
+        let spinner = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                let started = Instant::now();
+                while !stop.load(Ordering::Relaxed) && started.elapsed() < Duration::from_secs(5) {
+                    std::hint::spin_loop();
+                }
+            })
+        };
+
+        let config = RuntimeConfig {
+            interval_ms: 200,
+            ..RuntimeConfig::default()
+        };
+        let queue = spawn_sampler(&config).unwrap();
+        let first = queue.pop().unwrap().unwrap();
+        queue.close();
+        stop.store(true, Ordering::Relaxed);
+        spinner.join().unwrap();
+
+        // Without the priming read this would be the artificial 0.
+        assert!(first.cpu_usage_percent > 0.0);
+    }
+}
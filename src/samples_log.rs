@@ -0,0 +1,469 @@
+//! `--samples-log`: every sample of a live or batch run written to a
+//! file for `replay`, as JSON lines or, for high sample rates, a compact
+//! length-prefixed binary format.
+//!
+//! A binary log starts with `MAGIC`, the format version and `LAYOUT`, so
+//! a reader built for another layout rejects the file instead of
+//! misreading it. Each snapshot follows as a little-endian u32 payload
+//! length and the payload, fields in `LAYOUT` order. Process lists and
+//! the monitor's own usage are not recorded.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::config::{ByteUnits, OutputFormat, SamplesLogFormat, SeverityBands};
+use crate::error::MonitorError;
+use crate::metrics::{DiskUsage, FdUsage, SystemSnapshot, TaskCounts};
+use crate::output::print_snapshot;
+
+/// First bytes of a binary samples log.
+pub const MAGIC: &[u8; 8] = b"RMSAMPLE";
+
+/// Binary format version; bumped with `LAYOUT`.
+pub const VERSION: u16 = 1;
+
+/// Fields of a binary record in order. `?` marks a presence byte (0 or
+/// 1) before the value, `str` a u16 length and UTF-8 bytes.
+pub const LAYOUT: &str = "ts_ns:u64 cpu:f32 steal:f32 iowait:f32 ram:f32 ram_used:u64 \
+    ram_total:u64 swap:f32 io_read:f64 io_write:f64 temp:?f32 battery:?f32 power:?f32 \
+    fd:?(u64,u64) tasks:?(u64,u64) uptime:?u64 disk:u16*(str,f32)";
+
+/// Largest record payload a reader accepts. Real records are a few
+/// hundred bytes plus the watched paths, so a longer length means the
+/// file is corrupt, and isn't trusted with an allocation.
+pub const MAX_RECORD_LEN: usize = 1 << 20;
+
+/// Writer of one run's samples log.
+pub struct SamplesLog {
+    out: BufWriter<File>,
+    format: SamplesLogFormat,
+    /// Payload of the binary record being written, reused across samples.
+    record: Vec<u8>,
+}
+
+impl SamplesLog {
+    /// Create `path`, replacing an existing file; a binary log starts with
+    /// its header.
+    pub fn create(path: &str, format: SamplesLogFormat) -> Result<Self, MonitorError> {
+        let file = File::create(path).map_err(|e| {
+            MonitorError::Config(format!("Failed to create samples log {}: {}", path, e))
+        })?;
+
+        let mut out = BufWriter::new(file);
+        if format == SamplesLogFormat::Bin {
+            out.write_all(MAGIC)?;
+            out.write_all(&VERSION.to_le_bytes())?;
+            out.write_all(&(LAYOUT.len() as u16).to_le_bytes())?;
+            out.write_all(LAYOUT.as_bytes())?;
+        }
+
+        Ok(Self {
+            out,
+            format,
+            record: Vec::new(),
+        })
+    }
+
+    /// Append one snapshot. Writes are buffered; see `flush`.
+    pub fn write(&mut self, snapshot: &SystemSnapshot) -> io::Result<()> {
+        match self.format {
+            SamplesLogFormat::Json => print_snapshot(
+                &mut self.out,
+                snapshot,
+                OutputFormat::Json,
+                None,
+                &SeverityBands::default(),
+                ByteUnits::default(),
+                None,
+            ),
+            SamplesLogFormat::Bin => {
+                self.record.clear();
+                encode_snapshot(snapshot, &mut self.record)?;
+                self.out
+                    .write_all(&(self.record.len() as u32).to_le_bytes())?;
+                self.out.write_all(&self.record)
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Append the binary record payload of `snapshot` to `buf`. More than
+/// u16::MAX watched paths, or a path longer than u16::MAX bytes, can't be
+/// encoded and is an `InvalidInput` error.
+pub fn encode_snapshot(snapshot: &SystemSnapshot, buf: &mut Vec<u8>) -> io::Result<()> {
+    let ts_ns = snapshot
+        .timestamp
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+
+    buf.extend_from_slice(&ts_ns.to_le_bytes());
+    buf.extend_from_slice(&snapshot.cpu_usage_percent.to_le_bytes());
+    buf.extend_from_slice(&snapshot.cpu_steal_percent.to_le_bytes());
+    buf.extend_from_slice(&snapshot.cpu_iowait_percent.to_le_bytes());
+    buf.extend_from_slice(&snapshot.ram_usage_percent.to_le_bytes());
+    buf.extend_from_slice(&snapshot.ram_used_bytes.to_le_bytes());
+    buf.extend_from_slice(&snapshot.ram_total_bytes.to_le_bytes());
+    buf.extend_from_slice(&snapshot.swap_usage_percent.to_le_bytes());
+    buf.extend_from_slice(&snapshot.io_read_bytes_per_s.to_le_bytes());
+    buf.extend_from_slice(&snapshot.io_write_bytes_per_s.to_le_bytes());
+
+    for value in [
+        snapshot.cpu_temp_celsius,
+        snapshot.battery_percent,
+        snapshot.power_draw_watts,
+    ] {
+        buf.push(value.is_some() as u8);
+        if let Some(v) = value {
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+
+    let pairs = [
+        snapshot.fd_usage.map(|fd| (fd.open, fd.max)),
+        snapshot.task_counts.map(|t| (t.processes, t.threads)),
+    ];
+    for pair in pairs {
+        buf.push(pair.is_some() as u8);
+        if let Some((a, b)) = pair {
+            buf.extend_from_slice(&a.to_le_bytes());
+            buf.extend_from_slice(&b.to_le_bytes());
+        }
+    }
+
+    buf.push(snapshot.uptime_secs.is_some() as u8);
+    if let Some(uptime) = snapshot.uptime_secs {
+        buf.extend_from_slice(&uptime.to_le_bytes());
+    }
+
+    let too_long = |what: String| io::Error::new(io::ErrorKind::InvalidInput, what);
+    let disks = u16::try_from(snapshot.disk_usage.len()).map_err(|_| {
+        too_long(format!(
+            "{} watched paths don't fit a samples log record",
+            snapshot.disk_usage.len()
+        ))
+    })?;
+    buf.extend_from_slice(&disks.to_le_bytes());
+    for disk in &snapshot.disk_usage {
+        let len = u16::try_from(disk.path.len()).map_err(|_| {
+            too_long(format!(
+                "Watched path of {} bytes is too long for a samples log record",
+                disk.path.len()
+            ))
+        })?;
+        buf.extend_from_slice(&len.to_le_bytes());
+        buf.extend_from_slice(disk.path.as_bytes());
+        buf.extend_from_slice(&disk.used_percent.to_le_bytes());
+    }
+    Ok(())
+}
+
+/// Decode a record payload written by `encode_snapshot`.
+pub fn decode_snapshot(payload: &[u8]) -> Result<SystemSnapshot, MonitorError> {
+    let mut r = Fields { buf: payload };
+
+    let timestamp = UNIX_EPOCH + Duration::from_nanos(r.u64()?);
+    let cpu_usage_percent = r.f32()?;
+    let cpu_steal_percent = r.f32()?;
+    let cpu_iowait_percent = r.f32()?;
+    let ram_usage_percent = r.f32()?;
+    let ram_used_bytes = r.u64()?;
+    let ram_total_bytes = r.u64()?;
+    let swap_usage_percent = r.f32()?;
+    let io_read_bytes_per_s = r.f64()?;
+    let io_write_bytes_per_s = r.f64()?;
+    let cpu_temp_celsius = r.opt(Fields::f32)?;
+    let battery_percent = r.opt(Fields::f32)?;
+    let power_draw_watts = r.opt(Fields::f32)?;
+    let fd_usage = r.opt(|r| {
+        Ok(FdUsage {
+            open: r.u64()?,
+            max: r.u64()?,
+        })
+    })?;
+    let task_counts = r.opt(|r| {
+        Ok(TaskCounts {
+            processes: r.u64()?,
+            threads: r.u64()?,
+        })
+    })?;
+    let uptime_secs = r.opt(Fields::u64)?;
+
+    let disks = u16::from_le_bytes(r.take()?);
+    let mut disk_usage = Vec::with_capacity(disks as usize);
+    for _ in 0..disks {
+        let len = u16::from_le_bytes(r.take()?) as usize;
+        let path = String::from_utf8(r.bytes(len)?.to_vec())
+            .map_err(|_| MonitorError::Parse("Disk path is not UTF-8".into()))?;
+        disk_usage.push(DiskUsage {
+            path,
+            used_percent: r.f32()?,
+        });
+    }
+
+    if !r.buf.is_empty() {
+        return Err(MonitorError::Parse(format!(
+            "{} unexpected bytes after snapshot record",
+            r.buf.len()
+        )));
+    }
+
+    Ok(SystemSnapshot {
+        timestamp,
+        cpu_usage_percent,
+        cpu_steal_percent,
+        cpu_iowait_percent,
+        ram_usage_percent,
+        ram_used_bytes,
+        ram_total_bytes,
+        swap_usage_percent,
+        io_read_bytes_per_s,
+        io_write_bytes_per_s,
+        cpu_temp_celsius,
+        battery_percent,
+        power_draw_watts,
+        fd_usage,
+        task_counts,
+        disk_usage,
+        top_processes: Vec::new(),
+        top_processes_by_ram: Vec::new(),
+        self_usage: None,
+        uptime_secs,
+        instant: None,
+    })
+}
+
+/// Cursor over a record payload.
+struct Fields<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Fields<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], MonitorError> {
+        if self.buf.len() < n {
+            return Err(MonitorError::Parse("Truncated snapshot record".into()));
+        }
+        let (head, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(head)
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], MonitorError> {
+        let mut out = [0; N];
+        out.copy_from_slice(self.bytes(N)?);
+        Ok(out)
+    }
+
+    fn u64(&mut self) -> Result<u64, MonitorError> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, MonitorError> {
+        Ok(f32::from_le_bytes(self.take()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, MonitorError> {
+        Ok(f64::from_le_bytes(self.take()?))
+    }
+
+    /// A value behind a presence byte.
+    fn opt<T>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> Result<T, MonitorError>,
+    ) -> Result<Option<T>, MonitorError> {
+        match self.take::<1>()? {
+            [0] => Ok(None),
+            [1] => read(self).map(Some),
+            [other] => Err(MonitorError::Parse(format!(
+                "Invalid presence byte {} in snapshot record",
+                other
+            ))),
+        }
+    }
+}
+
+/// Records of a binary samples log, after its header.
+pub struct BinSnapshotReader {
+    input: BufReader<File>,
+    payload: Vec<u8>,
+    /// A record length was out of range, so record boundaries are lost
+    /// and nothing after it is read.
+    corrupt: bool,
+}
+
+impl BinSnapshotReader {
+    /// Check the header of `input`, positioned at its start: the magic,
+    /// then a version and layout this build writes. `path` is for errors.
+    pub fn open(mut input: BufReader<File>, path: &str) -> Result<Self, MonitorError> {
+        let invalid = |why: String| {
+            MonitorError::Parse(format!("{}: incompatible samples log: {}", path, why))
+        };
+
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a binary samples log".into()));
+        }
+
+        let mut word = [0; 2];
+        input.read_exact(&mut word)?;
+        let version = u16::from_le_bytes(word);
+        if version != VERSION {
+            return Err(invalid(format!(
+                "version {} (this build reads {})",
+                version, VERSION
+            )));
+        }
+
+        input.read_exact(&mut word)?;
+        let mut layout = vec![0; u16::from_le_bytes(word) as usize];
+        input.read_exact(&mut layout)?;
+        if layout != LAYOUT.as_bytes() {
+            return Err(invalid("field layout differs from this build's".into()));
+        }
+
+        Ok(Self {
+            input,
+            payload: Vec::new(),
+            corrupt: false,
+        })
+    }
+
+    /// Next record; None at the end of the file. A record cut off by a
+    /// crash is an error, and so is a length over `MAX_RECORD_LEN`, which
+    /// also ends the file.
+    pub fn next_snapshot(&mut self) -> Option<Result<SystemSnapshot, MonitorError>> {
+        if self.corrupt {
+            return None;
+        }
+
+        let mut len = [0; 4];
+        match self.input.read(&mut len[..1]) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(e) => return Some(Err(e.into())),
+        }
+
+        let read = self.input.read_exact(&mut len[1..]).and_then(|()| {
+            let len = u32::from_le_bytes(len) as usize;
+            if len > MAX_RECORD_LEN {
+                self.corrupt = true;
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "record length {} is over the {} byte maximum; samples log is corrupt",
+                        len, MAX_RECORD_LEN
+                    ),
+                ));
+            }
+            self.payload.resize(len, 0);
+            self.input.read_exact(&mut self.payload)
+        });
+        Some(match read {
+            Ok(()) => decode_snapshot(&self.payload),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Err(MonitorError::Parse(
+                "Truncated snapshot record at end of samples log".into(),
+            )),
+            Err(e) => Err(e.into()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader(path: &std::path::Path) -> BinSnapshotReader {
+        let file = File::open(path).unwrap();
+        BinSnapshotReader::open(BufReader::new(file), "samples.bin").unwrap()
+    }
+
+    #[test]
+    fn binary_snapshots_round_trip() {
+        let mut full = SystemSnapshot::at_secs(1_700_000_000);
+        full.timestamp += Duration::from_nanos(123_456_789);
+        full.cpu_usage_percent = 42.345;
+        full.cpu_steal_percent = 1.5;
+        full.ram_usage_percent = 63.1;
+        full.ram_used_bytes = 5_000_000_000;
+        full.ram_total_bytes = 16_000_000_000;
+        full.io_read_bytes_per_s = 1_234_567.5;
+        full.cpu_temp_celsius = Some(71.5);
+        full.power_draw_watts = Some(-12.25);
+        full.fd_usage = Some(FdUsage {
+            open: 1_024,
+            max: 9_223_372_036_854_775_807,
+        });
+        full.task_counts = Some(TaskCounts {
+            processes: 312,
+            threads: 1_480,
+        });
+        full.uptime_secs = Some(350_735);
+        full.disk_usage = vec![
+            DiskUsage {
+                path: "/".into(),
+                used_percent: 51.0,
+            },
+            DiskUsage {
+                path: "/srv/données".into(),
+                used_percent: 99.5,
+            },
+        ];
+        let bare = SystemSnapshot::at_secs(1_700_000_001);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("samples.bin");
+        let mut log = SamplesLog::create(path.to_str().unwrap(), SamplesLogFormat::Bin).unwrap();
+        log.write(&full).unwrap();
+        log.write(&bare).unwrap();
+        log.flush().unwrap();
+
+        let mut reader = reader(&path);
+        for written in [&full, &bare] {
+            let read = reader.next_snapshot().unwrap().unwrap();
+            assert_eq!(format!("{:?}", read), format!("{:?}", written));
+        }
+        assert!(reader.next_snapshot().is_none());
+    }
+
+    #[test]
+    fn oversized_record_length_ends_the_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("samples.bin");
+        drop(SamplesLog::create(path.to_str().unwrap(), SamplesLogFormat::Bin).unwrap());
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&[0; 64]);
+        std::fs::write(&path, bytes).unwrap();
+
+        let mut reader = reader(&path);
+        let err = reader.next_snapshot().unwrap().unwrap_err();
+        assert!(
+            err.to_string().contains("over the 1048576 byte maximum"),
+            "{}",
+            err
+        );
+        assert!(reader.next_snapshot().is_none());
+    }
+
+    #[test]
+    fn paths_too_long_for_a_record_are_rejected() {
+        let mut snapshot = SystemSnapshot::at_secs(1_700_000_000);
+        snapshot.disk_usage = vec![DiskUsage {
+            path: "a".repeat(usize::from(u16::MAX) + 1),
+            used_percent: 10.0,
+        }];
+        let err = encode_snapshot(&snapshot, &mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        snapshot.disk_usage[0].path.pop();
+        let mut buf = Vec::new();
+        encode_snapshot(&snapshot, &mut buf).unwrap();
+        let read = decode_snapshot(&buf).unwrap();
+        assert_eq!(read.disk_usage[0].path.len(), usize::from(u16::MAX));
+    }
+}
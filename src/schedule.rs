@@ -0,0 +1,233 @@
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Fixed-rate sampling schedule.
+///
+/// Wake times are `start + k * interval`, so time spent sampling and
+/// printing does not accumulate as drift. Ticks that were missed entirely
+/// (e.g. the process was stopped) are skipped rather than replayed in a
+/// burst.
+///
+/// With jitter, each wake moves by a random offset within ±jitter of its
+/// scheduled time, so sampling does not alias with a workload of the same
+/// period. Offsets don't accumulate: the mean rate stays one per interval.
+#[derive(Debug, Clone)]
+pub struct Ticker {
+    interval: Duration,
+    next_tick: Instant,
+    jitter: Duration,
+    rng: XorShift,
+}
+
+impl Ticker {
+    /// First tick is one interval from now.
+    pub fn new(interval: Duration) -> Self {
+        Self::with_jitter(interval, Duration::ZERO)
+    }
+
+    /// Like `new`, with each wake moved by up to ±`jitter`. Jitter is
+    /// capped at half the interval so wakes stay in order.
+    pub fn with_jitter(interval: Duration, jitter: Duration) -> Self {
+        Self {
+            interval,
+            next_tick: Instant::now() + interval,
+            jitter: jitter.min(interval / 2),
+            rng: XorShift::from_clock(),
+        }
+    }
+
+    /// Sleep until the next scheduled tick (plus jitter) and advance the
+    /// schedule.
+    pub fn wait(&mut self) {
+        let wake = self.jittered(self.next_tick);
+        let now = Instant::now();
+        if now < wake {
+            thread::sleep(wake - now);
+        }
+
+        self.next_tick += self.interval;
+
+        // Fell behind by one or more whole intervals: skip to the next
+        // tick still in the future.
+        let now = Instant::now();
+        if self.next_tick < now && !self.interval.is_zero() {
+            let behind = now - self.next_tick;
+            let missed = behind.as_nanos() / self.interval.as_nanos() + 1;
+            self.next_tick += self.interval * missed as u32;
+        }
+    }
+
+    /// `tick` moved by a uniform random offset in [-jitter, +jitter].
+    fn jittered(&mut self, tick: Instant) -> Instant {
+        if self.jitter.is_zero() {
+            return tick;
+        }
+
+        let span = self.jitter.as_nanos() as u64 * 2;
+        let offset = self.rng.next() % (span + 1);
+        let early = self.jitter.as_nanos() as u64;
+        if offset >= early {
+            tick + Duration::from_nanos(offset - early)
+        } else {
+            tick.checked_sub(Duration::from_nanos(early - offset)).unwrap_or(tick)
+        }
+    }
+}
+
+/// xorshift64* generator: enough to decorrelate wake times, no crate
+/// needed.
+#[derive(Debug, Clone)]
+struct XorShift(u64);
+
+impl XorShift {
+    /// Seeded from the clock and pid, so concurrent monitors differ.
+    fn from_clock() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        // The state must never be zero.
+        Self((nanos ^ ((std::process::id() as u64) << 32)) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+/// Minutes since local midnight at `t`, for `--schedule` rules. UTC
+/// where the local time zone can't be determined.
+pub fn minute_of_day(t: SystemTime) -> u32 {
+    let secs = t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    local_minute_of_day(secs).unwrap_or(((secs / 60) % (24 * 60)) as u32)
+}
+
+#[cfg(unix)]
+fn local_minute_of_day(secs: u64) -> Option<u32> {
+    let time = libc::time_t::try_from(secs).ok()?;
+    // SAFETY: tm is plain data; localtime_r only writes into it and
+    // returns null on failure.
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return None;
+    }
+    Some((tm.tm_hour * 60 + tm.tm_min) as u32)
+}
+
+#[cfg(not(unix))]
+fn local_minute_of_day(_secs: u64) -> Option<u32> {
+    None
+}
+
+/// Periodic liveness check: `due()` is true once every `every`.
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    every: Duration,
+    last: Instant,
+}
+
+impl Heartbeat {
+    /// First heartbeat is due one period from now.
+    pub fn new(every: Duration) -> Self {
+        Self {
+            every,
+            last: Instant::now(),
+        }
+    }
+
+    /// Whether a heartbeat is due; restarts the period when it is.
+    pub fn due(&mut self) -> bool {
+        if self.last.elapsed() < self.every {
+            return false;
+        }
+
+        self.last = Instant::now();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_millis(100);
+    /// Scheduler slack allowed on a loaded test machine.
+    const TOLERANCE: Duration = Duration::from_millis(30);
+
+    /// Offset of `t` from the nearest tick of `start + k * INTERVAL`.
+    fn off_schedule(start: Instant, t: Instant) -> Duration {
+        let nanos = (t - start).as_nanos() % INTERVAL.as_nanos();
+        Duration::from_nanos(nanos.min(INTERVAL.as_nanos() - nanos) as u64)
+    }
+
+    #[test]
+    fn wakes_stay_on_schedule_despite_work() {
+        let start = Instant::now();
+        let mut ticker = Ticker::new(INTERVAL);
+        for k in 1..=5 {
+            ticker.wait();
+            let woke = Instant::now();
+            assert!(woke >= start + INTERVAL * k);
+            assert!(off_schedule(start, woke) <= TOLERANCE, "tick {}", k);
+            // Sampling cost that a sleep-then-work loop would add to
+            // every period.
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        let elapsed = start.elapsed();
+        assert!(elapsed < INTERVAL * 5 + Duration::from_millis(20) + TOLERANCE);
+    }
+
+    #[test]
+    fn missed_ticks_are_skipped_not_replayed() {
+        let start = Instant::now();
+        let mut ticker = Ticker::new(INTERVAL);
+        thread::sleep(INTERVAL * 3 + INTERVAL / 2);
+
+        // The overdue tick fires at once, the next one waits for the
+        // schedule instead of catching up in a burst.
+        ticker.wait();
+        let late = Instant::now();
+        ticker.wait();
+        let next = Instant::now();
+        assert!(next - late >= INTERVAL / 2 - Duration::from_millis(1));
+        assert!(off_schedule(start, next) <= TOLERANCE);
+    }
+
+    #[test]
+    fn jittered_wakes_stay_within_jitter() {
+        let jitter = Duration::from_millis(10);
+        let start = Instant::now();
+        let mut ticker = Ticker::with_jitter(INTERVAL, jitter);
+        for _ in 0..3 {
+            ticker.wait();
+            assert!(off_schedule(start, Instant::now()) <= jitter + TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn jitter_offsets_cover_the_band_and_no_more() {
+        let jitter = Duration::from_millis(10);
+        let mut ticker = Ticker::with_jitter(INTERVAL, jitter);
+        let tick = ticker.next_tick;
+        let (mut early, mut late) = (false, false);
+        for _ in 0..1000 {
+            let wake = ticker.jittered(tick);
+            early |= wake < tick;
+            late |= wake > tick;
+            assert!(wake >= tick - jitter && wake <= tick + jitter);
+        }
+        assert!(early && late);
+
+        // Capped at half the interval so wakes keep their order.
+        let capped = Ticker::with_jitter(INTERVAL, Duration::from_secs(1));
+        assert_eq!(capped.jitter, INTERVAL / 2);
+        let mut steady = Ticker::new(INTERVAL);
+        assert_eq!(steady.jittered(tick), tick);
+    }
+}
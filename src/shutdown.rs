@@ -0,0 +1,40 @@
+//! Clean shutdown on SIGINT/SIGTERM.
+//!
+//! The first signal only sets a flag; the monitoring loop stops at its
+//! next sample and runs its normal end-of-run work (summary, state file).
+//! The handler is then reset, so a second Ctrl-C kills the process as
+//! usual. On Windows the default Ctrl-C behavior is kept.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether a shutdown signal has been received.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(_signal: libc::c_int) {
+    // Only async-signal-safe work here.
+    REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Install the SIGINT/SIGTERM handlers.
+#[cfg(unix)]
+pub fn install() {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: the sigaction struct is fully initialized before use and
+        // the handler only stores to an atomic.
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESETHAND | libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn install() {}
@@ -0,0 +1,78 @@
+use std::error::Error;
+
+use crate::analyzer::SpikeEvent;
+use crate::config::RuntimeConfig;
+use crate::db::DbLogger;
+#[cfg(unix)]
+use crate::event_socket::EventSocket;
+use crate::logging::EventLogger;
+use crate::output::DEFAULT_PRECISION;
+#[cfg(unix)]
+use crate::syslog::SyslogLogger;
+
+/// Destination for spike events (log file, syslog, database...).
+///
+/// Sinks are called in order for every event; an error from one sink is
+/// reported and does not stop the others.
+pub trait EventSink {
+    /// Short description used in error messages, e.g. "log file".
+    fn name(&self) -> &'static str;
+
+    fn handle(&mut self, event: &SpikeEvent) -> Result<(), Box<dyn Error>>;
+}
+
+/// Open every sink enabled in the configuration.
+///
+/// A log file that can't be opened is fatal unless `log_best_effort`
+/// is set; it is then reported (prefixed with `tag`) and left out.
+pub fn build_sinks(
+    config: &RuntimeConfig,
+    tag: &str,
+) -> Result<Vec<Box<dyn EventSink>>, Box<dyn Error>> {
+    let mut sinks: Vec<Box<dyn EventSink>> = Vec::new();
+
+    if let Some(path) = &config.log_file {
+        let precision = config.precision.unwrap_or(DEFAULT_PRECISION);
+        match EventLogger::new(
+            path,
+            config.log_compress,
+            precision,
+            config.log_flush,
+            config.host.clone(),
+        ) {
+            Ok(logger) => sinks.push(Box::new(logger)),
+            Err(e) if config.log_best_effort => {
+                eprintln!(
+                    "{} Warning: cannot open log file {}: {e}; continuing without it",
+                    tag, path
+                );
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    #[cfg(unix)]
+    if let Some(syslog_config) = &config.syslog {
+        sinks.push(Box::new(SyslogLogger::new(syslog_config, &config.labels)?));
+    }
+    #[cfg(not(unix))]
+    if config.syslog.is_some() {
+        return Err("--syslog is only supported on Unix".into());
+    }
+
+    if let Some(path) = &config.db_file {
+        sinks.push(Box::new(DbLogger::new(path, config.host.clone())?));
+    }
+
+    #[cfg(unix)]
+    if let Some(path) = &config.event_socket {
+        let precision = config.precision.unwrap_or(DEFAULT_PRECISION);
+        sinks.push(Box::new(EventSocket::bind(path, precision, config.host.clone())?));
+    }
+    #[cfg(not(unix))]
+    if config.event_socket.is_some() {
+        return Err("--event-socket is only supported on Unix".into());
+    }
+
+    Ok(sinks)
+}
@@ -0,0 +1,157 @@
+//! Rolling per-resource sparklines for live mode.
+
+use std::collections::VecDeque;
+use std::io::{self, IsTerminal, Write};
+
+use crate::analyzer::resource_value;
+use crate::config::{ResourceKind, ResourceLabels};
+use crate::metrics::SystemSnapshot;
+
+/// Block glyphs from lowest to highest.
+const GLYPHS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Resources drawn, in order. `Io` is left out as the sum of the two IO
+/// series; resources that were not collected are skipped.
+const RESOURCES: [ResourceKind; 8] = [
+    ResourceKind::Cpu,
+    ResourceKind::Ram,
+    ResourceKind::IoRead,
+    ResourceKind::IoWrite,
+    ResourceKind::Temp,
+    ResourceKind::Power,
+    ResourceKind::Fd,
+    ResourceKind::Procs,
+];
+
+/// Glyph for `value` on a 0..=`max` scale. Values at or below 0 map to the
+/// lowest glyph, values at or above `max` to the highest; a zero `max`
+/// (an all-idle window) draws the lowest.
+pub fn glyph(value: f32, max: f32) -> char {
+    if value <= 0.0 || max <= 0.0 {
+        return GLYPHS[0];
+    }
+    let level = (value / max * GLYPHS.len() as f32) as usize;
+    GLYPHS[level.min(GLYPHS.len() - 1)]
+}
+
+/// Last `width` values of each resource, drawn as one line redrawn in
+/// place on stdout. Percentages are scaled to 0-100, other resources
+/// (MB/s, °C, counts) to the highest value in the window.
+#[derive(Debug)]
+pub struct Sparkline {
+    width: usize,
+    series: Vec<(ResourceKind, VecDeque<f32>)>,
+    drawn: bool,
+}
+
+impl Sparkline {
+    /// None when stdout is not a terminal.
+    pub fn new(width: usize) -> Option<Self> {
+        if !io::stdout().is_terminal() {
+            return None;
+        }
+
+        Some(Self {
+            width: width.max(1),
+            series: Vec::new(),
+            drawn: false,
+        })
+    }
+
+    /// Append one snapshot's values, dropping the oldest beyond `width`.
+    pub fn push(&mut self, snapshot: &SystemSnapshot) {
+        for resource in RESOURCES {
+            let Some(value) = resource_value(snapshot, resource) else {
+                continue;
+            };
+
+            let idx = match self.series.iter().position(|(r, _)| *r == resource) {
+                Some(idx) => idx,
+                None => {
+                    self.series
+                        .push((resource, VecDeque::with_capacity(self.width)));
+                    self.series.len() - 1
+                }
+            };
+            let values = &mut self.series[idx].1;
+            if values.len() == self.width {
+                values.pop_front();
+            }
+            values.push_back(value);
+        }
+    }
+
+    /// Redraw the line with the latest value after each sparkline.
+    pub fn draw(
+        &mut self,
+        out: &mut dyn Write,
+        labels: &ResourceLabels,
+        precision: usize,
+    ) -> io::Result<()> {
+        let mut line = String::new();
+        for (resource, values) in &self.series {
+            let max = if resource.unit() == "%" {
+                100.0
+            } else {
+                values.iter().copied().fold(0.0, f32::max)
+            };
+            let spark: String = values.iter().map(|&v| glyph(v, max)).collect();
+            let last = values.back().copied().unwrap_or(0.0);
+
+            if !line.is_empty() {
+                line.push_str("  ");
+            }
+            line.push_str(&format!(
+                "{} {:<width$} {:.precision$}{}",
+                labels.label(*resource),
+                spark,
+                last,
+                labels.unit(*resource),
+                width = self.width,
+            ));
+        }
+
+        // Carriage return and erase-to-end-of-line redraw in place.
+        write!(out, "\r{}\x1b[K", line)?;
+        out.flush()?;
+        self.drawn = true;
+        Ok(())
+    }
+
+    /// Erase the line, so an alert or the summary starts on a clean one.
+    /// The next `draw` puts it back.
+    pub fn clear(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        if self.drawn {
+            write!(out, "\r\x1b[K")?;
+            out.flush()?;
+            self.drawn = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_map_to_glyphs_across_the_range() {
+        let line: String = (0..=100)
+            .step_by(10)
+            .map(|v| glyph(v as f32, 100.0))
+            .collect();
+        assert_eq!(line, "▁▁▂▃▄▅▅▆▇██");
+
+        // Each eighth of the scale gets its own glyph.
+        for (i, expected) in GLYPHS.iter().enumerate() {
+            let mid = (i as f32 + 0.5) * 100.0 / 8.0;
+            assert_eq!(glyph(mid, 100.0), *expected, "{}", mid);
+        }
+
+        assert_eq!(glyph(-5.0, 100.0), '▁');
+        assert_eq!(glyph(250.0, 100.0), '█');
+        assert_eq!(glyph(f32::NAN, 100.0), '▁');
+        assert_eq!(glyph(3.0, 0.0), '▁');
+        assert_eq!(glyph(3.0, 6.0), '▅');
+    }
+}
@@ -0,0 +1,147 @@
+use std::error::Error;
+use std::os::unix::net::UnixDatagram;
+
+use crate::analyzer::{EventPhase, SpikeEvent};
+use crate::config::{ResourceLabels, SyslogConfig};
+use crate::sink::EventSink;
+
+/// Local syslog socket (also served by journald).
+const SYSLOG_SOCKET: &str = "/dev/log";
+
+/// Syslog severities used for spike events (RFC 5424).
+const SEVERITY_CRIT: u8 = 2;
+const SEVERITY_ERR: u8 = 3;
+const SEVERITY_WARNING: u8 = 4;
+
+/// Spike event sink writing to the local syslog daemon.
+pub struct SyslogLogger {
+    socket: UnixDatagram,
+    facility: u8,
+    ident: String,
+    labels: ResourceLabels,
+}
+
+impl SyslogLogger {
+    /// Connect to the local syslog socket. `labels` name the resources
+    /// in messages.
+    pub fn new(config: &SyslogConfig, labels: &ResourceLabels) -> Result<Self, Box<dyn Error>> {
+        let socket = UnixDatagram::unbound()?;
+        socket
+            .connect(SYSLOG_SOCKET)
+            .map_err(|e| format!("Failed to connect to {}: {}", SYSLOG_SOCKET, e))?;
+
+        Ok(Self {
+            socket,
+            facility: config.facility.code(),
+            ident: config.ident.clone(),
+            labels: labels.clone(),
+        })
+    }
+
+    /// Send one spike event as a syslog message.
+    pub fn log_event(&mut self, event: &SpikeEvent) -> Result<(), Box<dyn Error>> {
+        let resource = self.labels.label(event.resource);
+        let unit = self.labels.unit(event.resource);
+
+        let target = match (&event.path, &event.rule) {
+            (Some(p), _) => format!(" ({})", p),
+            (None, Some(rule)) => format!(" [{}]", rule),
+            (None, None) => String::new(),
+        };
+
+        let id = match &event.tag {
+            Some(t) => format!("{} tag={}", event.id, t),
+            None => event.id.clone(),
+        };
+
+        let duration_secs = match event.timestamp_end.duration_since(event.timestamp_start) {
+            Ok(d) => d.as_secs(),
+            Err(_) => 0,
+        };
+
+        let phase = match event.phase {
+            EventPhase::Start => " started",
+            EventPhase::End => "",
+            EventPhase::Flap => {
+                let pri = self.facility * 8 + SEVERITY_WARNING;
+                let message = format!(
+                    "<{}>{}[{}]: {}{} flapping: id={} spikes={} threshold={} duration={}s",
+                    pri,
+                    self.ident,
+                    std::process::id(),
+                    resource,
+                    target,
+                    id,
+                    event.peak_value,
+                    event.threshold,
+                    duration_secs,
+                );
+                self.socket.send(message.as_bytes())?;
+                return Ok(());
+            }
+            EventPhase::Growth => {
+                let pri = self.facility * 8 + SEVERITY_WARNING;
+                let message = format!(
+                    "<{}>{}[{}]: {}{} growing: id={} rate={:.2}MB/min threshold={:.2}MB/min duration={}s",
+                    pri,
+                    self.ident,
+                    std::process::id(),
+                    resource,
+                    target,
+                    id,
+                    event.peak_value,
+                    event.threshold,
+                    duration_secs,
+                );
+                self.socket.send(message.as_bytes())?;
+                return Ok(());
+            }
+        };
+
+        let pri = self.facility * 8 + severity(event);
+        let message = format!(
+            "<{}>{}[{}]: {}{} spike{}: id={} peak={:.2}{} threshold={:.2}{} duration={}s",
+            pri,
+            self.ident,
+            std::process::id(),
+            resource,
+            target,
+            phase,
+            id,
+            event.peak_value,
+            unit,
+            event.threshold,
+            unit,
+            duration_secs,
+        );
+
+        self.socket.send(message.as_bytes())?;
+
+        Ok(())
+    }
+}
+
+impl EventSink for SyslogLogger {
+    fn name(&self) -> &'static str {
+        "syslog"
+    }
+
+    fn handle(&mut self, event: &SpikeEvent) -> Result<(), Box<dyn Error>> {
+        self.log_event(event)
+    }
+}
+
+/// Map how far the peak went over the threshold to a syslog severity.
+fn severity(event: &SpikeEvent) -> u8 {
+    let Some(ratio) = event.over_ratio() else {
+        return SEVERITY_WARNING;
+    };
+
+    if ratio >= 1.5 {
+        SEVERITY_CRIT
+    } else if ratio >= 1.2 {
+        SEVERITY_ERR
+    } else {
+        SEVERITY_WARNING
+    }
+}
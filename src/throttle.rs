@@ -0,0 +1,135 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// How often repeats of the same error are summarized.
+pub const ERROR_ROLLUP: Duration = Duration::from_secs(60);
+
+/// Coalesces repeated identical error messages on stderr (or `out`).
+///
+/// The first occurrence is printed; identical repeats are counted and
+/// summarized once per `rollup` period. A different message, or `clear()`
+/// after a success, flushes the pending count first.
+#[derive(Debug)]
+pub struct ErrorThrottle<W: Write = io::Stderr> {
+    out: W,
+    prefix: &'static str,
+    rollup: Duration,
+    last: Option<String>,
+    repeats: u64,
+    since: Instant,
+}
+
+impl ErrorThrottle {
+    pub fn new(prefix: &'static str, rollup: Duration) -> Self {
+        Self::with_writer(io::stderr(), prefix, rollup)
+    }
+}
+
+impl<W: Write> ErrorThrottle<W> {
+    /// Like `new`, writing to `out` instead of stderr.
+    pub fn with_writer(out: W, prefix: &'static str, rollup: Duration) -> Self {
+        Self {
+            out,
+            prefix,
+            rollup,
+            last: None,
+            repeats: 0,
+            since: Instant::now(),
+        }
+    }
+
+    /// Report an error, printing it unless it repeats the previous one.
+    pub fn error(&mut self, message: String) {
+        if self.last.as_deref() == Some(message.as_str()) {
+            self.repeats += 1;
+            if self.since.elapsed() >= self.rollup {
+                self.flush();
+            }
+            return;
+        }
+
+        self.flush();
+        let _ = writeln!(self.out, "{} {}", self.prefix, message);
+        self.last = Some(message);
+        self.since = Instant::now();
+    }
+
+    /// Forget the last error (e.g. after a successful read).
+    pub fn clear(&mut self) {
+        self.flush();
+        self.last = None;
+    }
+
+    /// Print the pending repeat count, if any, and start a new period.
+    pub fn flush(&mut self) {
+        if self.repeats > 0 {
+            if let Some(last) = &self.last {
+                let _ = writeln!(
+                    self.out,
+                    "{} same error repeated {} times in last {}s: {}",
+                    self.prefix,
+                    self.repeats,
+                    self.since.elapsed().as_secs(),
+                    last
+                );
+            }
+        }
+
+        self.repeats = 0;
+        self.since = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(throttle: &ErrorThrottle<Vec<u8>>) -> Vec<&str> {
+        std::str::from_utf8(&throttle.out)
+            .unwrap()
+            .lines()
+            .collect()
+    }
+
+    #[test]
+    fn persistent_error_is_printed_once_then_rolled_up() {
+        let mut throttle = ErrorThrottle::with_writer(Vec::new(), "[monitor]", ERROR_ROLLUP);
+        for _ in 0..5 {
+            throttle.error("Error reading snapshot: EACCES".into());
+        }
+        assert_eq!(
+            lines(&throttle),
+            ["[monitor] Error reading snapshot: EACCES"]
+        );
+
+        // A successful read flushes the count and starts over.
+        throttle.clear();
+        throttle.error("Error reading snapshot: EACCES".into());
+        assert_eq!(
+            lines(&throttle),
+            [
+                "[monitor] Error reading snapshot: EACCES",
+                "[monitor] same error repeated 4 times in last 0s: Error reading snapshot: EACCES",
+                "[monitor] Error reading snapshot: EACCES",
+            ]
+        );
+    }
+
+    #[test]
+    fn repeats_are_summarized_each_rollup_period() {
+        let mut throttle = ErrorThrottle::with_writer(Vec::new(), "[monitor]", Duration::ZERO);
+        for _ in 0..3 {
+            throttle.error("boom".into());
+        }
+        throttle.error("other".into());
+        assert_eq!(
+            lines(&throttle),
+            [
+                "[monitor] boom",
+                "[monitor] same error repeated 1 times in last 0s: boom",
+                "[monitor] same error repeated 1 times in last 0s: boom",
+                "[monitor] other",
+            ]
+        );
+    }
+}
@@ -0,0 +1,41 @@
+use crate::config::{OutputFormat, ResourceKind, VersionConfig};
+use crate::logging::LOG_SCHEMA_VERSION;
+use crate::output::json_string;
+
+/// Crate version of this build.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Target triple this build was compiled for.
+pub const TARGET: &str = env!("RESOURCE_MONITOR_TARGET");
+
+/// Git commit of this build; None when built outside a git checkout.
+pub fn git_commit() -> Option<&'static str> {
+    Some(env!("RESOURCE_MONITOR_GIT_COMMIT")).filter(|c| !c.is_empty())
+}
+
+/// Version mode: print what this build is and supports, so tooling can
+/// check compatibility before reading its output or logs.
+pub fn run_version(config: VersionConfig) {
+    let resources: Vec<&str> = ResourceKind::ALL.iter().map(|r| r.name()).collect();
+
+    match config.output_format {
+        OutputFormat::Json => {
+            let resources: Vec<String> = resources.iter().map(|r| json_string(r)).collect();
+            println!(
+                "{{\"version\":{},\"log_schema\":{},\"commit\":{},\"target\":{},\"resources\":[{}]}}",
+                json_string(VERSION),
+                LOG_SCHEMA_VERSION,
+                git_commit().map_or("null".to_string(), json_string),
+                json_string(TARGET),
+                resources.join(",")
+            );
+        }
+        OutputFormat::Text | OutputFormat::Table | OutputFormat::OpenMetrics => {
+            println!("resource_monitor {}", VERSION);
+            println!("  log schema: {}", LOG_SCHEMA_VERSION);
+            println!("  commit:     {}", git_commit().unwrap_or("unknown"));
+            println!("  target:     {}", TARGET);
+            println!("  resources:  {}", resources.join(", "));
+        }
+    }
+}
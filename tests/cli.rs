@@ -0,0 +1,1056 @@
+use std::process::{Command, Output};
+
+use resource_monitor::logging::LOG_SCHEMA_VERSION;
+
+fn monitor(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_resource_monitor"))
+        .args(args)
+        .output()
+        .expect("failed to run resource_monitor")
+}
+
+#[test]
+fn json_stdout_carries_only_json_lines() {
+    let output = monitor(&[
+        "batch",
+        "--samples",
+        "3",
+        "--interval-ms",
+        "10",
+        "--output",
+        "json",
+        "--cpu-threshold",
+        "0",
+        "--min-spike-duration-secs",
+        "0",
+        "-vv",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines.len() >= 3, "{}", stdout);
+    let types: Vec<String> = lines
+        .iter()
+        .map(|line| {
+            let value: serde_json::Value =
+                serde_json::from_str(line).unwrap_or_else(|e| panic!("not JSON ({}): {}", e, line));
+            value["type"].as_str().unwrap_or_default().to_string()
+        })
+        .collect();
+    assert!(types.iter().any(|t| t == "event"), "{}", stdout);
+
+    // -vv diagnostics go to stderr instead.
+    assert!(!output.stderr.is_empty());
+}
+
+#[test]
+fn json_stream_opens_with_meta_line() {
+    let output = monitor(&[
+        "batch",
+        "--samples",
+        "2",
+        "--interval-ms",
+        "25",
+        "--output",
+        "json",
+        "--cpu-threshold",
+        "85",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let meta = &lines[0];
+    assert_eq!(meta["type"], "meta", "{}", stdout);
+    assert_eq!(meta["interval_ms"], 25);
+    assert_eq!(meta["thresholds"]["cpu"], 85.0);
+    assert!(meta["started_ts"].is_u64());
+    // Only the first line is a header.
+    assert!(lines[1..].iter().all(|line| line["type"] == "snapshot"));
+    assert_eq!(lines.len(), 3);
+}
+
+/// Replay fixture with CPU at `cpu` percent for `secs` one-second samples.
+fn cpu_fixture(dir: &std::path::Path, cpu: f32, secs: u64) -> String {
+    cpu_series_fixture(dir, &vec![cpu; secs as usize])
+}
+
+/// Replay fixture with one one-second sample per CPU percent in `cpu`.
+fn cpu_series_fixture(dir: &std::path::Path, cpu: &[f32]) -> String {
+    let path = dir.join("snapshots.jsonl");
+    let lines: String = cpu
+        .iter()
+        .enumerate()
+        .map(|(i, cpu)| {
+            format!(
+                "{{\"type\":\"snapshot\",\"ts\":{},\"cpu\":{},\"ram\":40.0,\"io_read\":0.0,\"io_write\":0.0}}\n",
+                1_700_000_000 + i,
+                cpu
+            )
+        })
+        .collect();
+    std::fs::write(&path, lines).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+fn replay_exit_code(snapshots: &str, exit_on_spike: &[&str]) -> Option<i32> {
+    let mut args = vec![
+        "replay",
+        "--snapshots",
+        snapshots,
+        "--cpu-threshold",
+        "90",
+        "--min-spike-duration-secs",
+        "3",
+    ];
+    args.extend_from_slice(exit_on_spike);
+    monitor(&args).status.code()
+}
+
+#[test]
+fn replay_yields_the_known_spike() {
+    let dir = tempfile::tempdir().unwrap();
+    let cpu = [10.0, 10.0, 95.0, 97.0, 99.0, 96.0, 10.0, 92.0, 10.0];
+    let snapshots = cpu_series_fixture(dir.path(), &cpu);
+
+    let output = monitor(&[
+        "replay",
+        "--snapshots",
+        &snapshots,
+        "--output",
+        "json",
+        "--cpu-threshold",
+        "90",
+        "--min-spike-duration-secs",
+        "3",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .filter(|value| value["type"] == "event")
+        .collect();
+    // The one-sample blip at 92% is too short.
+    assert_eq!(events.len(), 1, "{}", stdout);
+    assert_eq!(events[0]["resource"], "cpu");
+    assert_eq!(events[0]["ts_start"], 1_700_000_002);
+    assert_eq!(events[0]["ts_end"], 1_700_000_006);
+    assert_eq!(events[0]["peak"], 99.0);
+}
+
+#[test]
+fn exit_on_spike_sets_exit_status() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let busy = cpu_fixture(dir.path(), 99.0, 10);
+    assert_eq!(replay_exit_code(&busy, &[]), Some(0));
+    assert_eq!(replay_exit_code(&busy, &["--exit-on-spike"]), Some(1));
+    assert_eq!(replay_exit_code(&busy, &["--exit-on-spike", "7"]), Some(7));
+
+    let idle = cpu_fixture(dir.path(), 5.0, 10);
+    assert_eq!(replay_exit_code(&idle, &["--exit-on-spike"]), Some(0));
+}
+
+#[test]
+fn exit_on_spike_refuses_success_and_error_codes() {
+    let dir = tempfile::tempdir().unwrap();
+    let busy = cpu_fixture(dir.path(), 99.0, 10);
+
+    for code in ["0", "2"] {
+        let output = monitor(&["replay", "--snapshots", &busy, "--exit-on-spike", code]);
+        assert_eq!(output.status.code(), Some(2));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("reserved"), "{}", stderr);
+    }
+}
+
+#[test]
+fn failed_reads_are_counted_as_dropped() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("snapshots.jsonl");
+    let good = |ts: u64| {
+        format!(
+            "{{\"type\":\"snapshot\",\"ts\":{},\"cpu\":5.0,\"ram\":40.0,\"io_read\":0.0,\"io_write\":0.0}}\n",
+            ts
+        )
+    };
+    let lines = [
+        good(1_700_000_000),
+        "{\"type\":\"snapshot\",\"ts\":1700000001,\"cpu\":\"x\"}\n".to_string(),
+        good(1_700_000_002),
+        "{\"type\":\"snapshot\"\n".to_string(),
+    ];
+    std::fs::write(&path, lines.concat()).unwrap();
+
+    let output = monitor(&["replay", "--snapshots", path.to_str().unwrap()]);
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("done: 2 samples, 2 dropped"), "{}", stderr);
+}
+
+/// One end event line of the spike log.
+fn log_record(id: &str, ts_start: u64, duration: u64, peak: f32) -> String {
+    format!(
+        "{{\"v\":10,\"id\":\"{}\",\"phase\":\"end\",\"resource\":\"cpu\",\"ts_start\":{},\"ts_end\":{},\"duration_secs\":{},\"peak\":{},\"threshold\":90.0,\"top\":[]}}\n",
+        id,
+        ts_start,
+        ts_start + duration,
+        duration,
+        peak
+    )
+}
+
+/// Ids of the records `logs` prints in JSON for `args`.
+fn logged_ids(log: &str, args: &[&str]) -> Vec<String> {
+    let mut all = vec!["logs", "--log-file", log, "--output", "json"];
+    all.extend_from_slice(args);
+    let output = monitor(&all);
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            value["id"].as_str().unwrap().to_string()
+        })
+        .collect()
+}
+
+#[test]
+fn logs_sort_by_each_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("spikes.jsonl");
+    // (id, ts_start, duration, peak)
+    let records = [
+        ("a", 100, 30, 95.0),
+        ("b", 300, 3, 99.0),
+        ("c", 200, 10, 91.0),
+    ];
+    let lines: String = records
+        .iter()
+        .map(|&(id, ts, duration, peak)| log_record(id, ts, duration, peak))
+        .collect();
+    std::fs::write(&path, lines).unwrap();
+    let log = path.to_str().unwrap();
+
+    assert_eq!(logged_ids(log, &[]), ["a", "b", "c"]);
+    assert_eq!(logged_ids(log, &["--sort", "peak"]), ["c", "a", "b"]);
+    assert_eq!(logged_ids(log, &["--sort", "duration"]), ["b", "c", "a"]);
+    assert_eq!(logged_ids(log, &["--sort", "ts_start"]), ["a", "c", "b"]);
+    assert_eq!(
+        logged_ids(log, &["--sort", "peak", "--desc", "--limit", "2"]),
+        ["b", "a"]
+    );
+}
+
+#[test]
+fn ram_threshold_and_bytes_conflict() {
+    let output = monitor(&[
+        "batch",
+        "--samples",
+        "1",
+        "--ram-threshold",
+        "90",
+        "--ram-threshold-bytes",
+        "4G",
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn logs_read_several_files_and_dirs() {
+    let dir = tempfile::tempdir().unwrap();
+    let logs = dir.path().join("logs");
+    std::fs::create_dir(&logs).unwrap();
+    // Rotated logs in the directory, read in name order.
+    std::fs::write(logs.join("spikes.1.jsonl"), log_record("old", 100, 5, 95.0)).unwrap();
+    std::fs::write(logs.join("spikes.2.jsonl"), log_record("new", 300, 5, 95.0)).unwrap();
+    std::fs::write(logs.join("notes.txt"), "not a log\n").unwrap();
+    // A broken line only skips that record.
+    let extra = dir.path().join("extra.jsonl");
+    let contents = format!(
+        "{}{{broken\n{}",
+        log_record("x1", 200, 5, 99.0),
+        log_record("x2", 250, 5, 91.0)
+    );
+    std::fs::write(&extra, contents).unwrap();
+    let missing = dir.path().join("missing.jsonl");
+
+    let extra = extra.to_str().unwrap();
+    let missing = missing.to_str().unwrap();
+    let logs = logs.to_str().unwrap();
+    assert_eq!(
+        logged_ids(extra, &["--log-file", missing, "--log-dir", logs]),
+        ["x1", "x2", "old", "new"]
+    );
+    assert_eq!(
+        logged_ids(extra, &["--log-dir", logs, "--sort", "ts_start"]),
+        ["old", "x1", "x2", "new"]
+    );
+}
+
+/// Snapshot lines on a JSON stdout.
+fn snapshot_lines(output: &Output) -> usize {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| line.contains("\"type\":\"snapshot\""))
+        .count()
+}
+
+#[test]
+fn bounded_live_stops_like_batch() {
+    let json = ["--interval-ms", "10", "--output", "json"];
+
+    let mut args = vec!["live", "--max-samples", "3"];
+    args.extend_from_slice(&json);
+    let output = monitor(&args);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(snapshot_lines(&output), 3);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("done: 3 samples"), "{}", stderr);
+
+    let started = std::time::Instant::now();
+    let mut args = vec!["live", "--max-duration", "1"];
+    args.extend_from_slice(&json);
+    let output = monitor(&args);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    assert!(snapshot_lines(&output) > 3);
+}
+
+#[test]
+fn truncated_last_line_is_reported_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("spikes.jsonl");
+    let partial = "{\"v\":10,\"id\":\"c\",\"phase\":\"e";
+    let contents = format!(
+        "{}{}{}",
+        log_record("a", 100, 5, 95.0),
+        log_record("b", 200, 5, 95.0),
+        partial
+    );
+    std::fs::write(&path, contents).unwrap();
+    let log = path.to_str().unwrap();
+
+    let output = monitor(&["logs", "--log-file", log, "--output", "json"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr.matches("truncated last line").count(),
+        1,
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("Failed to parse"), "{}", stderr);
+    assert_eq!(logged_ids(log, &[]), ["a", "b"]);
+}
+
+#[test]
+fn output_file_receives_stream_instead_of_stdout() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("stream.jsonl");
+    std::fs::write(&path, "# earlier run\n").unwrap();
+    let file = path.to_str().unwrap();
+
+    let output = monitor(&[
+        "batch",
+        "--samples",
+        "2",
+        "--interval-ms",
+        "10",
+        "--output",
+        "json",
+        "--output-file",
+        file,
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(output.stdout.is_empty(), "{:?}", output);
+
+    // Appended after what was there.
+    let contents = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(lines[0], "# earlier run");
+    assert!(lines[1].contains("\"type\":\"meta\""), "{}", contents);
+    assert_eq!(lines.len(), 4, "{}", contents);
+}
+
+/// Start times of the events a JSON replay of `snapshots` prints.
+fn replayed_event_starts(snapshots: &str, args: &[&str]) -> Vec<u64> {
+    let mut all = vec!["replay", "--snapshots", snapshots, "--output", "json"];
+    all.extend_from_slice(args);
+    let output = monitor(&all);
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .filter(|value| value["type"] == "event")
+        .map(|value| value["ts_start"].as_u64().unwrap())
+        .collect()
+}
+
+#[test]
+fn no_events_fire_during_warmup() {
+    let dir = tempfile::tempdir().unwrap();
+    let busy = cpu_fixture(dir.path(), 99.0, 10);
+    let spike = ["--cpu-threshold", "90", "--min-spike-duration-secs", "3"];
+
+    let starts = |warmup: &str| {
+        let mut args = spike.to_vec();
+        args.extend_from_slice(&["--warmup-samples", warmup]);
+        replayed_event_starts(&busy, &args)
+    };
+    assert_eq!(starts("0"), [1_700_000_000]);
+    assert_eq!(starts("5"), [1_700_000_005]);
+    assert!(starts("10").is_empty());
+}
+
+#[test]
+fn comments_and_blank_lines_are_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("merged.jsonl");
+    let contents = [
+        "# host-a\n".to_string(),
+        log_record("a", 100, 5, 95.0),
+        "\n   \n".to_string(),
+        "  # host-b, appended\n".to_string(),
+        log_record("b", 200, 5, 95.0),
+        "{not json\n".to_string(),
+    ]
+    .concat();
+    std::fs::write(&path, contents).unwrap();
+    let log = path.to_str().unwrap();
+
+    let output = monitor(&["logs", "--log-file", log, "--output", "json"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // Only the malformed record warns.
+    assert_eq!(stderr.matches("Failed to parse").count(), 1, "{}", stderr);
+    assert_eq!(logged_ids(log, &[]), ["a", "b"]);
+}
+
+#[test]
+fn progress_is_suppressed_when_stderr_is_not_a_terminal() {
+    let output = monitor(&[
+        "batch",
+        "--samples",
+        "5",
+        "--interval-ms",
+        "10",
+        "--output",
+        "json",
+        "--progress",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("\x1b[K"), "{:?}", stderr);
+    assert!(!stderr.contains("/5 samples"), "{}", stderr);
+    assert_eq!(snapshot_lines(&output), 5);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains('\r'), "{:?}", stdout);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn list_prints_devices_and_interfaces_as_json() {
+    let output = monitor(&["list", "--output", "json"]);
+    assert!(output.status.success(), "{:?}", output);
+    let value: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(value["devices"].is_array(), "{}", value);
+    let interfaces = value["interfaces"].as_array().unwrap();
+    assert!(interfaces.iter().any(|i| i["name"] == "lo"), "{}", value);
+
+    let output = monitor(&["list", "--interfaces", "--output", "json"]);
+    let value: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(value.get("devices").is_none(), "{}", value);
+}
+
+#[test]
+fn host_label_is_logged_and_read_back() {
+    let dir = tempfile::tempdir().unwrap();
+    let busy = cpu_fixture(dir.path(), 99.0, 10);
+    let log = dir.path().join("spikes.jsonl");
+    let log = log.to_str().unwrap();
+
+    let output = monitor(&[
+        "replay",
+        "--snapshots",
+        &busy,
+        "--cpu-threshold",
+        "90",
+        "--host-label",
+        "pod-7",
+        "--log-file",
+        log,
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let logged = std::fs::read_to_string(log).unwrap();
+    let record: serde_json::Value = serde_json::from_str(logged.trim_end()).unwrap();
+    assert_eq!(record["host"], "pod-7");
+
+    let output = monitor(&["logs", "--log-file", log]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(" host=pod-7 "), "{}", stdout);
+}
+
+#[test]
+fn ratio_threshold_resolves_from_warmup_mean() {
+    let dir = tempfile::tempdir().unwrap();
+    // Warmup mean 20%, then a sustained 50%.
+    let mut cpu = vec![10.0, 30.0, 20.0, 20.0];
+    cpu.extend([50.0; 6]);
+    let snapshots = cpu_series_fixture(dir.path(), &cpu);
+
+    let ratio = |ratio: &'static str| ["--warmup-samples", "4", "--cpu-threshold", ratio];
+
+    let mut args = vec!["replay", "--snapshots", &snapshots];
+    args.extend_from_slice(&ratio("2x"));
+    let stderr = String::from_utf8(monitor(&args).stderr).unwrap();
+    assert!(
+        stderr.contains("CPU threshold: 2x warmup mean 20.00% = 40.00%"),
+        "{}",
+        stderr
+    );
+
+    assert_eq!(
+        replayed_event_starts(&snapshots, &ratio("2x")),
+        [1_700_000_004]
+    );
+    // 3x puts it at 60%, above the load.
+    assert!(replayed_event_starts(&snapshots, &ratio("3x")).is_empty());
+}
+
+#[test]
+fn stdout_and_log_events_are_identical() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut cpu = vec![97.1234; 5];
+    cpu.push(10.0);
+    let snapshots = cpu_series_fixture(dir.path(), &cpu);
+    let log = dir.path().join("spikes.jsonl");
+    let log = log.to_str().unwrap();
+
+    let output = monitor(&[
+        "replay",
+        "--snapshots",
+        &snapshots,
+        "--cpu-threshold",
+        "90",
+        "--output",
+        "json",
+        "--log-file",
+        log,
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let printed = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("{\"type\":\"event\","))
+        .unwrap();
+    let logged = std::fs::read_to_string(log).unwrap();
+    let logged = logged.trim_end().split_once(',').unwrap().1;
+    assert!(printed.contains("\"peak\":97.12,"), "{}", printed);
+    // Same fields, byte for byte, after the leading discriminator.
+    assert_eq!(printed, logged);
+}
+
+#[test]
+fn version_json_reports_build_and_capabilities() {
+    let output = monitor(&["version", "--json"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 1, "{}", stdout);
+
+    let version: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(version.as_object().unwrap().len(), 5, "{}", stdout);
+    assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(version["log_schema"], LOG_SCHEMA_VERSION);
+    assert!(version["commit"].is_string() || version["commit"].is_null());
+    assert!(!version["target"].as_str().unwrap().is_empty());
+    let resources: Vec<&str> = version["resources"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r.as_str().unwrap())
+        .collect();
+    for expected in ["cpu", "ram", "io"] {
+        assert!(resources.contains(&expected), "{}", stdout);
+    }
+}
+
+/// Run resource_monitor with MONITOR_ variables set.
+fn monitor_with_env(args: &[&str], vars: &[(&str, &str)]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_resource_monitor"))
+        .args(args)
+        .envs(vars.iter().copied())
+        .output()
+        .expect("failed to run resource_monitor")
+}
+
+/// The meta line of a JSON stdout.
+fn meta_line(output: &Output) -> serde_json::Value {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let first = stdout.lines().next().unwrap_or_default();
+    serde_json::from_str(first).unwrap_or_else(|e| panic!("not JSON ({}): {}", e, stdout))
+}
+
+#[test]
+fn invalid_env_value_is_an_error_naming_the_flag() {
+    let output = monitor_with_env(
+        &["batch", "--samples", "1"],
+        &[("MONITOR_CPU_THRESHOLD", "lots")],
+    );
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--cpu-threshold"), "{}", stderr);
+    assert!(stderr.contains("lots"), "{}", stderr);
+}
+
+#[test]
+fn openmetrics_exposes_spikes_closed_at_the_end() {
+    let dir = tempfile::tempdir().unwrap();
+    // Still over the threshold when the file ends.
+    let snapshots = cpu_series_fixture(dir.path(), &[10.0, 95.0, 97.0, 99.0]);
+    let output = monitor(&[
+        "replay",
+        "--snapshots",
+        &snapshots,
+        "--output",
+        "openmetrics",
+        "--cpu-threshold",
+        "90",
+        "--min-spike-duration-secs",
+        "1",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    // One exposition per snapshot plus one for the closed spike, each
+    // terminated by its own `# EOF`.
+    assert!(stdout.ends_with("# EOF\n"), "{}", stdout);
+    let expositions: Vec<&str> = stdout.split_terminator("# EOF\n").collect();
+    assert_eq!(expositions.len(), 5, "{}", stdout);
+
+    for exposition in &expositions {
+        assert!(exposition.contains("# TYPE resource_monitor_spikes counter\n"));
+        // Counter samples carry the _total/_created suffixes, the family
+        // name doesn't.
+        for line in exposition.lines() {
+            if let Some(rest) = line.strip_prefix("resource_monitor_spikes") {
+                assert!(
+                    rest.starts_with("_total{") || rest.starts_with("_created{"),
+                    "{}",
+                    line
+                );
+            }
+        }
+    }
+    assert!(!expositions[3].contains("resource_monitor_spikes_total"));
+    let last = expositions[4];
+    assert!(
+        last.contains("resource_monitor_spikes_total{resource=\"cpu\"} 1 1700000003\n"),
+        "{}",
+        last
+    );
+    assert!(last.contains("resource_monitor_spikes_created{resource=\"cpu\"} "));
+}
+
+#[test]
+fn log_best_effort_runs_without_an_unopenable_log() {
+    let dir = tempfile::tempdir().unwrap();
+    let snapshots = cpu_fixture(dir.path(), 99.0, 5);
+    // A regular file as the parent directory can't be created into,
+    // even by root.
+    let blocker = dir.path().join("not-a-dir");
+    std::fs::write(&blocker, "").unwrap();
+    let log = blocker.join("spikes.jsonl");
+    let args = [
+        "replay",
+        "--snapshots",
+        &snapshots,
+        "--output",
+        "json",
+        "--cpu-threshold",
+        "90",
+        "--log-file",
+        log.to_str().unwrap(),
+    ];
+
+    // Fail-fast by default.
+    let output = monitor(&args);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+
+    let mut best_effort = args.to_vec();
+    best_effort.push("--log-best-effort");
+    let output = monitor(&best_effort);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(snapshot_lines(&output), 5);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Warning"), "{}", stderr);
+    assert!(!log.exists());
+}
+
+#[test]
+fn json_array_output_is_one_array() {
+    let output = monitor(&[
+        "batch",
+        "--samples",
+        "3",
+        "--interval-ms",
+        "10",
+        "--output",
+        "json",
+        "--json-array",
+        "--cpu-threshold",
+        "0",
+        "--min-spike-duration-secs",
+        "0",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    let run: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    let items = run.as_array().unwrap();
+    let types: Vec<&str> = items.iter().map(|v| v["type"].as_str().unwrap()).collect();
+    assert_eq!(types[0], "meta");
+    assert_eq!(types.iter().filter(|t| **t == "snapshot").count(), 3);
+    assert!(types.contains(&"event"), "{}", stdout);
+
+    // Without json output there is nothing to frame.
+    let output = monitor(&["batch", "--samples", "1", "--json-array"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn logs_min_over_ratio_keeps_the_worse_spikes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("spikes.jsonl");
+    // Threshold 90: over ratios 1.06, 1.5 and 2.0.
+    let lines = [
+        log_record("barely", 100, 5, 95.0),
+        log_record("half", 200, 5, 135.0),
+        log_record("double", 300, 5, 180.0),
+    ];
+    std::fs::write(&path, lines.concat()).unwrap();
+    let log = path.to_str().unwrap();
+
+    assert_eq!(
+        logged_ids(log, &["--min-over-ratio", "1.5"]),
+        ["half", "double"]
+    );
+    assert_eq!(logged_ids(log, &["--min-over-ratio", "1.9"]), ["double"]);
+    assert_eq!(
+        logged_ids(log, &["--min-over-ratio", "3"]),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn once_prints_exactly_one_snapshot() {
+    let output = monitor(&["live", "--once", "--output", "json"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(snapshot_lines(&output), 1);
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().count(), 1);
+}
+
+#[test]
+fn tagged_events_are_logged_and_filtered_by_tag() {
+    let dir = tempfile::tempdir().unwrap();
+    let snapshots = cpu_fixture(dir.path(), 99.0, 10);
+    let log = dir.path().join("spikes.jsonl");
+    let log = log.to_str().unwrap();
+
+    for tag in [Some("deploy-1234"), None, Some("deploy-1235")] {
+        let mut args = vec![
+            "replay",
+            "--snapshots",
+            &snapshots,
+            "--cpu-threshold",
+            "90",
+            "--log-file",
+            log,
+        ];
+        if let Some(tag) = tag {
+            args.extend(["--tag", tag]);
+        }
+        let output = monitor(&args);
+        assert!(output.status.success(), "{:?}", output);
+    }
+
+    let tagged = |tag: &str| -> Vec<serde_json::Value> {
+        let output = monitor(&["logs", "--log-file", log, "--output", "json", "--tag", tag]);
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    };
+    let records = tagged("deploy-1234");
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0]["tag"], "deploy-1234");
+    assert_eq!(records[0]["resource"], "cpu");
+    assert_eq!(tagged("deploy-1235").len(), 1);
+    assert!(tagged("deploy-9").is_empty());
+    assert_eq!(logged_ids(log, &[]).len(), 3);
+}
+
+#[test]
+fn vv_reports_the_spike_opening() {
+    let dir = tempfile::tempdir().unwrap();
+    let snapshots = cpu_series_fixture(dir.path(), &[10.0, 95.0, 97.0, 99.0, 10.0]);
+    let run = |verbosity: &[&str]| {
+        let mut args = vec!["replay", "--snapshots", &snapshots, "--cpu-threshold", "90"];
+        args.extend_from_slice(verbosity);
+        let output = monitor(&args);
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    let stderr = run(&["-vv"]);
+    assert!(
+        stderr.contains("[monitor-debug] cpu spike opened: value=95 threshold=90 start=1700000001"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("[monitor-debug] cpu spike closed after 3s"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("[monitor-trace]"), "{}", stderr);
+
+    // -v shows transitions but not the per-sample values.
+    let stderr = run(&["-v"]);
+    assert!(
+        stderr.contains("[monitor-debug] cpu spike opened"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("[monitor-trace]"), "{}", stderr);
+
+    // The default stays quiet.
+    let stderr = run(&[]);
+    assert!(!stderr.contains("[monitor-debug]"), "{}", stderr);
+    assert!(!stderr.contains("[monitor-trace]"), "{}", stderr);
+}
+
+#[test]
+fn crlf_line_ending_separates_every_record() {
+    let dir = tempfile::tempdir().unwrap();
+    let snapshots = cpu_series_fixture(dir.path(), &[10.0, 95.0, 97.0, 99.0, 10.0]);
+    let run = |ending: &str| {
+        let output = monitor(&[
+            "replay",
+            "--snapshots",
+            &snapshots,
+            "--output",
+            "json",
+            "--cpu-threshold",
+            "90",
+            "--line-ending",
+            ending,
+        ]);
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let crlf = run("crlf");
+    // The meta line, five snapshots and the event, each ended by CRLF,
+    // with no bare LF.
+    assert_eq!(crlf.matches("\r\n").count(), 7, "{:?}", crlf);
+    assert_eq!(crlf.matches('\n').count(), 7, "{:?}", crlf);
+    assert!(crlf.ends_with("}\r\n"), "{:?}", crlf);
+
+    let lf = run("lf");
+    assert!(!lf.contains('\r'), "{:?}", lf);
+    assert_eq!(lf.matches('\n').count(), 7, "{:?}", lf);
+}
+
+#[test]
+fn logs_filter_by_top_process_name() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("spikes.jsonl");
+    let with_top = |id: &str, ts: u64, names: &[&str]| {
+        let top: Vec<String> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                format!(
+                    "{{\"pid\":{},\"name\":\"{}\",\"cpu\":50.0,\"ram_bytes\":1024}}",
+                    100 + i,
+                    name
+                )
+            })
+            .collect();
+        log_record(id, ts, 5, 95.0).replace("\"top\":[]", &format!("\"top\":[{}]", top.join(",")))
+    };
+    let lines = [
+        with_top("db", 100, &["postgres", "nginx"]),
+        with_top("db-worker", 200, &["postgres: autovacuum worker"]),
+        with_top("web", 300, &["nginx", "node"]),
+        with_top("none", 400, &[]),
+    ];
+    std::fs::write(&path, lines.concat()).unwrap();
+    let log = path.to_str().unwrap();
+
+    assert_eq!(
+        logged_ids(log, &["--proc-name", "postgres"]),
+        ["db", "db-worker"]
+    );
+    assert_eq!(logged_ids(log, &["--proc-name", "nginx"]), ["db", "web"]);
+    assert_eq!(
+        logged_ids(log, &["--proc-regex", "^postgres$|^node$"]),
+        ["db", "web"]
+    );
+    // Records without top processes never match.
+    assert!(logged_ids(log, &["--proc-regex", ".*"])
+        .iter()
+        .all(|id| id != "none"));
+    assert!(logged_ids(log, &["--proc-name", "redis"]).is_empty());
+
+    let output = monitor(&["logs", "--log-file", log, "--proc-regex", "("]);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+}
+
+#[test]
+fn quiet_json_prints_no_snapshot_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let snapshots = cpu_series_fixture(dir.path(), &[10.0, 95.0, 97.0, 99.0, 10.0]);
+    let output = monitor(&[
+        "replay",
+        "--snapshots",
+        &snapshots,
+        "--quiet",
+        "--output",
+        "json",
+        "--cpu-threshold",
+        "90",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let types: Vec<String> = stdout
+        .lines()
+        .map(|line| {
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            value["type"].as_str().unwrap_or_default().to_string()
+        })
+        .collect();
+    assert_eq!(types, ["meta", "event"], "{}", stdout);
+    assert!(!stdout.contains("\"type\":\"snapshot\""), "{}", stdout);
+}
+
+#[test]
+fn env_variables_fill_in_missing_flags() {
+    let vars = [
+        ("MONITOR_CPU_THRESHOLD", "85"),
+        ("MONITOR_INTERVAL_MS", "25"),
+        ("MONITOR_QUIET", "true"),
+        ("MONITOR_OUTPUT", "json"),
+    ];
+    let batch = ["batch", "--samples", "2"];
+
+    let output = monitor_with_env(&batch, &vars);
+    assert!(output.status.success(), "{:?}", output);
+    let meta = meta_line(&output);
+    assert_eq!(meta["interval_ms"], 25);
+    assert_eq!(meta["thresholds"]["cpu"], 85.0);
+    assert_eq!(snapshot_lines(&output), 0);
+
+    // A flag wins over its variable.
+    let mut flagged = batch.to_vec();
+    flagged.extend(["--cpu-threshold", "70", "--interval-ms", "30"]);
+    let meta = meta_line(&monitor_with_env(&flagged, &vars));
+    assert_eq!(meta["interval_ms"], 30);
+    assert_eq!(meta["thresholds"]["cpu"], 70.0);
+
+    // The shared options read the same variables in replay.
+    let dir = tempfile::tempdir().unwrap();
+    let snapshots = cpu_fixture(dir.path(), 10.0, 3);
+    let replay = ["replay", "--snapshots", &snapshots];
+    let meta = meta_line(&monitor_with_env(&replay, &vars));
+    assert_eq!(meta["thresholds"]["cpu"], 85.0);
+}
+
+#[test]
+fn env_variable_gives_way_to_a_conflicting_flag() {
+    let json = ["--samples", "1", "--output", "json"];
+    let batch = |flags: &[&str], var: (&str, &str)| {
+        let mut args = vec!["batch"];
+        args.extend_from_slice(&json);
+        args.extend_from_slice(flags);
+        let output = monitor_with_env(&args, &[var]);
+        assert!(output.status.success(), "{:?}", output);
+        output
+    };
+
+    let meta = meta_line(&batch(&["--rate", "4"], ("MONITOR_INTERVAL_MS", "500")));
+    assert_eq!(meta["interval_ms"], 250);
+
+    let output = batch(
+        &["--ram-threshold-bytes", "4G"],
+        ("MONITOR_RAM_THRESHOLD", "80"),
+    );
+    let meta = meta_line(&output);
+    assert_eq!(meta["thresholds"]["ram_bytes"], 4u64 << 30);
+    assert!(meta["thresholds"]["ram"].is_null(), "{}", meta);
+
+    batch(&["--raw-bytes"], ("MONITOR_BINARY_UNITS", "true"));
+
+    let live = [
+        "live",
+        "--max-duration",
+        "1",
+        "--interval-ms",
+        "10",
+        "--output",
+        "json",
+    ];
+    let output = monitor_with_env(&live, &[("MONITOR_MAX_SAMPLES", "3")]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(snapshot_lines(&output) > 3);
+}
+
+#[test]
+fn conflicting_options_set_the_same_way_are_an_error() {
+    let both_flags = monitor(&["batch", "--rate", "4", "--interval-ms", "500"]);
+    assert_eq!(both_flags.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&both_flags.stderr);
+    assert!(
+        stderr.contains("'--interval-ms' cannot be used with '--rate'"),
+        "{}",
+        stderr
+    );
+
+    let both_vars = monitor_with_env(
+        &["batch", "--samples", "1"],
+        &[("MONITOR_RATE", "4"), ("MONITOR_INTERVAL_MS", "500")],
+    );
+    assert_eq!(both_vars.status.code(), Some(2));
+}
+
+#[test]
+fn invalid_choice_in_env_is_an_error_naming_the_flag() {
+    let cases = [
+        ("MONITOR_OUTPUT", "jsn", "--output"),
+        ("MONITOR_LOG_FLUSH", "sometimes", "--log-flush"),
+        ("MONITOR_LINE_ENDING", "cr", "--line-ending"),
+        ("MONITOR_SYSLOG_FACILITY", "local9", "--syslog-facility"),
+    ];
+    for (name, value, flag) in cases {
+        let output = monitor_with_env(&["batch", "--samples", "1"], &[(name, value)]);
+        assert_eq!(output.status.code(), Some(2), "{}={}", name, value);
+        assert!(output.stdout.is_empty(), "{:?}", output);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(flag), "{}", stderr);
+        assert!(stderr.contains(value), "{}", stderr);
+    }
+}